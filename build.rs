@@ -1,9 +1,13 @@
-use std::fs;
+use std::{env, fs, path::PathBuf};
 
 fn main() {
-    let home = env!("HOME");
-    let config_dir = format!("{}/.config/morfo/", home);
-    let config_path = format!("{}/config.toml", config_dir);
+    // HOME is not set on Windows, where the profile directory is used instead
+    let home = env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+        .expect("could not locate the home directory");
+    let config_dir = home.join(".config").join("morfo");
+    let config_path = config_dir.join("config.toml");
 
     // Create directory if it doesn't exist
     fs::create_dir_all(config_dir).unwrap();
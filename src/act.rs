@@ -1,73 +1,736 @@
-use std::path::PathBuf;
+//! The dependency tree of a program.
+//!
+//! Starting from the main file, morfo follows every `#include "..."` to its header, looked
+//! up like the preprocessor does, and a source file next to the header with the same
+//! name, such as `util.c` for `util.h`, becomes a translation unit the including file
+//! depends on. Sources named differently from their header, or living elsewhere, can be
+//! declared with `header_sources`.
+//! Every translation unit is compiled to an object, and the objects are linked into the
+//! executable.
+//!
+//! The headers the sources include are part of the tree too, including the headers no
+//! source implements, so that changing any of them rebuilds the sources including them.
+//!
+//! A C++ source importing a module, as in `import math;`, depends on the source of the
+//! project that declares its interface with `export module math;`, and on the sources
+//! implementing it with `module math;`. Partitions imported with `import :ops;` are found
+//! the same way. The interfaces are compiled before the sources importing them.
+//!
+//! Between runs, morfo keeps the includes found in every file in a [`ScanCache`] in the
+//! build directory and builds the tree with [`Act::build_cached`], so only the files
+//! that changed are scanned again.
+//!
+//! The tree can be walked with [`Act::nodes`], [`Act::edges`] and [`Act::includes`], and
+//! serialized, say to JSON, to be inspected by other tools:
+//!
+//! ```no_run
+//! use std::{collections::BTreeMap, path::Path};
+//!
+//! use morfo::act::{
+//!     dirinfo::{self, Exclude},
+//!     Act, IncludePaths,
+//! };
+//!
+//! let main_file = Path::new("main.c");
+//! let dirinfo = dirinfo::get_dir_info(Path::new("."), &Exclude::default());
+//! let paths = IncludePaths::default();
+//! let act = Act::build(main_file, &dirinfo, &paths, &BTreeMap::new()).unwrap();
+//! for (dependent, dependency) in act.edges() {
+//!     println!("{} -> {}", dependent.display(), dependency.display());
+//! }
+//! println!("{}", serde_json::to_string_pretty(&act).unwrap());
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
 
 use dirinfo::DirInfo;
+pub use scan::ScanCache;
+use scan::Scanner;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{MorfoError, MorfoResult},
+    utils,
+};
 
 mod builder;
 pub mod dirinfo;
+mod modules;
+mod preprocess;
+mod scan;
 
-#[derive(Debug, PartialEq)]
-pub struct ACT {
-    pub name: String,
+/// Where the headers included by the sources are looked up.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IncludePaths {
+    /// The include directories, searched in order after the directory of the including
+    /// file, like `-I`.
+    pub dirs: Vec<PathBuf>,
+    /// Whether `#include <...>` is looked up in the include directories too. Headers found
+    /// there belong to the project, and every other angled header is a system header.
+    pub angled: bool,
+    /// The directories the compiler searches for headers outside the project, if known.
+    /// A quoted include found neither in the project nor in them is an error; when they
+    /// are not known, it is left to the compiler.
+    pub system: Option<Vec<PathBuf>>,
+    /// The headers morfo generates during the build, which may not exist yet.
+    pub generated: Vec<PathBuf>,
+}
+
+/// A translation unit and the translation units it depends on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Act {
+    /// The source file of the translation unit.
+    pub name: PathBuf,
+    /// The header the translation unit was included through, as written in the
+    /// `#include`, or `None` for the main file and generated sources.
     pub header: Option<String>,
+    /// Linker arguments the translation unit needs.
     pub linkers: Vec<String>,
-    pub dependencies: Vec<ACT>,
+    /// The translation units this one depends on, in the order they are included.
+    pub dependencies: Vec<Act>,
+    /// The project headers the source includes, directly or through other headers, as
+    /// they are found. Headers no source implements are only tracked here, so a change to
+    /// them still rebuilds the translation units including them.
+    #[serde(default)]
+    pub headers: Vec<PathBuf>,
+    /// The C++ module the translation unit is the interface of, such as `math` or the
+    /// partition `math:ops`, if any.
+    #[serde(default)]
+    pub module: Option<String>,
+    /// The C++ modules whose interfaces the translation unit imports, which are compiled
+    /// before it.
+    #[serde(default)]
+    pub imports: Vec<String>,
 }
 
-impl ACT {
-    fn new(name: &str) -> Self {
+impl Act {
+    fn new(name: &Path) -> Self {
         Self {
-            name: name.to_string(),
+            name: name.to_path_buf(),
             header: Option::default(),
             linkers: Vec::default(),
             dependencies: Vec::default(),
+            headers: Vec::default(),
+            module: None,
+            imports: Vec::default(),
         }
     }
 
-    pub fn build(filepath: &PathBuf, dirinfo: &DirInfo) -> Self {
-        let mut current = ACT::new(filepath.to_str().unwrap());
+    /// Builds the dependency tree of `filepath` from the sources in `dirinfo`, looking up
+    /// the included headers in `paths`. Includes in conditional branches that are not
+    /// compiled with `macros` defined, such as the predefined macros of the compiler,
+    /// are skipped.
+    /// A source that includes its own header, directly or through others, does not
+    /// depend on itself.
+    ///
+    /// # Errors
+    ///
+    /// [`FileNotFound`] if `filepath` or one of its dependencies does not exist,
+    /// [`UnreadableSource`] if one cannot be read, [`AmbiguousInclude`] if more than
+    /// one source could implement an included header, and [`UnresolvedInclude`] if a
+    /// quoted include is not found, when the directories of the compiler are known.
+    ///
+    /// [`FileNotFound`]: MorfoError::FileNotFound
+    /// [`UnreadableSource`]: MorfoError::UnreadableSource
+    /// [`AmbiguousInclude`]: MorfoError::AmbiguousInclude
+    /// [`UnresolvedInclude`]: MorfoError::UnresolvedInclude
+    pub fn build(
+        filepath: &Path,
+        dirinfo: &DirInfo,
+        paths: &IncludePaths,
+        macros: &BTreeMap<String, String>,
+    ) -> MorfoResult<Self> {
+        let mut scanner = Scanner::new(macros, None);
+        Act::build_within(filepath, dirinfo, paths, &mut scanner, &mut Vec::new())
+    }
 
-        let includes = builder::get_all_includes(filepath).unwrap();
+    /// Builds the tree of `filepath` like [`Act::build`], only scanning the files that
+    /// changed since their scan was put in `cache`, and putting in the new scans.
+    ///
+    /// # Errors
+    ///
+    /// Like [`Act::build`].
+    pub fn build_cached(
+        filepath: &Path,
+        dirinfo: &DirInfo,
+        paths: &IncludePaths,
+        macros: &BTreeMap<String, String>,
+        cache: &mut ScanCache,
+    ) -> MorfoResult<Self> {
+        let mut scanner = Scanner::new(macros, Some(cache));
+        Act::build_within(filepath, dirinfo, paths, &mut scanner, &mut Vec::new())
+    }
+
+    /// Builds the tree of `filepath`, which is included by each of `including`.
+    fn build_within(
+        filepath: &Path,
+        dirinfo: &DirInfo,
+        paths: &IncludePaths,
+        scanner: &mut Scanner,
+        including: &mut Vec<PathBuf>,
+    ) -> MorfoResult<Self> {
+        let mut current = Act::new(filepath);
+        including.push(utils::normalize(filepath));
+
+        let includes = scanner.includes(filepath)?;
         for include in includes {
-            // find include in dirinfo.header_files
-            for header in &dirinfo.header_files {
-                if header.to_str().unwrap() != include {
+            let Some(header) = builder::resolve_include(&include, filepath, paths) else {
+                builder::check_unresolved(&include, filepath, paths, dirinfo)?;
+                continue;
+            };
+            current.add_header(&header, dirinfo, paths, scanner)?;
+
+            for c in source_of(&header, dirinfo, paths, scanner)? {
+                if including.contains(&utils::normalize(&c)) {
                     continue;
                 }
 
-                // replace the .h with .c extension and find it in dirinfo.c_files
-                let mut c_file = header.clone();
-                c_file.set_extension("c");
-                for c in &dirinfo.c_files {
-                    if c.to_str().unwrap() != c_file.to_str().unwrap() {
-                        continue;
-                    }
+                // if found, add it as a dependency
+                let mut act = Act::build_within(&c, dirinfo, paths, scanner, including)?;
+                act.header = Some(include.header.clone());
+                current.dependencies.push(act);
+            }
+        }
 
-                    // if found, add it as a dependency
-                    let act = ACT::build(c, dirinfo);
+        // the interfaces of the C++ modules imported are compiled first, and a module
+        // imported by name brings its implementation units along
+        let decls = scanner.modules(filepath)?;
+        current.module = decls.provides().map(str::to_owned);
+        current.imports = decls.requires();
+        for module in &current.imports {
+            let implementations = decls.imports.contains(module) && !module.contains(':');
+            for unit in module_units(module, implementations, dirinfo, scanner)? {
+                if !including.contains(&utils::normalize(&unit)) {
+                    let act = Act::build_within(&unit, dirinfo, paths, scanner, including)?;
                     current.dependencies.push(act);
                 }
             }
         }
 
-        current
+        including.pop();
+        Ok(current)
+    }
+
+    /// Adds `header` and the project headers it includes to the headers of this unit.
+    fn add_header(
+        &mut self,
+        header: &Path,
+        dirinfo: &DirInfo,
+        paths: &IncludePaths,
+        scanner: &mut Scanner,
+    ) -> MorfoResult<()> {
+        if self.headers.iter().any(|known| known == header) {
+            return Ok(());
+        }
+        self.headers.push(header.to_path_buf());
+        for include in scanner.includes(header)? {
+            match builder::resolve_include(&include, header, paths) {
+                Some(nested) => self.add_header(&nested, dirinfo, paths, scanner)?,
+                None => builder::check_unresolved(&include, header, paths, dirinfo)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a generated `source` as a translation unit the tree depends on.
+    pub fn add_generated(&mut self, source: &Path) {
+        self.dependencies.push(Act::new(source));
+    }
+
+    /// Returns the sources of every translation unit in the tree, dependencies first.
+    /// A source reachable through more than one path is listed once.
+    pub fn sources(&self) -> Vec<PathBuf> {
+        self.nodes().map(|node| node.name.clone()).collect()
+    }
+
+    /// Returns an iterator over the translation units in the tree, dependencies first
+    /// and this one last. A translation unit reachable through more than one path is
+    /// visited once.
+    pub fn nodes(&self) -> impl Iterator<Item = &Act> {
+        let mut nodes = Vec::new();
+        self.collect_nodes(&mut nodes);
+        nodes.into_iter()
+    }
+
+    fn collect_nodes<'a>(&'a self, nodes: &mut Vec<&'a Act>) {
+        for dependency in &self.dependencies {
+            dependency.collect_nodes(nodes);
+        }
+        if nodes.iter().all(|node| node.name != self.name) {
+            nodes.push(self);
+        }
+    }
+
+    /// Returns an iterator over the dependencies in the tree, as pairs of the source of
+    /// the dependent translation unit and the source of its dependency. A dependency
+    /// reachable through more than one path is visited once.
+    pub fn edges(&self) -> impl Iterator<Item = (&Path, &Path)> {
+        let mut edges = Vec::new();
+        for node in self.nodes() {
+            for dependency in &node.dependencies {
+                let edge = (node.name.as_path(), dependency.name.as_path());
+                if !edges.contains(&edge) {
+                    edges.push(edge);
+                }
+            }
+        }
+        edges.into_iter()
+    }
+
+    /// Returns an iterator over the headers in the tree, as pairs of the source of a
+    /// translation unit and a header it includes, directly or through other headers.
+    pub fn includes(&self) -> impl Iterator<Item = (&Path, &Path)> {
+        self.nodes().flat_map(|node| {
+            node.headers
+                .iter()
+                .map(|header| (node.name.as_path(), header.as_path()))
+        })
+    }
+
+    /// Returns every file in the tree once: the sources, dependencies first, then the
+    /// headers they include.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files = self.sources();
+        for (_, header) in self.includes() {
+            if !files.iter().any(|file| file == header) {
+                files.push(header.to_path_buf());
+            }
+        }
+        files
+    }
+
+    /// Renders the tree with a line for every translation unit, under the unit depending
+    /// on it and followed by the header it is included through. A unit reachable through
+    /// more than one path is only expanded the first time, and marked with `(*)` after.
+    ///
+    /// ```text
+    /// main.c
+    /// ├── lib/util.c (lib/util.h)
+    /// │   └── common/log.c (../common/log.h)
+    /// └── inc/api.c (api.h)
+    /// ```
+    pub fn tree(&self) -> String {
+        let mut tree = String::new();
+        self.render(&mut tree, "", "", &mut Vec::new());
+        tree
+    }
+
+    /// Renders this unit after `lead` and its dependencies after `indent`.
+    fn render<'a>(&'a self, tree: &mut String, lead: &str, indent: &str, seen: &mut Vec<&'a Path>) {
+        let _ = write!(tree, "{}{}", lead, self.name.display());
+        if let Some(header) = &self.header {
+            let _ = write!(tree, " ({})", header);
+        }
+        if seen.contains(&self.name.as_path()) && !self.dependencies.is_empty() {
+            tree.push_str(" (*)\n");
+            return;
+        }
+        tree.push('\n');
+        seen.push(&self.name);
+
+        for (i, dependency) in self.dependencies.iter().enumerate() {
+            let (branch, nested) = match i + 1 == self.dependencies.len() {
+                true => ("└── ", "    "),
+                false => ("├── ", "│   "),
+            };
+            let lead = format!("{}{}", indent, branch);
+            let indent = format!("{}{}", indent, nested);
+            dependency.render(tree, &lead, &indent, seen);
+        }
+    }
+}
+
+/// Returns the sources implementing `header`: the sources declared for it, or else the
+/// source of the same name next to it or, if there is none, the one source of that name
+/// elsewhere that includes it.
+///
+/// # Errors
+///
+/// [`AmbiguousInclude`] if more than one source elsewhere could implement `header`.
+///
+/// [`AmbiguousInclude`]: MorfoError::AmbiguousInclude
+fn source_of(
+    header: &Path,
+    dirinfo: &DirInfo,
+    paths: &IncludePaths,
+    scanner: &mut Scanner,
+) -> MorfoResult<Vec<PathBuf>> {
+    if let Some(sources) = dirinfo.header_sources.get(&utils::normalize(header)) {
+        return Ok(sources.clone());
+    }
+
+    // replace the .h with .c extension and find it in dirinfo.c_files
+    let c_file = header.with_extension("c");
+    if let Some(c) = dirinfo
+        .c_files
+        .iter()
+        .find(|c| utils::normalize(c) == c_file)
+    {
+        return Ok(vec![c.clone()]);
+    }
+    // or with the .m extension of Objective-C
+    let m_file = header.with_extension("m");
+    if let Some(m) = dirinfo
+        .objc_files
+        .iter()
+        .find(|m| utils::normalize(m) == m_file)
+    {
+        return Ok(vec![m.clone()]);
+    }
+    // a Fortran source declaring its procedures to C in a header of the same name
+    if let Some(f) = dirinfo
+        .fortran_files
+        .iter()
+        .find(|f| utils::normalize(f).with_extension("h") == header)
+    {
+        return Ok(vec![f.clone()]);
+    }
+
+    let mut candidates = Vec::new();
+    for c in dirinfo
+        .c_files
+        .iter()
+        .filter(|c| c.file_stem() == header.file_stem())
+    {
+        let includes = scanner.includes(c)?;
+        if includes
+            .iter()
+            .any(|include| builder::resolve_include(include, c, paths).as_deref() == Some(header))
+        {
+            candidates.push(c);
+        }
+    }
+    match candidates[..] {
+        [] | [_] => Ok(candidates.into_iter().cloned().collect()),
+        _ => Err(MorfoError::AmbiguousInclude(
+            header.to_path_buf(),
+            candidates.into_iter().cloned().collect(),
+        )),
+    }
+}
+
+/// Returns the C++ sources of `module`: the unit declaring its interface, or the
+/// partition it names, followed by its implementation units if `implementations`. A
+/// module no source of the project declares, such as `std`, has none.
+///
+/// # Errors
+///
+/// [`AmbiguousModule`] if more than one source declares the interface of `module`.
+///
+/// [`AmbiguousModule`]: MorfoError::AmbiguousModule
+fn module_units(
+    module: &str,
+    implementations: bool,
+    dirinfo: &DirInfo,
+    scanner: &mut Scanner,
+) -> MorfoResult<Vec<PathBuf>> {
+    let mut interfaces = Vec::new();
+    let mut units = Vec::new();
+    for source in &dirinfo.cpp_files {
+        let decls = scanner.modules(source)?;
+        if decls.provides() == Some(module) {
+            interfaces.push(source.clone());
+        } else if implementations && decls.implements(module) {
+            units.push(source.clone());
+        }
+    }
+    match interfaces[..] {
+        [] => Ok(Vec::new()),
+        [_] => Ok(interfaces.into_iter().chain(units).collect()),
+        _ => Err(MorfoError::AmbiguousModule(module.to_owned(), interfaces)),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use dirinfo::Exclude;
 
     #[test]
     fn act_new() {
-        let act = ACT::new("main.c");
+        let act = Act::new(Path::new("main.c"));
         assert_eq!(
             act,
-            ACT {
-                name: "main.c".to_string(),
+            Act {
+                name: PathBuf::from("main.c"),
                 header: None,
                 linkers: Vec::default(),
                 dependencies: Vec::default(),
+                headers: Vec::default(),
+                module: None,
+                imports: Vec::default(),
             }
         );
     }
+
+    fn diamond() -> Act {
+        let mut util = Act::new(Path::new("util.c"));
+        util.dependencies.push(Act::new(Path::new("log.c")));
+        let mut main = Act::new(Path::new("main.c"));
+        main.dependencies.push(util);
+        main.dependencies.push(Act::new(Path::new("log.c")));
+        main
+    }
+
+    #[test]
+    fn act_sources() {
+        assert_eq!(
+            diamond().sources(),
+            vec![
+                PathBuf::from("log.c"),
+                PathBuf::from("util.c"),
+                PathBuf::from("main.c")
+            ]
+        );
+    }
+
+    #[test]
+    fn act_edges() {
+        let act = diamond();
+        assert_eq!(
+            act.edges().collect::<Vec<_>>(),
+            vec![
+                (Path::new("util.c"), Path::new("log.c")),
+                (Path::new("main.c"), Path::new("util.c")),
+                (Path::new("main.c"), Path::new("log.c")),
+            ]
+        );
+    }
+
+    #[test]
+    fn act_tree() {
+        let included = |name: &str, header: &str, dependencies: Vec<Act>| Act {
+            header: Some(header.to_owned()),
+            dependencies,
+            ..Act::new(Path::new(name))
+        };
+        let log = included("log.c", "log.h", Vec::new());
+        let util = included("util.c", "util.h", vec![log]);
+        let mut main = Act::new(Path::new("main.c"));
+        main.dependencies.push(util.clone());
+        main.dependencies
+            .push(included("net.c", "net.h", vec![util]));
+        main.headers = vec![PathBuf::from("util.h"), PathBuf::from("config.h")];
+
+        assert_eq!(
+            main.tree(),
+            concat!(
+                "main.c\n",
+                "├── util.c (util.h)\n",
+                "│   └── log.c (log.h)\n",
+                "└── net.c (net.h)\n",
+                "    └── util.c (util.h) (*)\n",
+            )
+        );
+        assert_eq!(
+            main.files(),
+            ["log.c", "util.c", "net.c", "main.c", "util.h", "config.h"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn act_serde_round_trip() {
+        let mut act = diamond();
+        act.dependencies[0].header = Some("util.h".to_owned());
+
+        let json = serde_json::to_string(&act).unwrap();
+        assert_eq!(serde_json::from_str::<Act>(&json).unwrap(), act);
+    }
+
+    #[test]
+    fn act_build_resolves_includes() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        for subdir in ["lib", "common", "inc"] {
+            std::fs::create_dir(dir.join(subdir)).unwrap();
+        }
+        let files = [
+            ("main.c", "#include \"lib/util.h\"\n#include \"api.h\"\n"),
+            ("lib/util.h", ""),
+            (
+                "lib/util.c",
+                "#include \"util.h\"\n#include \"../common/log.h\"\n",
+            ),
+            ("common/log.h", ""),
+            ("common/log.c", "#include \"log.h\"\n"),
+            ("inc/api.h", ""),
+            ("inc/api.c", ""),
+        ];
+        for (name, contents) in files {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+
+        let act = Act::build(
+            &dir.join("main.c"),
+            &dirinfo::get_dir_info(dir, &Exclude::default()),
+            &IncludePaths {
+                dirs: vec![dir.join("inc")],
+                angled: false,
+                ..IncludePaths::default()
+            },
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            act.sources(),
+            ["common/log.c", "lib/util.c", "inc/api.c", "main.c"].map(|name| dir.join(name))
+        );
+        assert_eq!(act.dependencies[0].header.as_deref(), Some("lib/util.h"));
+        assert_eq!(act.dependencies[1].header.as_deref(), Some("api.h"));
+    }
+
+    /// Writes `files` into `dir`, creating their directories.
+    fn write_files(dir: &Path, files: &[(&str, &str)]) {
+        for (name, contents) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+    }
+
+    fn build_in(dir: &Path, include_dirs: &[&str]) -> MorfoResult<Act> {
+        let paths = IncludePaths {
+            dirs: include_dirs
+                .iter()
+                .map(|include| dir.join(include))
+                .collect(),
+            angled: false,
+            ..IncludePaths::default()
+        };
+        Act::build(
+            &dir.join("main.c"),
+            &dirinfo::get_dir_info(dir, &Exclude::default()),
+            &paths,
+            &BTreeMap::new(),
+        )
+    }
+
+    #[test]
+    fn act_build_same_header_names() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        write_files(
+            dir,
+            &[
+                ("main.c", "#include \"a/util.h\"\n#include \"b/util.h\"\n"),
+                ("a/util.h", ""),
+                ("a/util.c", "#include \"util.h\"\n"),
+                ("b/util.h", ""),
+                ("b/util.c", "#include \"util.h\"\n"),
+            ],
+        );
+
+        assert_eq!(
+            build_in(dir, &[]).unwrap().sources(),
+            ["a/util.c", "b/util.c", "main.c"].map(|name| dir.join(name))
+        );
+    }
+
+    #[test]
+    fn act_build_source_elsewhere() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        write_files(
+            dir,
+            &[
+                ("main.c", "#include \"util.h\"\n"),
+                ("include/util.h", ""),
+                ("src/util.c", "#include \"util.h\"\n"),
+                ("tests/util.c", "int main(void) { return 0; }\n"),
+            ],
+        );
+        assert_eq!(
+            build_in(dir, &["include"]).unwrap().sources(),
+            ["src/util.c", "main.c"].map(|name| dir.join(name))
+        );
+
+        write_files(dir, &[("lib/util.c", "#include \"../include/util.h\"\n")]);
+        assert_eq!(
+            build_in(dir, &["include"]),
+            Err(MorfoError::AmbiguousInclude(
+                dir.join("include/util.h"),
+                vec![dir.join("lib/util.c"), dir.join("src/util.c")]
+            ))
+        );
+    }
+
+    #[test]
+    fn act_build_declared_sources() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        write_files(
+            dir,
+            &[
+                ("main.c", "#include \"net.h\"\n"),
+                ("include/net.h", ""),
+                ("src/net_posix.c", "#include \"net.h\"\n"),
+                ("src/net_common.c", ""),
+                ("src/net_win32.c", "#include \"net.h\"\n"),
+            ],
+        );
+
+        let mut dirinfo = dirinfo::get_dir_info(dir, &Exclude::default());
+        dirinfo.header_sources = BTreeMap::from([(
+            dir.join("include/net.h"),
+            vec![dir.join("src/net_posix.c"), dir.join("src/net_common.c")],
+        )]);
+        let paths = IncludePaths {
+            dirs: vec![dir.join("include")],
+            angled: false,
+            ..IncludePaths::default()
+        };
+        let act = Act::build(&dir.join("main.c"), &dirinfo, &paths, &BTreeMap::new()).unwrap();
+        assert_eq!(
+            act.sources(),
+            ["src/net_common.c", "src/net_posix.c", "main.c"].map(|name| dir.join(name))
+        );
+        assert!(act
+            .dependencies
+            .iter()
+            .all(|dependency| dependency.header.as_deref() == Some("net.h")));
+    }
+
+    #[test]
+    fn act_build_header_only() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        write_files(
+            dir,
+            &[
+                ("main.c", "#include \"vec.h\"\n#include <stdio.h>\n"),
+                ("vec.h", "#include \"alloc.h\"\n"),
+                ("alloc.h", "#include \"vec.h\"\n"),
+            ],
+        );
+
+        let act = build_in(dir, &[]).unwrap();
+        assert!(act.dependencies.is_empty());
+        assert_eq!(
+            act.includes().collect::<Vec<_>>(),
+            vec![
+                (dir.join("main.c").as_path(), dir.join("vec.h").as_path()),
+                (dir.join("main.c").as_path(), dir.join("alloc.h").as_path()),
+            ]
+        );
+    }
+
+    #[test]
+    fn act_build_missing_file() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dirinfo = dirinfo::get_dir_info(tmp_dir.path(), &Exclude::default());
+        let main_file = tmp_dir.path().join("main.c");
+        assert_eq!(
+            Act::build(
+                &main_file,
+                &dirinfo,
+                &IncludePaths::default(),
+                &BTreeMap::new()
+            ),
+            Err(MorfoError::FileNotFound(main_file))
+        );
+    }
 }
@@ -1,28 +1,191 @@
-use std::{fs, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
 
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 
-use crate::error::MorfoResult;
+use super::{
+    dirinfo::DirInfo,
+    preprocess::{self, Conditionals},
+    IncludePaths,
+};
+use crate::{
+    error::{MorfoError, MorfoResult},
+    utils,
+};
 
-#[allow(dead_code)]
-pub fn get_all_includes(filepath: &PathBuf) -> MorfoResult<Vec<String>> {
+/// An `#include` of a header.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Include {
+    /// The header, as written.
+    pub header: String,
+    /// Whether the header is written in angle brackets, `#include <...>`, rather than
+    /// in quotes.
+    pub angled: bool,
+}
+
+/// Returns the headers `filepath` includes, in quotes or angle brackets, in the branches
+/// of its conditionals compiled with `macros` defined.
+///
+/// # Errors
+///
+/// If `filepath` does not exist or cannot be read.
+pub fn get_all_includes(
+    filepath: &Path,
+    macros: &BTreeMap<String, String>,
+) -> MorfoResult<Vec<Include>> {
+    Ok(includes_in(&read_source(filepath)?, macros))
+}
+
+/// Returns the contents of the source or header at `filepath`.
+///
+/// # Errors
+///
+/// If `filepath` does not exist or cannot be read.
+pub fn read_source(filepath: &Path) -> MorfoResult<Vec<u8>> {
+    fs::read(filepath).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => MorfoError::FileNotFound(filepath.to_path_buf()),
+        kind => MorfoError::UnreadableSource(filepath.to_path_buf(), kind),
+    })
+}
+
+/// Returns the headers the source `contents` include, like [`get_all_includes`].
+pub fn includes_in(contents: &[u8], macros: &BTreeMap<String, String>) -> Vec<Include> {
     let mut includes = Vec::new();
 
-    let contents = fs::read_to_string(filepath)?;
-    let re = Regex::new(r#"#include\s*"(.*)""#).unwrap();
+    // sources are not always UTF-8, say a Latin-1 comment, but includes are ASCII
+    let contents = String::from_utf8_lossy(contents);
+    let re = Regex::new(r#"^include\s*(?:"([^"]*)"|<([^>]*)>)"#).unwrap();
 
-    for line in contents.lines() {
-        if let Some(cap) = re.captures(line) {
-            includes.push(cap[1].to_string());
+    let mut conditionals = Conditionals::new(macros);
+    for line in preprocess::logical_lines(&contents) {
+        let Some(directive) = line.trim_start().strip_prefix('#') else {
+            continue;
+        };
+        let directive = directive.trim_start();
+        if !directive.starts_with("include") {
+            conditionals.apply(directive);
+        } else if let Some(cap) = re.captures(directive).filter(|_| conditionals.active()) {
+            includes.push(match (cap.get(1), cap.get(2)) {
+                (Some(header), _) => Include {
+                    header: header.as_str().to_string(),
+                    angled: false,
+                },
+                (_, Some(header)) => Include {
+                    header: header.as_str().to_string(),
+                    angled: true,
+                },
+                (None, None) => unreachable!("one of the alternatives matched"),
+            });
         }
     }
 
-    Ok(includes)
+    includes
+}
+
+/// Returns the header `include` in `including` refers to, the way the preprocessor finds
+/// it: a quoted header relative to the directory of `including` first, then in each of
+/// the include directories in order. An angled header is only looked up in the include
+/// directories, and only if angled includes are searched, so system headers are never
+/// found. Returns `None` if no such header exists.
+pub fn resolve_include(
+    include: &Include,
+    including: &Path,
+    paths: &IncludePaths,
+) -> Option<PathBuf> {
+    let including_dir = match include.angled {
+        true if !paths.angled => return None,
+        true => None,
+        false => Some(including.parent().unwrap_or(Path::new(""))),
+    };
+    including_dir
+        .into_iter()
+        .chain(paths.dirs.iter().map(PathBuf::as_path))
+        .map(|dir| utils::normalize(&dir.join(&include.header)))
+        .find(|header| header.is_file())
+}
+
+/// Fails if the quoted `include` in `including`, which is not in the project, is not
+/// found in the directories of the compiler either, suggesting the headers of `dirinfo`
+/// with a similar name. Nothing is checked unless those directories are known.
+///
+/// # Errors
+///
+/// [`MorfoError::UnresolvedInclude`] if the header is found nowhere.
+pub fn check_unresolved(
+    include: &Include,
+    including: &Path,
+    paths: &IncludePaths,
+    dirinfo: &DirInfo,
+) -> MorfoResult<()> {
+    let Some(system) = &paths.system else {
+        return Ok(());
+    };
+    let found = system.iter().any(|dir| dir.join(&include.header).is_file())
+        || paths
+            .generated
+            .iter()
+            .any(|header| header.ends_with(&include.header));
+    if include.angled || found {
+        return Ok(());
+    }
+
+    let mut searched: Vec<PathBuf> = Vec::new();
+    let dirs = including
+        .parent()
+        .into_iter()
+        .chain(paths.dirs.iter().map(PathBuf::as_path))
+        .chain(system.iter().map(PathBuf::as_path));
+    for dir in dirs.map(utils::normalize) {
+        if !searched.contains(&dir) {
+            searched.push(dir);
+        }
+    }
+    Err(MorfoError::UnresolvedInclude(
+        including.to_path_buf(),
+        include.header.clone(),
+        searched,
+        suggestions(&include.header, dirinfo),
+    ))
+}
+
+/// Returns up to three headers of the project named like `header`, closest first.
+fn suggestions(header: &str, dirinfo: &DirInfo) -> Vec<PathBuf> {
+    let file_name = |path: &Path| {
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let name = file_name(Path::new(header));
+    let mut close: Vec<(usize, PathBuf)> = dirinfo
+        .header_files
+        .iter()
+        .map(|candidate| {
+            let distance = utils::edit_distance(&name, &file_name(candidate));
+            (distance, utils::normalize(candidate))
+        })
+        // a typo or two, but not so many that any short name would do
+        .filter(|(distance, _)| *distance <= 2 && *distance < name.len() / 2)
+        .collect();
+    close.sort();
+    close.into_iter().take(3).map(|(_, path)| path).collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::act::dirinfo::{self, Exclude};
+
+    fn include(header: &str, angled: bool) -> Include {
+        Include {
+            header: header.to_owned(),
+            angled,
+        }
+    }
 
     #[test]
     fn builder_get_all_includes() {
@@ -42,7 +205,121 @@ mod tests {
         // create path to tmp_file
         let temp_file = tmp_file.to_str().unwrap();
 
-        let includes = get_all_includes(&PathBuf::from(temp_file)).unwrap();
-        assert_eq!(includes, vec!["aux.h"]);
+        let includes = get_all_includes(Path::new(temp_file), &BTreeMap::new()).unwrap();
+        assert_eq!(
+            includes,
+            vec![
+                include("stdio.h", true),
+                include("aux.h", false),
+                include("string.h", true)
+            ]
+        );
+    }
+
+    #[test]
+    fn builder_get_all_includes_not_utf8() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let tmp_file = tmp_dir.path().join("main.c");
+        fs::write(&tmp_file, b"/* caf\xe9 */\n#include \"aux.h\"\n").unwrap();
+
+        assert_eq!(
+            get_all_includes(&tmp_file, &BTreeMap::new()).unwrap(),
+            vec![include("aux.h", false)]
+        );
+    }
+
+    #[test]
+    fn builder_get_all_includes_conditional() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let tmp_file = tmp_dir.path().join("main.c");
+        fs::write(
+            &tmp_file,
+            "#ifdef _WIN32\n#  include \"win.h\"\n#else\n#  include \"posix.h\"\n#endif\n\
+             #if 0\n#include \"old.h\"\n#endif\n\
+             // #include \"commented.h\"\n",
+        )
+        .unwrap();
+
+        let windows = BTreeMap::from([("_WIN32".to_owned(), "1".to_owned())]);
+        assert_eq!(
+            get_all_includes(&tmp_file, &windows).unwrap(),
+            vec![include("win.h", false)]
+        );
+        assert_eq!(
+            get_all_includes(&tmp_file, &BTreeMap::new()).unwrap(),
+            vec![include("posix.h", false)]
+        );
+    }
+
+    #[test]
+    fn builder_resolve_angled_include() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let include_dir = tmp_dir.path().join("include");
+        fs::create_dir_all(include_dir.join("mylib")).unwrap();
+        fs::write(include_dir.join("mylib").join("foo.h"), "").unwrap();
+        let main_file = tmp_dir.path().join("main.c");
+        let mut paths = IncludePaths {
+            dirs: vec![include_dir.clone()],
+            angled: false,
+            ..IncludePaths::default()
+        };
+
+        let foo = include("mylib/foo.h", true);
+        assert_eq!(resolve_include(&foo, &main_file, &paths), None);
+        paths.angled = true;
+        assert_eq!(
+            resolve_include(&foo, &main_file, &paths),
+            Some(include_dir.join("mylib").join("foo.h"))
+        );
+        let stdio = include("stdio.h", true);
+        assert_eq!(resolve_include(&stdio, &main_file, &paths), None);
+    }
+
+    #[test]
+    fn builder_get_all_includes_missing() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let tmp_file = tmp_dir.path().join("main.c");
+
+        assert_eq!(
+            get_all_includes(&tmp_file, &BTreeMap::new()),
+            Err(MorfoError::FileNotFound(tmp_file))
+        );
+    }
+
+    #[test]
+    fn builder_unresolved_include_suggestions() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let system = dir.join("usr/include");
+        fs::create_dir_all(&system).unwrap();
+        fs::write(system.join("stdio.h"), "").unwrap();
+        fs::create_dir_all(dir.join("include")).unwrap();
+        fs::write(dir.join("include/util.h"), "").unwrap();
+        fs::write(dir.join("include/utils.h"), "").unwrap();
+        fs::write(dir.join("include/net.h"), "").unwrap();
+        let dirinfo = dirinfo::get_dir_info(dir, &Exclude::default());
+        let main_file = dir.join("main.c");
+        let mut paths = IncludePaths {
+            generated: vec![dir.join(".out/include/config.h")],
+            ..IncludePaths::default()
+        };
+
+        // the compiler is left to find it unless its directories are known
+        let utl = include("utl.h", false);
+        assert!(check_unresolved(&utl, &main_file, &paths, &dirinfo).is_ok());
+
+        paths.system = Some(vec![system.clone()]);
+        for found in [include("stdio.h", false), include("config.h", false)] {
+            assert!(check_unresolved(&found, &main_file, &paths, &dirinfo).is_ok());
+        }
+        assert_eq!(
+            check_unresolved(&utl, &main_file, &paths, &dirinfo),
+            Err(MorfoError::UnresolvedInclude(
+                main_file.clone(),
+                "utl.h".to_owned(),
+                vec![dir.to_path_buf(), system],
+                vec![dir.join("include/util.h")],
+            ))
+        );
     }
 }
@@ -1,37 +1,168 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
 
-use walkdir::WalkDir;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use walkdir::{DirEntry, WalkDir};
 
+use crate::{
+    error::{MorfoError, MorfoResult},
+    toolchain::Language,
+    utils,
+};
+
+/// The headers and sources of a project, in which dependencies are looked up.
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct DirInfo {
+    /// Every `.h` file.
     pub header_files: Vec<PathBuf>,
+    /// Every `.c` file.
     pub c_files: Vec<PathBuf>,
+    /// Every Fortran source, such as a `.f90` file.
+    pub fortran_files: Vec<PathBuf>,
+    /// Every `.m` file, of Objective-C.
+    pub objc_files: Vec<PathBuf>,
+    /// Every C++ source, such as a `.cpp` file or a `.cppm` module interface, in which
+    /// the C++ modules are looked up.
+    pub cpp_files: Vec<PathBuf>,
+    /// The sources declared to implement a header, by the normalized path of the header.
+    pub header_sources: BTreeMap<PathBuf, Vec<PathBuf>>,
+}
+
+/// The version control directories, which are never scanned.
+const VCS_DIRS: [&str; 4] = [".git", ".hg", ".svn", ".jj"];
+
+/// The files and directories left out when scanning a project: the version control
+/// directories, the build directory and those matched by the `exclude` patterns.
+/// The default leaves out the version control directories only.
+#[derive(Debug, Clone, Default)]
+pub struct Exclude {
+    project_dir: PathBuf,
+    build_dir: Option<PathBuf>,
+    patterns: GlobSet,
 }
 
-pub fn get_dir_info(root: &Path) -> DirInfo {
-    let mut header_files = Vec::new();
-    let mut c_files = Vec::new();
+impl Exclude {
+    /// Returns the exclusions of a project in `project_dir`, building into `build_dir`,
+    /// with `patterns` relative to `project_dir`. A directory matched by a pattern is
+    /// left out with everything under it.
+    ///
+    /// # Errors
+    ///
+    /// [`InvlidConfig`] if a pattern is invalid.
+    ///
+    /// [`InvlidConfig`]: MorfoError::InvlidConfig
+    pub fn new(project_dir: &Path, build_dir: &Path, patterns: &[String]) -> MorfoResult<Exclude> {
+        let mut set = GlobSetBuilder::new();
+        for pattern in patterns {
+            let glob = GlobBuilder::new(pattern.trim_end_matches('/'))
+                .literal_separator(true)
+                .build()
+                .map_err(|e| MorfoError::InvlidConfig(format!("exclude pattern: {}", e)))?;
+            set.add(glob);
+        }
+        Ok(Exclude {
+            project_dir: utils::normalize(project_dir),
+            build_dir: fs::canonicalize(build_dir).ok(),
+            patterns: set
+                .build()
+                .map_err(|e| MorfoError::InvlidConfig(format!("exclude pattern: {}", e)))?,
+        })
+    }
 
-    // Use walkdir to find all c and h files in subdirectories
-    for entry in WalkDir::new(root) {
-        if let Ok(entry) = entry {
+    fn excludes(&self, entry: &DirEntry) -> bool {
+        if entry.depth() == 0 {
+            return false;
+        }
+        if entry.file_type().is_dir() {
+            let vcs = entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| VCS_DIRS.contains(&name));
+            if vcs
+                || (self.build_dir.is_some()
+                    && fs::canonicalize(entry.path()).ok() == self.build_dir)
+            {
+                return true;
+            }
+        }
+        let path = utils::normalize(entry.path());
+        let relative = path.strip_prefix(&self.project_dir).unwrap_or(&path);
+        self.patterns.is_match(relative)
+    }
+}
+
+/// Finds the headers and sources under `root`, in subdirectories too, sorted by path.
+pub fn get_dir_info(root: &Path, exclude: &Exclude) -> DirInfo {
+    let mut dirinfo = DirInfo::default();
+    dirinfo.scan(WalkDir::new(root), exclude);
+    dirinfo
+}
+
+/// Finds the headers and sources directly in `main_dir` and under each of `roots`, in
+/// their subdirectories too, sorted by path.
+///
+/// # Errors
+///
+/// [`FileNotFound`] if one of `roots` is not a directory.
+///
+/// [`FileNotFound`]: MorfoError::FileNotFound
+pub fn get_roots_info(
+    main_dir: &Path,
+    roots: &[PathBuf],
+    exclude: &Exclude,
+) -> MorfoResult<DirInfo> {
+    let mut dirinfo = DirInfo::default();
+    dirinfo.scan(WalkDir::new(main_dir).max_depth(1), exclude);
+    for root in roots {
+        if !root.is_dir() {
+            return Err(MorfoError::FileNotFound(root.clone()));
+        }
+        dirinfo.scan(WalkDir::new(root), exclude);
+    }
+
+    // the main file may well be in one of the roots
+    for files in [
+        &mut dirinfo.header_files,
+        &mut dirinfo.c_files,
+        &mut dirinfo.fortran_files,
+        &mut dirinfo.objc_files,
+        &mut dirinfo.cpp_files,
+    ] {
+        files.sort_by_cached_key(|file| utils::normalize(file));
+        files.dedup_by_key(|file| utils::normalize(file));
+    }
+    Ok(dirinfo)
+}
+
+impl DirInfo {
+    /// Adds the headers and sources `walk` finds, but those of `exclude`.
+    fn scan(&mut self, walk: WalkDir, exclude: &Exclude) {
+        let walk = walk.sort_by_file_name().into_iter();
+        for entry in walk
+            .filter_entry(|entry| !exclude.excludes(entry))
+            .flatten()
+        {
             let path = entry.path();
             if !path.is_file() {
                 continue;
             }
             if let Some(extension) = path.extension() {
                 match extension.to_str() {
-                    Some("h") => header_files.push(path.to_path_buf()),
-                    Some("c") => c_files.push(path.to_path_buf()),
-                    _ => (),
+                    Some("h") => self.header_files.push(path.to_path_buf()),
+                    Some("c") => self.c_files.push(path.to_path_buf()),
+                    Some("m") => self.objc_files.push(path.to_path_buf()),
+                    _ => match Language::of(path) {
+                        Language::Cxx => self.cpp_files.push(path.to_path_buf()),
+                        Language::Fortran => self.fortran_files.push(path.to_path_buf()),
+                        _ => (),
+                    },
                 }
             }
         }
     }
-
-    DirInfo {
-        header_files,
-        c_files,
-    }
 }
 
 // Write a test for get_dir_info
@@ -53,7 +184,7 @@ mod tests {
         fs::write(&c_file, "").unwrap();
         fs::write(other_file, "").unwrap();
 
-        let dir_info = get_dir_info(root);
+        let dir_info = get_dir_info(root, &Exclude::default());
         assert_eq!(dir_info.header_files, Vec::<PathBuf>::new());
         assert_eq!(dir_info.c_files, vec![c_file]);
     }
@@ -78,8 +209,91 @@ mod tests {
         fs::write(&h_file_aux, "").unwrap();
         fs::write(&c_file_aux, "").unwrap();
 
-        let dir_info = get_dir_info(root);
+        let dir_info = get_dir_info(root, &Exclude::default());
         assert_eq!(dir_info.header_files, vec![h_file_aux]);
         assert_eq!(dir_info.c_files, vec![c_file, c_file_aux]);
     }
+
+    #[test]
+    fn get_dir_info_excludes() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let root = tmp_dir.path();
+        for dir in [".git", ".out", "third_party/zlib", "examples", "src"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "main.c",
+            ".git/hook.c",
+            ".out/main.c",
+            "third_party/zlib/zlib.c",
+            "examples/demo.c",
+            "src/util.c",
+            "src/util_test.c",
+        ] {
+            fs::write(root.join(file), "").unwrap();
+        }
+
+        let patterns = ["third_party/**", "examples/", "**/*_test.c"].map(String::from);
+        let exclude = Exclude::new(root, &root.join(".out"), &patterns).unwrap();
+        let dir_info = get_dir_info(root, &exclude);
+        assert_eq!(
+            dir_info.c_files,
+            vec![root.join("main.c"), root.join("src/util.c")]
+        );
+
+        assert!(matches!(
+            Exclude::new(root, &root.join(".out"), &["[".to_string()]),
+            Err(MorfoError::InvlidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn get_roots_info_only_roots() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let root = tmp_dir.path();
+        for dir in ["src/net", "vendor/mini", "tests", "examples"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "main.c",
+            "src/util.c",
+            "src/util.h",
+            "src/net/net.c",
+            "vendor/mini/mini.c",
+            "tests/util.c",
+            "examples/demo.c",
+        ] {
+            fs::write(root.join(file), "").unwrap();
+        }
+
+        let dir_info = get_roots_info(
+            root,
+            &[root.join("src"), root.join("vendor/mini")],
+            &Exclude::default(),
+        )
+        .unwrap();
+        assert_eq!(dir_info.header_files, vec![root.join("src/util.h")]);
+        assert_eq!(
+            dir_info.c_files,
+            [
+                "main.c",
+                "src/net/net.c",
+                "src/util.c",
+                "vendor/mini/mini.c"
+            ]
+            .map(|f| root.join(f))
+        );
+
+        let dir_info =
+            get_roots_info(&root.join("src"), &[root.join("src")], &Exclude::default()).unwrap();
+        assert_eq!(
+            dir_info.c_files,
+            ["src/net/net.c", "src/util.c"].map(|f| root.join(f))
+        );
+
+        assert_eq!(
+            get_roots_info(root, &[root.join("lib")], &Exclude::default()),
+            Err(MorfoError::FileNotFound(root.join("lib")))
+        );
+    }
 }
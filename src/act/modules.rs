@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::preprocess::{self, Conditionals};
+
+/// The C++20 module declarations of a source.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModuleDecls {
+    /// The module the source is a unit of, such as `math`, or `math:ops` for a partition.
+    pub name: Option<String>,
+    /// Whether the source is an interface unit, declared with `export module`.
+    pub interface: bool,
+    /// The modules the source imports, in order, with the partitions of its own module
+    /// by their full name. Header units are left out.
+    pub imports: Vec<String>,
+}
+
+impl ModuleDecls {
+    /// Returns the module the compiler writes an interface of when compiling the source:
+    /// that of an interface unit or of a partition, which can be imported.
+    pub fn provides(&self) -> Option<&str> {
+        self.name
+            .as_deref()
+            .filter(|name| self.interface || name.contains(':'))
+    }
+
+    /// Returns the modules the source needs the interfaces of: those it imports and,
+    /// for an implementation unit, its own module.
+    pub fn requires(&self) -> Vec<String> {
+        let mut requires = Vec::new();
+        if let Some(name) = self.name.as_deref().filter(|_| self.provides().is_none()) {
+            requires.push(name.to_owned());
+        }
+        requires.extend(self.imports.iter().cloned());
+        requires
+    }
+
+    /// Returns whether the source implements the primary interface of `module`, as
+    /// `module math;` does for `math`.
+    pub fn implements(&self, module: &str) -> bool {
+        !self.interface && self.name.as_deref() == Some(module)
+    }
+}
+
+/// Returns the module declarations of the source `contents`, in the branches of its
+/// conditionals compiled with `macros` defined.
+pub fn modules_in(contents: &[u8], macros: &BTreeMap<String, String>) -> ModuleDecls {
+    let contents = String::from_utf8_lossy(contents);
+    let module_re = Regex::new(r"^(export\s+)?module\s+([\w.]+(?::[\w.]+)?)\s*;").unwrap();
+    let import_re =
+        Regex::new(r"^(?:export\s+)?import\s+([\w.]+(?::[\w.]+)?|:[\w.]+)\s*;").unwrap();
+
+    let mut decls = ModuleDecls::default();
+    let mut conditionals = Conditionals::new(macros);
+    for line in preprocess::logical_lines(&contents) {
+        let line = line.trim_start();
+        if let Some(directive) = line.strip_prefix('#') {
+            conditionals.apply(directive.trim_start());
+            continue;
+        }
+        if !conditionals.active() {
+            continue;
+        }
+        if let Some(cap) = module_re.captures(line) {
+            decls.name = Some(cap[2].to_owned());
+            decls.interface = cap.get(1).is_some();
+        } else if let Some(cap) = import_re.captures(line) {
+            let import = match cap[1].strip_prefix(':') {
+                // a partition of the module the source is a unit of
+                Some(partition) => match &decls.name {
+                    Some(name) => {
+                        let module = name.split(':').next().unwrap_or(name);
+                        format!("{}:{}", module, partition)
+                    }
+                    None => continue,
+                },
+                None => cap[1].to_owned(),
+            };
+            if !decls.imports.contains(&import) {
+                decls.imports.push(import);
+            }
+        }
+    }
+    decls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn module_declarations() {
+        let source = br#"
+module;
+#include <cstdio>
+export module math:ops;
+import :detail;
+export import geometry;
+import <vector>;
+#ifdef NEVER
+import never;
+#endif
+// import commented;
+int f();
+"#;
+        let decls = modules_in(source, &BTreeMap::new());
+        assert_eq!(decls.name.as_deref(), Some("math:ops"));
+        assert!(decls.interface);
+        assert_eq!(decls.provides(), Some("math:ops"));
+        assert_eq!(decls.imports, ["math:detail", "geometry"]);
+        assert_eq!(decls.requires(), ["math:detail", "geometry"]);
+
+        let decls = modules_in(b"module math;\nimport std;\n", &BTreeMap::new());
+        assert_eq!(decls.provides(), None);
+        assert!(decls.implements("math"));
+        assert_eq!(decls.requires(), ["math", "std"]);
+
+        assert_eq!(
+            modules_in(b"#include \"util.h\"\nint main() {}\n", &BTreeMap::new()),
+            ModuleDecls::default()
+        );
+    }
+}
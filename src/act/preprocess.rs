@@ -0,0 +1,514 @@
+//! Just enough of the C preprocessor to know which includes are compiled.
+//!
+//! Comments are removed and continued lines joined, then `#if`, `#ifdef`, `#ifndef`,
+//! `#elif`, `#else` and `#endif` are followed, with `#define` and `#undef` applied as
+//! they are met, so an include in a branch that is not compiled is skipped. `#if`
+//! expressions are evaluated like the preprocessor does, with undefined names being 0.
+//! An expression it cannot evaluate, such as one calling a function-like macro, is
+//! taken to be true, so its includes are still found.
+
+use std::collections::BTreeMap;
+
+/// A macro definition.
+#[derive(Debug, Clone, PartialEq)]
+enum Definition {
+    Object(String),
+    Function,
+}
+
+/// A conditional group being read.
+#[derive(Debug, Clone, Copy)]
+struct Group {
+    /// Whether the lines of the current branch are compiled.
+    active: bool,
+    /// Whether a branch of the group was taken already, so later branches are not.
+    taken: bool,
+    /// Whether the group is within compiled lines at all.
+    enclosing: bool,
+}
+
+/// The state of the conditionals at a line of a source.
+#[derive(Debug, Clone)]
+pub struct Conditionals {
+    macros: BTreeMap<String, Definition>,
+    groups: Vec<Group>,
+}
+
+impl Conditionals {
+    /// Returns the state at the start of a source, with the object-like `macros` defined.
+    pub fn new(macros: &BTreeMap<String, String>) -> Conditionals {
+        Conditionals {
+            macros: macros
+                .iter()
+                .map(|(name, value)| (name.clone(), Definition::Object(value.clone())))
+                .collect(),
+            groups: Vec::new(),
+        }
+    }
+
+    /// Returns whether the lines at this point are compiled.
+    pub fn active(&self) -> bool {
+        self.groups.last().is_none_or(|group| group.active)
+    }
+
+    /// Applies the preprocessor `directive`, without its `#`, such as `ifdef DEBUG`.
+    /// Directives other than conditionals and definitions are ignored.
+    pub fn apply(&mut self, directive: &str) {
+        let directive = directive.trim();
+        let (name, rest) = directive
+            .split_once(|c: char| c.is_whitespace() || c == '(')
+            .map(|(name, _)| (name, directive[name.len()..].trim()))
+            .unwrap_or((directive, ""));
+        let active = self.active();
+        match name {
+            "if" => {
+                let taken = active && self.evaluate(rest);
+                self.push(taken, active);
+            }
+            "ifdef" => {
+                let taken = active && self.macros.contains_key(identifier(rest));
+                self.push(taken, active);
+            }
+            "ifndef" => {
+                let taken = active && !self.macros.contains_key(identifier(rest));
+                self.push(taken, active);
+            }
+            "elif" | "elifdef" | "elifndef" => {
+                let Some(group) = self.groups.last().copied() else {
+                    return;
+                };
+                let taken = group.enclosing
+                    && !group.taken
+                    && match name {
+                        "elifdef" => self.macros.contains_key(identifier(rest)),
+                        "elifndef" => !self.macros.contains_key(identifier(rest)),
+                        _ => self.evaluate(rest),
+                    };
+                self.replace(taken);
+            }
+            "else" => {
+                if let Some(group) = self.groups.last().copied() {
+                    self.replace(group.enclosing && !group.taken);
+                }
+            }
+            "endif" => {
+                self.groups.pop();
+            }
+            "define" if active => {
+                let name = identifier(rest);
+                let definition = match rest[name.len()..].strip_prefix('(') {
+                    Some(_) => Definition::Function,
+                    None => Definition::Object(rest[name.len()..].trim().to_owned()),
+                };
+                self.macros.insert(name.to_owned(), definition);
+            }
+            "undef" if active => {
+                self.macros.remove(identifier(rest));
+            }
+            _ => (),
+        }
+    }
+
+    fn push(&mut self, taken: bool, enclosing: bool) {
+        self.groups.push(Group {
+            active: taken,
+            taken,
+            enclosing,
+        });
+    }
+
+    /// Moves the innermost group to its next branch, which is compiled if `taken`.
+    fn replace(&mut self, taken: bool) {
+        if let Some(group) = self.groups.last_mut() {
+            group.active = taken;
+            group.taken |= taken;
+        }
+    }
+
+    /// Evaluates the `#if` expression `expression`, taking one that cannot be evaluated
+    /// to be true.
+    fn evaluate(&self, expression: &str) -> bool {
+        let Some(tokens) = tokenize(expression) else {
+            return true;
+        };
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            macros: &self.macros,
+            depth: 0,
+        };
+        match parser.conditional() {
+            Some(value) if parser.position == parser.tokens.len() => value != 0,
+            _ => true,
+        }
+    }
+}
+
+/// Returns the lines of `contents` with comments removed and continued lines joined.
+/// A comment spanning lines leaves the lines it ends on, so the lines after it are kept.
+pub fn logical_lines(contents: &str) -> Vec<String> {
+    let contents = contents.replace("\\\r\n", "").replace("\\\n", "");
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut chars = contents.chars().peekable();
+    let mut quote = None;
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\n', _) => {
+                lines.push(std::mem::take(&mut line));
+                quote = None;
+            }
+            ('\\', Some(_)) => {
+                line.push(c);
+                if let Some(escaped) = chars.next() {
+                    line.push(escaped);
+                }
+            }
+            ('"' | '\'', None) => {
+                quote = Some(c);
+                line.push(c);
+            }
+            (c, Some(open)) if c == open => {
+                quote = None;
+                line.push(c);
+            }
+            ('/', None) if chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            ('/', None) if chars.peek() == Some(&'*') => {
+                chars.next();
+                line.push(' ');
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    if c == '\n' {
+                        lines.push(std::mem::take(&mut line));
+                    }
+                    previous = c;
+                }
+            }
+            _ => line.push(c),
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Returns the identifier `text` starts with.
+fn identifier(text: &str) -> &str {
+    let end = text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(text.len());
+    &text[..end]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Identifier(String),
+    Punctuator(&'static str),
+}
+
+/// The punctuators of `#if` expressions, longest first.
+const PUNCTUATORS: [&str; 24] = [
+    "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "(", ")", "!", "~", "*", "/", "%", "+", "-",
+    "<", ">", "&", "^", "|", "?", ":",
+];
+
+/// Splits `expression` into tokens, or returns `None` if it has any other token, such
+/// as a string.
+fn tokenize(expression: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = expression.trim_start();
+    while !rest.is_empty() {
+        let first = rest.chars().next().unwrap_or_default();
+        if first.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(number(&rest[..end])?));
+            rest = &rest[end..];
+        } else if first.is_ascii_alphabetic() || first == '_' {
+            let name = identifier(rest);
+            tokens.push(Token::Identifier(name.to_owned()));
+            rest = &rest[name.len()..];
+        } else if let Some(punctuator) = PUNCTUATORS.iter().find(|p| rest.starts_with(**p)) {
+            tokens.push(Token::Punctuator(punctuator));
+            rest = &rest[punctuator.len()..];
+        } else {
+            return None;
+        }
+        rest = rest.trim_start();
+    }
+    Some(tokens)
+}
+
+/// Parses an integer literal, in decimal, octal or hexadecimal, with any suffix.
+fn number(literal: &str) -> Option<i64> {
+    let digits = literal.trim_end_matches(['u', 'U', 'l', 'L']);
+    if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        i64::from_str_radix(hex, 16).ok()
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i64::from_str_radix(&digits[1..], 8).ok()
+    } else {
+        digits.parse().ok()
+    }
+}
+
+/// How deep macros may expand into each other before the evaluation fails.
+const MAX_DEPTH: usize = 32;
+
+/// A recursive descent parser evaluating `#if` expressions as it goes.
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    macros: &'a BTreeMap<String, Definition>,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn eat(&mut self, punctuator: &str) -> bool {
+        match self.peek() {
+            Some(Token::Punctuator(p)) if *p == punctuator => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn conditional(&mut self) -> Option<i64> {
+        let condition = self.binary(0)?;
+        if !self.eat("?") {
+            return Some(condition);
+        }
+        let then = self.conditional()?;
+        if !self.eat(":") {
+            return None;
+        }
+        let otherwise = self.conditional()?;
+        Some(if condition != 0 { then } else { otherwise })
+    }
+
+    /// Parses binary operators binding at least as tight as `precedence`.
+    fn binary(&mut self, precedence: u8) -> Option<i64> {
+        let mut left = self.unary()?;
+        loop {
+            let Some(Token::Punctuator(operator)) = self.peek() else {
+                return Some(left);
+            };
+            let operator = *operator;
+            let Some(binding) = binding(operator) else {
+                return Some(left);
+            };
+            if binding < precedence {
+                return Some(left);
+            }
+            self.position += 1;
+            let right = self.binary(binding + 1)?;
+            left = apply(operator, left, right)?;
+        }
+    }
+
+    fn unary(&mut self) -> Option<i64> {
+        if self.eat("!") {
+            return Some((self.unary()? == 0) as i64);
+        }
+        if self.eat("~") {
+            return Some(!self.unary()?);
+        }
+        if self.eat("-") {
+            return Some(self.unary()?.wrapping_neg());
+        }
+        if self.eat("+") {
+            return self.unary();
+        }
+        if self.eat("(") {
+            let value = self.conditional()?;
+            return self.eat(")").then_some(value);
+        }
+        match self.tokens.get(self.position).cloned()? {
+            Token::Number(value) => {
+                self.position += 1;
+                Some(value)
+            }
+            Token::Identifier(name) if name == "defined" => {
+                self.position += 1;
+                let parenthesized = self.eat("(");
+                let Some(Token::Identifier(name)) = self.tokens.get(self.position).cloned() else {
+                    return None;
+                };
+                self.position += 1;
+                if parenthesized && !self.eat(")") {
+                    return None;
+                }
+                Some(self.macros.contains_key(&name) as i64)
+            }
+            Token::Identifier(name) => {
+                self.position += 1;
+                match self.macros.get(&name) {
+                    None => Some(0),
+                    Some(Definition::Function) => None,
+                    Some(Definition::Object(value)) => self.expand(value),
+                }
+            }
+            Token::Punctuator(_) => None,
+        }
+    }
+
+    /// Evaluates the body of an object-like macro.
+    fn expand(&mut self, value: &str) -> Option<i64> {
+        if self.depth == MAX_DEPTH {
+            return None;
+        }
+        let mut parser = Parser {
+            tokens: tokenize(value)?,
+            position: 0,
+            macros: self.macros,
+            depth: self.depth + 1,
+        };
+        let value = parser.conditional()?;
+        (parser.position == parser.tokens.len()).then_some(value)
+    }
+}
+
+/// Returns how tightly the binary `operator` binds, or `None` if it is not one.
+fn binding(operator: &str) -> Option<u8> {
+    Some(match operator {
+        "||" => 1,
+        "&&" => 2,
+        "|" => 3,
+        "^" => 4,
+        "&" => 5,
+        "==" | "!=" => 6,
+        "<" | ">" | "<=" | ">=" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "%" => 10,
+        _ => return None,
+    })
+}
+
+fn apply(operator: &str, left: i64, right: i64) -> Option<i64> {
+    Some(match operator {
+        "||" => (left != 0 || right != 0) as i64,
+        "&&" => (left != 0 && right != 0) as i64,
+        "|" => left | right,
+        "^" => left ^ right,
+        "&" => left & right,
+        "==" => (left == right) as i64,
+        "!=" => (left != right) as i64,
+        "<" => (left < right) as i64,
+        ">" => (left > right) as i64,
+        "<=" => (left <= right) as i64,
+        ">=" => (left >= right) as i64,
+        "<<" => left.checked_shl(u32::try_from(right).ok()?)?,
+        ">>" => left.checked_shr(u32::try_from(right).ok()?)?,
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "*" => left.wrapping_mul(right),
+        "/" => left.checked_div(right)?,
+        "%" => left.checked_rem(right)?,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compiled(source: &str, macros: &[(&str, &str)]) -> Vec<String> {
+        let macros = macros
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let mut conditionals = Conditionals::new(&macros);
+        let mut compiled = Vec::new();
+        for line in logical_lines(source) {
+            match line.trim().strip_prefix('#') {
+                Some(directive) => conditionals.apply(directive),
+                None if conditionals.active() && !line.trim().is_empty() => {
+                    compiled.push(line.trim().to_owned())
+                }
+                None => (),
+            }
+        }
+        compiled
+    }
+
+    #[test]
+    fn preprocess_conditionals() {
+        let source = "\
+#ifdef _WIN32
+win
+#elif defined(__linux__) && __GNUC__ >= 4
+linux
+#else
+other
+#endif
+#if 0
+never /* #endif */
+#endif
+#define LEVEL 2
+#if LEVEL * 2 == 4 && !defined(NDEBUG)
+level
+#endif
+#undef LEVEL
+#ifndef LEVEL
+undefined
+#endif
+";
+        assert_eq!(
+            compiled(source, &[("__linux__", "1"), ("__GNUC__", "13")]),
+            vec!["linux", "level", "undefined"]
+        );
+        assert_eq!(
+            compiled(source, &[("_WIN32", "1"), ("NDEBUG", "")]),
+            vec!["win", "undefined"]
+        );
+        assert_eq!(compiled(source, &[]), vec!["other", "level", "undefined"]);
+    }
+
+    #[test]
+    fn preprocess_nested_and_unknown() {
+        let source = "\
+#if 0
+#if 1
+never
+#else
+never
+#endif
+#elif 1
+taken
+#else
+never
+#endif
+#define VERSION(major) major
+#if VERSION(2) > 1
+unknown
+#endif
+";
+        assert_eq!(compiled(source, &[]), vec!["taken", "unknown"]);
+    }
+
+    #[test]
+    fn preprocess_logical_lines() {
+        assert_eq!(
+            logical_lines("a // b\n#define X \\\n  1\nc /* d\ne */ f \"/*\"\n"),
+            vec!["a ", "#define X   1", "c  ", " f \"/*\""]
+        );
+    }
+}
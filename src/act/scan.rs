@@ -0,0 +1,200 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{
+    builder::{self, Include},
+    modules::{self, ModuleDecls},
+};
+use crate::error::{MorfoError, MorfoResult};
+
+/// The includes found in every file of a dependency tree, kept between runs so that
+/// only the files that changed since are scanned again.
+///
+/// Every file is still read to tell whether it changed, but the includes of an
+/// unchanged file are not looked for again. Scans are only reused with the same macros.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScanCache {
+    /// The SHA-256 of the macros the files were scanned with.
+    macros: String,
+    /// The scan of every file, by path.
+    files: BTreeMap<PathBuf, Scan>,
+    /// The files looked at since the cache was loaded.
+    #[serde(skip)]
+    used: BTreeSet<PathBuf>,
+}
+
+/// The includes and module declarations found in a file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Scan {
+    /// The SHA-256 of the file.
+    hash: String,
+    /// The includes in the branches compiled, in order.
+    includes: Vec<Include>,
+    /// The C++ module declarations in the branches compiled.
+    modules: ModuleDecls,
+}
+
+impl ScanCache {
+    /// Reads the cache saved at `path`. A cache that is missing or cannot be read, say
+    /// one written by another version, is empty.
+    pub fn load(path: &Path) -> ScanCache {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    /// Saves the cache at `path`, keeping only the files looked at since it was loaded.
+    ///
+    /// # Errors
+    ///
+    /// If the cache cannot be written.
+    pub fn save(&mut self, path: &Path) -> MorfoResult<()> {
+        let used = &self.used;
+        self.files.retain(|file, _| used.contains(file));
+        let json =
+            serde_json::to_string(self).map_err(|e| MorfoError::InvlidConfig(e.to_string()))?;
+        fs::write(path, json + "\n")?;
+        Ok(())
+    }
+}
+
+/// Finds the includes of files compiled with a set of macros, through a cache if any.
+pub(super) struct Scanner<'a> {
+    macros: &'a BTreeMap<String, String>,
+    cache: Option<&'a mut ScanCache>,
+}
+
+impl<'a> Scanner<'a> {
+    /// Returns a scanner for `macros`, reusing the scans in `cache` made with the same.
+    pub(super) fn new(
+        macros: &'a BTreeMap<String, String>,
+        mut cache: Option<&'a mut ScanCache>,
+    ) -> Scanner<'a> {
+        if let Some(cache) = &mut cache {
+            let hash = hash_macros(macros);
+            if cache.macros != hash {
+                cache.macros = hash;
+                cache.files.clear();
+            }
+        }
+        Scanner { macros, cache }
+    }
+
+    /// Returns the headers `filepath` includes, like [`builder::get_all_includes`].
+    pub(super) fn includes(&mut self, filepath: &Path) -> MorfoResult<Vec<Include>> {
+        let Some(cache) = &mut self.cache else {
+            return builder::get_all_includes(filepath, self.macros);
+        };
+        Ok(scan(cache, filepath, self.macros)?.includes.clone())
+    }
+
+    /// Returns the C++ module declarations of `filepath`.
+    pub(super) fn modules(&mut self, filepath: &Path) -> MorfoResult<ModuleDecls> {
+        let Some(cache) = &mut self.cache else {
+            let contents = builder::read_source(filepath)?;
+            return Ok(modules::modules_in(&contents, self.macros));
+        };
+        Ok(scan(cache, filepath, self.macros)?.modules.clone())
+    }
+}
+
+/// Returns the scan of `filepath` in `cache`, scanning it again if it changed.
+fn scan<'c>(
+    cache: &'c mut ScanCache,
+    filepath: &Path,
+    macros: &BTreeMap<String, String>,
+) -> MorfoResult<&'c Scan> {
+    let contents = builder::read_source(filepath)?;
+    let hash = format!("{:x}", Sha256::digest(&contents));
+    let path = filepath.to_path_buf();
+    cache.used.insert(path.clone());
+    let fresh = cache.files.get(&path).is_some_and(|scan| scan.hash == hash);
+    if !fresh {
+        let scan = Scan {
+            hash,
+            includes: builder::includes_in(&contents, macros),
+            modules: modules::modules_in(&contents, macros),
+        };
+        cache.files.insert(path.clone(), scan);
+    }
+    Ok(&cache.files[&path])
+}
+
+fn hash_macros(macros: &BTreeMap<String, String>) -> String {
+    let mut hasher = Sha256::new();
+    for (name, value) in macros {
+        hasher.update(name.as_bytes());
+        hasher.update([0]);
+        hasher.update(value.as_bytes());
+        hasher.update([0]);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::act::{
+        dirinfo::{self, Exclude},
+        Act, IncludePaths,
+    };
+
+    fn build(dir: &Path, macros: &BTreeMap<String, String>, cache: &mut ScanCache) -> Act {
+        let dirinfo = dirinfo::get_dir_info(dir, &Exclude::default());
+        let paths = IncludePaths::default();
+        Act::build_cached(&dir.join("main.c"), &dirinfo, &paths, macros, cache).unwrap()
+    }
+
+    #[test]
+    fn scan_cache_rescans_changed_files() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(dir.join("main.c"), "#include \"util.h\"\n").unwrap();
+        fs::write(dir.join("util.h"), "").unwrap();
+        fs::write(dir.join("util.c"), "#include \"util.h\"\n").unwrap();
+        fs::write(dir.join("log.h"), "").unwrap();
+        fs::write(dir.join("log.c"), "").unwrap();
+
+        let cache_path = dir.join("scan.json");
+        let macros = BTreeMap::new();
+        let mut cache = ScanCache::load(&cache_path);
+        assert_eq!(build(dir, &macros, &mut cache).sources().len(), 2);
+        cache.save(&cache_path).unwrap();
+
+        // an unchanged file is not scanned again, so a doctored scan is believed
+        let mut cache = ScanCache::load(&cache_path);
+        assert_eq!(cache.files.len(), 3);
+        let util = cache.files.get_mut(&dir.join("util.c")).unwrap();
+        util.includes.push(Include {
+            header: "log.h".to_owned(),
+            angled: false,
+        });
+        assert_eq!(
+            build(dir, &macros, &mut cache).sources(),
+            ["log.c", "util.c", "main.c"].map(|name| dir.join(name))
+        );
+
+        // a changed file is
+        fs::write(dir.join("util.c"), "#include \"util.h\"\n\n").unwrap();
+        assert_eq!(build(dir, &macros, &mut cache).sources().len(), 2);
+
+        // and so is every file when the macros change
+        fs::write(
+            dir.join("main.c"),
+            "#ifdef LOG\n#include \"log.h\"\n#endif\n",
+        )
+        .unwrap();
+        assert!(build(dir, &macros, &mut cache).dependencies.is_empty());
+        let cache_macros = cache.macros.clone();
+        let macros = BTreeMap::from([("LOG".to_owned(), "1".to_owned())]);
+        assert_eq!(build(dir, &macros, &mut cache).sources().len(), 2);
+        assert_ne!(cache.macros, cache_macros);
+    }
+}
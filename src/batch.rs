@@ -0,0 +1,165 @@
+//! Building many independent main files at once.
+//!
+//! `morfo build 'submissions/*.c'` or `morfo build submissions/` builds every main file
+//! selected as a program of its own, a few at a time, and reports which ones built. A
+//! failing main file does not stop the others. Each main file puts its artifacts in a
+//! subdirectory of the build directory named after its path, so that programs living
+//! in the same directory never share an object.
+
+use std::path::{Component, Path, PathBuf};
+
+use globset::GlobBuilder;
+use walkdir::WalkDir;
+
+use crate::{
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    utils,
+};
+
+/// The outcome of building one main file of a batch.
+#[derive(Debug)]
+pub struct BatchBuild {
+    /// The main file built.
+    pub main_file: PathBuf,
+    /// The executable built, or why it could not be.
+    pub result: MorfoResult<PathBuf>,
+}
+
+/// Returns whether `pattern` selects several main files rather than naming one: a
+/// directory, or a glob that is not the name of a file.
+pub fn is_batch(pattern: &Path) -> bool {
+    pattern.is_dir() || (!pattern.is_file() && has_wildcard(&pattern.to_string_lossy()))
+}
+
+/// Returns the main files `pattern` selects, sorted: the C sources directly in a
+/// directory, or the files matching a glob such as `submissions/*.c`, in which `*`
+/// stays within a directory and `**` matches any number of them.
+///
+/// # Errors
+///
+/// [`MorfoError::InvlidConfig`] if the glob is invalid, and [`MorfoError::FileNotFound`]
+/// if nothing matches.
+pub fn main_files(pattern: &Path) -> MorfoResult<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = if pattern.is_dir() {
+        WalkDir::new(pattern)
+            .min_depth(1)
+            .max_depth(1)
+            .into_iter()
+            .filter_map(Result::ok)
+            .map(|entry| entry.into_path())
+            .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "c"))
+            .collect()
+    } else {
+        let glob = GlobBuilder::new(&pattern.to_string_lossy())
+            .literal_separator(true)
+            .build()
+            .map_err(|e| MorfoError::InvlidConfig(format!("main file pattern: {}", e)))?
+            .compile_matcher();
+
+        // only the directories after the last one written out in full are searched
+        let base: PathBuf = pattern
+            .components()
+            .take_while(|component| !has_wildcard(&component.as_os_str().to_string_lossy()))
+            .collect();
+        let depth = pattern.components().count() - base.components().count();
+        let mut walk = WalkDir::new(if base.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            &base
+        })
+        .min_depth(1);
+        if !pattern.to_string_lossy().contains("**") {
+            walk = walk.max_depth(depth);
+        }
+        walk.into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| match entry.path().strip_prefix(".") {
+                Ok(path) if base.as_os_str().is_empty() => path.to_path_buf(),
+                _ => entry.into_path(),
+            })
+            .filter(|path| glob.is_match(path))
+            .collect()
+    };
+    if files.is_empty() {
+        return Err(MorfoError::FileNotFound(pattern.to_path_buf()));
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Builds each of `main_files` as a program of its own, `jobs` at a time, and returns
+/// how each went, in the order of `main_files`.
+pub fn build_all(main_files: &[PathBuf], config: &Config, jobs: usize) -> Vec<BatchBuild> {
+    utils::parallel_map(main_files, jobs, |main_file| {
+        let config = config.clone().with_batch_dir(&batch_dir(main_file));
+        BatchBuild {
+            main_file: main_file.clone(),
+            result: crate::build(main_file.clone(), config),
+        }
+    })
+}
+
+/// Returns the subdirectory of the build directory for `main_file`: its path, without
+/// the root or the parent directories it starts with.
+fn batch_dir(main_file: &Path) -> PathBuf {
+    utils::normalize(main_file)
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect()
+}
+
+fn has_wildcard(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '{'])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::config::ConfigBuilder;
+
+    #[test]
+    fn batch_builds_each_main_file() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path().join("submissions");
+        fs::create_dir_all(dir.join("late")).unwrap();
+        fs::write(dir.join("alice.c"), "int main(void) { return 0; }\n").unwrap();
+        fs::write(dir.join("bob.c"), "int main(void) { return missing; }\n").unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        fs::write(dir.join("late/carol.c"), "int main(void) { return 0; }\n").unwrap();
+
+        assert!(is_batch(&dir));
+        assert!(is_batch(&dir.join("*.c")));
+        assert!(!is_batch(&dir.join("alice.c")));
+        assert_eq!(
+            main_files(&dir).unwrap(),
+            [dir.join("alice.c"), dir.join("bob.c")]
+        );
+        assert_eq!(
+            main_files(&dir.join("**/*.c")).unwrap(),
+            [
+                dir.join("alice.c"),
+                dir.join("bob.c"),
+                dir.join("late/carol.c")
+            ]
+        );
+        assert_eq!(
+            main_files(&dir.join("*.h")).unwrap_err(),
+            MorfoError::FileNotFound(dir.join("*.h"))
+        );
+
+        let out = tmp_dir.path().join(".out");
+        let config = ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir(out.to_str().unwrap())
+            .build();
+        let builds = build_all(&main_files(&dir).unwrap(), &config, 2);
+        let alice = out.join(batch_dir(&dir.join("alice.c"))).join("alice");
+        assert_eq!(builds[0].result, Ok(alice.clone()));
+        assert!(alice.is_file());
+        assert!(builds[1].result.is_err());
+    }
+}
@@ -0,0 +1,97 @@
+//! Build provenance macros injected into every compilation.
+//!
+//! With `build_info = true` in the config, morfo defines the following string macros
+//! so programs can report how they were built:
+//!
+//! | Macro                   | Value                                               |
+//! |-------------------------|-----------------------------------------------------|
+//! | `MORFO_GIT_HASH`        | The short hash of `HEAD`, or `"unknown"`            |
+//! | `MORFO_BUILD_TIME`      | The UTC time of the build, e.g. `"2024-01-31T12:00:00Z"`, or `SOURCE_DATE_EPOCH` |
+//! | `MORFO_PROJECT_VERSION` | The `version` in the `[project]` section, or `"unknown"` |
+
+use std::{collections::BTreeMap, path::Path, process::Command};
+
+use crate::{config::Config, repro, utils};
+
+/// Returns the build provenance macros for the project in `project_dir`, quoted as C strings.
+pub fn defines(config: &Config, project_dir: &Path) -> BTreeMap<String, String> {
+    let build_time = repro::build_time(config);
+
+    let mut defines = BTreeMap::new();
+    defines.insert(
+        "MORFO_GIT_HASH".to_owned(),
+        quoted(&git_hash(project_dir).unwrap_or_else(|| "unknown".to_owned())),
+    );
+    defines.insert(
+        "MORFO_BUILD_TIME".to_owned(),
+        quoted(&utils::format_utc(build_time)),
+    );
+    defines.insert(
+        "MORFO_PROJECT_VERSION".to_owned(),
+        quoted(&config.get_project_version().unwrap_or("unknown".to_owned())),
+    );
+    defines
+}
+
+/// Returns the config with the build provenance macros defined, unless they are already defined.
+pub fn apply(config: Config, project_dir: &Path) -> Config {
+    let existing = config.get_defines();
+    defines(&config, project_dir)
+        .into_iter()
+        .filter(|(name, _)| !existing.contains_key(name))
+        .fold(config, |config, (name, value)| {
+            config.with_define(&name, &value)
+        })
+}
+
+fn git_hash(dir: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+fn quoted(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigBuilder;
+
+    #[test]
+    fn buildinfo_defines_outside_git() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let config: Config = toml::from_str(
+            r#"
+            [project]
+            version = "1.2.3""#,
+        )
+        .unwrap();
+
+        let defines = defines(&config, tmp_dir.path());
+        assert_eq!(defines["MORFO_GIT_HASH"], "\"unknown\"");
+        assert_eq!(defines["MORFO_PROJECT_VERSION"], "\"1.2.3\"");
+        assert!(defines["MORFO_BUILD_TIME"].ends_with("Z\""));
+    }
+
+    #[test]
+    fn buildinfo_apply_keeps_user_defines() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let config = ConfigBuilder::default()
+            .add_define("MORFO_GIT_HASH", "\"abc123\"")
+            .build();
+
+        let config = apply(config, tmp_dir.path());
+        let defines = config.get_defines();
+        assert_eq!(defines["MORFO_GIT_HASH"], "\"abc123\"");
+        assert_eq!(defines["MORFO_PROJECT_VERSION"], "\"unknown\"");
+    }
+}
@@ -0,0 +1,882 @@
+//! The user-level object cache.
+//!
+//! With `enabled = true` in the `[cache]` section, every compiled object is stored in a
+//! cache shared by all projects, under the user cache directory by default. An object is
+//! keyed by the compiler and its version, the flags, and the preprocessed source, so the
+//! same translation unit compiled in another directory or project is copied from the
+//! cache instead of being compiled again. Include directories, macros and the other
+//! options of the preprocessor only matter through the preprocessed source and are left
+//! out of the key. When debug information is generated,
+//! the working directory is part of the key as well, since it is recorded in the object.
+//!
+//! Objects copied from the cache are not compiled, so their warnings are not shown again.
+//!
+//! `morfo cache stats` reports, for the build directory of the project and for the
+//! cache, how many artifacts they hold, their size, the largest of them and how often
+//! they were hit. An artifact of the build directory is hit when a build finds it up to
+//! date. `morfo cache gc --max-size 2G` evicts the least recently used objects.
+//!
+//! Teams and CI can share objects through a remote backend declared under
+//! `[cache.remote]`: a plain HTTP server that stores what is `PUT` under a key and serves
+//! it back on `GET`, or an S3-compatible bucket. Objects missing locally are fetched from
+//! the remote, and compiled objects are uploaded unless `read_only` is set. Requests are
+//! made with `curl`. If the remote cannot be reached, morfo warns once and carries on
+//! with the local cache.
+
+use std::{
+    env, fmt, fs, io,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::SystemTime,
+};
+
+use colored::Colorize;
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+use crate::{
+    compile_object, compile_only_flags, compiler_identity,
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    fingerprint, fortran, layout, modules, object_flags, source_compiler_command,
+    toolchain::Language,
+    utils,
+};
+
+/// The directory of the cached objects, inside the cache directory.
+const OBJECTS: &str = "objects";
+
+/// The file counting hits and misses, inside the cache directory.
+const STATS: &str = "stats.json";
+
+/// Bumped whenever the key derivation changes, so stale entries are never hit.
+const KEY_VERSION: &str = "morfo-cache-2";
+
+/// The remote backend of the cache, declared under `[cache.remote]`.
+///
+/// The HTTP backend sends the token in the environment variable named by `token_env`
+/// (`MORFO_CACHE_TOKEN` by default) as a bearer token. The S3 backend signs requests
+/// with `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and, if set, `AWS_SESSION_TOKEN`;
+/// its `url` is the path-style URL of the bucket and prefix.
+///
+/// # Examples
+///
+/// ```toml
+/// [cache.remote]
+/// url = "https://s3.eu-west-1.amazonaws.com/build-cache/morfo"
+/// backend = "s3"
+/// region = "eu-west-1"
+/// read_only = true
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize)]
+pub struct RemoteCache {
+    url: String,
+    backend: Option<RemoteBackend>,
+    region: Option<String>,
+    read_only: Option<bool>,
+    token_env: Option<String>,
+    timeout: Option<u64>,
+}
+
+/// The protocol of a remote cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RemoteBackend {
+    #[default]
+    Http,
+    S3,
+}
+
+/// The result of looking up an object in the cache.
+pub(crate) enum Lookup {
+    /// The object was copied from the cache.
+    Hit,
+    /// The object is not cached. It should be stored under the key once compiled.
+    Miss(String),
+    /// The source cannot be preprocessed, so it has no key.
+    Uncacheable,
+}
+
+/// The object cache of a build.
+pub(crate) struct ObjectCache {
+    dir: PathBuf,
+    compiler: String,
+    remote: Option<Remote>,
+    hits: u64,
+    misses: u64,
+}
+
+impl ObjectCache {
+    /// Opens the cache, or returns `None` if it is not enabled.
+    ///
+    /// # Errors
+    ///
+    /// If the cache directory is unknown.
+    pub(crate) fn open(config: &Config) -> MorfoResult<Option<ObjectCache>> {
+        if !config.get_cache() {
+            return Ok(None);
+        }
+        Ok(Some(ObjectCache {
+            dir: config.get_cache_dir()?,
+            compiler: compiler_identity(config).join("\0"),
+            remote: config.get_remote_cache().map(|settings| Remote {
+                settings,
+                available: true,
+            }),
+            hits: 0,
+            misses: 0,
+        }))
+    }
+
+    /// Compiles `source` into `object`, copying the object from the cache when it was
+    /// compiled before and storing it otherwise.
+    ///
+    /// # Errors
+    ///
+    /// If the compilation fails or the object cannot be copied.
+    pub(crate) fn compile(
+        &mut self,
+        source: &Path,
+        object: &Path,
+        config: &Config,
+    ) -> MorfoResult<()> {
+        match self.lookup(source, object, config) {
+            Lookup::Hit => Ok(()),
+            Lookup::Miss(key) => {
+                compile_object(source, object, config)?;
+                self.store(&key, object)
+            }
+            Lookup::Uncacheable => compile_object(source, object, config),
+        }
+    }
+
+    /// Copies the object compiled from `source` from the cache to `object`, if it is cached.
+    pub(crate) fn lookup(&mut self, source: &Path, object: &Path, config: &Config) -> Lookup {
+        // a source that cannot be preprocessed is compiled to report the error
+        let Some(key) = self.key(source, object, config) else {
+            return Lookup::Uncacheable;
+        };
+        let cached = self.entry(&key);
+        if let Some(remote) = self.remote.as_mut().filter(|_| !cached.exists()) {
+            remote.fetch(&key, &cached);
+        }
+        if fs::copy(&cached, object).is_err() {
+            self.misses += 1;
+            return Lookup::Miss(key);
+        }
+        // the modification time orders entries for `gc`
+        let _ = fs::File::options()
+            .write(true)
+            .open(&cached)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        self.hits += 1;
+        Lookup::Hit
+    }
+
+    /// Stores the compiled `object` under `key`, the key returned by a missed lookup.
+    ///
+    /// # Errors
+    ///
+    /// If the object cannot be copied into the cache.
+    pub(crate) fn store(&mut self, key: &str, object: &Path) -> MorfoResult<()> {
+        let cached = self.entry(key);
+        let partial = partial_path(&cached)?;
+        fs::copy(object, &partial)?;
+        fs::rename(&partial, &cached)?;
+        if let Some(remote) = &mut self.remote {
+            remote.store(key, &cached);
+        }
+        Ok(())
+    }
+
+    /// Adds the hits and misses of this build to the statistics of the cache, and
+    /// returns the hits.
+    pub(crate) fn finish(self) -> u64 {
+        add_counts(&self.dir.join(STATS), self.hits, self.misses);
+        self.hits
+    }
+
+    /// Returns the key of the object compiled from `source`, or `None` if the source
+    /// cannot be preprocessed, as the Fortran sources are not, or is compiled against the
+    /// interfaces of C++ modules.
+    fn key(&self, source: &Path, object: &Path, config: &Config) -> Option<String> {
+        let flags = object_flags(source, object, config);
+        if fortran::is_fortran(source) || modules::uses_modules(&flags) {
+            return None;
+        }
+        let mut preprocess_cmd = source_compiler_command(source, config);
+        preprocess_cmd
+            .args(&flags)
+            .args(config.get_family().preprocess_args(source))
+            .stderr(Stdio::null());
+        let output = preprocess_cmd.output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(KEY_VERSION);
+        hasher.update([0]);
+        hasher.update(&self.compiler);
+        hasher.update([0]);
+        hasher.update(config.get_compiler(Language::of(source)));
+        for flag in compile_only_flags(&flags) {
+            hasher.update([0]);
+            hasher.update(flag);
+        }
+        if config.get_debug() || config.get_split_debug() {
+            hasher.update([0]);
+            hasher.update(env::current_dir().ok()?.to_string_lossy().as_bytes());
+        }
+        hasher.update([0]);
+        hasher.update(&output.stdout);
+        Some(format!("{:x}", hasher.finalize()))
+    }
+
+    fn entry(&self, key: &str) -> PathBuf {
+        self.dir.join(OBJECTS).join(&key[..2]).join(&key[2..])
+    }
+}
+
+/// Returns a unique path to write the entry at `cached` to before renaming it into place,
+/// since another build may store the same entry at the same time.
+fn partial_path(cached: &Path) -> io::Result<PathBuf> {
+    if let Some(parent) = cached.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(cached.with_extension(format!("{}.tmp", std::process::id())))
+}
+
+/// A remote cache, used until it fails.
+struct Remote {
+    settings: RemoteCache,
+    available: bool,
+}
+
+impl Remote {
+    /// Downloads the entry for `key` to `cached`, returning whether it existed.
+    fn fetch(&mut self, key: &str, cached: &Path) -> bool {
+        if !self.available {
+            return false;
+        }
+        let Ok(partial) = partial_path(cached) else {
+            return false;
+        };
+        match self.request(key, "--output", &partial) {
+            Ok(200) => {
+                if fs::rename(&partial, cached).is_ok() {
+                    return true;
+                }
+                // the remote answered, so only this entry is missed
+                let _ = fs::remove_file(&partial);
+                false
+            }
+            Ok(404) => {
+                let _ = fs::remove_file(&partial);
+                false
+            }
+            Ok(status) => {
+                let _ = fs::remove_file(&partial);
+                self.disable(&format!("HTTP {}", status));
+                false
+            }
+            Err(reason) => {
+                let _ = fs::remove_file(&partial);
+                self.disable(&reason);
+                false
+            }
+        }
+    }
+
+    /// Uploads `cached` as the entry for `key`, unless the remote is read-only.
+    fn store(&mut self, key: &str, cached: &Path) {
+        if !self.available || self.settings.read_only.unwrap_or(false) {
+            return;
+        }
+        match self.request(key, "--upload-file", cached) {
+            Ok(status) if (200..300).contains(&status) => (),
+            Ok(status) => self.disable(&format!("HTTP {}", status)),
+            Err(reason) => self.disable(&reason),
+        }
+    }
+
+    /// Runs `curl` on the URL of `key`, with `option` naming the file to download to or
+    /// upload, and returns the HTTP status, or why the remote could not be reached.
+    fn request(&self, key: &str, option: &str, file: &Path) -> Result<u32, String> {
+        let timeout = self.settings.timeout.unwrap_or(30).to_string();
+        let mut curl_cmd = Command::new("curl");
+        curl_cmd
+            .args(["--silent", "--show-error", "--connect-timeout", "5"])
+            .args(["--max-time", &timeout])
+            // objects are small, so waiting for `100 Continue` only costs a round trip
+            .args(["--header", "Expect:"])
+            .args(["--write-out", "\n%{http_code}", "--config", "-"])
+            .arg(option)
+            .arg(file)
+            .arg(format!(
+                "{}/{}",
+                self.settings.url.trim_end_matches('/'),
+                key
+            ))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let mut child = curl_cmd.spawn().map_err(|e| format!("curl: {}", e))?;
+        // credentials are passed on stdin so they do not show up in the process list
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(self.auth_config().as_bytes());
+        }
+        let output = child.wait_with_output().map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+        }
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .last()
+            .and_then(|status| status.trim().parse().ok())
+            .ok_or_else(|| "no HTTP status from curl".to_owned())
+    }
+
+    /// Returns the `curl` config that authenticates with the remote.
+    fn auth_config(&self) -> String {
+        let quoted =
+            |value: &str| format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut config = String::new();
+        match self.settings.backend.unwrap_or_default() {
+            RemoteBackend::Http => {
+                let token_env = self
+                    .settings
+                    .token_env
+                    .as_deref()
+                    .unwrap_or("MORFO_CACHE_TOKEN");
+                if let Ok(token) = env::var(token_env) {
+                    let header = format!("Authorization: Bearer {}", token);
+                    config.push_str(&format!("header = {}\n", quoted(&header)));
+                }
+            }
+            RemoteBackend::S3 => {
+                let region = self.settings.region.as_deref().unwrap_or("us-east-1");
+                let sigv4 = format!("aws:amz:{}:s3", region);
+                config.push_str(&format!("aws-sigv4 = {}\n", quoted(&sigv4)));
+                if let (Ok(id), Ok(secret)) = (
+                    env::var("AWS_ACCESS_KEY_ID"),
+                    env::var("AWS_SECRET_ACCESS_KEY"),
+                ) {
+                    let user = format!("{}:{}", id, secret);
+                    config.push_str(&format!("user = {}\n", quoted(&user)));
+                }
+                if let Ok(token) = env::var("AWS_SESSION_TOKEN") {
+                    let header = format!("x-amz-security-token: {}", token);
+                    config.push_str(&format!("header = {}\n", quoted(&header)));
+                }
+            }
+        }
+        config
+    }
+
+    /// Stops using the remote for the rest of the build.
+    fn disable(&mut self, reason: &str) {
+        self.available = false;
+        eprintln!(
+            "{}",
+            format!(
+                "warning: remote cache {} unavailable, continuing without it: {}",
+                self.settings.url, reason
+            )
+            .yellow()
+        );
+    }
+}
+
+/// Counts the artifacts a build of `config` found up to date as hits of the build
+/// directory, and those it built as misses.
+pub(crate) fn record_build(config: &Config, fresh: usize, built: usize) {
+    add_counts(&layout::build_stats(config), fresh as u64, built as u64);
+}
+
+/// Adds `hits` and `misses` to the counts in the stats file at `path`. Statistics are
+/// best effort, so a file that cannot be written is left as it is.
+fn add_counts(path: &Path, hits: u64, misses: u64) {
+    let (old_hits, old_misses) = read_counts(path);
+    let json = serde_json::json!({
+        "hits": old_hits + hits,
+        "misses": old_misses + misses,
+    });
+    let _ = fs::write(path, json.to_string());
+}
+
+fn read_counts(path: &Path) -> (u64, u64) {
+    let stats: serde_json::Value = fs::read_to_string(path)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+    (
+        stats["hits"].as_u64().unwrap_or(0),
+        stats["misses"].as_u64().unwrap_or(0),
+    )
+}
+
+/// The size and use of the cache or of the build directory.
+#[derive(Debug, PartialEq)]
+pub struct CacheStats {
+    pub dir: PathBuf,
+    pub entries: usize,
+    /// The total size of the cached objects or the artifacts, in bytes.
+    pub size: u64,
+    pub hits: u64,
+    pub misses: u64,
+    /// The largest entries with their size, the largest first.
+    pub largest: Vec<(PathBuf, u64)>,
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lookups = self.hits + self.misses;
+        let rate = if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 * 100.0 / lookups as f64
+        };
+        writeln!(f, "Directory: {}", self.dir.display())?;
+        writeln!(f, "Entries:   {}", self.entries)?;
+        writeln!(f, "Size:      {}", utils::format_size(self.size))?;
+        write!(f, "Hits:      {} / {} ({:.1}%)", self.hits, lookups, rate)?;
+        if !self.largest.is_empty() {
+            write!(f, "\nLargest:")?;
+        }
+        for (path, size) in &self.largest {
+            let path = path.strip_prefix(&self.dir).unwrap_or(path);
+            write!(
+                f,
+                "\n  {:>10}  {}",
+                utils::format_size(*size),
+                path.display()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the size and use of the cache, with its `top` largest objects.
+///
+/// # Errors
+///
+/// If the cache directory is unknown.
+pub fn stats(config: &Config, top: usize) -> MorfoResult<CacheStats> {
+    let dir = config.get_cache_dir()?;
+    let entries = entries(&dir)
+        .into_iter()
+        .map(|(path, size, _)| (path, size))
+        .collect();
+    let (hits, misses) = read_counts(&dir.join(STATS));
+    Ok(summarize(dir, entries, (hits, misses), top))
+}
+
+/// Returns the size and use of the build directory of the project, counting the objects
+/// and executables built by morfo, with its `top` largest artifacts.
+pub fn build_dir_stats(config: &Config, top: usize) -> CacheStats {
+    let dir = config.get_build_dir();
+    let artifacts = WalkDir::new(&dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file() && fingerprint::is_recorded(entry.path()))
+        .filter_map(|entry| {
+            let size = entry.metadata().ok()?.len();
+            Some((entry.into_path(), size))
+        })
+        .collect();
+    let counts = read_counts(&layout::build_stats(config));
+    summarize(dir, artifacts, counts, top)
+}
+
+fn summarize(
+    dir: PathBuf,
+    mut entries: Vec<(PathBuf, u64)>,
+    (hits, misses): (u64, u64),
+    top: usize,
+) -> CacheStats {
+    entries.sort_by(|(a_path, a_size), (b_path, b_size)| {
+        b_size.cmp(a_size).then_with(|| a_path.cmp(b_path))
+    });
+    CacheStats {
+        entries: entries.len(),
+        size: entries.iter().map(|(_, size)| size).sum(),
+        hits,
+        misses,
+        largest: entries.into_iter().take(top).collect(),
+        dir,
+    }
+}
+
+/// What `gc` removed from the cache.
+#[derive(Debug, PartialEq)]
+pub struct GcReport {
+    pub removed: usize,
+    /// The size of the removed objects, in bytes.
+    pub freed: u64,
+}
+
+impl fmt::Display for GcReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Removed {} objects, freeing {}.",
+            self.removed,
+            utils::format_size(self.freed)
+        )
+    }
+}
+
+/// Removes the least recently used objects until the cache is no larger than `max_size`,
+/// e.g. "2G".
+///
+/// # Errors
+///
+/// If the size is invalid, the cache directory is unknown or an object cannot be removed.
+pub fn gc(config: &Config, max_size: &str) -> MorfoResult<GcReport> {
+    let max_size = utils::parse_size(max_size)
+        .ok_or_else(|| MorfoError::InvlidConfig(format!("invalid cache size `{}`", max_size)))?;
+    let mut entries = entries(&config.get_cache_dir()?);
+    entries.sort_by_key(|(_, _, used)| *used);
+
+    let mut size: u64 = entries.iter().map(|(_, size, _)| size).sum();
+    let mut report = GcReport {
+        removed: 0,
+        freed: 0,
+    };
+    for (path, entry_size, _) in entries {
+        if size <= max_size {
+            break;
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => (),
+            // another gc got there first
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(MorfoError::from(e)),
+        }
+        size -= entry_size;
+        report.removed += 1;
+        report.freed += entry_size;
+    }
+    Ok(report)
+}
+
+/// Returns every cached object with its size and the time it was last used.
+pub(crate) fn entries(dir: &Path) -> Vec<(PathBuf, u64, SystemTime)> {
+    WalkDir::new(dir.join(OBJECTS))
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| entry.path().extension().is_none_or(|ext| ext != "tmp"))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((
+                entry.into_path(),
+                metadata.len(),
+                metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile_act, prepare};
+    use std::{
+        collections::HashMap,
+        io::{BufRead, BufReader, Read},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    fn config(project_dir: &Path, cache_dir: &Path) -> Config {
+        config_with_remote(project_dir, cache_dir, "")
+    }
+
+    fn config_with_remote(project_dir: &Path, cache_dir: &Path, remote: &str) -> Config {
+        toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            builddir = "{}"
+
+            [cache]
+            enabled = true
+            dir = "{}"
+            {}"#,
+            project_dir.join(".out").display(),
+            cache_dir.display(),
+            remote
+        ))
+        .unwrap()
+    }
+
+    fn build(project_dir: &Path, cache_dir: &Path) {
+        build_with_remote(project_dir, cache_dir, "");
+    }
+
+    fn build_with_remote(project_dir: &Path, cache_dir: &Path, remote: &str) {
+        let main_file = project_dir.join("main.c");
+        let config = config_with_remote(project_dir, cache_dir, remote);
+        let (act, config) = prepare(&main_file, config).unwrap();
+        compile_act(&act, &config).unwrap();
+    }
+
+    /// An HTTP cache server in memory, recording the `Authorization` header of each request.
+    struct Server {
+        url: String,
+        objects: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+        auth: Arc<Mutex<Vec<String>>>,
+    }
+
+    fn serve() -> Server {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/cache", listener.local_addr().unwrap());
+        let objects = Arc::new(Mutex::new(HashMap::new()));
+        let auth = Arc::new(Mutex::new(Vec::new()));
+        let (server_objects, server_auth) = (objects.clone(), auth.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut reader = BufReader::new(stream.unwrap());
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    let header = header.trim();
+                    if header.is_empty() {
+                        break;
+                    }
+                    let (name, value) = header.split_once(": ").unwrap_or((header, ""));
+                    match name.to_ascii_lowercase().as_str() {
+                        "content-length" => length = value.parse().unwrap(),
+                        "authorization" => server_auth.lock().unwrap().push(value.to_owned()),
+                        _ => (),
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let mut words = request.split_whitespace();
+                let (method, path) = (words.next().unwrap(), words.next().unwrap().to_owned());
+                let mut objects = server_objects.lock().unwrap();
+                let (status, body) = match method {
+                    "PUT" => {
+                        objects.insert(path, body);
+                        ("201 Created", Vec::new())
+                    }
+                    _ => match objects.get(&path) {
+                        Some(object) => ("200 OK", object.clone()),
+                        None => ("404 Not Found", Vec::new()),
+                    },
+                };
+                let mut stream = reader.into_inner();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                );
+                let _ = stream.write_all(&body);
+            }
+        });
+        Server { url, objects, auth }
+    }
+
+    fn write_main(project_dir: &Path) {
+        fs::write(project_dir.join("main.c"), "int main(void) { return 0; }\n").unwrap();
+    }
+
+    #[test]
+    fn cache_remote_shared_across_machines() {
+        let server = serve();
+        env::set_var("MORFO_TEST_CACHE_TOKEN", "secret");
+        let remote = format!(
+            "[cache.remote]\nurl = \"{}\"\ntoken_env = \"MORFO_TEST_CACHE_TOKEN\"",
+            server.url
+        );
+        let (first, second) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let (first_cache, second_cache) =
+            (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        write_main(first.path());
+        write_main(second.path());
+
+        build_with_remote(first.path(), first_cache.path(), &remote);
+        assert_eq!(server.objects.lock().unwrap().len(), 1);
+
+        build_with_remote(second.path(), second_cache.path(), &remote);
+        let stats = stats(&config(second.path(), second_cache.path()), 5).unwrap();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (1, 1, 0));
+        assert!(server
+            .auth
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|auth| auth == "Bearer secret"));
+    }
+
+    #[test]
+    fn cache_remote_read_only() {
+        let server = serve();
+        let remote = format!("[cache.remote]\nurl = \"{}\"\nread_only = true", server.url);
+        let (project, cache_dir) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        write_main(project.path());
+
+        build_with_remote(project.path(), cache_dir.path(), &remote);
+        assert!(server.objects.lock().unwrap().is_empty());
+        assert_eq!(
+            stats(&config(project.path(), cache_dir.path()), 5)
+                .unwrap()
+                .entries,
+            1
+        );
+    }
+
+    #[test]
+    fn cache_remote_fetch_not_stored_is_a_miss() {
+        let server = serve();
+        server
+            .objects
+            .lock()
+            .unwrap()
+            .insert("/cache/abcd".to_owned(), b"object".to_vec());
+        let settings: RemoteCache = toml::from_str(&format!("url = \"{}\"", server.url)).unwrap();
+        let mut remote = Remote {
+            settings,
+            available: true,
+        };
+        let dir = tempfile::tempdir().unwrap();
+        // the download cannot be renamed over a directory
+        let cached = dir.path().join("ab").join("cd");
+        fs::create_dir_all(cached.join("taken")).unwrap();
+
+        assert!(!remote.fetch("abcd", &cached));
+        assert!(remote.available);
+        assert_eq!(fs::read_dir(dir.path().join("ab")).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn cache_remote_unreachable() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/cache", listener.local_addr().unwrap());
+        drop(listener);
+        let remote = format!("[cache.remote]\nurl = \"{}\"", url);
+        let (project, cache_dir) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        write_main(project.path());
+
+        build_with_remote(project.path(), cache_dir.path(), &remote);
+        assert_eq!(
+            stats(&config(project.path(), cache_dir.path()), 5)
+                .unwrap()
+                .entries,
+            1
+        );
+    }
+
+    #[test]
+    fn cache_shared_across_projects() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        for project in [&first, &second] {
+            fs::write(
+                project.path().join("main.c"),
+                "int main(void) { return 0; }\n",
+            )
+            .unwrap();
+        }
+
+        build(first.path(), cache_dir.path());
+        build(second.path(), cache_dir.path());
+
+        let stats = stats(&config(first.path(), cache_dir.path()), 5).unwrap();
+        assert_eq!(stats.entries, 1);
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        assert!(stats.size > 0);
+    }
+
+    #[test]
+    fn cache_shared_across_include_dirs() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        for project in [&first, &second] {
+            let project = project.path();
+            fs::create_dir(project.join("include")).unwrap();
+            fs::write(project.join("include/answer.h"), "#define ANSWER 0\n").unwrap();
+            fs::write(
+                project.join("main.c"),
+                "#include \"answer.h\"\nint main(void) { return ANSWER; }\n",
+            )
+            .unwrap();
+            let config: Config = toml::from_str(&format!(
+                r#"
+                cc = "gcc"
+                cflags = ["-I {}"]
+                builddir = "{}"
+
+                [cache]
+                enabled = true
+                dir = "{}""#,
+                project.join("include").display(),
+                project.join(".out").display(),
+                cache_dir.path().display()
+            ))
+            .unwrap();
+            let (act, config) = prepare(&project.join("main.c"), config).unwrap();
+            compile_act(&act, &config).unwrap();
+        }
+
+        let stats = stats(&config(first.path(), cache_dir.path()), 5).unwrap();
+        assert_eq!((stats.entries, stats.hits, stats.misses), (1, 1, 1));
+    }
+
+    #[test]
+    fn cache_stats_build_dir() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let project = tempfile::tempdir().unwrap();
+        write_main(project.path());
+        build(project.path(), cache_dir.path());
+        build(project.path(), cache_dir.path());
+
+        let config = config(project.path(), cache_dir.path());
+        let stats = build_dir_stats(&config, 1);
+        assert_eq!(stats.entries, 2);
+        // the object and the executable are built, then both up to date
+        assert_eq!((stats.hits, stats.misses), (2, 2));
+        assert_eq!(stats.largest.len(), 1);
+        assert_eq!(stats.largest[0].0, project.path().join(".out/main"));
+        assert!(stats.size > stats.largest[0].1);
+        assert!(stats.to_string().contains("Largest:"));
+    }
+
+    #[test]
+    fn cache_gc() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let project = tempfile::tempdir().unwrap();
+        let main_file = project.path().join("main.c");
+        for code in 0..3 {
+            fs::write(
+                &main_file,
+                format!("int main(void) {{ return {}; }}\n", code),
+            )
+            .unwrap();
+            build(project.path(), cache_dir.path());
+        }
+
+        let config = config(project.path(), cache_dir.path());
+        let before = stats(&config, 5).unwrap();
+        assert_eq!(before.entries, 3);
+
+        let report = gc(&config, &(before.size - 1).to_string()).unwrap();
+        assert_eq!(report.removed, 1);
+        assert_eq!(stats(&config, 5).unwrap().entries, 2);
+
+        gc(&config, "0").unwrap();
+        assert_eq!(stats(&config, 5).unwrap().entries, 0);
+    }
+}
@@ -8,93 +8,1999 @@
 //! [`parse_config_file`]: fn.parse_config_file.html
 
 use std::{
+    collections::{BTreeMap, HashMap},
+    env::{self, consts::EXE_SUFFIX},
     fs,
     path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
 };
 
-use crate::error::{MorfoError, MorfoResult};
+use crate::{
+    cache::RemoteCache,
+    container::Container,
+    diagnostic::{Annotations, MessageFormat},
+    distributed::Distributed,
+    error::{MorfoError, MorfoResult},
+    flash::Flash,
+    generate::Generator,
+    libraries::Library,
+    package::{Package, Release},
+    pgo::PgoPhase,
+    plugin::Plugin,
+    probe::Check,
+    rustlib::RustCrate,
+    template,
+    toolchain::{self, CompilerFamily, Language},
+    utils,
+    warnings::Warnings,
+};
+
+/// `Config` holds the configuration for the compiler.
+///
+/// It includes the compiler command (`cc`) and the compiler flags (`cflags`).
+/// This struct is created by [`ConfigBuilder`].
+/// This struct can be created from a config file using [`parse_config_file`].
+///
+/// [`ConfigBuilder`]: struct.ConfigBuilder.html
+/// [`parse_config_file`]: fn.parse_config_file.html
+///
+/// # Examples
+///
+/// ```
+/// use morfo::config::ConfigBuilder;
+///
+/// let config = ConfigBuilder::default()
+///     .set_cc("gcc")
+///     .add_cflag("-O2")
+///     .set_build_dir(".out")
+///     .build();
+///
+/// assert_eq!(config.get_cc(), "gcc");
+/// assert_eq!(config.get_cflags(), vec!["-O2"]);
+/// ```
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    cc: String,
+    compilers: Option<Compilers>,
+    family: Option<CompilerFamily>,
+    cc_candidates: Option<Vec<String>>,
+    cflags: Option<Vec<String>>,
+    fc: Option<String>,
+    fflags: Option<Vec<String>>,
+    linker: Option<String>,
+    ar: Option<String>,
+    ranlib: Option<String>,
+    builddir: Option<String>,
+    bindir: Option<String>,
+    src: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    includes: Option<Vec<String>>,
+    angle_includes: Option<bool>,
+    header_sources: Option<BTreeMap<String, Vec<String>>>,
+    runner: Option<String>,
+    exe_suffix: Option<String>,
+    defines: Option<BTreeMap<String, String>>,
+    build_info: Option<bool>,
+    unity: Option<bool>,
+    keep_going: Option<bool>,
+    max_errors: Option<u32>,
+    compile_timeout: Option<u64>,
+    build_priority: Option<BuildPriority>,
+    notify: Option<bool>,
+    notify_after: Option<u64>,
+    embed: Option<Vec<String>>,
+    opt_level: Option<String>,
+    std: Option<String>,
+    debug: Option<bool>,
+    split_debug: Option<bool>,
+    hardening: Option<bool>,
+    symbol_check: Option<bool>,
+    warnings: Option<Warnings>,
+    sanitizers: Option<Vec<String>>,
+    heap_budget: Option<String>,
+    profiling: Option<Profiling>,
+    linkage: Option<Linkage>,
+    project: Option<Project>,
+    run: Option<Run>,
+    features: Option<BTreeMap<String, Check>>,
+    generators: Option<BTreeMap<String, Generator>>,
+    libraries: Option<BTreeMap<String, Library>>,
+    dependencies: Option<Dependencies>,
+    link_group: Option<bool>,
+    frameworks: Option<Vec<String>>,
+    rpath: Option<Vec<String>>,
+    bundle_libs: Option<bool>,
+    pgo: Option<Pgo>,
+    matrix: Option<Matrix>,
+    cache: Option<Cache>,
+    distributed: Option<Distributed>,
+    container: Option<Container>,
+    flash: Option<Flash>,
+    package: Option<Package>,
+    release: Option<Release>,
+    plugins: Option<BTreeMap<String, Plugin>>,
+    script: Option<String>,
+    targets: Option<HashMap<String, Target>>,
+    profiles: Option<HashMap<String, Profile>>,
+    #[serde(skip)]
+    target: Option<String>,
+    #[serde(skip)]
+    profile: Option<String>,
+    #[serde(skip)]
+    batch_dir: Option<PathBuf>,
+    #[serde(skip)]
+    pgo_phase: Option<PgoPhase>,
+    #[serde(skip)]
+    frame_pointers: bool,
+    #[serde(skip)]
+    time_trace: bool,
+    #[serde(skip)]
+    time_trace_output: Option<PathBuf>,
+    #[serde(skip)]
+    message_format: MessageFormat,
+    #[serde(skip)]
+    annotations: Option<Annotations>,
+    #[serde(skip)]
+    summary_path: Option<PathBuf>,
+    #[serde(skip)]
+    dry_run: bool,
+    #[serde(skip)]
+    rebuild: bool,
+    #[serde(skip)]
+    no_lock: bool,
+    #[serde(skip)]
+    tty: bool,
+    #[serde(skip)]
+    source_flags: Option<BTreeMap<PathBuf, Vec<String>>>,
+    #[serde(skip)]
+    output_file: Option<PathBuf>,
+    #[serde(skip)]
+    no_echo: bool,
+    reproducible: Option<bool>,
+    #[serde(skip)]
+    source_date_epoch: Option<u64>,
+    #[serde(skip)]
+    build_env: Option<BTreeMap<String, Option<String>>>,
+}
+
+/// `Compilers` holds the `[compilers]` section: the compiler of every language, chosen
+/// by the extension of each source.
+///
+/// `c` takes the place of `cc`. The C++ compiler `cxx` defaults to the one named like
+/// the C compiler, such as `g++` for `gcc` or `clang++-17` for `clang-17`, the assembler
+/// `asm` to the C compiler, which assembles `.s` and `.S` files, and `cuda` to `nvcc`.
+/// The Fortran compiler is `fc`. A program with C++ or CUDA sources is linked with the
+/// C++ compiler, so that the standard library of C++ is linked.
+///
+/// # Examples
+///
+/// ```toml
+/// [compilers]
+/// c = "clang"
+/// cxx = "clang++"
+/// asm = "as"
+/// cuda = "nvcc"
+/// ```
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct Compilers {
+    c: Option<String>,
+    cxx: Option<String>,
+    asm: Option<String>,
+    cuda: Option<String>,
+}
+
+/// `Dependencies` holds the `[dependencies]` section: the projects built by their own
+/// build systems and linked into the program, such as Rust crates.
+///
+/// # Examples
+///
+/// ```toml
+/// [dependencies.rust.mycrate]
+/// path = "rust/mycrate"
+/// ```
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct Dependencies {
+    rust: Option<BTreeMap<String, RustCrate>>,
+}
+
+/// `Profile` holds a named set of build settings, declared under `[profiles.<name>]`.
+///
+/// Any field that is set replaces (or, for `cflags`, extends) the corresponding
+/// top-level setting when the profile is selected with [`Config::for_profile`].
+///
+/// [`Config::for_profile`]: struct.Config.html#method.for_profile
+///
+/// # Examples
+///
+/// ```toml
+/// [profiles.ship]
+/// opt_level = "s"
+/// hardening = true
+/// ```
+///
+/// Some profiles are built in and can be used without being declared.
+/// Declaring them in the config overrides the built-in settings field by field.
+///
+/// | Profile   | opt_level | debug  |
+/// |-----------|-----------|--------|
+/// | `debug`   | `0`       | `true` |
+/// | `release` | `2`       |        |
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct Profile {
+    cflags: Option<Vec<String>>,
+    opt_level: Option<String>,
+    debug: Option<bool>,
+    split_debug: Option<bool>,
+    hardening: Option<bool>,
+    profiling: Option<Profiling>,
+    linkage: Option<Linkage>,
+}
+
+impl Profile {
+    /// Returns the built-in profile called `name`, if there is one.
+    fn builtin(name: &str) -> Option<Profile> {
+        match name {
+            "debug" => Some(Profile {
+                opt_level: Some("0".to_owned()),
+                debug: Some(true),
+                ..Profile::default()
+            }),
+            "release" => Some(Profile {
+                opt_level: Some("2".to_owned()),
+                ..Profile::default()
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns `self` with every unset field taken from `base`.
+    fn or(self, base: Profile) -> Profile {
+        Profile {
+            cflags: self.cflags.or(base.cflags),
+            opt_level: self.opt_level.or(base.opt_level),
+            debug: self.debug.or(base.debug),
+            split_debug: self.split_debug.or(base.split_debug),
+            hardening: self.hardening.or(base.hardening),
+            profiling: self.profiling.or(base.profiling),
+            linkage: self.linkage.or(base.linkage),
+        }
+    }
+}
+
+/// A profiler the program is instrumented for, set with `profiling`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Profiling {
+    /// Build with `-pg` and print gprof's flat profile after the program runs.
+    Gprof,
+}
+
+/// How the program is linked against its libraries, set with `linkage`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Linkage {
+    /// Link shared libraries at run time, the compilers' default.
+    Dynamic,
+    /// Copy every library into the executable, including the C library.
+    Static,
+}
+
+/// How much of the machine the compilers get, set with `build_priority`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildPriority {
+    /// The priority of morfo itself.
+    #[default]
+    Normal,
+    /// A lower priority, so that the rest of the machine stays responsive.
+    Low,
+    /// Only what the rest of the machine leaves unused.
+    Idle,
+}
+
+impl FromStr for BuildPriority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(BuildPriority::Normal),
+            "low" => Ok(BuildPriority::Low),
+            "idle" => Ok(BuildPriority::Idle),
+            _ => Err(format!(
+                "unknown build priority `{}`, expected `normal`, `low` or `idle`",
+                s
+            )),
+        }
+    }
+}
+
+/// `Pgo` configures `morfo pgo`, declared under `[pgo]`.
+///
+/// # Examples
+///
+/// ```toml
+/// [pgo]
+/// # `{exe}` is replaced with the path of the instrumented executable
+/// train = "./bench.sh {exe}"
+/// ```
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct Pgo {
+    train: Option<String>,
+}
+
+/// `Matrix` lists the compilers and C standards `morfo build --matrix` builds every
+/// combination of, declared under `[matrix]`.
+///
+/// # Examples
+///
+/// ```toml
+/// [matrix]
+/// cc = ["gcc", "clang"]
+/// std = ["c99", "c17"]
+/// ```
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct Matrix {
+    cc: Option<Vec<String>>,
+    std: Option<Vec<String>>,
+}
+
+/// `Cache` configures the object cache shared by every project, declared under `[cache]`.
+///
+/// # Examples
+///
+/// ```toml
+/// [cache]
+/// enabled = true
+/// # defaults to the user cache directory, e.g. ~/.cache/morfo
+/// dir = "/var/cache/morfo"
+///
+/// [cache.remote]
+/// url = "https://cache.example.com/morfo"
+/// ```
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct Cache {
+    enabled: Option<bool>,
+    dir: Option<String>,
+    remote: Option<RemoteCache>,
+}
+
+/// `Project` describes the project being built, declared under `[project]`.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct Project {
+    name: Option<String>,
+    version: Option<String>,
+}
+
+/// `Run` holds the settings of the run step, declared under `[run]`.
+///
+/// # Examples
+///
+/// ```toml
+/// [run]
+/// net = false
+/// user = "nobody"
+/// umask = "027"
+/// ```
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct Run {
+    net: Option<bool>,
+    user: Option<String>,
+    umask: Option<String>,
+}
+
+/// `Target` holds the overrides for a named build target, declared under `[targets.<name>]`.
+///
+/// Any field that is set replaces (or, for `cflags`, extends) the corresponding
+/// top-level setting when the target is selected with [`Config::for_target`].
+///
+/// [`Config::for_target`]: struct.Config.html#method.for_target
+///
+/// # Examples
+///
+/// ```toml
+/// [targets.arm-linux]
+/// cc = "arm-linux-gnueabihf-gcc"
+/// runner = "qemu-arm -L /usr/arm-linux-gnueabihf"
+/// linker = "lld"
+/// ar = "arm-linux-gnueabihf-ar"
+/// ranlib = "arm-linux-gnueabihf-ranlib"
+/// ```
+///
+/// Some targets are built in and can be used without being declared.
+/// Declaring them in the config overrides the built-in settings field by field.
+///
+/// | Target               | cc         | exe_suffix | runner | linkage  | ar / ranlib         |
+/// |----------------------|------------|------------|--------|----------|---------------------|
+/// | `wasm32-emscripten`  | `emcc`     | `.js`      | `node` |          | `emar` / `emranlib` |
+/// | `musl`               | `musl-gcc` |            |        | `static` |                     |
+///
+/// For a standalone `.wasm` module instead of the JS glue, set `exe_suffix = ".wasm"`
+/// and `runner = "wasmtime"`.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct Target {
+    cc: Option<String>,
+    cflags: Option<Vec<String>>,
+    runner: Option<String>,
+    exe_suffix: Option<String>,
+    linkage: Option<Linkage>,
+    linker: Option<String>,
+    ar: Option<String>,
+    ranlib: Option<String>,
+}
+
+impl Target {
+    /// Returns the built-in target called `name`, if there is one.
+    fn builtin(name: &str) -> Option<Target> {
+        match name {
+            "wasm32-emscripten" => Some(Target {
+                cc: Some("emcc".to_owned()),
+                cflags: None,
+                runner: Some("node".to_owned()),
+                exe_suffix: Some(".js".to_owned()),
+                ar: Some("emar".to_owned()),
+                ranlib: Some("emranlib".to_owned()),
+                ..Target::default()
+            }),
+            "musl" => Some(Target {
+                cc: Some("musl-gcc".to_owned()),
+                linkage: Some(Linkage::Static),
+                ..Target::default()
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns `self` with every unset field taken from `base`.
+    fn or(self, base: Target) -> Target {
+        Target {
+            cc: self.cc.or(base.cc),
+            cflags: self.cflags.or(base.cflags),
+            runner: self.runner.or(base.runner),
+            exe_suffix: self.exe_suffix.or(base.exe_suffix),
+            linkage: self.linkage.or(base.linkage),
+            linker: self.linker.or(base.linker),
+            ar: self.ar.or(base.ar),
+            ranlib: self.ranlib.or(base.ranlib),
+        }
+    }
+}
+
+impl Config {
+    /// Returns the compiler command, the C compiler of the `[compilers]` section if it
+    /// is set and `cc` otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::{Config, ConfigBuilder};
+    ///
+    /// let config = ConfigBuilder::default().set_cc("gcc").build();
+    /// assert_eq!(config.get_cc(), "gcc");
+    ///
+    /// let config: Config = toml::from_str("cc = \"gcc\"\n[compilers]\nc = \"clang\"").unwrap();
+    /// assert_eq!(config.get_cc(), "clang");
+    /// ```
+    pub fn get_cc(&self) -> &String {
+        self.compilers
+            .as_ref()
+            .and_then(|compilers| compilers.c.as_ref())
+            .filter(|c| !c.trim().is_empty())
+            .unwrap_or(&self.cc)
+    }
+
+    /// Returns the compiler of the sources in `language`, from the `[compilers]` section
+    /// or derived from the C compiler, as [`Compilers`] describes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::{config::Config, toolchain::Language};
+    ///
+    /// let config: Config = toml::from_str("cc = \"gcc-12\"").unwrap();
+    /// assert_eq!(config.get_compiler(Language::C), "gcc-12");
+    /// assert_eq!(config.get_compiler(Language::Cxx), "g++-12");
+    /// assert_eq!(config.get_compiler(Language::Asm), "gcc-12");
+    /// assert_eq!(config.get_compiler(Language::Cuda), "nvcc");
+    ///
+    /// let config: Config = toml::from_str("[compilers]\ncxx = \"clang++\"").unwrap();
+    /// assert_eq!(config.get_compiler(Language::Cxx), "clang++");
+    /// ```
+    pub fn get_compiler(&self, language: Language) -> String {
+        let compilers = self.compilers.clone().unwrap_or_default();
+        let cc = self.get_cc();
+        match language {
+            Language::C => cc.clone(),
+            Language::Cxx => compilers.cxx.unwrap_or_else(|| toolchain::cxx_compiler(cc)),
+            Language::Asm => compilers.asm.unwrap_or_else(|| cc.clone()),
+            Language::Cuda => compilers.cuda.unwrap_or("nvcc".to_owned()),
+            Language::Fortran => self.get_fc(),
+        }
+    }
+
+    /// Returns the config compiling with `cc`, whose family is then told from its name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::{config::ConfigBuilder, toolchain::CompilerFamily};
+    ///
+    /// let config = ConfigBuilder::default().set_cc("gcc").build().with_cc("clang");
+    /// assert_eq!(config.get_cc(), "clang");
+    /// assert_eq!(config.get_family(), CompilerFamily::Clang);
+    /// ```
+    pub fn with_cc(mut self, cc: &str) -> Config {
+        self.cc = cc.to_owned();
+        if let Some(compilers) = &mut self.compilers {
+            compilers.c = None;
+        }
+        self.family = None;
+        self
+    }
+
+    /// Returns the compilers probed for when `cc` is not set, in order.
+    /// If the candidates are not set, it will return `cc`, `gcc`, `clang` and `cl`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert_eq!(config.get_cc_candidates(), vec!["cc", "gcc", "clang", "cl"]);
+    /// ```
+    pub fn get_cc_candidates(&self) -> Vec<String> {
+        self.cc_candidates.clone().unwrap_or_else(|| {
+            toolchain::DEFAULT_CANDIDATES
+                .iter()
+                .map(|cc| cc.to_string())
+                .collect()
+        })
+    }
+
+    /// Returns the config with `cc` filled in from the PATH if it is not set.
+    /// The family of the detected compiler is recorded so its flags are spelled correctly.
+    ///
+    /// # Errors
+    ///
+    /// If `cc` is not set and none of the candidates could be run.
+    pub fn detect_cc(mut self) -> MorfoResult<Config> {
+        if !self.get_cc().trim().is_empty() {
+            return Ok(self);
+        }
+        // the compilers installed here say nothing about the ones in the image
+        if self.get_container().is_some() {
+            self.cc = "cc".to_owned();
+            return Ok(self);
+        }
+
+        let compiler = toolchain::detect(&self.get_cc_candidates())?;
+        self.cc = compiler.command;
+        self.family.get_or_insert(compiler.family);
+        Ok(self)
+    }
+
+    /// Returns the family of the compiler, which decides how flags are spelled.
+    /// If the family is not set, it is guessed from the compiler command.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    /// use morfo::toolchain::CompilerFamily;
+    ///
+    /// let config = ConfigBuilder::default().set_cc("cl").build();
+    /// assert_eq!(config.get_family(), CompilerFamily::Msvc);
+    /// ```
+    pub fn get_family(&self) -> CompilerFamily {
+        self.family
+            .unwrap_or_else(|| CompilerFamily::from_cc(self.get_cc()))
+    }
+
+    /// Returns the compiler flags, each its own argument.
+    ///
+    /// Every flag set is split like a shell would, so `-I include` is two arguments and
+    /// quotes keep spaces, as in `-DGREETING='"hello world"'`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().add_cflag("-O2").build();
+    /// assert_eq!(config.get_cflags(), vec!["-O2"]);
+    ///
+    /// let config = ConfigBuilder::default().add_cflag("-I 'my include'").build();
+    /// assert_eq!(config.get_cflags(), vec!["-I", "my include"]);
+    /// ```
+    pub fn get_cflags(&self) -> Vec<String> {
+        self.cflags
+            .iter()
+            .flatten()
+            .flat_map(|cflag| utils::split_words(cflag))
+            .collect()
+    }
+
+    /// Returns the config with the flags in `cflags` added, split like a shell would, as
+    /// when they come from the `CFLAGS` environment variable or `--cflags`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default()
+    ///     .add_cflag("-g")
+    ///     .build()
+    ///     .with_cflags("-Wall -DNAME='\"a b\"'");
+    /// assert_eq!(config.get_cflags(), vec!["-g", "-Wall", "-DNAME=\"a b\""]);
+    /// ```
+    pub fn with_cflags(mut self, cflags: &str) -> Config {
+        self.cflags
+            .get_or_insert_with(Vec::new)
+            .push(cflags.to_owned());
+        self
+    }
+
+    /// Returns the compiler of the Fortran sources.
+    /// If the Fortran compiler is not set, it will return "gfortran".
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::{Config, ConfigBuilder};
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert_eq!(config.get_fc(), "gfortran");
+    ///
+    /// let config: Config = toml::from_str(r#"fc = "flang-new""#).unwrap();
+    /// assert_eq!(config.get_fc(), "flang-new");
+    /// ```
+    pub fn get_fc(&self) -> String {
+        self.fc.clone().unwrap_or("gfortran".to_owned())
+    }
+
+    /// Returns the flags the Fortran sources are compiled with, each split like a shell
+    /// would, in place of `cflags`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    ///
+    /// let config: Config = toml::from_str(r#"fflags = ["-ffree-form -Wall"]"#).unwrap();
+    /// assert_eq!(config.get_fflags(), vec!["-ffree-form", "-Wall"]);
+    /// ```
+    pub fn get_fflags(&self) -> Vec<String> {
+        self.fflags
+            .iter()
+            .flatten()
+            .flat_map(|fflag| utils::split_words(fflag))
+            .collect()
+    }
+
+    /// Returns the linker, if set. A linker name such as `lld` or `mold` is passed to the
+    /// compiler as `-fuse-ld=<name>`, and any other command, such as
+    /// `arm-none-eabi-gcc`, links the executable in place of the compiler. With `auto`,
+    /// unoptimized builds link with mold or else lld when the compiler can use them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    ///
+    /// let config: Config = toml::from_str(r#"linker = "mold""#).unwrap();
+    /// assert_eq!(config.get_linker().as_deref(), Some("mold"));
+    ///
+    /// let config: Config = toml::from_str(r#"linker = """#).unwrap();
+    /// assert_eq!(config.get_linker(), None);
+    /// ```
+    pub fn get_linker(&self) -> Option<String> {
+        self.linker
+            .clone()
+            .filter(|linker| !linker.trim().is_empty())
+    }
+
+    /// Returns the archiver the tools building the dependencies use, such as `llvm-ar`,
+    /// if set.
+    pub fn get_ar(&self) -> Option<String> {
+        self.ar.clone().filter(|ar| !ar.trim().is_empty())
+    }
+
+    /// Returns the command indexing the archives the tools building the dependencies
+    /// create, such as `arm-none-eabi-ranlib`, if set.
+    pub fn get_ranlib(&self) -> Option<String> {
+        self.ranlib
+            .clone()
+            .filter(|ranlib| !ranlib.trim().is_empty())
+    }
+
+    /// Returns the build directory.
+    /// If the build directory is not set, it will return ".out".
+    ///
+    /// The artifacts of a target or a profile go to a subdirectory of it, as laid out
+    /// by [`layout`](crate::layout).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    /// use std::path::PathBuf;
+    ///
+    /// let config = ConfigBuilder::default().set_build_dir(".build").build();
+    /// assert_eq!(config.get_build_dir(), PathBuf::from(".build"));
+    /// ```
+    pub fn get_build_dir(&self) -> PathBuf {
+        match &self.builddir {
+            Some(build_dir) => Path::new(build_dir).to_path_buf(),
+            None => Path::new(".out").to_path_buf(),
+        }
+    }
+
+    /// Returns the directory the executables go to, if it is set apart from the build
+    /// directory, which keeps the objects and the other intermediate files.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    /// use std::path::PathBuf;
+    ///
+    /// let config: Config = toml::from_str(r#"bindir = "bin""#).unwrap();
+    /// assert_eq!(config.get_bin_dir(), Some(PathBuf::from("bin")));
+    /// ```
+    pub fn get_bin_dir(&self) -> Option<PathBuf> {
+        self.bindir
+            .as_deref()
+            .filter(|bin_dir| !bin_dir.trim().is_empty())
+            .map(PathBuf::from)
+    }
+
+    /// Returns the directories the sources and headers of the project are found in, with
+    /// their subdirectories, besides the directory of the main file itself.
+    /// If no source directories are set, it will return an empty vector and everything
+    /// under the directory of the main file is part of the project.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    ///
+    /// let config: Config = toml::from_str(r#"src = ["src/", "vendor/mini/"]"#).unwrap();
+    /// assert_eq!(config.get_src(), vec!["src/", "vendor/mini/"]);
+    /// ```
+    pub fn get_src(&self) -> Vec<String> {
+        self.src.clone().unwrap_or_default()
+    }
+
+    /// Returns the patterns of the files and directories left out when looking for the
+    /// sources and headers of the project, relative to the project directory.
+    /// If the option is not set, it will return an empty vector, though the build
+    /// directory and version control directories are always left out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    ///
+    /// let config: Config = toml::from_str(r#"exclude = [".out", "third_party/**"]"#).unwrap();
+    /// assert_eq!(config.get_exclude(), vec![".out", "third_party/**"]);
+    /// ```
+    pub fn get_exclude(&self) -> Vec<String> {
+        self.exclude.clone().unwrap_or_default()
+    }
+
+    /// Returns the include directories.
+    /// If the include directories are not set, it will return an empty vector.
+    ///
+    /// They are passed to the compiler with `-I`, and searched for quoted includes,
+    /// after the directory of the including file, when finding the dependencies.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert_eq!(config.get_includes(), Vec::<String>::new());
+    /// ```
+    pub fn get_includes(&self) -> Vec<String> {
+        self.includes.clone().unwrap_or_default()
+    }
+
+    /// Returns the config with `dir` added to the include directories, unless it is
+    /// there already.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build().with_include("vendor/include");
+    /// assert_eq!(config.with_include("vendor/include").get_includes(), ["vendor/include"]);
+    /// ```
+    pub fn with_include(mut self, dir: &str) -> Config {
+        let includes = self.includes.get_or_insert_with(Vec::new);
+        if !includes.iter().any(|include| include == dir) {
+            includes.push(dir.to_owned());
+        }
+        self
+    }
+
+    /// Returns whether `#include <...>` headers found in the include directories are part
+    /// of the project, so their sources are built too, like quoted includes.
+    /// If the option is not set, it will return false.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert!(!config.get_angle_includes());
+    /// assert!(config.with_angle_includes(true).get_angle_includes());
+    /// ```
+    pub fn get_angle_includes(&self) -> bool {
+        self.angle_includes.unwrap_or(false)
+    }
+
+    /// Returns the config with angled includes looked up in the include directories or not.
+    pub fn with_angle_includes(mut self, angle_includes: bool) -> Config {
+        self.angle_includes = Some(angle_includes);
+        self
+    }
+
+    /// Returns the sources implementing each header, for headers whose sources are not
+    /// found by name, such as `include/foo.h` implemented by `src/foo_posix.c`.
+    /// If no sources are declared, it will return an empty map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    ///
+    /// let config: Config = toml::from_str(
+    ///     r#"header_sources = { "include/foo.h" = ["src/foo_posix.c"] }"#,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(config.get_header_sources()["include/foo.h"], vec!["src/foo_posix.c"]);
+    /// ```
+    pub fn get_header_sources(&self) -> BTreeMap<String, Vec<String>> {
+        self.header_sources.clone().unwrap_or_default()
+    }
+
+    /// Returns the command used to launch the built executable, split into words like a
+    /// shell would, so quotes keep spaces.
+    /// If no runner is set, it will return an empty vector and the executable is run directly.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().set_runner("qemu-arm -L /usr/arm-linux-gnueabihf").build();
+    /// assert_eq!(config.get_runner(), vec!["qemu-arm", "-L", "/usr/arm-linux-gnueabihf"]);
+    ///
+    /// let config = ConfigBuilder::default().set_runner("qemu-arm -L '/opt/my sysroot'").build();
+    /// assert_eq!(config.get_runner(), vec!["qemu-arm", "-L", "/opt/my sysroot"]);
+    /// ```
+    pub fn get_runner(&self) -> Vec<String> {
+        utils::split_words(self.runner.as_deref().unwrap_or_default())
+    }
+
+    /// Returns the preprocessor macros to define, by name.
+    /// If no defines are set, it will return an empty map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().add_define("DEBUG", "1").build();
+    /// assert_eq!(config.get_defines().get("DEBUG").unwrap(), "1");
+    /// ```
+    pub fn get_defines(&self) -> BTreeMap<String, String> {
+        self.defines.clone().unwrap_or_default()
+    }
+
+    /// Returns the config with the macro `name` defined to `value`, replacing any earlier definition.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default()
+    ///     .add_define("LEVEL", "1")
+    ///     .build()
+    ///     .with_define("LEVEL", "2");
+    /// assert_eq!(config.get_defines().get("LEVEL").unwrap(), "2");
+    /// ```
+    pub fn with_define(mut self, name: &str, value: &str) -> Config {
+        self.defines
+            .get_or_insert_with(BTreeMap::new)
+            .insert(name.to_owned(), value.to_owned());
+        self
+    }
+
+    /// Returns whether build provenance macros such as `MORFO_GIT_HASH` are defined.
+    /// If the option is not set, it will return false.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert!(!config.get_build_info());
+    /// ```
+    pub fn get_build_info(&self) -> bool {
+        self.build_info.unwrap_or(false)
+    }
+
+    /// Returns the patterns of the files embedded into the program, relative to the project directory.
+    /// If the option is not set, it will return an empty vector.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    ///
+    /// let config: Config = toml::from_str(r#"embed = ["assets/shader.glsl", "data/*.json"]"#).unwrap();
+    /// assert_eq!(config.get_embed(), vec!["assets/shader.glsl", "data/*.json"]);
+    /// ```
+    pub fn get_embed(&self) -> Vec<String> {
+        self.embed.clone().unwrap_or_default()
+    }
+
+    /// Returns whether every translation unit is compiled together as a single unity (jumbo) source.
+    /// If the option is not set, it will return false.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert!(!config.get_unity());
+    /// assert!(config.with_unity(true).get_unity());
+    /// ```
+    pub fn get_unity(&self) -> bool {
+        self.unity.unwrap_or(false)
+    }
+
+    /// Returns the config with unity builds turned on or off.
+    pub fn with_unity(mut self, unity: bool) -> Config {
+        self.unity = Some(unity);
+        self
+    }
+
+    /// Returns whether the remaining sources are still compiled after one fails, so that
+    /// every failure is reported at the end. If the option is not set, it will return false.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert!(!config.get_keep_going());
+    /// assert!(config.with_keep_going(true).get_keep_going());
+    /// ```
+    pub fn get_keep_going(&self) -> bool {
+        self.keep_going.unwrap_or(false)
+    }
+
+    /// Returns the config with keep-going turned on or off.
+    pub fn with_keep_going(mut self, keep_going: bool) -> Config {
+        self.keep_going = Some(keep_going);
+        self
+    }
+
+    /// Returns whether a desktop notification is shown when a build finishes. If the
+    /// option is not set, it will return false.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    /// use std::time::Duration;
+    ///
+    /// let config: Config = toml::from_str("cc = \"gcc\"\nnotify_after = 30").unwrap();
+    /// assert!(!config.get_notify());
+    /// assert_eq!(config.get_notify_after(), Duration::from_secs(30));
+    /// assert!(config.with_notify(true).get_notify());
+    /// ```
+    pub fn get_notify(&self) -> bool {
+        self.notify.unwrap_or(false)
+    }
+
+    /// Returns how long a build must take for its notification to be shown, no time at
+    /// all if the option is not set.
+    pub fn get_notify_after(&self) -> Duration {
+        Duration::from_secs(self.notify_after.unwrap_or(0))
+    }
+
+    /// Returns the config with the notifications turned on or off.
+    pub fn with_notify(mut self, notify: bool) -> Config {
+        self.notify = Some(notify);
+        self
+    }
+
+    /// Returns how many errors the compiler reports for a source before giving up, if limited.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert_eq!(config.get_max_errors(), None);
+    /// assert_eq!(config.with_max_errors(5).get_max_errors(), Some(5));
+    /// ```
+    pub fn get_max_errors(&self) -> Option<u32> {
+        self.max_errors
+    }
+
+    /// Returns the config with the compiler stopping after `max_errors` errors per source.
+    pub fn with_max_errors(mut self, max_errors: u32) -> Config {
+        self.max_errors = Some(max_errors);
+        self
+    }
+
+    /// Returns how long a single run of the compiler may take before it is killed, if
+    /// there is a limit. It is set in seconds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    /// use std::time::Duration;
+    ///
+    /// let config: Config = toml::from_str("compile_timeout = 300").unwrap();
+    /// assert_eq!(config.get_compile_timeout(), Some(Duration::from_secs(300)));
+    /// ```
+    pub fn get_compile_timeout(&self) -> Option<Duration> {
+        self.compile_timeout.map(Duration::from_secs)
+    }
+
+    /// Returns the scheduling priority the compiler and the linker run with. If the
+    /// option is not set, it will return [`BuildPriority::Normal`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::{BuildPriority, Config};
+    ///
+    /// let config: Config = toml::from_str(r#"build_priority = "low""#).unwrap();
+    /// assert_eq!(config.get_build_priority(), BuildPriority::Low);
+    /// let config = config.with_build_priority(BuildPriority::Normal);
+    /// assert_eq!(config.get_build_priority(), BuildPriority::Normal);
+    /// ```
+    pub fn get_build_priority(&self) -> BuildPriority {
+        self.build_priority.unwrap_or_default()
+    }
+
+    /// Returns the config running the compiler and the linker with `priority`.
+    pub fn with_build_priority(mut self, priority: BuildPriority) -> Config {
+        self.build_priority = Some(priority);
+        self
+    }
+
+    /// Returns whether the commands morfo would run are printed instead of run.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert!(!config.get_dry_run());
+    /// assert!(config.with_dry_run(true).get_dry_run());
+    /// ```
+    pub fn get_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Returns the config with dry runs turned on or off.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Config {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Returns whether every artifact is built again, even the ones that are up to date.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert!(!config.get_rebuild());
+    /// assert!(config.with_rebuild(true).get_rebuild());
+    /// ```
+    pub fn get_rebuild(&self) -> bool {
+        self.rebuild
+    }
+
+    /// Returns the config with forced rebuilds turned on or off.
+    pub fn with_rebuild(mut self, rebuild: bool) -> Config {
+        self.rebuild = rebuild;
+        self
+    }
+
+    /// Returns whether the build directory is used without locking it against other runs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert!(!config.get_no_lock());
+    /// assert!(config.with_no_lock(true).get_no_lock());
+    /// ```
+    pub fn get_no_lock(&self) -> bool {
+        self.no_lock
+    }
+
+    /// Returns the config with locking the build directory turned off or on.
+    pub fn with_no_lock(mut self, no_lock: bool) -> Config {
+        self.no_lock = no_lock;
+        self
+    }
+
+    /// Returns whether the program runs in a pseudo-terminal instead of with pipes.
+    /// Only Unix has pseudo-terminals.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert!(!config.get_tty());
+    /// assert!(config.with_tty(true).get_tty());
+    /// ```
+    pub fn get_tty(&self) -> bool {
+        self.tty
+    }
+
+    /// Returns the config with the program running in a pseudo-terminal or not.
+    pub fn with_tty(mut self, tty: bool) -> Config {
+        self.tty = tty;
+        self
+    }
+
+    /// Returns the file the output of the program is also written to, if set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert_eq!(config.get_output_file(), None);
+    ///
+    /// let config = config.with_output_file(Path::new("run.log"));
+    /// assert_eq!(config.get_output_file(), Some(Path::new("run.log")));
+    /// ```
+    pub fn get_output_file(&self) -> Option<&Path> {
+        self.output_file.as_deref()
+    }
+
+    /// Returns the config with the output of the program also written to `output_file`.
+    pub fn with_output_file(mut self, output_file: &Path) -> Config {
+        self.output_file = Some(output_file.to_path_buf());
+        self
+    }
+
+    /// Returns whether the output of the program is only written to the output file, and
+    /// not echoed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert!(!config.get_no_echo());
+    /// assert!(config.with_no_echo(true).get_no_echo());
+    /// ```
+    pub fn get_no_echo(&self) -> bool {
+        self.no_echo
+    }
+
+    /// Returns the config with echoing the output of the program turned off or on.
+    pub fn with_no_echo(mut self, no_echo: bool) -> Config {
+        self.no_echo = no_echo;
+        self
+    }
+
+    /// Returns the command that trains an instrumented build for `morfo pgo`, split into words
+    /// like a shell would, with `{exe}` replaced by `executable`.
+    /// If no training command is set, it will return None and the program itself is run.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::{Config, ConfigBuilder};
+    /// use std::path::Path;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert_eq!(config.get_pgo_train(Path::new(".out/main")), None);
+    ///
+    /// let config: Config =
+    ///     toml::from_str("[pgo]\ntrain = \"{exe} --input 'data set.txt'\"").unwrap();
+    /// assert_eq!(
+    ///     config.get_pgo_train(Path::new(".out/main")).unwrap(),
+    ///     vec![".out/main", "--input", "data set.txt"]
+    /// );
+    /// ```
+    pub fn get_pgo_train(&self, executable: &Path) -> Option<Vec<String>> {
+        let train = self.pgo.as_ref()?.train.as_ref()?;
+        let executable = executable.to_string_lossy();
+        Some(
+            utils::split_words(train)
+                .into_iter()
+                .map(|word| word.replace("{exe}", &executable))
+                .collect(),
+        )
+    }
+
+    /// Returns the profile-guided optimization phase the build is in, if any.
+    pub fn get_pgo_phase(&self) -> Option<PgoPhase> {
+        self.pgo_phase
+    }
+
+    /// Returns the config building for the profile-guided optimization `phase`.
+    pub fn with_pgo_phase(mut self, phase: PgoPhase) -> Config {
+        self.pgo_phase = Some(phase);
+        self
+    }
+
+    /// Returns whether frame pointers are kept so that profilers can walk the stack.
+    pub fn get_frame_pointers(&self) -> bool {
+        self.frame_pointers
+    }
+
+    /// Returns the config building with frame pointers and debug information, for profiling.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build().with_frame_pointers();
+    /// assert!(config.get_frame_pointers());
+    /// assert!(config.get_debug());
+    /// ```
+    pub fn with_frame_pointers(mut self) -> Config {
+        self.frame_pointers = true;
+        self.debug = Some(true);
+        self
+    }
+
+    /// Returns whether Clang traces where the time of every compilation goes, for the
+    /// report after the build.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    /// use std::path::Path;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert!(!config.get_time_trace());
+    /// let config = config.with_time_trace(Some(Path::new("trace.json")));
+    /// assert!(config.get_time_trace());
+    /// assert_eq!(config.get_time_trace_output(), Some(Path::new("trace.json")));
+    /// ```
+    pub fn get_time_trace(&self) -> bool {
+        self.time_trace
+    }
+
+    /// Returns where the traces of every compilation are written merged into one, if
+    /// anywhere.
+    pub fn get_time_trace_output(&self) -> Option<&Path> {
+        self.time_trace_output.as_deref()
+    }
+
+    /// Returns the config tracing the time of every compilation, writing the traces
+    /// merged to `output` if there is one.
+    pub fn with_time_trace(mut self, output: Option<&Path>) -> Config {
+        self.time_trace = true;
+        self.time_trace_output = output.map(Path::to_path_buf);
+        self
+    }
+
+    /// Returns how the diagnostics of the compiler are printed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::{config::ConfigBuilder, diagnostic::MessageFormat};
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert_eq!(config.get_message_format(), MessageFormat::Human);
+    /// let config = config.with_message_format(MessageFormat::Short);
+    /// assert_eq!(config.get_message_format(), MessageFormat::Short);
+    /// ```
+    pub fn get_message_format(&self) -> MessageFormat {
+        self.message_format
+    }
+
+    /// Returns the config printing the diagnostics of the compiler in `message_format`.
+    pub fn with_message_format(mut self, message_format: MessageFormat) -> Config {
+        self.message_format = message_format;
+        self
+    }
+
+    /// Returns where the diagnostics are also printed as annotations, if anywhere.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::{config::ConfigBuilder, diagnostic::Annotations};
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert_eq!(config.get_annotations(), None);
+    /// let config = config.with_annotations(Annotations::Github);
+    /// assert_eq!(config.get_annotations(), Some(Annotations::Github));
+    /// ```
+    pub fn get_annotations(&self) -> Option<Annotations> {
+        self.annotations
+    }
+
+    /// Returns the config also printing the diagnostics as `annotations`.
+    pub fn with_annotations(mut self, annotations: Annotations) -> Config {
+        self.annotations = Some(annotations);
+        self
+    }
+
+    /// Returns where the summary of the build is written instead of the build directory,
+    /// if set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    /// use std::path::Path;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert_eq!(config.get_summary_path(), None);
+    /// let config = config.with_summary_path(Path::new("ci/summary.json"));
+    /// assert_eq!(config.get_summary_path(), Some(Path::new("ci/summary.json")));
+    /// ```
+    pub fn get_summary_path(&self) -> Option<&Path> {
+        self.summary_path.as_deref()
+    }
+
+    /// Returns the config writing the summary of the build to `path`.
+    pub fn with_summary_path(mut self, path: &Path) -> Config {
+        self.summary_path = Some(path.to_path_buf());
+        self
+    }
+
+    /// Returns whether compiled objects are shared through the user-level cache.
+    /// If the `[cache]` section is not set, it will return false.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    ///
+    /// let config: Config = toml::from_str("cc = \"gcc\"\n[cache]\nenabled = true").unwrap();
+    /// assert!(config.get_cache());
+    /// ```
+    pub fn get_cache(&self) -> bool {
+        self.cache
+            .as_ref()
+            .and_then(|cache| cache.enabled)
+            .unwrap_or(false)
+    }
+
+    /// Returns the directory of the user-level cache: `dir` in the `[cache]` section,
+    /// or `morfo` in the user cache directory.
+    ///
+    /// # Errors
+    ///
+    /// If `dir` is not set and the user cache directory is unknown.
+    pub fn get_cache_dir(&self) -> MorfoResult<PathBuf> {
+        match self.cache.as_ref().and_then(|cache| cache.dir.as_ref()) {
+            Some(dir) => Ok(PathBuf::from(dir)),
+            None => dirs::cache_dir()
+                .map(|dir| dir.join("morfo"))
+                .ok_or(MorfoError::MissingHomeDirectory),
+        }
+    }
+
+    /// Returns the remote backend of the cache, if `[cache.remote]` is set.
+    pub fn get_remote_cache(&self) -> Option<RemoteCache> {
+        self.cache.as_ref()?.remote.clone()
+    }
+
+    /// Returns the hosts to distribute compilation to, if the `[distributed]` section is set.
+    pub fn get_distributed(&self) -> Option<Distributed> {
+        self.distributed.clone()
+    }
+
+    /// Returns the board to flash, if the `[flash]` section is set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::{config::Config, flash::FlashTool};
+    ///
+    /// let config: Config = toml::from_str("[flash]\nchip = \"nRF52840_xxAA\"").unwrap();
+    /// let flash = config.get_flash().unwrap();
+    /// assert_eq!(flash.get_tool(), FlashTool::ProbeRs);
+    /// assert_eq!(flash.get_chip(), Some("nRF52840_xxAA"));
+    /// ```
+    pub fn get_flash(&self) -> Option<Flash> {
+        self.flash.clone()
+    }
+
+    /// Returns what `morfo package` puts into the archive besides the executable.
+    /// If the `[package]` section is not declared, only the executable goes in.
+    pub fn get_package(&self) -> Package {
+        self.package.clone().unwrap_or_default()
+    }
+
+    /// Returns how `morfo package --sign` signs the archives.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    ///
+    /// let config: Config = toml::from_str("[release]\nsign = \"gpg\"").unwrap();
+    /// assert_eq!(config.get_release().get_sign(), Some("gpg"));
+    /// assert_eq!(config.get_release().get_key(), None);
+    /// ```
+    pub fn get_release(&self) -> Release {
+        self.release.clone().unwrap_or_default()
+    }
+
+    /// Returns the plugins run on the events of the builds, by name.
+    /// If no plugins are declared, it will return an empty map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert!(config.get_plugins().is_empty());
+    /// ```
+    pub fn get_plugins(&self) -> BTreeMap<String, Plugin> {
+        self.plugins.clone().unwrap_or_default()
+    }
+
+    /// Returns the build script run before every build, if one is set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    /// use std::path::PathBuf;
+    ///
+    /// let config: Config = toml::from_str(r#"script = "build.rhai""#).unwrap();
+    /// assert_eq!(config.get_script(), Some(PathBuf::from("build.rhai")));
+    /// ```
+    pub fn get_script(&self) -> Option<PathBuf> {
+        self.script.as_ref().map(PathBuf::from)
+    }
+
+    /// Returns the extra flags the build script set for `source`, if any.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    /// use std::path::Path;
+    ///
+    /// let config = ConfigBuilder::default()
+    ///     .build()
+    ///     .with_source_flags(Path::new("fast.c"), vec!["-O3".to_owned()]);
+    /// assert_eq!(config.get_source_flags(Path::new("./fast.c")), ["-O3"]);
+    /// assert!(config.get_source_flags(Path::new("slow.c")).is_empty());
+    /// ```
+    pub fn get_source_flags(&self, source: &Path) -> Vec<String> {
+        self.source_flags
+            .as_ref()
+            .and_then(|flags| flags.get(&utils::normalize(source)))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Returns the config compiling `source` with `flags` on top of the others, after
+    /// those it was given before.
+    pub fn with_source_flags(mut self, source: &Path, flags: Vec<String>) -> Config {
+        self.source_flags
+            .get_or_insert_with(BTreeMap::new)
+            .entry(utils::normalize(source))
+            .or_default()
+            .extend(flags);
+        self
+    }
+
+    /// Returns the container to build in, if an image is set in the `[container]` section
+    /// or with [`Config::with_container_image`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert!(config.get_container().is_none());
+    ///
+    /// let config = config.with_container_image("ubuntu:24.04");
+    /// assert_eq!(config.get_container().unwrap().get_image(), Some("ubuntu:24.04"));
+    /// ```
+    pub fn get_container(&self) -> Option<Container> {
+        self.container
+            .clone()
+            .filter(|container| container.get_image().is_some())
+    }
+
+    /// Returns the config building in a container of `image`.
+    pub fn with_container_image(mut self, image: &str) -> Config {
+        self.container = Some(match self.container {
+            Some(container) => container.with_image(image),
+            None => Container::new(image),
+        });
+        self
+    }
+
+    /// Returns whether the build is reproducible, producing the same bytes from the same sources.
+    /// If the option is not set, it will return false.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert!(!config.get_reproducible());
+    /// assert!(config.with_reproducible(true).get_reproducible());
+    /// ```
+    pub fn get_reproducible(&self) -> bool {
+        self.reproducible.unwrap_or(false)
+    }
+
+    /// Returns the config with reproducible builds turned on or off.
+    pub fn with_reproducible(mut self, reproducible: bool) -> Config {
+        self.reproducible = Some(reproducible);
+        self
+    }
+
+    /// Returns the time, in seconds since the Unix epoch, that a reproducible build
+    /// records as its build time.
+    pub fn get_source_date_epoch(&self) -> Option<u64> {
+        self.source_date_epoch
+    }
+
+    /// Returns the config recording `epoch` as the build time.
+    pub fn with_source_date_epoch(mut self, epoch: u64) -> Config {
+        self.source_date_epoch = Some(epoch);
+        self
+    }
+
+    /// Returns the value of the environment variable `name` for the build: the one given
+    /// with [`Config::with_build_env`] if there is one, or that of morfo otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    /// use std::collections::BTreeMap;
+    ///
+    /// let env = BTreeMap::from([("CFLAGS".to_owned(), Some("-O2".to_owned()))]);
+    /// let config = ConfigBuilder::default().build().with_build_env(env);
+    /// assert_eq!(config.get_env_var("CFLAGS").as_deref(), Some("-O2"));
+    /// ```
+    pub fn get_env_var(&self, name: &str) -> Option<String> {
+        match self.build_env.as_ref().and_then(|env| env.get(name)) {
+            Some(value) => value.clone(),
+            None => env::var(name).ok(),
+        }
+    }
+
+    /// Returns the environment variables given with [`Config::with_build_env`], by name,
+    /// `None` for those unset.
+    pub fn get_build_env(&self) -> BTreeMap<String, Option<String>> {
+        self.build_env.clone().unwrap_or_default()
+    }
+
+    /// Returns the config building with the environment variables `env` in place of those
+    /// of morfo, by name, `None` for those unset. They are read by morfo and passed to the
+    /// compilers, so that a process building for others, such as the daemon, does not
+    /// change its own environment.
+    pub fn with_build_env(mut self, env: BTreeMap<String, Option<String>>) -> Config {
+        self.build_env = Some(env);
+        self
+    }
+
+    /// Returns the GCC-style optimization level (`0`, `1`, `2`, `3`, `s`, `z`), if it is set.
+    /// It is translated into the right flag for the compiler family.
+    pub fn get_opt_level(&self) -> Option<String> {
+        self.opt_level.clone()
+    }
+
+    /// Returns the config optimizing at `opt_level`.
+    pub fn with_opt_level(mut self, opt_level: &str) -> Config {
+        self.opt_level = Some(opt_level.to_owned());
+        self
+    }
+
+    /// Returns the C standard to compile against, e.g. `c99` or `gnu17`, if it is set.
+    /// It is translated into the right flag for the compiler family.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    ///
+    /// let config: Config = toml::from_str(r#"std = "c11""#).unwrap();
+    /// assert_eq!(config.get_std(), Some("c11".to_owned()));
+    /// assert_eq!(config.with_std("c17").get_std(), Some("c17".to_owned()));
+    /// ```
+    pub fn get_std(&self) -> Option<String> {
+        self.std.clone()
+    }
+
+    /// Returns the config compiling against the C standard `std`.
+    pub fn with_std(mut self, std: &str) -> Config {
+        self.std = Some(std.to_owned());
+        self
+    }
+
+    /// Returns the compilers of the build matrix, or an empty vector if it has none.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    ///
+    /// let config: Config = toml::from_str(
+    ///     r#"
+    ///     [matrix]
+    ///     cc = ["gcc", "clang"]
+    ///     std = ["c99", "c17"]
+    ///     "#,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(config.get_matrix_cc(), vec!["gcc", "clang"]);
+    /// assert_eq!(config.get_matrix_std(), vec!["c99", "c17"]);
+    /// ```
+    pub fn get_matrix_cc(&self) -> Vec<String> {
+        self.matrix
+            .as_ref()
+            .and_then(|matrix| matrix.cc.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the C standards of the build matrix, or an empty vector if it has none.
+    pub fn get_matrix_std(&self) -> Vec<String> {
+        self.matrix
+            .as_ref()
+            .and_then(|matrix| matrix.std.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the most heap memory, in bytes, a heap-profiled run may use.
+    /// The budget is written like `64M` or `1.5GiB`. If it is not set, there is no limit.
+    ///
+    /// # Errors
+    ///
+    /// If the budget is not a valid size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    ///
+    /// let config: Config = toml::from_str(r#"heap_budget = "64K""#).unwrap();
+    /// assert_eq!(config.get_heap_budget(), Ok(Some(65536)));
+    /// ```
+    pub fn get_heap_budget(&self) -> MorfoResult<Option<u64>> {
+        match &self.heap_budget {
+            Some(budget) => utils::parse_size(budget).map(Some).ok_or_else(|| {
+                MorfoError::InvlidConfig(format!("invalid heap_budget `{}`", budget))
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the profiler the program is instrumented for, if any.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::{Config, Profiling};
+    ///
+    /// let config: Config = toml::from_str(
+    ///     r#"
+    ///     [profiles.prof]
+    ///     profiling = "gprof""#,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(config.get_profiling(), None);
+    /// assert_eq!(
+    ///     config.for_profile("prof").unwrap().get_profiling(),
+    ///     Some(Profiling::Gprof)
+    /// );
+    /// ```
+    pub fn get_profiling(&self) -> Option<Profiling> {
+        self.profiling
+    }
+
+    /// Returns the config instrumenting the program for `profiling`.
+    pub fn with_profiling(mut self, profiling: Profiling) -> Config {
+        self.profiling = Some(profiling);
+        self
+    }
+
+    /// Returns whether debug information is generated in addition to `cflags`.
+    /// If the option is not set, it will return false.
+    pub fn get_debug(&self) -> bool {
+        self.debug.unwrap_or(false)
+    }
+
+    /// Returns whether debug information is generated and kept out of the executable.
+    /// If the option is not set, it will return false.
+    pub fn get_split_debug(&self) -> bool {
+        self.split_debug.unwrap_or(false)
+    }
+
+    /// Returns whether the hardened-build security flags are applied.
+    /// If the option is not set, it will return false.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert!(!config.get_hardening());
+    /// assert!(config.with_hardening(true).get_hardening());
+    /// ```
+    pub fn get_hardening(&self) -> bool {
+        self.hardening.unwrap_or(false)
+    }
+
+    /// Returns how the program is linked, if it is set. Otherwise the compiler's default is used.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::{ConfigBuilder, Linkage};
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert_eq!(config.get_linkage(), None);
+    /// assert_eq!(
+    ///     config.for_target("musl").unwrap().get_linkage(),
+    ///     Some(Linkage::Static)
+    /// );
+    /// ```
+    pub fn get_linkage(&self) -> Option<Linkage> {
+        self.linkage
+    }
 
-/// `Config` holds the configuration for the compiler.
-///
-/// It includes the compiler command (`cc`) and the compiler flags (`cflags`).
-/// This struct is created by [`ConfigBuilder`].
-/// This struct can be created from a config file using [`parse_config_file`].
-///
-/// [`ConfigBuilder`]: struct.ConfigBuilder.html
-/// [`parse_config_file`]: fn.parse_config_file.html
-///
-/// # Examples
-///
-/// ```
-/// use morfo::config::ConfigBuilder;
-///
-/// let config = ConfigBuilder::default()
-///     .set_cc("gcc")
-///     .add_cflag("-O2")
-///     .set_build_dir(".out")
-///     .build();
-///
-/// assert_eq!(config.get_cc(), "gcc");
-/// assert_eq!(config.get_cflags(), vec!["-O2"]);
-/// ```
-#[derive(Debug, serde::Deserialize)]
-pub struct Config {
-    cc: String,
-    cflags: Option<Vec<String>>,
-    builddir: Option<String>,
-    includes: Option<Vec<String>>,
-}
+    /// Returns the config linking with `linkage`.
+    pub fn with_linkage(mut self, linkage: Linkage) -> Config {
+        self.linkage = Some(linkage);
+        self
+    }
 
-impl Config {
-    /// Returns the compiler command.
+    /// Returns the config with the hardened-build security flags turned on or off.
+    pub fn with_hardening(mut self, hardening: bool) -> Config {
+        self.hardening = Some(hardening);
+        self
+    }
+
+    /// Returns whether the objects are checked for symbols defined twice before linking.
+    /// If the option is not set, it will return false.
     ///
     /// # Examples
     ///
     /// ```
     /// use morfo::config::ConfigBuilder;
     ///
-    /// let config = ConfigBuilder::default().set_cc("gcc").build();
-    /// assert_eq!(config.get_cc(), "gcc");
+    /// let config = ConfigBuilder::default().build();
+    /// assert!(!config.get_symbol_check());
+    /// assert!(config.with_symbol_check(true).get_symbol_check());
     /// ```
-    pub fn get_cc(&self) -> &String {
-        &self.cc
+    pub fn get_symbol_check(&self) -> bool {
+        self.symbol_check.unwrap_or(false)
+    }
+
+    /// Returns the config with the duplicate symbol check turned on or off.
+    pub fn with_symbol_check(mut self, symbol_check: bool) -> Config {
+        self.symbol_check = Some(symbol_check);
+        self
+    }
+
+    /// Returns which compiler warnings are errors, if the policy is set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::{
+    ///     config::Config,
+    ///     warnings::{Level, Warnings},
+    /// };
+    ///
+    /// let config: Config = toml::from_str(r#"warnings = "deny""#).unwrap();
+    /// assert_eq!(config.get_warnings(), Some(Warnings::All(Level::Deny)));
+    /// ```
+    pub fn get_warnings(&self) -> Option<Warnings> {
+        self.warnings.clone()
     }
 
-    /// Returns the compiler flags.
+    /// Returns the sanitizers to build with, e.g. `address` or `undefined`.
+    /// If no sanitizers are set, it will return an empty vector.
     ///
     /// # Examples
     ///
     /// ```
     /// use morfo::config::ConfigBuilder;
     ///
-    /// let config = ConfigBuilder::default().add_cflag("-O2").build();
-    /// assert_eq!(config.get_cflags(), vec!["-O2"]);
+    /// let config = ConfigBuilder::default().build().with_sanitizers(&["address"]);
+    /// assert_eq!(config.get_sanitizers(), vec!["address"]);
     /// ```
-    pub fn get_cflags(&self) -> Vec<String> {
-        self.cflags.clone().unwrap_or_default()
+    pub fn get_sanitizers(&self) -> Vec<String> {
+        self.sanitizers.clone().unwrap_or_default()
     }
 
-    /// Returns the build directory.
-    /// If the build directory is not set, it will return ".out".
+    /// Returns the config with `sanitizers` added to the sanitizers to build with.
+    pub fn with_sanitizers(mut self, sanitizers: &[&str]) -> Config {
+        let current = self.sanitizers.get_or_insert_with(Vec::new);
+        for sanitizer in sanitizers {
+            if !current.iter().any(|s| s == sanitizer) {
+                current.push(sanitizer.to_string());
+            }
+        }
+        self
+    }
+
+    /// Returns the config with the settings of the profile `name` applied.
+    ///
+    /// # Errors
+    ///
+    /// If no profile called `name` is declared in the config or built in.
     ///
     /// # Examples
     ///
     /// ```
     /// use morfo::config::ConfigBuilder;
-    /// use std::path::PathBuf;
     ///
-    /// let config = ConfigBuilder::default().set_build_dir(".build").build();
-    /// assert_eq!(config.get_build_dir(), PathBuf::from(".build"));
+    /// let config = ConfigBuilder::default().set_cc("gcc").build();
+    /// let release = config.for_profile("release").unwrap();
+    /// assert_eq!(release.get_opt_level(), Some("2".to_owned()));
+    /// assert!(config.for_profile("fastest").is_err());
     /// ```
-    pub fn get_build_dir(&self) -> PathBuf {
-        match &self.builddir {
-            Some(build_dir) => Path::new(build_dir).to_path_buf(),
-            None => Path::new(".out").to_path_buf(),
+    pub fn for_profile(&self, name: &str) -> MorfoResult<Config> {
+        let declared = self
+            .profiles
+            .as_ref()
+            .and_then(|profiles| profiles.get(name))
+            .cloned();
+        let profile = match (declared, Profile::builtin(name)) {
+            (Some(declared), Some(builtin)) => declared.or(builtin),
+            (Some(profile), None) | (None, Some(profile)) => profile,
+            (None, None) => return Err(MorfoError::UnknownProfile(name.to_owned())),
+        };
+
+        let mut config = self.clone();
+        if let Some(cflags) = profile.cflags {
+            config.cflags.get_or_insert_with(Vec::new).extend(cflags);
         }
+        config.opt_level = profile.opt_level.or(config.opt_level);
+        config.debug = profile.debug.or(config.debug);
+        config.split_debug = profile.split_debug.or(config.split_debug);
+        config.hardening = profile.hardening.or(config.hardening);
+        config.profiling = profile.profiling.or(config.profiling);
+        config.linkage = profile.linkage.or(config.linkage);
+        config.profile = Some(name.to_owned());
+        Ok(config)
     }
 
-    /// Returns the include directories.
-    /// If the include directories are not set, it will return an empty vector.
+    /// Returns the name of the profile selected with [`Config::for_profile`], if any.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().set_cc("gcc").build();
+    /// assert_eq!(config.get_profile(), None);
+    /// assert_eq!(config.for_profile("release").unwrap().get_profile(), Some("release".to_owned()));
+    /// ```
+    pub fn get_profile(&self) -> Option<String> {
+        self.profile.clone()
+    }
+
+    /// Returns the name of the project, if it is set in the `[project]` section.
+    pub fn get_project_name(&self) -> Option<String> {
+        self.project
+            .as_ref()
+            .and_then(|project| project.name.clone())
+    }
+
+    /// Returns whether the programs morfo runs can use the network, which `net = false`
+    /// in the `[run]` section turns off. If the option is not set, it will return true.
+    ///
+    /// Without the network, programs run in a network namespace of their own with no
+    /// interface up, even loopback, so that tests cannot call external services by
+    /// accident. This is only supported on Linux, through `unshare`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    ///
+    /// let config: Config = toml::from_str("[run]\nnet = false").unwrap();
+    /// assert!(!config.get_run_net());
+    /// ```
+    pub fn get_run_net(&self) -> bool {
+        self.run.as_ref().and_then(|run| run.net).unwrap_or(true)
+    }
+
+    /// Returns the user the programs morfo runs are started as, from `user` in the
+    /// `[run]` section, if set. Only root can start them as another user.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    ///
+    /// let config: Config = toml::from_str("[run]\nuser = \"nobody\"").unwrap();
+    /// assert_eq!(config.get_run_user(), Some("nobody".to_owned()));
+    /// ```
+    pub fn get_run_user(&self) -> Option<String> {
+        self.run.as_ref().and_then(|run| run.user.clone())
+    }
+
+    /// Returns the file mode creation mask of the programs morfo runs, from `umask` in the
+    /// `[run]` section, written in octal, if set.
+    ///
+    /// # Errors
+    ///
+    /// If the umask is not an octal number up to `777`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    ///
+    /// let config: Config = toml::from_str("[run]\numask = \"027\"").unwrap();
+    /// assert_eq!(config.get_run_umask(), Ok(Some(0o027)));
+    /// ```
+    pub fn get_run_umask(&self) -> MorfoResult<Option<u32>> {
+        match self.run.as_ref().and_then(|run| run.umask.as_ref()) {
+            Some(umask) => u32::from_str_radix(umask, 8)
+                .ok()
+                .filter(|umask| *umask <= 0o777)
+                .map(Some)
+                .ok_or_else(|| MorfoError::InvlidConfig(format!("invalid umask `{}`", umask))),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the version of the project, if it is set in the `[project]` section.
+    pub fn get_project_version(&self) -> Option<String> {
+        self.project
+            .as_ref()
+            .and_then(|project| project.version.clone())
+    }
+
+    /// Returns the feature checks whose results are written to the generated `config.h`, by macro name.
+    /// If no features are declared, it will return an empty map.
     ///
     /// # Examples
     ///
@@ -102,10 +2008,202 @@ impl Config {
     /// use morfo::config::ConfigBuilder;
     ///
     /// let config = ConfigBuilder::default().build();
-    /// assert_eq!(config.get_includes(), Vec::<String>::new());
+    /// assert!(config.get_features().is_empty());
     /// ```
-    pub fn get_includes(&self) -> Vec<String> {
-        self.includes.clone().unwrap_or_default()
+    pub fn get_features(&self) -> BTreeMap<String, Check> {
+        self.features.clone().unwrap_or_default()
+    }
+
+    /// Returns the code generators, by the file pattern they apply to.
+    /// If no generators are declared, it will return an empty map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().build();
+    /// assert!(config.get_generators().is_empty());
+    /// ```
+    pub fn get_generators(&self) -> BTreeMap<String, Generator> {
+        self.generators.clone().unwrap_or_default()
+    }
+
+    /// Returns the libraries to link, by name.
+    /// If no libraries are declared, it will return an empty map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    ///
+    /// let config: Config = toml::from_str(
+    ///     r#"
+    ///     [libraries]
+    ///     parse = { path = "vendor/libparse.a", deps = ["m"] }
+    ///     m = {}"#,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(config.get_libraries()["parse"].get_deps(), vec!["m"]);
+    /// ```
+    pub fn get_libraries(&self) -> BTreeMap<String, Library> {
+        self.libraries.clone().unwrap_or_default()
+    }
+
+    /// Returns the config linking `library` as `name`, replacing any library of that name.
+    pub fn with_library(mut self, name: &str, library: Library) -> Config {
+        self.libraries
+            .get_or_insert_with(BTreeMap::new)
+            .insert(name.to_owned(), library);
+        self
+    }
+
+    /// Returns the Rust crates of the `[dependencies.rust]` section, by name.
+    /// If no crates are declared, it will return an empty map.
+    pub fn get_rust_crates(&self) -> BTreeMap<String, RustCrate> {
+        self.dependencies
+            .as_ref()
+            .and_then(|dependencies| dependencies.rust.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns whether the libraries are linked as a group, so that they may depend on each other.
+    /// If the option is not set, it will return false.
+    pub fn get_link_group(&self) -> bool {
+        self.link_group.unwrap_or(false)
+    }
+
+    /// Returns the macOS frameworks the executable is linked with, such as `Foundation`.
+    /// If the option is not set, it will return an empty vector.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    ///
+    /// let config: Config = toml::from_str(r#"frameworks = ["Foundation"]"#).unwrap();
+    /// assert_eq!(config.get_frameworks(), vec!["Foundation"]);
+    /// ```
+    pub fn get_frameworks(&self) -> Vec<String> {
+        self.frameworks.clone().unwrap_or_default()
+    }
+
+    /// Returns the runtime library search paths embedded into the executable.
+    /// `$ORIGIN` stands for the directory of the executable.
+    /// If the option is not set, it will return an empty vector.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    ///
+    /// let config: Config = toml::from_str(r#"rpath = ["$ORIGIN/../lib"]"#).unwrap();
+    /// assert_eq!(config.get_rpath(), vec!["$ORIGIN/../lib"]);
+    /// ```
+    pub fn get_rpath(&self) -> Vec<String> {
+        self.rpath.clone().unwrap_or_default()
+    }
+
+    /// Returns whether the non-system shared libraries are copied next to the executable.
+    /// If the option is not set, it will return false.
+    pub fn get_bundle_libs(&self) -> bool {
+        self.bundle_libs.unwrap_or(false)
+    }
+
+    /// Returns the suffix appended to the name of the built executable.
+    /// If the suffix is not set, it will return the platform's suffix (`.exe` on Windows, empty elsewhere).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().set_cc("gcc").build();
+    /// let wasm = config.for_target("wasm32-emscripten").unwrap();
+    /// assert_eq!(wasm.get_exe_suffix(), ".js");
+    /// ```
+    pub fn get_exe_suffix(&self) -> String {
+        self.exe_suffix
+            .clone()
+            .unwrap_or_else(|| EXE_SUFFIX.to_owned())
+    }
+
+    /// Returns the config with the overrides of the target `name` applied.
+    ///
+    /// # Errors
+    ///
+    /// If no target called `name` is declared in the config or built in.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::ConfigBuilder;
+    ///
+    /// let config = ConfigBuilder::default().set_cc("gcc").build();
+    /// assert!(config.for_target("arm-linux").is_err());
+    /// ```
+    pub fn for_target(&self, name: &str) -> MorfoResult<Config> {
+        let declared = self
+            .targets
+            .as_ref()
+            .and_then(|targets| targets.get(name))
+            .cloned();
+        let target = match (declared, Target::builtin(name)) {
+            (Some(declared), Some(builtin)) => declared.or(builtin),
+            (Some(target), None) | (None, Some(target)) => target,
+            (None, None) => return Err(MorfoError::UnknownTarget(name.to_owned())),
+        };
+
+        let mut config = self.clone();
+        if let Some(cc) = &target.cc {
+            config = config.with_cc(cc);
+            config.family = self.family;
+        }
+        if let Some(cflags) = &target.cflags {
+            config
+                .cflags
+                .get_or_insert_with(Vec::new)
+                .extend(cflags.iter().cloned());
+        }
+        if let Some(runner) = &target.runner {
+            config.runner = Some(runner.clone());
+        }
+        if let Some(exe_suffix) = &target.exe_suffix {
+            config.exe_suffix = Some(exe_suffix.clone());
+        }
+        if let Some(linkage) = target.linkage {
+            config.linkage = Some(linkage);
+        }
+        if let Some(linker) = &target.linker {
+            config.linker = Some(linker.clone());
+        }
+        if let Some(ar) = &target.ar {
+            config.ar = Some(ar.clone());
+        }
+        if let Some(ranlib) = &target.ranlib {
+            config.ranlib = Some(ranlib.clone());
+        }
+        config.target = Some(name.to_owned());
+        Ok(config)
+    }
+
+    /// Returns the name of the target selected with [`Config::for_target`], if any.
+    pub fn get_target(&self) -> Option<String> {
+        self.target.clone()
+    }
+
+    /// Returns the subdirectory of the build directory that a main file built in a batch,
+    /// one side of a differential test or a cell of the build matrix puts its artifacts
+    /// in, if any.
+    pub fn get_batch_dir(&self) -> Option<&Path> {
+        self.batch_dir.as_deref()
+    }
+
+    /// Returns the config putting the artifacts in the subdirectory `batch_dir` of the
+    /// build directory, so the main files of a batch do not share their artifacts.
+    pub fn with_batch_dir(mut self, batch_dir: &Path) -> Config {
+        self.batch_dir = Some(batch_dir.to_path_buf());
+        self
     }
 }
 
@@ -137,6 +2235,8 @@ pub struct ConfigBuilder {
     cflags: Vec<String>,
     build_dir: Option<PathBuf>,
     includes: Vec<PathBuf>,
+    runner: Option<String>,
+    defines: BTreeMap<String, String>,
 }
 
 impl ConfigBuilder {
@@ -160,17 +2260,105 @@ impl ConfigBuilder {
         self
     }
 
+    pub fn add_define(mut self, name: &str, value: &str) -> Self {
+        self.defines.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn set_runner(mut self, runner: &str) -> Self {
+        self.runner = Some(runner.to_string());
+        self
+    }
+
     pub fn build(self) -> Config {
         Config {
             cc: self.cc,
+            compilers: None,
+            family: None,
+            cc_candidates: None,
             cflags: Option::Some(self.cflags),
+            fc: None,
+            fflags: None,
+            linker: None,
+            ar: None,
+            ranlib: None,
             builddir: self.build_dir.map(|p| p.to_str().unwrap().to_string()),
+            bindir: None,
             includes: self
                 .includes
                 .iter()
                 .map(|p| p.to_str().unwrap().to_string())
                 .collect::<Vec<String>>()
                 .into(),
+            src: None,
+            exclude: None,
+            angle_includes: None,
+            header_sources: None,
+            runner: self.runner,
+            exe_suffix: None,
+            defines: Option::Some(self.defines),
+            build_info: None,
+            unity: None,
+            keep_going: None,
+            notify: None,
+            notify_after: None,
+            max_errors: None,
+            build_priority: None,
+            compile_timeout: None,
+            embed: None,
+            opt_level: None,
+            std: None,
+            debug: None,
+            split_debug: None,
+            hardening: None,
+            symbol_check: None,
+            warnings: None,
+            sanitizers: None,
+            heap_budget: None,
+            profiling: None,
+            linkage: None,
+            project: None,
+            run: None,
+            features: None,
+            generators: None,
+            libraries: None,
+            dependencies: None,
+            link_group: None,
+            frameworks: None,
+            rpath: None,
+            bundle_libs: None,
+            pgo: None,
+            matrix: None,
+            cache: None,
+            distributed: None,
+            container: None,
+            flash: None,
+            package: None,
+            release: None,
+            plugins: None,
+            script: None,
+            targets: None,
+            profiles: None,
+            target: None,
+            profile: None,
+            batch_dir: None,
+            pgo_phase: None,
+            frame_pointers: false,
+            time_trace: false,
+            time_trace_output: None,
+            message_format: MessageFormat::Human,
+            annotations: None,
+            summary_path: None,
+            dry_run: false,
+            rebuild: false,
+            no_lock: false,
+            tty: false,
+            source_flags: None,
+            output_file: None,
+            no_echo: false,
+            reproducible: None,
+            source_date_epoch: None,
+            build_env: None,
         }
     }
 }
@@ -200,7 +2388,7 @@ pub fn find_config_file() -> MorfoResult<PathBuf> {
     }
 
     let home = dirs::home_dir().ok_or(MorfoError::MissingHomeDirectory)?;
-    let global_config = home.join(".config/morfo/config.toml");
+    let global_config = home.join(".config").join("morfo").join("config.toml");
     let home_config = Path::new(&global_config);
     if home_config.exists() {
         Ok(home_config.to_path_buf())
@@ -233,6 +2421,31 @@ pub fn find_config_file() -> MorfoResult<PathBuf> {
 /// let config = morfo::config::parse_config_file(&PathBuf::from("./morfo.toml"));
 /// ```
 pub fn parse_config_file(filepath: &PathBuf) -> MorfoResult<Config> {
+    parse_config_file_with_vars(filepath, &[])
+}
+
+/// Parses the config file at `filepath` like [`parse_config_file`], with the variables
+/// of its `[vars]` section overridden by `overrides` of the form `vars.name=value`.
+///
+/// # Errors
+///
+/// If the config file cannot be read or parsed, an override is invalid, or the config
+/// uses a variable that is not declared.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::PathBuf;
+///
+/// let config = morfo::config::parse_config_file_with_vars(
+///     &PathBuf::from("./morfo.toml"),
+///     &["vars.opt=-O3".to_owned()],
+/// );
+/// ```
+pub fn parse_config_file_with_vars(
+    filepath: &PathBuf,
+    overrides: &[String],
+) -> MorfoResult<Config> {
     // assert the file exists
     if !filepath.exists() {
         return Err(MorfoError::FileNotFound(filepath.clone()));
@@ -247,7 +2460,9 @@ pub fn parse_config_file(filepath: &PathBuf) -> MorfoResult<Config> {
 
     let config = fs::read_to_string(filepath)?;
 
-    let config: Config = toml::from_str(&config)?;
+    let mut config: toml::Table = toml::from_str(&config)?;
+    template::expand(&mut config, overrides)?;
+    let config: Config = toml::Value::Table(config).try_into()?;
     Ok(config)
 }
 
@@ -321,7 +2536,8 @@ mod tests {
             cc = 'gcc'
             cflags = ['-Wall', '-Wextra']
             builddir = ".build"
-            includes = ["src/include", "src/aux/include"]"#;
+            includes = ["src/include", "src/aux/include"]
+            defines = { DEBUG = "1", VERSION = '"1.2.3"' }"#;
 
         let temp_dir = tempfile::tempdir().unwrap();
         let temp_path = temp_dir.path().join("config.toml");
@@ -343,6 +2559,105 @@ mod tests {
             config.includes.unwrap(),
             vec!["src/include", "src/aux/include"]
         );
+        let defines = config.defines.unwrap();
+        assert_eq!(defines.get("DEBUG").unwrap(), "1");
+        assert_eq!(defines.get("VERSION").unwrap(), "\"1.2.3\"");
+    }
+
+    #[test]
+    fn config_for_target() {
+        let toml_contents = r#"
+            cc = 'gcc'
+            cflags = ['-Wall']
+
+            [targets.arm-linux]
+            cc = 'arm-linux-gnueabihf-gcc'
+            cflags = ['-static']
+            runner = 'qemu-arm -L /usr/arm-linux-gnueabihf'"#;
+        let config: Config = toml::from_str(toml_contents).unwrap();
+
+        let target = config.for_target("arm-linux").unwrap();
+        assert_eq!(target.get_cc(), "arm-linux-gnueabihf-gcc");
+        assert_eq!(target.get_cflags(), vec!["-Wall", "-static"]);
+        assert_eq!(
+            target.get_runner(),
+            vec!["qemu-arm", "-L", "/usr/arm-linux-gnueabihf"]
+        );
+
+        assert!(config.get_runner().is_empty());
+        assert_eq!(
+            config.for_target("riscv").unwrap_err(),
+            MorfoError::UnknownTarget("riscv".to_owned())
+        );
+    }
+
+    #[test]
+    fn config_for_builtin_target() {
+        let toml_contents = r#"
+            cc = 'gcc'
+
+            [targets.wasm32-emscripten]
+            exe_suffix = '.wasm'
+            runner = 'wasmtime'"#;
+        let config: Config = toml::from_str(toml_contents).unwrap();
+
+        let wasm = config.for_target("wasm32-emscripten").unwrap();
+        assert_eq!(wasm.get_cc(), "emcc");
+        assert_eq!(wasm.get_exe_suffix(), ".wasm");
+        assert_eq!(wasm.get_runner(), vec!["wasmtime"]);
+    }
+
+    #[test]
+    fn config_for_profile() {
+        let toml_contents = r#"
+            cc = 'gcc'
+            cflags = ['-Wall']
+
+            [profiles.release]
+            cflags = ['-flto']
+            hardening = true
+            linkage = 'static'
+
+            [profiles.tiny]
+            opt_level = 's'"#;
+        let config: Config = toml::from_str(toml_contents).unwrap();
+
+        let release = config.for_profile("release").unwrap();
+        assert_eq!(release.get_cflags(), vec!["-Wall", "-flto"]);
+        assert_eq!(release.get_opt_level(), Some("2".to_owned()));
+        assert!(release.get_hardening());
+        assert_eq!(release.get_linkage(), Some(Linkage::Static));
+        assert!(!release.get_debug());
+
+        let debug = config.for_profile("debug").unwrap();
+        assert_eq!(debug.get_opt_level(), Some("0".to_owned()));
+        assert!(debug.get_debug());
+
+        let tiny = config.for_profile("tiny").unwrap();
+        assert_eq!(tiny.get_opt_level(), Some("s".to_owned()));
+        assert_eq!(
+            config.for_profile("huge").unwrap_err(),
+            MorfoError::UnknownProfile("huge".to_owned())
+        );
+    }
+
+    #[test]
+    fn config_detect_cc_keeps_configured() {
+        let config = ConfigBuilder::default()
+            .set_cc("morfo-no-such-compiler")
+            .build()
+            .detect_cc()
+            .unwrap();
+        assert_eq!(config.get_cc(), "morfo-no-such-compiler");
+    }
+
+    #[test]
+    fn config_detect_cc_without_candidates() {
+        let config: Config = toml::from_str("cc_candidates = ['morfo-no-such-compiler']").unwrap();
+        assert_eq!(
+            config.detect_cc().unwrap_err(),
+            MorfoError::MissingCompiler(vec!["morfo-no-such-compiler".to_owned()])
+        );
     }
 
     #[test]
@@ -381,8 +2696,8 @@ mod tests {
         let mut temp_file = File::create(&temp_path).unwrap();
 
         let toml_contents = r#"
-            builddir = ".build"
-            includes = ["src/include", "src/aux/include"]"#;
+            cc = 5
+            builddir = ".build""#;
         write!(temp_file, "{}", toml_contents).unwrap();
 
         let config = parse_config_file(&temp_path);
@@ -390,7 +2705,7 @@ mod tests {
         assert!(config.is_err());
         assert_eq!(
             config.unwrap_err(),
-            MorfoError::InvlidConfig("missing field `cc`".to_owned())
+            MorfoError::InvlidConfig("invalid type: integer `5`, expected a string".to_owned())
         );
     }
 }
@@ -0,0 +1,153 @@
+//! Container builds.
+//!
+//! With `--in-container <image>` or an `image` in the `[container]` section, every
+//! compiler and linker invocation runs in a fresh container of the image, so the
+//! toolchain is pinned by the image instead of whatever is installed locally. The
+//! working directory, the build directory and the temporary directory are mounted at
+//! the same paths, so the artifacts stay on the host. Sources outside those directories
+//! are not visible in the container.
+//!
+//! Containers are run with podman if it is installed, and docker otherwise. With docker,
+//! the container runs as the owner of the working directory, so the artifacts are not
+//! owned by root.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{config::Config, utils};
+
+/// The container to build in, declared in the `[container]` section of the config.
+///
+/// # Examples
+///
+/// ```toml
+/// [container]
+/// image = "ubuntu:24.04"
+/// engine = "docker"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize)]
+pub struct Container {
+    image: Option<String>,
+    engine: Option<String>,
+}
+
+impl Container {
+    /// Returns a container of `image`.
+    pub fn new(image: &str) -> Container {
+        Container {
+            image: Some(image.to_owned()),
+            engine: None,
+        }
+    }
+
+    /// Returns the container with the image replaced by `image`.
+    pub fn with_image(mut self, image: &str) -> Container {
+        self.image = Some(image.to_owned());
+        self
+    }
+
+    /// Returns the image to build in, if set.
+    pub fn get_image(&self) -> Option<&str> {
+        self.image.as_deref()
+    }
+
+    /// Returns the container engine: `engine` if it is set, otherwise podman if it is
+    /// installed, otherwise docker.
+    pub fn get_engine(&self) -> String {
+        match &self.engine {
+            Some(engine) => engine.clone(),
+            None if utils::tool_available("podman") => "podman".to_owned(),
+            None => "docker".to_owned(),
+        }
+    }
+}
+
+/// Returns the command that runs the compiler in `container`.
+pub(crate) fn compiler_command(config: &Config, container: &Container) -> Command {
+    let engine = container.get_engine();
+    let mut cmd = Command::new(&engine);
+    cmd.args(["run", "--rm"]);
+
+    let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    for dir in mounts(&cwd, config) {
+        cmd.arg("--volume").arg(format!("{0}:{0}", dir.display()));
+    }
+    cmd.arg("--workdir").arg(&cwd);
+
+    if engine.ends_with("podman") {
+        cmd.arg("--userns=keep-id");
+    } else {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            if let Ok(metadata) = cwd.metadata() {
+                cmd.arg("--user")
+                    .arg(format!("{}:{}", metadata.uid(), metadata.gid()));
+            }
+        }
+    }
+    // the environment of the engine is not passed on to the container
+    if let Some(epoch) = config.get_source_date_epoch() {
+        cmd.arg("--env").arg(format!("SOURCE_DATE_EPOCH={}", epoch));
+    }
+
+    cmd.arg(container.get_image().unwrap_or_default())
+        .arg(config.get_cc());
+    cmd
+}
+
+/// Returns the directories to mount: the working directory, and the build directory, the
+/// directory of the executables and the temporary directory unless they are inside it.
+fn mounts(cwd: &Path, config: &Config) -> Vec<PathBuf> {
+    let mut mounts = vec![cwd.to_path_buf()];
+    let others = [
+        Some(config.get_build_dir()),
+        config.get_bin_dir(),
+        Some(env::temp_dir()),
+    ];
+    for dir in others
+        .iter()
+        .flatten()
+        .filter_map(|dir| dir.canonicalize().ok())
+    {
+        if !mounts.iter().any(|mount| dir.starts_with(mount)) {
+            mounts.push(dir);
+        }
+    }
+    mounts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn container_compiler_command() {
+        let config: Config = toml::from_str(
+            r#"
+            cc = "gcc"
+            builddir = ".out"
+
+            [container]
+            image = "ubuntu:24.04"
+            engine = "docker""#,
+        )
+        .unwrap();
+        let container = config.get_container().unwrap();
+        let cmd = compiler_command(&config.with_source_date_epoch(0), &container);
+        let args: Vec<_> = cmd
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+
+        let cwd = env::current_dir().unwrap();
+        assert_eq!(cmd.get_program(), "docker");
+        assert_eq!(&args[..2], ["run", "--rm"]);
+        assert!(args.contains(&format!("{0}:{0}", cwd.display())));
+        assert!(args.contains(&"SOURCE_DATE_EPOCH=0".to_owned()));
+        assert_eq!(&args[args.len() - 2..], ["ubuntu:24.04", "gcc"]);
+    }
+}
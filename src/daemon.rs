@@ -0,0 +1,432 @@
+//! A build server keeping a project warm between builds.
+//!
+//! `morfo daemon` listens on `daemon.sock` in the build directory and builds the main
+//! files it is asked to, one at a time, keeping the scans of the sources and the macros
+//! the compiler predefines in memory between the builds instead of reading and asking
+//! for them again. While it runs, `morfo run` and `morfo build` send their command line
+//! to it and print what it prints, then run the executable it built themselves, so the
+//! program still gets the terminal. Without a daemon, or when it cannot be reached, they
+//! build as usual.
+//!
+//! The daemon builds in the working directory and with the environment of the command
+//! asking, and takes the config file anew for every build. The environment is passed to
+//! the build through its config and on to the compilers, leaving the daemon's own
+//! untouched. Restart it after changing
+//! the compiler, whose predefined macros it keeps.
+//!
+//! With `--http <addr>`, the daemon also serves an HTTP API starting builds, streaming
+//! what they print and telling how they went, described in [`http`].
+//!
+//! Daemons listen on Unix sockets, so they only run on Unix.
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener},
+    os::{
+        fd::{AsRawFd, OwnedFd},
+        unix::net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+};
+
+use crate::{
+    config::Config,
+    diagnostic,
+    error::{MorfoError, MorfoResult},
+    layout, warm, Artifact, CompileStats,
+};
+
+mod http;
+
+/// The environment variables of the asking command that change what is built.
+const ENV: [&str; 4] = ["CFLAGS", "GITHUB_ACTIONS", "SOURCE_DATE_EPOCH", "VERBOSITY"];
+
+/// A build asked of the daemon.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Request {
+    /// The main file to build.
+    pub main: PathBuf,
+    /// The working directory of the command asking.
+    pub cwd: PathBuf,
+    /// The command line of the command asking, its program first.
+    pub args: Vec<String>,
+    /// The environment variables of the command asking that change what is built.
+    pub env: BTreeMap<String, String>,
+}
+
+impl Request {
+    /// Returns the request building `main` for the command line of this process.
+    ///
+    /// # Errors
+    ///
+    /// If the working directory is unknown.
+    pub fn current(main: &Path) -> MorfoResult<Request> {
+        Ok(Request {
+            main: main.to_path_buf(),
+            cwd: env::current_dir()?,
+            args: env::args().collect(),
+            env: ENV
+                .iter()
+                .filter_map(|name| Some((name.to_string(), env::var(name).ok()?)))
+                .collect(),
+        })
+    }
+
+    /// Returns the environment to build with, for [`Config::with_build_env`]: the
+    /// variables of the command asking that change what is built, `None` for those unset.
+    pub fn build_env(&self) -> BTreeMap<String, Option<String>> {
+        ENV.iter()
+            .map(|name| (name.to_string(), self.env.get(*name).cloned()))
+            .collect()
+    }
+}
+
+/// What the daemon sends back, one JSON line each: what it printed while building, and
+/// lastly what it built.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+enum Frame {
+    Stdout(String),
+    Stderr(String),
+    Built(Result<Built, String>),
+}
+
+/// An executable the daemon built.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Built {
+    executable_path: PathBuf,
+    compile_stats: CompileStats,
+}
+
+/// Listens on the socket of the build directory of `config`, and on `http` if given,
+/// building with `build` every request made, until the process is stopped.
+///
+/// # Errors
+///
+/// [`MorfoError::DaemonRunning`] if another daemon listens on the socket, or if the
+/// socket or the HTTP listener cannot be created.
+pub fn serve(
+    config: &Config,
+    http: Option<SocketAddr>,
+    build: impl Fn(&Request) -> MorfoResult<Artifact> + Sync,
+) -> MorfoResult<()> {
+    let socket = layout::daemon_socket(config);
+    if UnixStream::connect(&socket).is_ok() {
+        return Err(MorfoError::DaemonRunning(socket));
+    }
+    // the socket of a daemon that was stopped is left behind
+    let _ = fs::remove_file(&socket);
+    fs::create_dir_all(config.get_build_dir())?;
+    let listener = UnixListener::bind(&socket)?;
+    let http = http.map(TcpListener::bind).transpose()?;
+    warm::enable();
+    eprintln!("Listening on {}", socket.display());
+
+    let builder = Builder {
+        build,
+        lock: Mutex::new(()),
+        home: Request::current(Path::new(""))?,
+    };
+    thread::scope(|scope| {
+        if let Some(http) = http {
+            if let Ok(addr) = http.local_addr() {
+                eprintln!("Listening on http://{}", addr);
+            }
+            let builder = &builder;
+            scope.spawn(move || http::serve(http, builder, scope));
+        }
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            // a client that went away is no reason to stop serving the others
+            let _ = answer(stream, &builder);
+        }
+    });
+    Ok(())
+}
+
+/// Builds the requests of every client, one at a time.
+struct Builder<B> {
+    build: B,
+    /// Held while building, as stdout and stderr are redirected for one build.
+    lock: Mutex<()>,
+    /// The request of the daemon itself, whose working directory and environment the
+    /// builds asked over HTTP have.
+    home: Request,
+}
+
+impl<B: Fn(&Request) -> MorfoResult<Artifact> + Sync> Builder<B> {
+    /// Builds `request` in its working directory, passing what is printed meanwhile to
+    /// `output`. Its environment is left to `build` to pass on.
+    fn build(
+        &self,
+        request: &Request,
+        output: &(dyn Fn(Frame) + Sync),
+    ) -> MorfoResult<MorfoResult<Artifact>> {
+        let _lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let _cwd = Cwd(&self.home.cwd);
+        env::set_current_dir(&request.cwd)?;
+        diagnostic::forget_reported();
+        capture(output, || (self.build)(request))
+    }
+}
+
+/// Changes the working directory back to the one held when dropped, so that a build
+/// that panicked does not leave the daemon in another project.
+struct Cwd<'a>(&'a Path);
+
+impl Drop for Cwd<'_> {
+    fn drop(&mut self) {
+        let _ = env::set_current_dir(self.0);
+    }
+}
+
+/// Reads a request from `stream` and builds it, sending back what was printed meanwhile
+/// and what was built.
+fn answer<B: Fn(&Request) -> MorfoResult<Artifact> + Sync>(
+    stream: UnixStream,
+    builder: &Builder<B>,
+) -> MorfoResult<()> {
+    let mut line = String::new();
+    BufReader::new(stream.try_clone()?).read_line(&mut line)?;
+    let request: Request =
+        serde_json::from_str(&line).map_err(|e| MorfoError::DaemonBuild(e.to_string()))?;
+
+    let stream = Mutex::new(stream);
+    let result = builder.build(&request, &|frame| {
+        let _ = send(&stream, &frame);
+    })?;
+    let built = result
+        .map(|artifact| Built {
+            executable_path: artifact.executable_path,
+            compile_stats: artifact.compile_stats,
+        })
+        .map_err(|e| e.to_string());
+    send(&stream, &Frame::Built(built))
+}
+
+/// Runs `f` with stdout and stderr, those of the compilers it starts included, passed
+/// to `output` line by line.
+fn capture<T>(output: &(dyn Fn(Frame) + Sync), f: impl FnOnce() -> T) -> MorfoResult<T> {
+    let (stdout_reader, stdout_writer) = io::pipe()?;
+    let (stderr_reader, stderr_writer) = io::pipe()?;
+    io::stdout().flush()?;
+    let mut redirected = Redirected(Vec::new());
+    redirected.redirect(1, stdout_writer.into())?;
+    redirected.redirect(2, stderr_writer.into())?;
+
+    thread::scope(|scope| {
+        // line by line, so that no character is split between frames
+        let forward = |reader: io::PipeReader, frame: fn(String) -> Frame| {
+            scope.spawn(move || {
+                let mut reader = BufReader::new(reader);
+                let mut line = Vec::new();
+                while let Ok(1..) = reader.read_until(b'\n', &mut line) {
+                    output(frame(String::from_utf8_lossy(&line).into_owned()));
+                    line.clear();
+                }
+            });
+        };
+        forward(stdout_reader, Frame::Stdout);
+        forward(stderr_reader, Frame::Stderr);
+
+        // putting the descriptors back closes the last ends writing to the pipes, which
+        // the forwarding threads wait for, so it must happen here even if `f` panics
+        let redirected = redirected;
+        let result = f();
+        drop(redirected);
+        Ok(result)
+    })
+}
+
+/// The standard streams redirected, with copies of what they pointed at before, which
+/// they are pointed back at when dropped.
+struct Redirected(Vec<(i32, OwnedFd)>);
+
+impl Redirected {
+    fn redirect(&mut self, fd: i32, to: OwnedFd) -> io::Result<()> {
+        let saved = redirect(fd, to)?;
+        self.0.push((fd, saved));
+        Ok(())
+    }
+}
+
+impl Drop for Redirected {
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        for (fd, saved) in self.0.drain(..).rev() {
+            let _ = redirect(fd, saved);
+        }
+    }
+}
+
+/// Points the file descriptor `fd` at `to`, returning a copy of what it pointed at.
+fn redirect(fd: i32, to: OwnedFd) -> io::Result<OwnedFd> {
+    // SAFETY: `fd` is a standard stream, open for the life of the process
+    let saved = unsafe { std::os::fd::BorrowedFd::borrow_raw(fd) }.try_clone_to_owned()?;
+    // SAFETY: both descriptors are open
+    if unsafe { libc::dup2(to.as_raw_fd(), fd) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(saved)
+}
+
+fn send(stream: &Mutex<UnixStream>, frame: &Frame) -> MorfoResult<()> {
+    let json = serde_json::to_string(frame).map_err(|e| MorfoError::DaemonBuild(e.to_string()))?;
+    let mut stream = stream.lock().unwrap_or_else(|e| e.into_inner());
+    stream.write_all((json + "\n").as_bytes())?;
+    Ok(())
+}
+
+/// Has the daemon listening on the socket of the build directory of `config` build
+/// `request`, printing what it prints, and returns what it built, to run with `config`.
+/// Returns `None` if no daemon can be reached, so that the caller builds itself.
+///
+/// # Errors
+///
+/// [`MorfoError::DaemonBuild`] with the error of the daemon if the build failed.
+pub fn compile(config: &Config, request: &Request) -> Option<MorfoResult<Artifact>> {
+    let mut stream = UnixStream::connect(layout::daemon_socket(config)).ok()?;
+    let json = serde_json::to_string(request).ok()?;
+    stream.write_all((json + "\n").as_bytes()).ok()?;
+
+    for line in BufReader::new(stream).lines() {
+        let frame: Frame = serde_json::from_str(&line.ok()?).ok()?;
+        match frame {
+            Frame::Stdout(text) => {
+                print!("{}", text);
+                let _ = io::stdout().flush();
+            }
+            Frame::Stderr(text) => eprint!("{}", text),
+            Frame::Built(built) => {
+                return Some(
+                    built
+                        .map(|built| Artifact {
+                            executable_path: built.executable_path,
+                            compile_stats: built.compile_stats,
+                            config: config.clone(),
+                        })
+                        .map_err(MorfoError::DaemonBuild),
+                )
+            }
+        }
+    }
+    // the daemon went away before it was done
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        ffi::OsStr,
+        panic::{self, AssertUnwindSafe},
+    };
+
+    use super::*;
+    use crate::config::ConfigBuilder;
+
+    #[test]
+    fn daemon_build_env_reaches_compilers() {
+        let request = Request {
+            main: PathBuf::from("main.c"),
+            cwd: env::current_dir().unwrap(),
+            args: Vec::new(),
+            env: BTreeMap::from([("SOURCE_DATE_EPOCH".to_owned(), "1".to_owned())]),
+        };
+        let config = ConfigBuilder::default()
+            .set_cc("gcc")
+            .build()
+            .with_build_env(request.build_env());
+        assert_eq!(
+            config.get_env_var("SOURCE_DATE_EPOCH").as_deref(),
+            Some("1")
+        );
+        assert_eq!(config.get_env_var("CFLAGS"), None);
+
+        let cmd = crate::compiler_command(&config);
+        let envs: Vec<_> = cmd.get_envs().collect();
+        assert!(envs.contains(&(OsStr::new("SOURCE_DATE_EPOCH"), Some(OsStr::new("1")))));
+        assert!(envs.contains(&(OsStr::new("CFLAGS"), None)));
+    }
+
+    #[test]
+    fn daemon_build_panic_restores_streams() {
+        let stream = |fd| {
+            // SAFETY: stat is plain data that fstat fills in
+            let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+            // SAFETY: stat is valid for the duration of the call
+            unsafe { libc::fstat(fd, &mut stat) };
+            (stat.st_dev, stat.st_ino)
+        };
+        let streams = [stream(1), stream(2)];
+        let cwd = env::current_dir().unwrap();
+        let request = Request {
+            main: PathBuf::from("main.c"),
+            cwd: cwd.clone(),
+            args: Vec::new(),
+            env: BTreeMap::from([("VERBOSITY".to_owned(), "1".to_owned())]),
+        };
+        let builder = Builder {
+            build: |_: &Request| -> MorfoResult<Artifact> { panic!("the build panicked") },
+            lock: Mutex::new(()),
+            home: request.clone(),
+        };
+
+        let built = panic::catch_unwind(AssertUnwindSafe(|| builder.build(&request, &|_| ())));
+        assert!(built.is_err());
+        assert_eq!([stream(1), stream(2)], streams);
+        assert_eq!(env::current_dir().unwrap(), cwd);
+        assert!(env::var("VERBOSITY").is_err());
+    }
+
+    #[test]
+    fn daemon_builds_requests() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let main_file = dir.join("main.c");
+        fs::write(&main_file, "int main(void) { return 0; }\n").unwrap();
+        let config = ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir(dir.join(".out").to_str().unwrap())
+            .build();
+        let request = Request {
+            main: main_file.clone(),
+            cwd: env::current_dir().unwrap(),
+            args: vec!["morfo".to_owned(), "build".to_owned()],
+            env: BTreeMap::new(),
+        };
+        assert!(compile(&config, &request).is_none());
+
+        let socket = layout::daemon_socket(&config);
+        let listener = {
+            fs::create_dir_all(dir.join(".out")).unwrap();
+            UnixListener::bind(&socket).unwrap()
+        };
+        let served = {
+            let config = config.clone();
+            let builder = Builder {
+                build: move |request: &Request| {
+                    assert_eq!(request.args, ["morfo", "build"]);
+                    crate::compile(&request.main, &config)
+                },
+                lock: Mutex::new(()),
+                home: request.clone(),
+            };
+            thread::spawn(move || {
+                let stream = listener.incoming().next().unwrap().unwrap();
+                answer(stream, &builder)
+            })
+        };
+        let artifact = compile(&config, &request).unwrap().unwrap();
+        served.join().unwrap().unwrap();
+        assert_eq!(artifact.executable_path, dir.join(".out/main"));
+        assert_eq!(
+            artifact.compile_stats.built,
+            [dir.join(".out/main.o"), dir.join(".out/main")]
+        );
+    }
+}
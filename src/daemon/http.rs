@@ -0,0 +1,691 @@
+//! The HTTP API of the daemon.
+//!
+//! `morfo daemon --http 127.0.0.1:7878` also serves these routes, so that web
+//! playgrounds and other tools can drive builds without a shell:
+//!
+//! | Route                    | Answer                                                  |
+//! |--------------------------|---------------------------------------------------------|
+//! | `POST /builds`           | Starts building `{"main": "main.c", "args": [...]}`     |
+//! | `GET /builds`            | The status of every build started                       |
+//! | `GET /builds/<id>`       | The status of a build, and its executable once built    |
+//! | `GET /builds/<id>/log`   | What the build printed, as server-sent events           |
+//!
+//! The `args` are options of `morfo build`, such as `["--profile", "release"]`, among
+//! `--target`, `--profile`, `-D`, `--hardened`, `--check-symbols`, `--unity`, `--static`,
+//! `--reproducible`, `--keep-going`, `--max-errors`, `--rebuild`, `--build-priority`,
+//! `--time-trace`, `--message-format`, `--annotations` and `--notify`. The others, such as
+//! `--config`, `--set`, `--cflags` and `--in-container`, could make the daemon read or
+//! run anything, and are refused. The builds run one at a time in the working directory
+//! of the daemon, like those asked by `morfo run`. A status is JSON like `{"id": 1, "main": "main.c", "status": "succeeded",
+//! "executable_path": ".out/main", "error": null, "compile_stats": {...}}`, its `status`
+//! one of `running`, `succeeded` and `failed`. The log sends every line printed on stdout
+//! as a `stdout` event and on stderr as a `stderr` event, then the status as a `done`
+//! event.
+//!
+//! A request body is at most 1 MiB, and a client that sends nothing for 30 seconds while
+//! its request is read is hung up on. So that web pages cannot start builds, a request
+//! must name the address the daemon listens on as its `Host`, must not carry an `Origin`,
+//! as browsers add to the requests of pages, and a `POST` must be `application/json`.
+//! The last 100 builds are kept, each with up to 1 MiB of its log.
+//!
+//! Anyone who can reach the address can build, so it is best kept on a loopback address.
+
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{Arc, Condvar, Mutex},
+    thread::Scope,
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+
+use super::{Builder, Frame, Request};
+use crate::{error::MorfoResult, Artifact, CompileStats};
+
+/// The largest request body read.
+const MAX_BODY: usize = 1024 * 1024;
+
+/// How long a client may take to send each part of its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How many builds are kept, the oldest forgotten first.
+const MAX_BUILDS: usize = 100;
+
+/// How much of the log of a build is kept, in bytes.
+const MAX_LOG: usize = 1024 * 1024;
+
+/// The options of `morfo build` a client may pass, with whether each takes a value.
+const BUILD_OPTIONS: [(&str, bool); 17] = [
+    ("--target", true),
+    ("--profile", true),
+    ("-D", true),
+    ("--hardened", false),
+    ("--check-symbols", false),
+    ("--unity", false),
+    ("--static", false),
+    ("--reproducible", false),
+    ("-k", false),
+    ("--keep-going", false),
+    ("--max-errors", true),
+    ("--rebuild", false),
+    ("--build-priority", true),
+    ("--time-trace", false),
+    ("--message-format", true),
+    ("--annotations", true),
+    ("--notify", false),
+];
+
+/// The builds started over HTTP, by id counting from 1, the last [`MAX_BUILDS`] of them.
+#[derive(Debug, Default)]
+struct Builds {
+    builds: Mutex<VecDeque<Arc<HttpBuild>>>,
+}
+
+impl Builds {
+    fn get(&self, id: usize) -> Option<Arc<HttpBuild>> {
+        let builds = self.builds.lock().unwrap_or_else(|e| e.into_inner());
+        builds.iter().find(|build| build.id == id).cloned()
+    }
+
+    /// Adds a build of `main` with the next id, forgetting the oldest if there are too
+    /// many.
+    fn add(&self, main: PathBuf) -> Arc<HttpBuild> {
+        let mut builds = self.builds.lock().unwrap_or_else(|e| e.into_inner());
+        let build = Arc::new(HttpBuild {
+            id: builds.back().map_or(0, |build| build.id) + 1,
+            main,
+            state: Mutex::new(State::default()),
+            changed: Condvar::new(),
+        });
+        builds.push_back(Arc::clone(&build));
+        if builds.len() > MAX_BUILDS {
+            builds.pop_front();
+        }
+        build
+    }
+}
+
+/// A build started over HTTP.
+#[derive(Debug)]
+struct HttpBuild {
+    id: usize,
+    main: PathBuf,
+    state: Mutex<State>,
+    /// Notified whenever the state changes.
+    changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// What was printed, by `stdout` or `stderr`.
+    log: Vec<(&'static str, String)>,
+    /// The bytes in the log.
+    log_size: usize,
+    /// What was built, once done.
+    result: Option<Result<(PathBuf, CompileStats), String>>,
+}
+
+impl State {
+    /// Adds `text` printed on `event` to the log, unless the log is full, which the
+    /// log then says once.
+    fn record(&mut self, event: &'static str, text: String) {
+        if self.log_size > MAX_LOG {
+            return;
+        }
+        self.log_size += text.len();
+        if self.log_size > MAX_LOG {
+            self.log.push((
+                "stderr",
+                "morfo: the rest of the log is left out\n".to_owned(),
+            ));
+        } else {
+            self.log.push((event, text));
+        }
+    }
+}
+
+impl HttpBuild {
+    fn status(&self) -> Value {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        status(self, &state)
+    }
+}
+
+fn status(build: &HttpBuild, state: &State) -> Value {
+    let (status, executable_path, error, compile_stats) = match &state.result {
+        None => ("running", None, None, None),
+        Some(Ok((executable, stats))) => ("succeeded", Some(executable), None, Some(stats)),
+        Some(Err(e)) => ("failed", None, Some(e), None),
+    };
+    json!({
+        "id": build.id,
+        "main": build.main,
+        "status": status,
+        "executable_path": executable_path,
+        "error": error,
+        "compile_stats": compile_stats,
+    })
+}
+
+/// Answers the connections to `listener`, each on a thread of `scope`, until the
+/// process is stopped.
+pub(super) fn serve<'scope, 'env, B>(
+    listener: TcpListener,
+    builder: &'env Builder<B>,
+    scope: &'scope Scope<'scope, 'env>,
+) where
+    B: Fn(&Request) -> MorfoResult<Artifact> + Sync,
+{
+    let builds = Arc::new(Builds::default());
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let builds = Arc::clone(&builds);
+        scope.spawn(move || handle(stream, builder, &builds, scope));
+    }
+}
+
+/// Answers the request read from `stream`, starting builds on threads of `scope`.
+fn handle<'scope, 'env, B>(
+    mut stream: TcpStream,
+    builder: &'env Builder<B>,
+    builds: &Arc<Builds>,
+    scope: &'scope Scope<'scope, 'env>,
+) -> io::Result<()>
+where
+    B: Fn(&Request) -> MorfoResult<Artifact> + Sync,
+{
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let Some(request) = read_request(&stream)? else {
+        return respond(
+            &mut stream,
+            "413 Payload Too Large",
+            &json!({ "error": format!("the body is larger than {} bytes", MAX_BODY) }),
+        );
+    };
+    // a page the user visits may send requests here; browsers add its `Origin` to those
+    // that could start a build, and name its own host as the `Host` of those sent through
+    // a name of its resolving to this address
+    if request.host != Some(stream.local_addr()?.to_string()) || request.origin {
+        return respond(
+            &mut stream,
+            "403 Forbidden",
+            &json!({ "error": "requests from browsers or to another host are refused" }),
+        );
+    }
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("POST", ["builds"]) => {
+            if !request.json {
+                return respond(
+                    &mut stream,
+                    "415 Unsupported Media Type",
+                    &json!({ "error": "expected application/json" }),
+                );
+            }
+            let Some((main, args)) = parse_build(&request.body) else {
+                return respond(
+                    &mut stream,
+                    "400 Bad Request",
+                    &json!({ "error": "expected {\"main\": \"<path>\", \"args\": [...]}" }),
+                );
+            };
+            if let Err(e) = check_args(&args) {
+                return respond(&mut stream, "400 Bad Request", &json!({ "error": e }));
+            }
+            let build = start(builder, builds, scope, main, args);
+            respond(&mut stream, "202 Accepted", &build.status())
+        }
+        ("GET", ["builds"]) => {
+            let statuses: Vec<Value> = {
+                let builds = builds.builds.lock().unwrap_or_else(|e| e.into_inner());
+                builds.iter().map(|build| build.status()).collect()
+            };
+            respond(&mut stream, "200 OK", &json!(statuses))
+        }
+        ("GET", ["builds", id]) => match id.parse().ok().and_then(|id| builds.get(id)) {
+            Some(build) => respond(&mut stream, "200 OK", &build.status()),
+            None => not_found(&mut stream),
+        },
+        ("GET", ["builds", id, "log"]) => match id.parse().ok().and_then(|id| builds.get(id)) {
+            Some(build) => stream_log(&mut stream, &build),
+            None => not_found(&mut stream),
+        },
+        _ => not_found(&mut stream),
+    }
+}
+
+/// Returns the main file and the options of a `POST /builds` body, if it is one.
+fn parse_build(body: &[u8]) -> Option<(PathBuf, Vec<String>)> {
+    let body: Value = serde_json::from_slice(body).ok()?;
+    let main = PathBuf::from(body["main"].as_str()?);
+    let args = match &body["args"] {
+        Value::Null => Vec::new(),
+        args => args
+            .as_array()?
+            .iter()
+            .map(|arg| arg.as_str().map(str::to_owned))
+            .collect::<Option<_>>()?,
+    };
+    Some((main, args))
+}
+
+/// Checks that `args` only holds the options of [`BUILD_OPTIONS`], with their values.
+fn check_args(args: &[String]) -> Result<(), String> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let (name, attached) = match arg.split_once('=') {
+            Some((name, _)) if name.starts_with("--") => (name, true),
+            // `-DNAME=VALUE`
+            _ if arg.starts_with("-D") && arg.len() > 2 => ("-D", true),
+            _ => (arg.as_str(), false),
+        };
+        match BUILD_OPTIONS.iter().find(|(option, _)| *option == name) {
+            Some((_, true)) if !attached => {
+                if args.next().is_none_or(|value| value.starts_with('-')) {
+                    return Err(format!("`{}` needs a value", name));
+                }
+            }
+            Some((_, takes_value)) if attached && !takes_value => {
+                return Err(format!("`{}` takes no value", name));
+            }
+            Some(_) => (),
+            None => return Err(format!("`{}` is not allowed over HTTP", name)),
+        }
+    }
+    Ok(())
+}
+
+/// Starts building `main` with the options `args` on a thread of `scope`.
+fn start<'scope, 'env, B>(
+    builder: &'env Builder<B>,
+    builds: &Arc<Builds>,
+    scope: &'scope Scope<'scope, 'env>,
+    main: PathBuf,
+    args: Vec<String>,
+) -> Arc<HttpBuild>
+where
+    B: Fn(&Request) -> MorfoResult<Artifact> + Sync,
+{
+    let build = builds.add(main.clone());
+    let mut command_line = vec![
+        "morfo".to_owned(),
+        "build".to_owned(),
+        main.to_string_lossy().into_owned(),
+    ];
+    command_line.extend(args);
+    let request = Request {
+        main,
+        cwd: builder.home.cwd.clone(),
+        args: command_line,
+        env: builder.home.env.clone(),
+    };
+
+    let running = Arc::clone(&build);
+    scope.spawn(move || {
+        let update = |change: &dyn Fn(&mut State)| {
+            let mut state = running.state.lock().unwrap_or_else(|e| e.into_inner());
+            change(&mut state);
+            running.changed.notify_all();
+        };
+        let output = |frame: Frame| {
+            let line = match frame {
+                Frame::Stdout(text) => ("stdout", text),
+                Frame::Stderr(text) => ("stderr", text),
+                Frame::Built(_) => return,
+            };
+            update(&|state| state.record(line.0, line.1.clone()));
+        };
+        let result = match builder.build(&request, &output) {
+            Ok(Ok(artifact)) => Ok((artifact.executable_path, artifact.compile_stats)),
+            Ok(Err(e)) | Err(e) => Err(e.to_string()),
+        };
+        update(&|state| state.result = Some(result.clone()));
+    });
+    build
+}
+
+/// Sends what `build` printed as server-sent events as it prints it, and its status
+/// once it is done.
+fn stream_log(stream: &mut TcpStream, build: &HttpBuild) -> io::Result<()> {
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\
+          Connection: close\r\n\r\n",
+    )?;
+    let mut sent = 0;
+    let mut state = build.state.lock().unwrap_or_else(|e| e.into_inner());
+    loop {
+        let mut events = String::new();
+        for (event, text) in &state.log[sent..] {
+            events.push_str(&format!("event: {}\n", event));
+            for line in text.trim_end_matches('\n').split('\n') {
+                events.push_str(&format!("data: {}\n", line));
+            }
+            events.push('\n');
+        }
+        sent = state.log.len();
+        let done = state.result.is_some();
+        if done {
+            events.push_str(&format!("event: done\ndata: {}\n\n", status(build, &state)));
+        }
+        // the build goes on while the events are sent
+        drop(state);
+        stream.write_all(events.as_bytes())?;
+        if done {
+            return Ok(());
+        }
+        state = build.state.lock().unwrap_or_else(|e| e.into_inner());
+        while state.log.len() == sent && state.result.is_none() {
+            state = build.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+/// A request read from a client.
+#[derive(Debug, Default)]
+struct HttpRequest {
+    method: String,
+    path: String,
+    /// The `Host` header, if sent.
+    host: Option<String>,
+    /// Whether an `Origin` header was sent.
+    origin: bool,
+    /// Whether the body is `application/json`.
+    json: bool,
+    body: Vec<u8>,
+}
+
+/// Reads the request on `stream`, or returns `None` if the body is larger than
+/// [`MAX_BODY`].
+fn read_request(stream: &TcpStream) -> io::Result<Option<HttpRequest>> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let mut request = HttpRequest {
+        method: parts.next().unwrap_or_default().to_owned(),
+        path: parts.next().unwrap_or_default().to_owned(),
+        ..HttpRequest::default()
+    };
+
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.to_ascii_lowercase().as_str() {
+            "content-length" => length = value.parse().unwrap_or(0),
+            "host" => request.host = Some(value.to_owned()),
+            "origin" => request.origin = true,
+            "content-type" => {
+                let media_type = value.split(';').next().unwrap_or_default();
+                request.json = media_type.trim().eq_ignore_ascii_case("application/json");
+            }
+            _ => (),
+        }
+    }
+    if length > MAX_BODY {
+        return Ok(None);
+    }
+    request.body = vec![0; length];
+    reader.read_exact(&mut request.body)?;
+    Ok(Some(request))
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &Value) -> io::Result<()> {
+    let body = body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+fn not_found(stream: &mut TcpStream) -> io::Result<()> {
+    respond(stream, "404 Not Found", &json!({ "error": "not found" }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, thread};
+
+    use super::*;
+    use crate::config::ConfigBuilder;
+
+    /// Sends a request to `addr`, as a client outside a browser does, and returns the
+    /// body of the answer.
+    fn request(addr: &str, method: &str, path: &str, body: &str) -> String {
+        let headers = format!("Host: {}\r\nContent-Type: application/json\r\n", addr);
+        let answer = request_with(addr, method, path, &headers, body);
+        answer.split_once("\r\n\r\n").unwrap().1.to_owned()
+    }
+
+    /// Sends a request with `headers` to `addr` and returns the whole answer.
+    fn request_with(addr: &str, method: &str, path: &str, headers: &str, body: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\n{}Content-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            headers,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut answer = String::new();
+        stream.read_to_string(&mut answer).unwrap();
+        answer
+    }
+
+    fn refusing_builder() -> Builder<impl Fn(&Request) -> MorfoResult<Artifact> + Sync> {
+        Builder {
+            build: |_: &Request| -> MorfoResult<Artifact> { unreachable!() },
+            lock: Mutex::new(()),
+            home: Request {
+                main: PathBuf::new(),
+                cwd: env::current_dir().unwrap(),
+                args: Vec::new(),
+                env: Default::default(),
+            },
+        }
+    }
+
+    #[test]
+    fn daemon_http_builds() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let main_file = dir.join("main.c");
+        fs::write(&main_file, "int main(void) { return 0; }\n").unwrap();
+        let config = ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir(dir.join(".out").to_str().unwrap())
+            .build();
+        let builder = Builder {
+            build: |request: &Request| {
+                assert_eq!(request.args[3..], ["--profile", "release"]);
+                crate::compile(&request.main, &config)
+            },
+            lock: Mutex::new(()),
+            home: Request {
+                main: PathBuf::new(),
+                cwd: env::current_dir().unwrap(),
+                args: Vec::new(),
+                env: Default::default(),
+            },
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let builds = Arc::new(Builds::default());
+
+        thread::scope(|scope| {
+            let builds = &builds;
+            let builder = &builder;
+            scope.spawn(move || {
+                for stream in listener.incoming().take(4) {
+                    handle(stream.unwrap(), builder, builds, scope).unwrap();
+                }
+            });
+
+            let body = format!(
+                r#"{{"main": "{}", "args": ["--profile", "release"]}}"#,
+                main_file.display()
+            );
+            let started: Value =
+                serde_json::from_str(&request(&addr, "POST", "/builds", &body)).unwrap();
+            assert_eq!(started["id"], 1);
+
+            let log = request(&addr, "GET", "/builds/1/log", "");
+            assert!(log.contains("event: done\n"));
+            let status: Value =
+                serde_json::from_str(&request(&addr, "GET", "/builds/1", "")).unwrap();
+            assert_eq!(status["status"], "succeeded");
+            assert_eq!(
+                status["executable_path"],
+                dir.join(".out/main").to_str().unwrap()
+            );
+            assert!(request(&addr, "GET", "/builds/2", "").contains("not found"));
+        });
+    }
+
+    #[test]
+    fn daemon_http_refuses_web_pages() {
+        let builder = refusing_builder();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let builds = Arc::new(Builds::default());
+        let body = r#"{"main": "main.c", "args": []}"#;
+        let host = format!("Host: {}\r\n", addr);
+        let json = "Content-Type: application/json\r\n";
+        let refused = [
+            (
+                format!("{}{}Origin: http://example.com\r\n", host, json),
+                "403",
+            ),
+            (format!("Host: example.com\r\n{}", json), "403"),
+            (json.to_owned(), "403"),
+            (format!("{}Content-Type: text/plain\r\n", host), "415"),
+        ];
+
+        thread::scope(|scope| {
+            let builds = &builds;
+            let builder = &builder;
+            let count = refused.len();
+            scope.spawn(move || {
+                for stream in listener.incoming().take(count) {
+                    handle(stream.unwrap(), builder, builds, scope).unwrap();
+                }
+            });
+
+            for (headers, status) in &refused {
+                let answer = request_with(&addr, "POST", "/builds", headers, body);
+                assert!(
+                    answer.starts_with(&format!("HTTP/1.1 {}", status)),
+                    "{}",
+                    answer
+                );
+            }
+        });
+        assert!(builds.get(1).is_none());
+    }
+
+    #[test]
+    fn daemon_http_build_args() {
+        let allowed = [
+            "--profile",
+            "release",
+            "--message-format=short",
+            "-DX=1",
+            "-D",
+            "Y",
+            "-k",
+        ];
+        assert_eq!(check_args(&allowed.map(String::from)), Ok(()));
+        for (args, error) in [
+            (
+                &["--config", "evil.toml"][..],
+                "`--config` is not allowed over HTTP",
+            ),
+            (&["--set", "cc=sh"], "`--set` is not allowed over HTTP"),
+            (
+                &["--cflags=-fplugin=evil.so"],
+                "`--cflags` is not allowed over HTTP",
+            ),
+            (
+                &["--in-container", "evil"],
+                "`--in-container` is not allowed over HTTP",
+            ),
+            (&["--target", "--config"], "`--target` needs a value"),
+            (&["--unity=yes"], "`--unity` takes no value"),
+            (&["main.c"], "`main.c` is not allowed over HTTP"),
+        ] {
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            assert_eq!(check_args(&args), Err(error.to_owned()));
+        }
+    }
+
+    #[test]
+    fn daemon_http_limits_memory() {
+        let builds = Builds::default();
+        for _ in 0..=MAX_BUILDS {
+            builds.add(PathBuf::from("main.c"));
+        }
+        assert!(builds.get(1).is_none());
+        assert!(builds.get(MAX_BUILDS + 1).is_some());
+
+        let mut state = State::default();
+        let line = "x".repeat(1024) + "\n";
+        for _ in 0..2 * MAX_LOG / line.len() {
+            state.record("stdout", line.clone());
+        }
+        assert!(state.log.len() <= MAX_LOG / line.len() + 1);
+        assert_eq!(
+            state.log.last().unwrap(),
+            &(
+                "stderr",
+                "morfo: the rest of the log is left out\n".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn daemon_http_body_too_large() {
+        let builder = refusing_builder();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let builds = Arc::new(Builds::default());
+
+        thread::scope(|scope| {
+            let builds = &builds;
+            let builder = &builder;
+            scope.spawn(move || {
+                let (stream, _) = listener.accept().unwrap();
+                handle(stream, builder, builds, scope).unwrap();
+            });
+
+            // the body is never sent, so only its announced length can be refused
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(
+                stream,
+                "POST /builds HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+                MAX_BODY + 1
+            )
+            .unwrap();
+            let mut answer = String::new();
+            stream.read_to_string(&mut answer).unwrap();
+            assert!(answer.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+        });
+    }
+}
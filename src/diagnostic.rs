@@ -0,0 +1,373 @@
+//! Diagnostics reported by compilers and analyzers.
+//!
+//! Every tool morfo drives reports problems in its own format. They are all parsed into
+//! [`Diagnostic`]s so they can be merged, de-duplicated and printed the same way.
+//!
+//! With `--message-format short`, the diagnostics of the compiler are not passed on as
+//! it prints them, but parsed, and printed strictly as `file:line:col: severity: message`
+//! lines, each only once in a build, so that VS Code tasks, the quickfix list of Vim and
+//! Emacs' compilation-mode can jump to them. The lines that are not diagnostics, such as
+//! source excerpts and the errors of the linker, are left out. With `--message-format
+//! sarif`, they are printed as usual, and written as a SARIF log once done, see
+//! [`sarif`](crate::sarif).
+//!
+//! On GitHub Actions, where `GITHUB_ACTIONS` is `true`, or with `--annotations github`,
+//! every diagnostic is also printed on stdout as a workflow command, like `::error
+//! file=main.c,line=3,col=5::message`, so that it shows inline on the pull request.
+//! They are left out of a SARIF log, whose upload annotates the pull request already.
+//!
+//! [`Diagnostic`]: struct.Diagnostic.html
+
+use std::{
+    collections::BTreeSet,
+    fmt,
+    path::{Component, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+};
+
+use regex::Regex;
+
+/// How serious a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl FromStr for Severity {
+    type Err = ();
+
+    /// Parses the severities used by GCC, Clang, clang-tidy and cppcheck.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" | "fatal error" => Ok(Severity::Error),
+            "warning" | "style" | "performance" | "portability" => Ok(Severity::Warning),
+            "note" | "information" => Ok(Severity::Note),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// How the diagnostics of the compiler and analyzers are printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MessageFormat {
+    /// As the tool prints them, with source excerpts and fix-its.
+    #[default]
+    Human,
+    /// One `file:line:col: severity: message` line per diagnostic, for editors.
+    Short,
+    /// As the tool prints them, and as a SARIF log on stdout once done.
+    Sarif,
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(MessageFormat::Human),
+            "short" => Ok(MessageFormat::Short),
+            "sarif" => Ok(MessageFormat::Sarif),
+            _ => Err(format!(
+                "unknown message format `{}`, expected `human`, `short` or `sarif`",
+                s
+            )),
+        }
+    }
+}
+
+/// Where diagnostics are also printed as annotations, set with `--annotations`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Annotations {
+    /// As the workflow commands of GitHub Actions.
+    Github,
+}
+
+impl FromStr for Annotations {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "github" => Ok(Annotations::Github),
+            _ => Err(format!("unknown annotations `{}`, expected `github`", s)),
+        }
+    }
+}
+
+/// A problem found in a source file.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    pub line: u32,
+    pub column: u32,
+    pub severity: Severity,
+    pub message: String,
+    /// The name of the check or warning option that produced the diagnostic, if known.
+    pub check: Option<String>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}: {}",
+            self.file.display(),
+            self.line,
+            self.column,
+            self.severity,
+            self.message
+        )?;
+        if let Some(check) = &self.check {
+            write!(f, " [{}]", check)?;
+        }
+        Ok(())
+    }
+}
+
+impl Diagnostic {
+    /// Returns the diagnostic as `file:line:col: severity: message`, without the check,
+    /// which is the line editors know how to jump from.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::diagnostic::parse;
+    ///
+    /// let diagnostics = parse("main.c:3:5: warning: unused variable 'x' [-Wunused-variable]");
+    /// assert_eq!(diagnostics[0].short(), "main.c:3:5: warning: unused variable 'x'");
+    /// ```
+    pub fn short(&self) -> String {
+        format!(
+            "{}:{}:{}: {}: {}",
+            self.file.display(),
+            self.line,
+            self.column,
+            self.severity,
+            self.message
+        )
+    }
+
+    /// Returns the diagnostic as a GitHub Actions workflow command, which annotates the
+    /// line on the pull request.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::diagnostic::parse;
+    ///
+    /// let diagnostics = parse("main.c:3:5: warning: 100% unused [-Wunused-variable]");
+    /// assert_eq!(
+    ///     diagnostics[0].github_annotation(),
+    ///     "::warning file=main.c,line=3,col=5,title=-Wunused-variable::100%25 unused"
+    /// );
+    /// ```
+    pub fn github_annotation(&self) -> String {
+        let command = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "notice",
+        };
+        let mut properties = vec![
+            format!("file={}", escape_property(&self.file.to_string_lossy())),
+            format!("line={}", self.line),
+        ];
+        if self.column > 0 {
+            properties.push(format!("col={}", self.column));
+        }
+        if let Some(check) = &self.check {
+            properties.push(format!("title={}", escape_property(check)));
+        }
+        format!(
+            "::{} {}::{}",
+            command,
+            properties.join(","),
+            escape_data(&self.message)
+        )
+    }
+}
+
+/// Escapes the message of a workflow command.
+fn escape_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a property of a workflow command, which also ends at `,` and `:`.
+fn escape_property(property: &str) -> String {
+    escape_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+/// Parses `file:line:col: severity: message [check]` lines, as printed by GCC, Clang and
+/// clang-tidy. Lines that are not diagnostics, such as source excerpts, are skipped.
+///
+/// # Examples
+///
+/// ```
+/// use morfo::diagnostic::{parse, Severity};
+///
+/// let diagnostics = parse("main.c:3:5: warning: unused variable 'x' [-Wunused-variable]");
+/// assert_eq!(diagnostics[0].line, 3);
+/// assert_eq!(diagnostics[0].severity, Severity::Warning);
+/// assert_eq!(diagnostics[0].check.as_deref(), Some("-Wunused-variable"));
+/// ```
+pub fn parse(output: &str) -> Vec<Diagnostic> {
+    let re = Regex::new(
+        r"^(.+?):(\d+):(?:(\d+):)? (fatal error|error|warning|note|style|performance|portability|information): (.*?)(?: \[([^\]]+)\])?$",
+    )
+    .unwrap();
+
+    output
+        .lines()
+        .filter_map(|line| {
+            let cap = re.captures(line.trim_end())?;
+            Some(Diagnostic {
+                file: PathBuf::from(&cap[1]),
+                line: cap[2].parse().ok()?,
+                column: cap
+                    .get(3)
+                    .and_then(|col| col.as_str().parse().ok())
+                    .unwrap_or(0),
+                severity: cap[4].parse().ok()?,
+                message: cap[5].to_owned(),
+                check: cap.get(6).map(|check| check.as_str().to_owned()),
+            })
+        })
+        .collect()
+}
+
+/// Sorts `diagnostics` by location and removes exact duplicates, which appear when a header
+/// is analyzed once per translation unit that includes it.
+pub fn dedup(diagnostics: &mut Vec<Diagnostic>) {
+    diagnostics.sort();
+    diagnostics.dedup();
+}
+
+/// The diagnostics of the compiler reported so far, so that those of a header are
+/// reported once however many sources include it.
+static REPORTED: Mutex<BTreeSet<Diagnostic>> = Mutex::new(BTreeSet::new());
+
+/// Records the diagnostics of `output`, printing those not reported before short to
+/// stderr, sorted by location, if `message_format` is short, and as `annotations` on
+/// stdout.
+pub(crate) fn report(
+    output: &str,
+    message_format: MessageFormat,
+    annotations: Option<Annotations>,
+) {
+    let mut diagnostics = parse(output);
+    // the compiler names a header `./util.h` or `util.h` depending on how it was included
+    for diagnostic in &mut diagnostics {
+        diagnostic.file = diagnostic
+            .file
+            .components()
+            .filter(|component| *component != Component::CurDir)
+            .collect();
+    }
+    dedup(&mut diagnostics);
+    let mut reported = REPORTED.lock().unwrap_or_else(|e| e.into_inner());
+    for diagnostic in diagnostics {
+        if reported.contains(&diagnostic) {
+            continue;
+        }
+        if message_format == MessageFormat::Short {
+            eprintln!("{}", diagnostic.short());
+        }
+        if annotations == Some(Annotations::Github) && message_format != MessageFormat::Sarif {
+            println!("{}", diagnostic.github_annotation());
+        }
+        reported.insert(diagnostic);
+    }
+}
+
+/// Forgets the diagnostics reported so far, so that the next build reports them again.
+pub(crate) fn forget_reported() {
+    REPORTED.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+/// Returns the diagnostics the compiler reported in this process, sorted by location,
+/// when they are printed short or as SARIF.
+pub fn reported() -> Vec<Diagnostic> {
+    let reported = REPORTED.lock().unwrap_or_else(|e| e.into_inner());
+    reported.iter().cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostic_parse_clang_tidy() {
+        let output = r#"
+2 warnings generated.
+/src/main.c:4:9: warning: Value stored to 'x' is never read [clang-analyzer-deadcode.DeadStores]
+    4 |     int x = 1;
+      |         ^
+/src/util.h:1:1: note: in file included from here
+"#;
+        let diagnostics = parse(output);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0],
+            Diagnostic {
+                file: PathBuf::from("/src/main.c"),
+                line: 4,
+                column: 9,
+                severity: Severity::Warning,
+                message: "Value stored to 'x' is never read".to_owned(),
+                check: Some("clang-analyzer-deadcode.DeadStores".to_owned()),
+            }
+        );
+        assert_eq!(diagnostics[1].severity, Severity::Note);
+        assert_eq!(diagnostics[1].check, None);
+    }
+
+    #[test]
+    fn diagnostic_dedup() {
+        let mut diagnostics =
+            parse("b.c:2:1: error: oops\na.c:1:1: warning: hmm\nb.c:2:1: error: oops\n");
+        dedup(&mut diagnostics);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].to_string(), "a.c:1:1: warning: hmm");
+    }
+
+    #[test]
+    fn diagnostic_message_format() {
+        assert_eq!("short".parse(), Ok(MessageFormat::Short));
+        assert_eq!("human".parse(), Ok(MessageFormat::Human));
+        assert!("json".parse::<MessageFormat>().is_err());
+
+        let diagnostics = parse("util.h:2:7: error: expected ';' [-Werror]\nutil.h:9: note: here");
+        let lines: Vec<_> = diagnostics.iter().map(Diagnostic::short).collect();
+        assert_eq!(
+            lines,
+            ["util.h:2:7: error: expected ';'", "util.h:9:0: note: here"]
+        );
+    }
+
+    #[test]
+    fn diagnostic_github_annotation() {
+        assert_eq!("github".parse(), Ok(Annotations::Github));
+        assert!("gitlab".parse::<Annotations>().is_err());
+
+        let diagnostics = parse("a,b:c.h:9: note: in expansion of macro 'MAX'");
+        assert_eq!(
+            diagnostics[0].github_annotation(),
+            "::notice file=a%2Cb%3Ac.h,line=9::in expansion of macro 'MAX'"
+        );
+    }
+}
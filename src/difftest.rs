@@ -0,0 +1,251 @@
+//! Differential testing of a program built two ways.
+//!
+//! `morfo difftest main.c --against cc=clang` builds the program with the config, and
+//! again with the settings given with `--against` changed, then runs both on the same
+//! inputs and reports every input on which they printed something else or exited
+//! otherwise. A program whose output depends on the compiler or the optimization level
+//! usually has undefined behavior somewhere. The settings that can be changed are:
+//!
+//! | Setting     | Meaning                                          |
+//! |-------------|--------------------------------------------------|
+//! | `cc`        | The compiler                                     |
+//! | `opt_level` | The optimization level, e.g. `0` or `3`          |
+//! | `cflags`    | Flags added after the others, e.g. `-fwrapv`     |
+//! | `profile`   | A profile declared in the config or built in     |
+//! | `target`    | A target declared in the config or built in      |
+//!
+//! The inputs are the `<name>.in` files of the directory given with `--inputs`, each fed
+//! to the program as its stdin, or no input at all. Each build puts its artifacts in a
+//! subdirectory of the build directory, `difftest/base` or `difftest/against`.
+
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    time::Duration,
+};
+
+use crate::{
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    snapshot,
+};
+
+/// How a run of the program ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// The program exited with this code.
+    Code(i32),
+    /// The program was killed by a signal.
+    Signal,
+    /// The program was killed after running for longer than the time limit.
+    TimedOut,
+}
+
+impl From<Option<ExitStatus>> for Exit {
+    fn from(status: Option<ExitStatus>) -> Self {
+        match status {
+            None => Exit::TimedOut,
+            Some(status) => status.code().map_or(Exit::Signal, Exit::Code),
+        }
+    }
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Exit::Code(code) => write!(f, "exit code {}", code),
+            Exit::Signal => write!(f, "killed by a signal"),
+            Exit::TimedOut => write!(f, "timed out"),
+        }
+    }
+}
+
+/// What one build of the program did on an input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    /// What the program wrote to its stdout.
+    pub stdout: Vec<u8>,
+    /// How the program ended.
+    pub exit: Exit,
+}
+
+/// What both builds of the program did on an input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comparison {
+    /// The file fed to the program as its stdin, if any.
+    pub input: Option<PathBuf>,
+    /// The run of the program built with the config.
+    pub base: Run,
+    /// The run of the program built with the settings changed.
+    pub against: Run,
+}
+
+impl Comparison {
+    /// Returns whether the builds printed something else or exited otherwise.
+    pub fn diverges(&self) -> bool {
+        self.base != self.against
+    }
+
+    /// Returns the lines of the output of the base build missing from the other marked
+    /// with `-`, and the lines only the other printed marked with `+`.
+    pub fn stdout_diff(&self) -> String {
+        snapshot::diff(
+            &String::from_utf8_lossy(&self.base.stdout),
+            &String::from_utf8_lossy(&self.against.stdout),
+        )
+    }
+}
+
+/// Returns `config` with the setting written as `key=value` changed.
+///
+/// # Errors
+///
+/// [`MorfoError::InvlidConfig`] if the setting is not `key=value` or cannot be changed,
+/// and the errors of [`Config::for_profile`] and [`Config::for_target`].
+///
+/// # Examples
+///
+/// ```
+/// use morfo::{config::ConfigBuilder, difftest::with_setting};
+///
+/// let config = ConfigBuilder::default().set_cc("gcc").build();
+/// let config = with_setting(config, "opt_level=3").unwrap();
+/// assert_eq!(config.get_opt_level(), Some("3".to_owned()));
+/// assert!(with_setting(config, "linker=lld").is_err());
+/// ```
+pub fn with_setting(config: Config, setting: &str) -> MorfoResult<Config> {
+    let Some((key, value)) = setting.split_once('=') else {
+        return Err(MorfoError::InvlidConfig(format!(
+            "--against `{}`: expected `key=value`",
+            setting
+        )));
+    };
+    match key.trim() {
+        "cc" => Ok(config.with_cc(value)),
+        "opt_level" => Ok(config.with_opt_level(value)),
+        "cflags" => Ok(config.with_cflags(value)),
+        "profile" => config.for_profile(value),
+        "target" => config.for_target(value),
+        key => Err(MorfoError::InvlidConfig(format!(
+            "--against `{}`: `{}` cannot be changed, only `cc`, `opt_level`, `cflags`, \
+             `profile` and `target`",
+            setting, key
+        ))),
+    }
+}
+
+/// Builds `main_file` with `base` and with `against`, runs both on every input in
+/// `inputs`, or once with no input, killing a run after `time_limit`, and returns what
+/// they did in the order of the inputs.
+///
+/// # Errors
+///
+/// If either build fails or a program cannot be run. A divergence is not an error, but
+/// a [`Comparison`].
+pub fn difftest(
+    main_file: &Path,
+    base: &Config,
+    against: &Config,
+    inputs: Option<&Path>,
+    time_limit: Duration,
+) -> MorfoResult<Vec<Comparison>> {
+    let inputs: Vec<Option<PathBuf>> = match inputs {
+        Some(dir) => {
+            let entries =
+                fs::read_dir(dir).map_err(|_| MorfoError::FileNotFound(dir.to_path_buf()))?;
+            let mut inputs = Vec::new();
+            for entry in entries {
+                let input = entry?.path();
+                if input.extension().is_some_and(|ext| ext == "in") {
+                    inputs.push(Some(input));
+                }
+            }
+            if inputs.is_empty() {
+                return Err(MorfoError::FileNotFound(dir.join("*.in")));
+            }
+            inputs.sort();
+            inputs
+        }
+        None => vec![None],
+    };
+
+    let base = base.clone().with_batch_dir(Path::new("difftest/base"));
+    let against = against
+        .clone()
+        .with_batch_dir(Path::new("difftest/against"));
+    let base_executable = crate::compile(main_file, &base)?.executable_path;
+    let against_executable = crate::compile(main_file, &against)?.executable_path;
+    let run = |executable: &Path, config: &Config, input: Option<&Path>| -> MorfoResult<Run> {
+        let captured = crate::capture_output(executable, config, input, Some(time_limit))?;
+        Ok(Run {
+            stdout: captured.stdout,
+            exit: captured.status.into(),
+        })
+    };
+    inputs
+        .into_iter()
+        .map(|input| {
+            Ok(Comparison {
+                base: run(&base_executable, &base, input.as_deref())?,
+                against: run(&against_executable, &against, input.as_deref())?,
+                input,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigBuilder;
+
+    #[test]
+    fn difftest_reports_divergence() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(
+            dir.join("scale.c"),
+            "#include <stdio.h>\n\
+             #ifndef FACTOR\n\
+             #define FACTOR 1\n\
+             #endif\n\
+             int main(void) {\n\
+                 long n;\n\
+                 scanf(\"%ld\", &n);\n\
+                 printf(\"%ld\\n\", n * FACTOR);\n\
+                 return n * FACTOR > 5;\n\
+             }\n",
+        )
+        .unwrap();
+        let inputs = dir.join("inputs");
+        fs::create_dir(&inputs).unwrap();
+        fs::write(inputs.join("1.in"), "0").unwrap();
+        fs::write(inputs.join("2.in"), "2").unwrap();
+        fs::write(inputs.join("3.in"), "3").unwrap();
+        let base = ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir(dir.join(".out").to_str().unwrap())
+            .build();
+        let against = with_setting(base.clone(), "cflags=-DFACTOR=2").unwrap();
+
+        let comparisons = difftest(
+            &dir.join("scale.c"),
+            &base,
+            &against,
+            Some(&inputs),
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        let diverging: Vec<_> = comparisons
+            .iter()
+            .map(|comparison| comparison.diverges())
+            .collect();
+        assert_eq!(diverging, [false, true, true]);
+        assert_eq!(comparisons[1].stdout_diff(), "-2\n+4\n");
+        assert_eq!(comparisons[2].base.exit, Exit::Code(0));
+        assert_eq!(comparisons[2].against.exit, Exit::Code(1));
+        assert!(dir.join(".out/difftest/base/scale").is_file());
+        assert!(dir.join(".out/difftest/against/scale").is_file());
+    }
+}
@@ -0,0 +1,549 @@
+//! Distributed compilation (experimental).
+//!
+//! With hosts in the `[distributed]` section, morfo compiles the translation units in
+//! parallel on those hosts over SSH and links locally, the way distcc does. Each source
+//! is preprocessed locally, so the hosts only need a compatible compiler, not the
+//! project or its headers. The preprocessed source is piped to the host and the object
+//! file is read back from the output of the SSH command.
+//!
+//! `compile_timeout` applies to every unit compiled on a host as it does locally, counting
+//! the time spent reaching the host.
+//!
+//! A host that cannot be reached is dropped for the rest of the build, and any
+//! translation units left over are compiled locally. Builds that read or write files
+//! next to the objects (`split_debug` and profile-guided optimization) are always
+//! compiled locally, as are the Fortran sources and the units of C++ modules.
+
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+use colored::Colorize;
+
+use crate::{
+    cache::{Lookup, ObjectCache},
+    compile_object, compile_only_flags,
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    fortran, interrupt, keep_going, modules, object_flags, source_compiler_command,
+    toolchain::{CompilerFamily, Language},
+    utils, warnings,
+};
+
+/// The exit status of `ssh` when the connection fails.
+const SSH_FAILURE: i32 = 255;
+
+/// The hosts to compile on, declared in the `[distributed]` section of the config.
+///
+/// Each host is an SSH destination such as `build1` or `alice@build2`, and runs up to
+/// `jobs` compilations at a time (4 by default). `cc` is the compiler on the hosts,
+/// which defaults to the local one, and `ssh` the command used to reach them.
+///
+/// # Examples
+///
+/// ```toml
+/// [distributed]
+/// hosts = ["build1", "alice@build2"]
+/// jobs = 8
+/// ssh = "ssh -o BatchMode=yes"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize)]
+pub struct Distributed {
+    hosts: Option<Vec<String>>,
+    jobs: Option<usize>,
+    cc: Option<String>,
+    ssh: Option<String>,
+}
+
+impl Distributed {
+    /// Returns the hosts to compile on.
+    pub fn get_hosts(&self) -> Vec<String> {
+        self.hosts.clone().unwrap_or_default()
+    }
+
+    /// Returns how many compilations each host runs at a time.
+    pub fn get_jobs(&self) -> usize {
+        self.jobs.unwrap_or(4).max(1)
+    }
+
+    /// Returns the command that runs a command on a host, split into words.
+    pub fn get_ssh(&self) -> Vec<String> {
+        self.ssh
+            .as_deref()
+            .unwrap_or("ssh")
+            .split_whitespace()
+            .map(str::to_owned)
+            .collect()
+    }
+}
+
+/// Returns whether the objects of the build can be compiled on other hosts.
+pub(crate) fn enabled(config: &Config) -> bool {
+    config
+        .get_distributed()
+        .is_some_and(|distributed| !distributed.get_hosts().is_empty())
+        && config.get_family() != CompilerFamily::Msvc
+        && !config.get_split_debug()
+        && config.get_pgo_phase().is_none()
+}
+
+/// A translation unit to compile.
+struct Job<'a> {
+    source: &'a Path,
+    object: &'a Path,
+    /// The key to store the object under in the cache, once compiled.
+    key: Option<String>,
+}
+
+/// Why a host did not compile a translation unit.
+enum RemoteError {
+    /// The host could not be reached, so the unit should be compiled elsewhere.
+    Unreachable(String),
+    /// The unit does not compile.
+    Failed(MorfoError),
+}
+
+/// Compiles each source into its object across the configured hosts, copying objects
+/// from `cache` when they are cached.
+///
+/// # Errors
+///
+/// If a source does not compile, or an object cannot be written.
+pub(crate) fn compile_objects(
+    jobs: &[(PathBuf, PathBuf)],
+    config: &Config,
+    mut cache: Option<&mut ObjectCache>,
+) -> MorfoResult<()> {
+    let distributed = config.get_distributed().unwrap_or_default();
+
+    // cached objects are copied first, so only misses are sent to the hosts
+    let mut pending = VecDeque::new();
+    let mut local = Vec::new();
+    for (source, object) in jobs {
+        let key = match cache.as_deref_mut() {
+            Some(cache) => match cache.lookup(source, object, config) {
+                Lookup::Hit => continue,
+                Lookup::Miss(key) => Some(key),
+                Lookup::Uncacheable => None,
+            },
+            None => None,
+        };
+        let job = Job {
+            source,
+            object,
+            key,
+        };
+        let modules = modules::uses_modules(&object_flags(source, object, config));
+        if fortran::is_fortran(source) || modules {
+            local.push(job);
+        } else {
+            pending.push_back(job);
+        }
+    }
+
+    let queue = Mutex::new(pending);
+    let compiled = Mutex::new(Vec::new());
+    let unreachable = Mutex::new(Vec::new());
+    let failure = Mutex::new(None);
+    let failed = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for host in distributed.get_hosts() {
+            for _ in 0..distributed.get_jobs() {
+                let host = host.clone();
+                let (distributed, queue, compiled, unreachable, failure, failed) = (
+                    &distributed,
+                    &queue,
+                    &compiled,
+                    &unreachable,
+                    &failure,
+                    &failed,
+                );
+                scope.spawn(move || loop {
+                    if failure.lock().unwrap().is_some()
+                        || unreachable.lock().unwrap().contains(&host)
+                    {
+                        return;
+                    }
+                    let Some(job) = queue.lock().unwrap().pop_front() else {
+                        return;
+                    };
+                    match compile_remote(&host, distributed, &job, config) {
+                        Ok(()) => compiled.lock().unwrap().push(job),
+                        Err(RemoteError::Unreachable(reason)) => {
+                            queue.lock().unwrap().push_front(job);
+                            let mut unreachable = unreachable.lock().unwrap();
+                            if !unreachable.contains(&host) {
+                                let warning = format!(
+                                    "warning: {} unreachable, compiling its jobs elsewhere: {}",
+                                    host, reason
+                                );
+                                eprintln!("{}", warning.yellow());
+                                unreachable.push(host);
+                            }
+                            return;
+                        }
+                        Err(RemoteError::Failed(e)) => {
+                            let mut failed = failed.lock().unwrap();
+                            if let Err(e) = keep_going(Err(e), job.source, config, &mut failed) {
+                                *failure.lock().unwrap() = Some(e);
+                                return;
+                            }
+                        }
+                    }
+                });
+            }
+        }
+    });
+    if let Some(e) = failure.into_inner().unwrap() {
+        return Err(e);
+    }
+
+    // the units always compiled here, then whatever the hosts could not take
+    let mut compiled = compiled.into_inner().unwrap();
+    let mut failed = failed.into_inner().unwrap();
+    for job in local.into_iter().chain(queue.into_inner().unwrap()) {
+        let result = compile_object(job.source, job.object, config);
+        if result.is_ok() {
+            compiled.push(job);
+        } else {
+            keep_going(result, job.source, config, &mut failed)?;
+        }
+    }
+    if let Some(cache) = cache {
+        for job in compiled {
+            if let Some(key) = &job.key {
+                cache.store(key, job.object)?;
+            }
+        }
+    }
+
+    if failed.is_empty() {
+        return Ok(());
+    }
+    // in the order of the sources rather than the order the hosts finished in
+    let failed = jobs
+        .iter()
+        .map(|(source, _)| source)
+        .filter(|source| failed.contains(source))
+        .cloned()
+        .collect();
+    Err(MorfoError::CompilationFailures(failed))
+}
+
+/// Preprocesses the source of `job` locally and compiles it on `host`.
+fn compile_remote(
+    host: &str,
+    distributed: &Distributed,
+    job: &Job,
+    config: &Config,
+) -> Result<(), RemoteError> {
+    let timeout = config.get_compile_timeout();
+    let timed_out = || {
+        let _ = fs::remove_file(job.object);
+        RemoteError::Failed(MorfoError::CompileTimeout(
+            job.source.to_path_buf(),
+            timeout.unwrap_or_default(),
+        ))
+    };
+    let flags = object_flags(job.source, job.object, config);
+    let mut preprocess_cmd = source_compiler_command(job.source, config);
+    preprocess_cmd.args(&flags).arg("-E").arg(job.source);
+    let preprocessed = output(&mut preprocess_cmd, Vec::new(), None, timeout)
+        .map_err(|e| RemoteError::Failed(e.into()))?
+        .ok_or_else(timed_out)?;
+    let mut diagnostics = String::from_utf8_lossy(&preprocessed.stderr).into_owned();
+    eprint!("{}", diagnostics);
+    if !preprocessed.status.success() {
+        return Err(RemoteError::Failed(MorfoError::CompilationFailure(
+            preprocessed.status.code(),
+        )));
+    }
+
+    let language = match job.source.extension().and_then(|ext| ext.to_str()) {
+        Some("c") => "cpp-output",
+        Some("m") => "objective-c-cpp-output",
+        _ => "c++-cpp-output",
+    };
+    // include directories and the other options of the preprocessor were already used
+    // here, and the files they name do not exist on the host
+    let remote_flags: Vec<_> = compile_only_flags(&flags)
+        .iter()
+        .map(|flag| utils::shell_quote(flag))
+        .collect();
+    let script = format!(
+        "t=$(mktemp -d) && trap 'rm -rf \"$t\"' EXIT && cat > \"$t/in\" && \
+         {} {} -x {} -c \"$t/in\" -o \"$t/out.o\" >&2 && cat \"$t/out.o\"",
+        utils::shell_quote(
+            &(distributed.cc.clone())
+                .unwrap_or_else(|| config.get_compiler(Language::of(job.source)))
+        ),
+        remote_flags.join(" "),
+        language
+    );
+
+    let ssh = distributed.get_ssh();
+    let mut ssh_cmd = Command::new(&ssh[0]);
+    ssh_cmd.args(&ssh[1..]).arg(host).arg(script);
+    if config.get_env_var("VERBOSITY").as_deref() == Some("1") {
+        println!("{}", format!("{:?}", ssh_cmd).replace('\"', ""));
+    }
+    let compiled = output(&mut ssh_cmd, preprocessed.stdout, Some(job.object), timeout)
+        .map_err(|e| RemoteError::Unreachable(format!("{}: {}", ssh[0], e)))?
+        .ok_or_else(timed_out)?;
+    match compiled.status.code() {
+        Some(0) => {
+            // the compiler's warnings
+            let warnings = String::from_utf8_lossy(&compiled.stderr);
+            eprint!("{}", warnings);
+            diagnostics.push_str(&warnings);
+            fs::write(job.object, compiled.stdout)
+                .and_then(|()| warnings::record(job.object, warnings::count(&diagnostics)))
+                .map_err(|e| RemoteError::Failed(e.into()))
+        }
+        Some(SSH_FAILURE) => Err(RemoteError::Unreachable(
+            String::from_utf8_lossy(&compiled.stderr).trim().to_owned(),
+        )),
+        code => {
+            eprint!("{}", String::from_utf8_lossy(&compiled.stderr));
+            Err(RemoteError::Failed(MorfoError::CompilationFailure(code)))
+        }
+    }
+}
+
+/// Runs `cmd` with `input` on its standard input and collects its output, or returns
+/// `None` if it runs for longer than `timeout`, killed along with the processes it
+/// started. `object` is removed if morfo is interrupted meanwhile.
+/// Errors from the command itself are left on its standard error.
+fn output(
+    cmd: &mut Command,
+    input: Vec<u8>,
+    object: Option<&Path>,
+    timeout: Option<Duration>,
+) -> io::Result<Option<Output>> {
+    cmd.stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child = interrupt::spawn_group(cmd, object)?;
+    let (stdin, stdout, stderr) = (child.stdin.take(), child.stdout.take(), child.stderr.take());
+    // the input is written and the output read from other threads so that no full pipe
+    // can block the command
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            if let Some(mut stdin) = stdin {
+                let _ = stdin.write_all(&input);
+            }
+        });
+        let stdout = scope.spawn(move || read_all(stdout));
+        let stderr = scope.spawn(move || read_all(stderr));
+        let status = match timeout {
+            Some(timeout) => interrupt::wait_timeout(&mut child, timeout)?,
+            None => Some(interrupt::wait(&mut child)?),
+        };
+        let (stdout, stderr) = (
+            stdout.join().unwrap_or_default(),
+            stderr.join().unwrap_or_default(),
+        );
+        Ok(status.map(|status| Output {
+            status,
+            stdout,
+            stderr,
+        }))
+    })
+}
+
+fn read_all(pipe: Option<impl Read>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    if let Some(mut pipe) = pipe {
+        let _ = pipe.read_to_end(&mut bytes);
+    }
+    bytes
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::{compile_act, prepare};
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Writes a stand-in for `ssh` that logs the host and runs the command locally,
+    /// logging it too.
+    fn fake_ssh(dir: &Path, exit_status: Option<i32>) -> PathBuf {
+        let path = dir.join("ssh");
+        let body = match exit_status {
+            Some(status) => format!("exit {}", status),
+            None => format!(
+                "shift\necho \"$*\" >> \"{}\"\nexec sh -c \"$*\"",
+                dir.join("commands.log").display()
+            ),
+        };
+        fs::write(
+            &path,
+            format!(
+                "#!/bin/sh\necho \"$1\" >> \"{}\"\n{}\n",
+                dir.join("hosts.log").display(),
+                body
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    fn build(dir: &Path, ssh: &Path, main: &str) -> MorfoResult<()> {
+        let main_file = dir.join("main.c");
+        fs::write(&main_file, main).unwrap();
+        fs::write(dir.join("data.txt"), "hello").unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            builddir = "{0}/.out"
+            embed = ["data.txt"]
+
+            [distributed]
+            hosts = ["build1", "build2"]
+            jobs = 1
+            ssh = "{1}""#,
+            dir.display(),
+            ssh.display()
+        ))
+        .unwrap();
+        let (act, config) = prepare(&main_file, config)?;
+        compile_act(&act, &config)?;
+        Ok(())
+    }
+
+    #[test]
+    fn distributed_compile() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let ssh = fake_ssh(tmp_dir.path(), None);
+        build(
+            tmp_dir.path(),
+            &ssh,
+            "#include \"embed.h\"\nint main(void) { return (int)embed_data_txt_size - 5; }\n",
+        )
+        .unwrap();
+
+        let log = fs::read_to_string(tmp_dir.path().join("hosts.log")).unwrap();
+        assert_eq!(log.lines().count(), 2);
+        assert!(log.lines().all(|host| host == "build1" || host == "build2"));
+        assert!(tmp_dir.path().join(".out/main").exists());
+    }
+
+    #[test]
+    fn distributed_compile_without_preprocessor_flags() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let ssh = fake_ssh(dir, None);
+        for include in ["include", "sys"] {
+            fs::create_dir(dir.join(include)).unwrap();
+        }
+        fs::write(dir.join("include/answer.h"), "#define ANSWER 0\n").unwrap();
+        let main_file = dir.join("main.c");
+        fs::write(
+            &main_file,
+            "#include \"answer.h\"\nint main(void) { return ANSWER; }\n",
+        )
+        .unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            cflags = ["-I {0}/include -isystem {0}/sys"]
+            builddir = "{0}/.out"
+
+            [distributed]
+            hosts = ["build1"]
+            ssh = "{1}""#,
+            dir.display(),
+            ssh.display()
+        ))
+        .unwrap();
+        let (act, config) = prepare(&main_file, config).unwrap();
+        compile_act(&act, &config).unwrap();
+
+        let commands = fs::read_to_string(dir.join("commands.log")).unwrap();
+        assert_eq!(commands.lines().count(), 1);
+        assert!(!commands.contains("-isystem"));
+        assert!(!commands.contains(&dir.join("include").display().to_string()));
+        assert!(dir.join(".out/main").exists());
+    }
+
+    #[test]
+    fn distributed_warnings_recorded() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let ssh = fake_ssh(tmp_dir.path(), None);
+        build(
+            tmp_dir.path(),
+            &ssh,
+            "int main(void) { int shifted = 1 << 40; return shifted - shifted; }\n",
+        )
+        .unwrap();
+
+        let objects = [tmp_dir.path().join(".out/main.o")];
+        assert_eq!(warnings::total(&objects), (1, 1));
+    }
+
+    #[test]
+    fn distributed_compile_timeout() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        // a host that hangs in a process of its own, like a wedged compiler
+        let ssh = dir.join("ssh");
+        fs::write(&ssh, "#!/bin/sh\nsleep 10\n").unwrap();
+        fs::set_permissions(&ssh, fs::Permissions::from_mode(0o755)).unwrap();
+        let main_file = dir.join("main.c");
+        fs::write(&main_file, "int main(void) { return 0; }\n").unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            builddir = "{0}/.out"
+            compile_timeout = 1
+
+            [distributed]
+            hosts = ["build1"]
+            ssh = "{1}""#,
+            dir.display(),
+            ssh.display()
+        ))
+        .unwrap();
+        let (act, config) = prepare(&main_file, config).unwrap();
+
+        let start = std::time::Instant::now();
+        assert_eq!(
+            compile_act(&act, &config),
+            Err(MorfoError::CompileTimeout(
+                main_file,
+                Duration::from_secs(1)
+            ))
+        );
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(!dir.join(".out/main.o").exists());
+    }
+
+    #[test]
+    fn distributed_unreachable_hosts() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let ssh = fake_ssh(tmp_dir.path(), Some(SSH_FAILURE));
+        build(tmp_dir.path(), &ssh, "int main(void) { return 0; }\n").unwrap();
+
+        let log = fs::read_to_string(tmp_dir.path().join("hosts.log")).unwrap();
+        assert_eq!(log.lines().count(), 2);
+        assert!(tmp_dir.path().join(".out/main").exists());
+    }
+
+    #[test]
+    fn distributed_compilation_failure() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let ssh = fake_ssh(tmp_dir.path(), None);
+        assert_eq!(
+            build(tmp_dir.path(), &ssh, "int main(void) { return missing; }\n"),
+            Err(MorfoError::CompilationFailure(Some(1)))
+        );
+    }
+}
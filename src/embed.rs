@@ -0,0 +1,211 @@
+//! Embedding files into the built program.
+//!
+//! Every file matched by the `embed` option is converted into a C source defining its
+//! contents as a byte array, which is compiled and linked like any other translation unit.
+//! The declarations are collected in `embed.h`, so C code can use the data directly:
+//!
+//! ```c
+//! #include "embed.h"
+//!
+//! /* assets/shader.glsl */
+//! glShaderSource(shader, 1, (const char **)&embed_assets_shader_glsl, NULL);
+//! ```
+//!
+//! The symbol of a file is `embed_` followed by its path relative to the project
+//! directory, with every character that is not a letter or a digit replaced by `_`.
+//! Its size in bytes is in `<symbol>_size`. A NUL byte follows the contents, so text
+//! files can be used as C strings.
+//!
+//! The generated sources live in `<builddir>/embed/` and are only rewritten when the
+//! embedded file changes.
+
+use std::{
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use globset::GlobBuilder;
+
+use crate::{
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    layout, utils,
+};
+
+/// The header declaring every embedded file.
+const HEADER: &str = "embed.h";
+
+/// Returns the C symbol for the embedded file at `path`, relative to the project directory.
+///
+/// # Examples
+///
+/// ```
+/// use morfo::embed::symbol;
+/// use std::path::Path;
+///
+/// assert_eq!(symbol(Path::new("assets/shader.glsl")), "embed_assets_shader_glsl");
+/// ```
+pub fn symbol(path: &Path) -> String {
+    let name: String = path
+        .to_string_lossy()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("embed_{}", name)
+}
+
+/// Generates the sources embedding the files matched by `embed` under `project_dir`
+/// and returns them.
+///
+/// # Errors
+///
+/// If a pattern is invalid or matches no files, or a file cannot be read or converted.
+pub(crate) fn embed(config: &Config, project_dir: &Path) -> MorfoResult<Vec<PathBuf>> {
+    let patterns = config.get_embed();
+    if patterns.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let files = utils::project_files(project_dir, &config.get_build_dir());
+    let mut embedded = Vec::new();
+    for pattern in patterns {
+        let matcher = GlobBuilder::new(&pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| MorfoError::InvlidConfig(format!("embed pattern: {}", e)))?
+            .compile_matcher();
+        let matched: Vec<_> = files
+            .iter()
+            .filter_map(|file| file.strip_prefix(project_dir).ok().map(|rel| (file, rel)))
+            .filter(|(_, rel)| matcher.is_match(rel))
+            .collect();
+        if matched.is_empty() {
+            return Err(MorfoError::FileNotFound(project_dir.join(pattern)));
+        }
+        for (file, rel) in matched {
+            if !embedded.iter().any(|(_, embedded_rel)| embedded_rel == rel) {
+                embedded.push((file.clone(), rel.to_path_buf()));
+            }
+        }
+    }
+
+    let dir = layout::embed_dir(config);
+    fs::create_dir_all(&dir)?;
+
+    let mut sources = Vec::new();
+    let mut header = String::from(
+        "/* Generated by morfo from the `embed` option of the config. Do not edit. */\n\
+         #ifndef MORFO_EMBED_H\n\
+         #define MORFO_EMBED_H\n\n\
+         #include <stddef.h>\n\n",
+    );
+    for (file, rel) in embedded {
+        let symbol = symbol(&rel);
+        let source = dir.join(format!("{}.c", symbol));
+        if is_stale(&file, &source) {
+            fs::write(&source, array_source(&symbol, &fs::read(&file)?))?;
+        }
+        sources.push(source);
+
+        let _ = writeln!(header, "/* {} */", rel.display());
+        let _ = writeln!(header, "extern const unsigned char {}[];", symbol);
+        let _ = writeln!(header, "extern const size_t {}_size;\n", symbol);
+    }
+    header.push_str("#endif /* MORFO_EMBED_H */\n");
+
+    let header_path = dir.join(HEADER);
+    if fs::read_to_string(&header_path).ok().as_deref() != Some(header.as_str()) {
+        fs::write(&header_path, header)?;
+    }
+    Ok(sources)
+}
+
+fn is_stale(file: &Path, source: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    match (modified(file), modified(source)) {
+        (Some(file), Some(source)) => file > source,
+        _ => true,
+    }
+}
+
+/// Returns a C source defining `symbol` as `contents`, followed by a NUL byte.
+fn array_source(symbol: &str, contents: &[u8]) -> String {
+    let mut source = format!(
+        "#include <stddef.h>\n\nconst unsigned char {}[] = {{",
+        symbol
+    );
+    for (i, byte) in contents.iter().chain(&[0]).enumerate() {
+        if i % 12 == 0 {
+            source.push_str("\n   ");
+        }
+        let _ = write!(source, " 0x{:02x},", byte);
+    }
+    let _ = write!(
+        source,
+        "\n}};\n\nconst size_t {}_size = {};\n",
+        symbol,
+        contents.len()
+    );
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile, run, RunOptions};
+
+    #[test]
+    fn embed_array_source() {
+        assert_eq!(
+            array_source("embed_hi_txt", b"hi"),
+            "#include <stddef.h>\n\n\
+             const unsigned char embed_hi_txt[] = {\n    0x68, 0x69, 0x00,\n};\n\n\
+             const size_t embed_hi_txt_size = 2;\n"
+        );
+    }
+
+    #[test]
+    fn embed_missing_file() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let config: Config = toml::from_str(r#"embed = ["data/*.json"]"#).unwrap();
+
+        assert_eq!(
+            embed(&config, tmp_dir.path()).unwrap_err(),
+            MorfoError::FileNotFound(tmp_dir.path().join("data/*.json"))
+        );
+    }
+
+    #[test]
+    fn embed_run() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let project = tmp_dir.path();
+        fs::create_dir(project.join("data")).unwrap();
+        fs::write(project.join("data").join("greeting.txt"), "hello").unwrap();
+        fs::write(project.join("data").join("ignored.bin"), "").unwrap();
+        let main_file = project.join("main.c");
+        fs::write(
+            &main_file,
+            "#include <stdio.h>\n#include \"embed.h\"\n\
+             int main(void) { printf(\"%s %zu\", embed_data_greeting_txt, embed_data_greeting_txt_size); return 0; }\n",
+        )
+        .unwrap();
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            builddir = "{}"
+            embed = ["data/*.txt"]"#,
+            project.join(".out").display()
+        ))
+        .unwrap();
+        let artifact = compile(&main_file, &config).unwrap();
+
+        let mut out = Vec::new();
+        run(&artifact, RunOptions::new(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "hello 5");
+        assert!(!layout::embed_dir(&config)
+            .join("embed_data_ignored_bin.c")
+            .exists());
+    }
+}
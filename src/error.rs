@@ -1,6 +1,8 @@
 //! Error handling for morfo.
 
-use std::{fmt, io::ErrorKind, path::PathBuf};
+use std::{fmt, io::ErrorKind, path::PathBuf, time::Duration};
+
+use crate::symbols::Duplicate;
 
 /// A specialized [`Result`] type for Morfo operations.
 ///
@@ -10,36 +12,156 @@ pub type MorfoResult<T> = Result<T, MorfoError>;
 /// You can use the `MorfoError` type to handle errors in your code.
 #[derive(PartialEq, Debug)]
 pub enum MorfoError {
+    AmbiguousInclude(PathBuf, Vec<PathBuf>),
+    AmbiguousModule(String, Vec<PathBuf>),
+    CommandFailure(String, Option<i32>),
     CompilationFailure(Option<i32>),
+    CompilationFailures(Vec<PathBuf>),
+    CompileTimeout(PathBuf, Duration),
+    DaemonBuild(String),
+    DaemonRunning(PathBuf),
+    DuplicateSymbols(Vec<Duplicate>),
     FileNotFound(PathBuf),
     InvlidConfig(String),
     InvalidConfigExtension(String),
     InvalidUnicode,
     IoError(ErrorKind),
+    MissingCodeBlock(PathBuf, Option<usize>),
+    MissingCompiler(Vec<String>),
     MissingConfigFile,
     MissingExecutable,
     MissingHomeDirectory,
+    MissingStaticLibrary(String),
+    MissingTool(String),
+    ScriptFailure(PathBuf, String),
+    UnreadableSource(PathBuf, ErrorKind),
+    UnresolvedInclude(PathBuf, String, Vec<PathBuf>, Vec<PathBuf>),
+    UnknownProfile(String),
+    UnknownTarget(String),
+    Unsupported(String),
+    Vetoed(String),
 }
 
 impl fmt::Display for MorfoError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            MorfoError::AmbiguousInclude(header, sources) => write!(
+                f,
+                "Ambiguous include: {} may be implemented by any of {}",
+                header.display(),
+                sources
+                    .iter()
+                    .map(|source| source.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            MorfoError::AmbiguousModule(module, sources) => write!(
+                f,
+                "Ambiguous module: the interface of `{}` is declared by each of {}",
+                module,
+                sources
+                    .iter()
+                    .map(|source| source.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            MorfoError::CommandFailure(cmd, code) => match code {
+                Some(code) => write!(f, "`{}` exited with code {}", cmd, code),
+                None => write!(f, "`{}` was terminated by signal", cmd),
+            },
             MorfoError::CompilationFailure(code) => match code {
                 Some(code) => {
                     write!(f, "Compilation failure: Process exited with code {}", code)
                 }
                 None => write!(f, "Compilation failure: Process terminated by signal"),
             },
+            MorfoError::CompilationFailures(sources) => write!(
+                f,
+                "Compilation failure: {} sources failed to compile: {}",
+                sources.len(),
+                sources
+                    .iter()
+                    .map(|source| source.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            MorfoError::CompileTimeout(unit, timeout) => write!(
+                f,
+                "Compile timeout: the compiler was killed after running for {}s on {}",
+                timeout.as_secs(),
+                unit.display()
+            ),
+            MorfoError::DaemonBuild(error) => write!(f, "Daemon: {}", error),
+            MorfoError::DaemonRunning(socket) => write!(
+                f,
+                "Daemon running: a daemon already listens on {}",
+                socket.display()
+            ),
+            MorfoError::DuplicateSymbols(duplicates) => write!(
+                f,
+                "Duplicate symbols: {}",
+                duplicates
+                    .iter()
+                    .map(Duplicate::to_string)
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ),
             MorfoError::FileNotFound(path) => write!(f, "File not found: {}", path.display()),
             MorfoError::InvlidConfig(msg) => write!(f, "Invalid config: {}", msg),
             MorfoError::InvalidConfigExtension(ext) => {
                 write!(f, "The config file must be a TOML file. Found: {}.", *ext)
             }
             MorfoError::InvalidUnicode => write!(f, "Invalid unicode"),
+            MorfoError::MissingCodeBlock(markdown, block) => match block {
+                Some(block) => write!(f, "No C code block {} in {}", block, markdown.display()),
+                None => write!(f, "No C code blocks in {}", markdown.display()),
+            },
+            MorfoError::MissingCompiler(candidates) => write!(
+                f,
+                "No compiler configured and none found on the PATH. Tried: {}",
+                candidates.join(", ")
+            ),
             MorfoError::MissingConfigFile => write!(f, "Config file missing."),
             MorfoError::MissingExecutable => write!(f, "Executable file missing."),
             MorfoError::MissingHomeDirectory => write!(f, "Home directory missing"),
+            MorfoError::MissingStaticLibrary(archive) => write!(
+                f,
+                "Static library `{}` not found; install its static development package or link dynamically",
+                archive
+            ),
+            MorfoError::MissingTool(tool) => {
+                write!(f, "`{}` is not installed or not on the PATH", tool)
+            }
+            MorfoError::ScriptFailure(script, msg) => {
+                write!(f, "Build script {} failed: {}", script.display(), msg)
+            }
+            MorfoError::UnreadableSource(path, kind) => {
+                write!(f, "Cannot read {}: {}", path.display(), kind)
+            }
+            MorfoError::UnresolvedInclude(including, header, searched, suggestions) => {
+                let join = |paths: &[PathBuf]| {
+                    paths
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect::<Vec<_>>()
+                };
+                write!(
+                    f,
+                    "Unresolved include: \"{}\" in {} is not in any of {}",
+                    header,
+                    including.display(),
+                    join(searched).join(", ")
+                )?;
+                if !suggestions.is_empty() {
+                    write!(f, "; did you mean {}?", join(suggestions).join(" or "))?;
+                }
+                Ok(())
+            }
             MorfoError::IoError(kind) => write!(f, "IO error: {}", kind),
+            MorfoError::UnknownProfile(name) => write!(f, "Unknown profile: {}", name),
+            MorfoError::UnknownTarget(name) => write!(f, "Unknown target: {}", name),
+            MorfoError::Unsupported(what) => write!(f, "Unsupported: {}", what),
+            MorfoError::Vetoed(reason) => write!(f, "The build script vetoed the build: {}", reason),
         }
     }
 }
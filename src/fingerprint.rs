@@ -0,0 +1,552 @@
+//! Build fingerprints.
+//!
+//! After every build, morfo records next to each artifact what it was built from: the
+//! compiler, the full command line, and the hashes of the sources, headers and objects
+//! that went into it. A build only compiles and links the artifacts whose record no longer
+//! matches the files on disk and the current config, so changing a flag or the compiler
+//! rebuilds exactly what it affects. `morfo explain` reports which artifacts are stale
+//! and why, and `--rebuild` builds every artifact regardless.
+//!
+//! Headers are found by asking the compiler for the user headers a source includes, so
+//! system headers are not tracked. MSVC cannot list them, so the project headers morfo
+//! found while building the dependency tree are tracked instead.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    act::Act,
+    compile_flags, compiler_identity,
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    fortran, layout, libraries, link_command, lock, modules, object_command, prepare,
+    source_compiler_command, unity_command, utils,
+};
+
+/// What an artifact was built from.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Fingerprint {
+    /// The command, version and image of the compiler.
+    compiler: [String; 3],
+    /// The full command line that built the artifact.
+    command: String,
+    /// The SHA-256 of every source, by path.
+    #[serde(default)]
+    sources: BTreeMap<PathBuf, String>,
+    /// The SHA-256 of every header the sources include, by path.
+    #[serde(default)]
+    headers: BTreeMap<PathBuf, String>,
+    /// The SHA-256 of every object linked, by path.
+    #[serde(default)]
+    objects: BTreeMap<PathBuf, String>,
+}
+
+/// Why an artifact is stale.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reason {
+    /// The artifact does not exist.
+    MissingOutput,
+    /// The artifact was not built by a build that recorded its fingerprint.
+    NotRecorded,
+    /// The compiler or its version changed.
+    CompilerChanged,
+    /// The command line changed, e.g. because of new `cflags` or defines.
+    FlagsChanged,
+    /// A source changed or was removed.
+    SourceChanged(PathBuf),
+    /// A header a source includes changed or was removed.
+    HeaderChanged(PathBuf),
+    /// A different set of sources is compiled.
+    SourcesChanged,
+    /// A different set of objects is linked.
+    ObjectsChanged,
+    /// A linked object changed since the link.
+    ObjectChanged(PathBuf),
+    /// A linked object is stale itself.
+    ObjectStale(PathBuf),
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::MissingOutput => write!(f, "missing output"),
+            Reason::NotRecorded => write!(f, "no record of a previous build"),
+            Reason::CompilerChanged => write!(f, "compiler changed"),
+            Reason::FlagsChanged => write!(f, "flags changed"),
+            Reason::SourceChanged(path) => write!(f, "source {} changed", path.display()),
+            Reason::HeaderChanged(path) => write!(f, "header {} changed", path.display()),
+            Reason::SourcesChanged => write!(f, "different sources compiled"),
+            Reason::ObjectsChanged => write!(f, "different objects linked"),
+            Reason::ObjectChanged(path) => write!(f, "object {} changed", path.display()),
+            Reason::ObjectStale(path) => write!(f, "object {} is stale", path.display()),
+        }
+    }
+}
+
+/// An artifact of the build and why it is stale, if it is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Artifact {
+    /// The path of the artifact.
+    pub path: PathBuf,
+    /// Why the artifact is stale. It is fresh if there is no reason.
+    pub reasons: Vec<Reason>,
+}
+
+impl Artifact {
+    /// Returns whether the artifact is up to date.
+    pub fn is_fresh(&self) -> bool {
+        self.reasons.is_empty()
+    }
+}
+
+impl fmt::Display for Artifact {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_fresh() {
+            return write!(f, "fresh {}", self.path.display());
+        }
+        let reasons: Vec<_> = self.reasons.iter().map(Reason::to_string).collect();
+        write!(f, "stale {}: {}", self.path.display(), reasons.join(", "))
+    }
+}
+
+/// Reports for every artifact of the build of `main_file` whether it is fresh or stale
+/// and why, objects first and the executable last.
+///
+/// # Errors
+///
+/// If the project cannot be prepared, e.g. because a generator fails.
+pub fn explain(main_file: PathBuf, config: Config) -> MorfoResult<Vec<Artifact>> {
+    let _lock = lock::lock(&config)?;
+    let (act, config) = prepare(&main_file, config)?;
+    artifacts(&act, &config)
+}
+
+/// Returns every artifact of the build of `act` and why it is stale, objects first and
+/// the executable last.
+///
+/// # Errors
+///
+/// If the libraries to link cannot be found.
+pub(crate) fn artifacts(act: &Act, config: &Config) -> MorfoResult<Vec<Artifact>> {
+    let compiler = compiler_identity(config);
+    let executable = layout::executable(&act.name, config);
+
+    if config.get_unity() {
+        let expected = Expected {
+            compiler: &compiler,
+            command: utils::format_command(&unity_command(act, config)?),
+            sources: act.sources(),
+            objects: libraries::archives(config),
+        };
+        return Ok(vec![Artifact {
+            reasons: check(&executable, expected),
+            path: executable,
+        }]);
+    }
+
+    let units: BTreeMap<&Path, &Act> = act
+        .nodes()
+        .map(|node| (node.name.as_path(), node))
+        .collect();
+    let interfaces = modules::interfaces(act);
+    let mut objects_of: BTreeMap<PathBuf, usize> = BTreeMap::new();
+    let mut artifacts: Vec<Artifact> = Vec::new();
+    for (source, object) in layout::objects(act, config) {
+        let expected = Expected {
+            compiler: &compiler,
+            command: utils::format_command(&object_command(&source, &object, config)),
+            sources: vec![source.clone()],
+            objects: Vec::new(),
+        };
+        let mut reasons = check(&object, expected);
+        // a module unit also writes the compiled interface of its module, and the units
+        // importing a module are compiled again after its interface
+        if let Some(unit) = units.get(source.as_path()) {
+            let bmi = unit
+                .module
+                .as_ref()
+                .map(|module| modules::bmi(module, config));
+            if reasons.is_empty() && bmi.is_some_and(|bmi| !bmi.is_file()) {
+                reasons.push(Reason::MissingOutput);
+            }
+            for interface in unit.imports.iter().filter_map(|name| interfaces.get(name)) {
+                let Some(&i) = objects_of.get(interface) else {
+                    continue;
+                };
+                if !artifacts[i].is_fresh() {
+                    reasons.push(Reason::ObjectStale(artifacts[i].path.clone()));
+                }
+            }
+        }
+        objects_of.insert(source, artifacts.len());
+        artifacts.push(Artifact {
+            reasons,
+            path: object,
+        });
+    }
+
+    let objects: Vec<_> = artifacts
+        .iter()
+        .map(|artifact| artifact.path.clone())
+        .collect();
+    let expected = Expected {
+        compiler: &compiler,
+        command: utils::format_command(&link_command(act, &objects, config)?),
+        sources: Vec::new(),
+        // the archives linked from a file are linked into the executable like objects
+        objects: objects
+            .into_iter()
+            .chain(libraries::archives(config))
+            .collect(),
+    };
+    let mut reasons = check(&executable, expected);
+    for artifact in artifacts.iter().filter(|artifact| !artifact.is_fresh()) {
+        reasons.push(Reason::ObjectStale(artifact.path.clone()));
+    }
+    artifacts.push(Artifact {
+        path: executable,
+        reasons,
+    });
+    Ok(artifacts)
+}
+
+/// What would build an artifact now.
+struct Expected<'a> {
+    compiler: &'a [String; 3],
+    command: String,
+    sources: Vec<PathBuf>,
+    objects: Vec<PathBuf>,
+}
+
+/// Compares the recorded fingerprint of `artifact` with the files on disk and what would
+/// build it now.
+fn check(artifact: &Path, mut expected: Expected) -> Vec<Reason> {
+    if !artifact.exists() {
+        return vec![Reason::MissingOutput];
+    }
+    let Some(recorded) = read(artifact) else {
+        return vec![Reason::NotRecorded];
+    };
+
+    let mut reasons = Vec::new();
+    if &recorded.compiler != expected.compiler {
+        reasons.push(Reason::CompilerChanged);
+    }
+    expected.sources.sort();
+    expected.objects.sort();
+    let same_sources = recorded.sources.keys().eq(expected.sources.iter());
+    let same_objects = recorded.objects.keys().eq(expected.objects.iter());
+    if !same_sources {
+        reasons.push(Reason::SourcesChanged);
+    }
+    if !same_objects {
+        reasons.push(Reason::ObjectsChanged);
+    }
+    // different sources or objects change the command as well
+    if same_sources && same_objects && recorded.command != expected.command {
+        reasons.push(Reason::FlagsChanged);
+    }
+    for (path, hash) in &recorded.sources {
+        if !matches(path, hash) {
+            reasons.push(Reason::SourceChanged(path.clone()));
+        }
+    }
+    for (path, hash) in &recorded.headers {
+        if !matches(path, hash) {
+            reasons.push(Reason::HeaderChanged(path.clone()));
+        }
+    }
+    for (path, hash) in &recorded.objects {
+        if !matches(path, hash) {
+            reasons.push(Reason::ObjectChanged(path.clone()));
+        }
+    }
+    reasons
+}
+
+/// Returns whether the file at `path` still has the SHA-256 `hash`.
+fn matches(path: &Path, hash: &str) -> bool {
+    utils::hash_file(path).is_ok_and(|current| current == hash)
+}
+
+/// Records the fingerprints of the objects of `act` compiled by `jobs`, pairs of a
+/// source and its object.
+///
+/// # Errors
+///
+/// If the headers cannot be listed, a file cannot be hashed or a fingerprint cannot be
+/// written.
+pub(crate) fn record_objects(
+    act: &Act,
+    jobs: &[(PathBuf, PathBuf)],
+    config: &Config,
+) -> MorfoResult<()> {
+    let compiler = compiler_identity(config);
+    for (source, object) in jobs {
+        let found = act
+            .includes()
+            .filter(|(includer, _)| includer == source)
+            .map(|(_, header)| header.to_path_buf())
+            .collect();
+        let fingerprint = Fingerprint {
+            compiler: compiler.clone(),
+            command: utils::format_command(&object_command(source, object, config)),
+            sources: hashes([source.clone()])?,
+            headers: hashes(headers(source, found, config)?)?,
+            objects: BTreeMap::new(),
+        };
+        write(object, &fingerprint)?;
+    }
+    Ok(())
+}
+
+/// Records the fingerprint of the executable of `act`, linked from `objects` or, in a
+/// unity build, compiled from the unity source.
+///
+/// # Errors
+///
+/// If the headers cannot be listed, a file cannot be hashed or the fingerprint cannot be
+/// written.
+pub(crate) fn record_executable(
+    act: &Act,
+    objects: &[PathBuf],
+    config: &Config,
+) -> MorfoResult<()> {
+    let fingerprint = if config.get_unity() {
+        let sources = act.sources();
+        let canonical: Vec<_> = sources
+            .iter()
+            .filter_map(|source| source.canonicalize().ok())
+            .collect();
+        let mut found: Vec<PathBuf> = Vec::new();
+        for (_, header) in act.includes() {
+            if !found.iter().any(|known| known == header) {
+                found.push(header.to_path_buf());
+            }
+        }
+        // the unity source includes every source by its canonical path
+        let headers = headers(&layout::unity_source(&act.name, config), found, config)?
+            .into_iter()
+            .filter(|header| {
+                !header
+                    .canonicalize()
+                    .is_ok_and(|header| canonical.contains(&header))
+            })
+            .collect::<Vec<_>>();
+        Fingerprint {
+            compiler: compiler_identity(config),
+            command: utils::format_command(&unity_command(act, config)?),
+            sources: hashes(sources)?,
+            headers: hashes(headers)?,
+            objects: hashes(libraries::archives(config))?,
+        }
+    } else {
+        Fingerprint {
+            compiler: compiler_identity(config),
+            command: utils::format_command(&link_command(act, objects, config)?),
+            sources: BTreeMap::new(),
+            headers: BTreeMap::new(),
+            objects: hashes(objects.iter().cloned().chain(libraries::archives(config)))?,
+        }
+    };
+    write(&layout::executable(&act.name, config), &fingerprint)
+}
+
+fn hashes(paths: impl IntoIterator<Item = PathBuf>) -> MorfoResult<BTreeMap<PathBuf, String>> {
+    paths
+        .into_iter()
+        .map(|path| Ok((path.clone(), utils::hash_file(&path)?)))
+        .collect()
+}
+
+/// Returns the user headers `source` includes, as the compiler finds them, or `found`,
+/// the headers morfo found, if the compiler cannot list them.
+fn headers(source: &Path, found: Vec<PathBuf>, config: &Config) -> MorfoResult<Vec<PathBuf>> {
+    let depend_args = config
+        .get_family()
+        .depend_args(source)
+        .filter(|_| !fortran::is_fortran(source));
+    let Some(depend_args) = depend_args else {
+        return Ok(found);
+    };
+    let mut depend_cmd = source_compiler_command(source, config);
+    depend_cmd
+        .args(config.get_cflags())
+        .args(compile_flags(config))
+        .args(config.get_source_flags(source))
+        .args(depend_args);
+
+    let output = utils::run_tool(&mut depend_cmd)?;
+    if !output.status.success() {
+        return Err(MorfoError::CompilationFailure(output.status.code()));
+    }
+    let rule = String::from_utf8_lossy(&output.stdout);
+    Ok(parse_rule(&rule)
+        .into_iter()
+        .filter(|path| utils::normalize(path) != utils::normalize(source))
+        .collect())
+}
+
+/// Returns the prerequisites of a make rule such as `main.o: main.c util.h`.
+fn parse_rule(rule: &str) -> Vec<PathBuf> {
+    let rule = rule.replace("\\\n", " ").replace("\\\r\n", " ");
+    let Some((_, prerequisites)) = rule.split_once(": ") else {
+        return Vec::new();
+    };
+
+    let mut paths = Vec::new();
+    let mut path = String::new();
+    let mut chars = prerequisites.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(' ') => path.push(' '),
+                Some(next) => {
+                    path.push('\\');
+                    path.push(next);
+                }
+                None => path.push('\\'),
+            },
+            c if c.is_whitespace() => {
+                if !path.is_empty() {
+                    paths.push(PathBuf::from(std::mem::take(&mut path)));
+                }
+            }
+            c => path.push(c),
+        }
+    }
+    if !path.is_empty() {
+        paths.push(PathBuf::from(path));
+    }
+    paths
+}
+
+/// Returns the sources `artifact` was last built from, and the objects linked into it
+/// with their SHA-256, if its fingerprint was recorded.
+pub(crate) fn recorded_inputs(
+    artifact: &Path,
+) -> Option<(Vec<PathBuf>, BTreeMap<PathBuf, String>)> {
+    let fingerprint = read(artifact)?;
+    Some((
+        fingerprint.sources.into_keys().collect(),
+        fingerprint.objects,
+    ))
+}
+
+/// Returns whether the fingerprint of `artifact` was recorded, i.e. whether it was built
+/// by morfo.
+pub(crate) fn is_recorded(artifact: &Path) -> bool {
+    fingerprint_path(artifact).exists()
+}
+
+/// Returns where the fingerprint of `artifact` is recorded.
+fn fingerprint_path(artifact: &Path) -> PathBuf {
+    let mut path = artifact.as_os_str().to_owned();
+    path.push(".fingerprint");
+    PathBuf::from(path)
+}
+
+fn read(artifact: &Path) -> Option<Fingerprint> {
+    let json = fs::read_to_string(fingerprint_path(artifact)).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+fn write(artifact: &Path, fingerprint: &Fingerprint) -> MorfoResult<()> {
+    let json = serde_json::to_string_pretty(fingerprint)
+        .map_err(|e| MorfoError::InvlidConfig(e.to_string()))?;
+    fs::write(fingerprint_path(artifact), json + "\n")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile_act;
+
+    #[test]
+    fn fingerprint_parse_rule() {
+        assert_eq!(
+            parse_rule("main.o: main.c util.h \\\n  my\\ dir/log.h\n"),
+            vec![
+                PathBuf::from("main.c"),
+                PathBuf::from("util.h"),
+                PathBuf::from("my dir/log.h")
+            ]
+        );
+        assert!(parse_rule("").is_empty());
+    }
+
+    #[test]
+    fn fingerprint_headers_found_without_compiler_list() {
+        let config: Config = toml::from_str("cc = \"cl\"\nfamily = \"msvc\"").unwrap();
+        let found = vec![PathBuf::from("vec.h")];
+        assert_eq!(
+            headers(Path::new("main.c"), found.clone(), &config).unwrap(),
+            found
+        );
+    }
+
+    #[test]
+    fn fingerprint_explain() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(dir.join("util.h"), "static int util(void) { return 0; }\n").unwrap();
+        fs::write(
+            dir.join("main.c"),
+            "#include \"util.h\"\nint main(void) { return util(); }\n",
+        )
+        .unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            builddir = "{}""#,
+            dir.join(".out").display()
+        ))
+        .unwrap();
+        let main_file = dir.join("main.c");
+        let out = dir.join(".out");
+        let explain = |config: &Config| {
+            explain(main_file.clone(), config.clone())
+                .unwrap()
+                .into_iter()
+                .map(|artifact| (artifact.path, artifact.reasons))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            explain(&config),
+            vec![
+                (out.join("main.o"), vec![Reason::MissingOutput]),
+                (
+                    out.join("main"),
+                    vec![
+                        Reason::MissingOutput,
+                        Reason::ObjectStale(out.join("main.o"))
+                    ]
+                ),
+            ]
+        );
+
+        let (act, prepared) = prepare(&main_file, config.clone()).unwrap();
+        compile_act(&act, &prepared).unwrap();
+        assert!(explain(&config)
+            .iter()
+            .all(|(_, reasons)| reasons.is_empty()));
+
+        fs::write(dir.join("util.h"), "static int util(void) { return 1; }\n").unwrap();
+        let reasons = explain(&config.clone().with_define("NDEBUG", "1"));
+        assert_eq!(
+            reasons[0],
+            (
+                out.join("main.o"),
+                vec![
+                    Reason::FlagsChanged,
+                    Reason::HeaderChanged(dir.join("util.h"))
+                ]
+            )
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Sampling profiles rendered as flamegraphs.
+//!
+//! `morfo profile main.c` builds the program with frame pointers and debug information,
+//! runs it under a sampling profiler and writes two files to `<builddir>/profile/`:
+//!
+//! * `<name>.folded` holds the collapsed stacks, one line per unique stack.
+//! * `<name>.svg` is an interactive flamegraph of those stacks.
+//!
+//! Linux uses `perf record` and macOS uses `dtrace`, which must be run as root.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use inferno::{
+    collapse::{dtrace, perf, Collapse},
+    flamegraph,
+};
+
+use crate::{
+    compile_act,
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    layout, lock, prepare, utils,
+};
+
+/// Samples per second taken by the profiler. An odd rate avoids sampling in lockstep
+/// with periodic work in the program.
+const FREQUENCY: u32 = 997;
+
+/// The files written by a profiling run.
+#[derive(Debug)]
+pub struct FlameGraph {
+    /// The collapsed stacks.
+    pub folded: PathBuf,
+    /// The rendered flamegraph.
+    pub svg: PathBuf,
+}
+
+/// Builds `main_file`, profiles it running with `prog_args` and renders the result.
+///
+/// # Errors
+///
+/// If there is no supported profiler on this platform, or the build, the profiler
+/// or rendering fails.
+pub fn profile(
+    main_file: PathBuf,
+    config: Config,
+    prog_args: Vec<String>,
+) -> MorfoResult<FlameGraph> {
+    let profiler = if cfg!(target_os = "linux") {
+        Profiler::Perf
+    } else if cfg!(target_os = "macos") {
+        Profiler::Dtrace
+    } else {
+        return Err(MorfoError::Unsupported(
+            "profiling on this platform".to_owned(),
+        ));
+    };
+
+    let _lock = lock::lock(&config)?;
+    let (act, config) = prepare(&main_file, config.with_frame_pointers())?;
+    compile_act(&act, &config)?;
+
+    let executable = layout::executable(&act.name, &config);
+    let dir = layout::flamegraph_dir(&config);
+    fs::create_dir_all(&dir)?;
+
+    let name = utils::file_name(&main_file);
+    let folded = dir.join(format!("{}.folded", name));
+    profiler.record(&executable, &prog_args, &dir, &folded)?;
+
+    let svg = dir.join(format!("{}.svg", name));
+    render(&folded, &svg, &name)?;
+    Ok(FlameGraph { folded, svg })
+}
+
+enum Profiler {
+    Perf,
+    Dtrace,
+}
+
+impl Profiler {
+    /// Runs `executable` under the profiler and writes the collapsed stacks to `folded`.
+    /// Raw profiler output is kept in `dir`.
+    fn record(
+        &self,
+        executable: &Path,
+        prog_args: &[String],
+        dir: &Path,
+        folded: &Path,
+    ) -> MorfoResult<()> {
+        match self {
+            Profiler::Perf => {
+                let data = dir.join("perf.data");
+                let mut record_cmd = Command::new("perf");
+                record_cmd
+                    .args(["record", "-g", "-F", &FREQUENCY.to_string(), "-o"])
+                    .arg(&data)
+                    .arg("--")
+                    .arg(executable)
+                    .args(prog_args);
+                let status = utils::run_tool(inherit_stdio(&mut record_cmd))?.status;
+                // perf exits with the program's status, so only a missing profile is fatal
+                if !data.exists() {
+                    return Err(MorfoError::CommandFailure(
+                        "perf record".to_owned(),
+                        status.code(),
+                    ));
+                }
+
+                let mut script_cmd = Command::new("perf");
+                script_cmd.arg("script").arg("-i").arg(&data);
+                let output = utils::run_tool(&mut script_cmd)?;
+                if !output.status.success() {
+                    return Err(MorfoError::CommandFailure(
+                        "perf script".to_owned(),
+                        output.status.code(),
+                    ));
+                }
+                perf::Folder::default()
+                    .collapse(&output.stdout[..], BufWriter::new(File::create(folded)?))?;
+            }
+            Profiler::Dtrace => {
+                let stacks = dir.join("dtrace.stacks");
+                let program = std::iter::once(executable.to_string_lossy().into_owned())
+                    .chain(prog_args.iter().cloned())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let mut record_cmd = Command::new("dtrace");
+                record_cmd
+                    .args(["-x", "ustackframes=100", "-n"])
+                    .arg(format!(
+                        "profile-{} /pid == $target/ {{ @[ustack()] = count(); }}",
+                        FREQUENCY
+                    ))
+                    .arg("-o")
+                    .arg(&stacks)
+                    .arg("-c")
+                    .arg(program);
+                let output = utils::run_tool(inherit_stdio(&mut record_cmd))?;
+                if !output.status.success() {
+                    return Err(MorfoError::CommandFailure(
+                        "dtrace".to_owned(),
+                        output.status.code(),
+                    ));
+                }
+                dtrace::Folder::default().collapse(
+                    BufReader::new(File::open(&stacks)?),
+                    BufWriter::new(File::create(folded)?),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lets the profiled program use the terminal like it would when run directly.
+fn inherit_stdio(cmd: &mut Command) -> &mut Command {
+    cmd.stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+}
+
+/// Renders the collapsed stacks in `folded` as a flamegraph titled `title`.
+fn render(folded: &Path, svg: &Path, title: &str) -> MorfoResult<()> {
+    let mut options = flamegraph::Options::default();
+    options.title = title.to_owned();
+    flamegraph::from_reader(
+        &mut options,
+        BufReader::new(File::open(folded)?),
+        BufWriter::new(File::create(svg)?),
+    )
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flamegraph_render() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let folded = tmp_dir.path().join("main.folded");
+        let svg = tmp_dir.path().join("main.svg");
+        fs::write(&folded, "main;work;spin 90\nmain;setup 10\n").unwrap();
+
+        render(&folded, &svg, "main").unwrap();
+
+        let svg = fs::read_to_string(svg).unwrap();
+        assert!(svg.starts_with("<?xml"));
+        assert!(svg.contains("spin"));
+    }
+}
@@ -0,0 +1,291 @@
+//! Flashing firmware to embedded boards.
+//!
+//! `morfo flash main.c` builds the main file, usually for a bare-metal target such as one
+//! whose `cc` is `arm-none-eabi-gcc`, and writes the firmware to the board declared in the
+//! `[flash]` section with probe-rs or OpenOCD, then resets the board so that it runs.
+//! With `--monitor`, morfo then prints what the board sends on its serial port until it
+//! is interrupted with Ctrl+C.
+//!
+//! The executable is passed to the flashing tool as it is built, so the target should
+//! link an ELF file with the linker script of the chip, e.g. through its `cflags`.
+
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::{
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    utils,
+};
+
+/// The tool writing the firmware to the board.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FlashTool {
+    /// `probe-rs`, given the name of the chip, such as `STM32F401RETx`.
+    #[default]
+    ProbeRs,
+    /// `openocd`, given the config files of the chip, such as `target/stm32f4x.cfg`, and
+    /// of the debug probe, such as `interface/stlink.cfg`.
+    Openocd,
+}
+
+/// The board to flash, declared in the `[flash]` section of the config.
+///
+/// `tool` is `probe-rs` (the default) or `openocd`. `chip` is the name of the chip for
+/// probe-rs, and the target config file for OpenOCD, which also needs the config file of
+/// the debug probe in `interface`. `port` is the serial port of the board, read at `baud`
+/// bauds (115200 by default) by `--monitor`.
+///
+/// # Examples
+///
+/// ```toml
+/// [flash]
+/// tool = "openocd"
+/// chip = "target/stm32f4x.cfg"
+/// interface = "interface/stlink.cfg"
+/// port = "/dev/ttyACM0"
+/// baud = 115200
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize)]
+pub struct Flash {
+    tool: Option<FlashTool>,
+    chip: Option<String>,
+    interface: Option<String>,
+    port: Option<String>,
+    baud: Option<u32>,
+}
+
+impl Flash {
+    /// Returns the tool writing the firmware to the board.
+    pub fn get_tool(&self) -> FlashTool {
+        self.tool.unwrap_or_default()
+    }
+
+    /// Returns the chip of the board, if set.
+    pub fn get_chip(&self) -> Option<&str> {
+        self.chip.as_deref()
+    }
+
+    /// Returns the config file of the debug probe for OpenOCD, if set.
+    pub fn get_interface(&self) -> Option<&str> {
+        self.interface.as_deref()
+    }
+
+    /// Returns the serial port of the board, if set.
+    pub fn get_port(&self) -> Option<&str> {
+        self.port.as_deref()
+    }
+
+    /// Returns the speed of the serial port in bauds.
+    pub fn get_baud(&self) -> u32 {
+        self.baud.unwrap_or(115_200)
+    }
+}
+
+/// Writes the firmware `elf` to the board of the `[flash]` section of `config` and
+/// resets it. In a dry run, the commands are printed instead.
+///
+/// # Errors
+///
+/// If the config has no `[flash]` section or no chip, or the tool is not installed or
+/// fails.
+pub fn flash(elf: &Path, config: &Config) -> MorfoResult<()> {
+    let flash = config.get_flash().ok_or_else(|| {
+        MorfoError::InvlidConfig("`morfo flash` needs a [flash] section".to_owned())
+    })?;
+    for mut cmd in commands(&flash, elf)? {
+        if config.get_dry_run() {
+            println!("{}", utils::format_command(&cmd));
+            continue;
+        }
+        let status = utils::run_tool(
+            cmd.stdin(Stdio::inherit())
+                .stdout(Stdio::inherit())
+                .stderr(Stdio::inherit()),
+        )?
+        .status;
+        if !status.success() {
+            return Err(MorfoError::CommandFailure(
+                utils::format_command(&cmd),
+                status.code(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the commands writing `elf` to the board and resetting it.
+fn commands(flash: &Flash, elf: &Path) -> MorfoResult<Vec<Command>> {
+    let chip = flash
+        .get_chip()
+        .ok_or_else(|| MorfoError::InvlidConfig("no chip in the [flash] section".to_owned()))?;
+    match flash.get_tool() {
+        FlashTool::ProbeRs => {
+            let mut download = Command::new("probe-rs");
+            download.args(["download", "--chip", chip]).arg(elf);
+            let mut reset = Command::new("probe-rs");
+            reset.args(["reset", "--chip", chip]);
+            Ok(vec![download, reset])
+        }
+        FlashTool::Openocd => {
+            let interface = flash.get_interface().ok_or_else(|| {
+                MorfoError::InvlidConfig(
+                    "no interface in the [flash] section, which OpenOCD needs".to_owned(),
+                )
+            })?;
+            let mut program = Command::new("openocd");
+            program
+                .args(["-f", interface, "-f", chip, "-c"])
+                .arg(format!(
+                    "program {} verify reset exit",
+                    utils::shell_quote(&elf.to_string_lossy())
+                ));
+            Ok(vec![program])
+        }
+    }
+}
+
+/// Prints what the board of the `[flash]` section of `config` sends on its serial port,
+/// until morfo is interrupted.
+///
+/// # Errors
+///
+/// If the config has no serial port, or it cannot be opened at its speed.
+#[cfg(unix)]
+pub fn monitor(config: &Config) -> MorfoResult<()> {
+    use std::{
+        fs::OpenOptions,
+        io::{self, Read, Write},
+        os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+    };
+
+    let flash = config.get_flash().unwrap_or_default();
+    let port = flash
+        .get_port()
+        .ok_or_else(|| MorfoError::InvlidConfig("no port in the [flash] section".to_owned()))?;
+    let speed = speed(flash.get_baud()).ok_or_else(|| {
+        MorfoError::InvlidConfig(format!("unsupported baud rate {}", flash.get_baud()))
+    })?;
+    let mut serial = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY)
+        .open(port)?;
+
+    // raw, so that the bytes of the board come through as they are sent
+    // SAFETY: the descriptor is open and `termios` is initialized by `tcgetattr`
+    unsafe {
+        let mut termios = std::mem::zeroed::<libc::termios>();
+        if libc::tcgetattr(serial.as_raw_fd(), &mut termios) != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        libc::cfmakeraw(&mut termios);
+        libc::cfsetspeed(&mut termios, speed);
+        if libc::tcsetattr(serial.as_raw_fd(), libc::TCSANOW, &termios) != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+    }
+
+    eprintln!(
+        "Monitoring {} at {} baud, Ctrl+C to stop",
+        port,
+        flash.get_baud()
+    );
+    let mut stdout = io::stdout();
+    let mut buffer = [0; 1024];
+    loop {
+        let read = serial.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+        stdout.write_all(&buffer[..read])?;
+        stdout.flush()?;
+    }
+}
+
+/// Prints what the board sends on its serial port. Serial ports are only opened on Unix.
+///
+/// # Errors
+///
+/// Always, as [`MorfoError::Unsupported`].
+#[cfg(not(unix))]
+pub fn monitor(_config: &Config) -> MorfoResult<()> {
+    Err(MorfoError::Unsupported("--monitor off Unix".to_owned()))
+}
+
+/// Returns the termios speed of `baud`, if it is a standard rate.
+#[cfg(unix)]
+fn speed(baud: u32) -> Option<libc::speed_t> {
+    Some(match baud {
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115_200 => libc::B115200,
+        230_400 => libc::B230400,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flash_commands() {
+        let elf = Path::new(".out/firmware");
+        let words = |cmd: &Command| {
+            std::iter::once(cmd.get_program())
+                .chain(cmd.get_args())
+                .map(|word| word.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let flash: Flash = toml::from_str(r#"chip = "STM32F401RETx""#).unwrap();
+        let flashing = commands(&flash, elf).unwrap();
+        assert_eq!(
+            words(&flashing[0]),
+            [
+                "probe-rs",
+                "download",
+                "--chip",
+                "STM32F401RETx",
+                ".out/firmware"
+            ]
+        );
+        assert_eq!(
+            words(&flashing[1]),
+            ["probe-rs", "reset", "--chip", "STM32F401RETx"]
+        );
+
+        let flash: Flash = toml::from_str(
+            r#"
+            tool = "openocd"
+            chip = "target/stm32f4x.cfg"
+            interface = "interface/stlink.cfg""#,
+        )
+        .unwrap();
+        let flashing = commands(&flash, elf).unwrap();
+        assert_eq!(
+            words(&flashing[0]),
+            [
+                "openocd",
+                "-f",
+                "interface/stlink.cfg",
+                "-f",
+                "target/stm32f4x.cfg",
+                "-c",
+                "program .out/firmware verify reset exit"
+            ]
+        );
+
+        let flash: Flash = toml::from_str(r#"tool = "openocd""#).unwrap();
+        assert!(matches!(
+            commands(&flash, elf),
+            Err(MorfoError::InvlidConfig(_))
+        ));
+    }
+}
@@ -0,0 +1,169 @@
+//! Fortran translation units.
+//!
+//! Sources with a Fortran extension, such as `.f90` or `.F90`, are translation units of
+//! the program like the C sources, but are compiled with `fc`, `gfortran` by default, and
+//! `fflags` in place of `cc` and `cflags`. The `opt_level`, `debug`, `includes` and
+//! `defines` of the config apply to them too, the defines being seen by the sources the
+//! compiler preprocesses, such as `.F90` files.
+//!
+//! A C file reaches a Fortran source through a header of the same name next to it, which
+//! declares the procedures it binds to C with `bind(c)`, just as a `.c` file is found
+//! from its `.h`. The translation units are compiled dependencies first, and the module
+//! files of the compiler are written to the build directory, where the sources that `use`
+//! a module find it.
+//!
+//! The program is linked by `cc` with the runtime library of `fc` when it has a Fortran
+//! source, so that a mixed C and Fortran program is built with a single morfo
+//! invocation. The Fortran sources are always compiled locally and are neither cached nor
+//! part of unity builds.
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{act::Act, config::Config, layout, source_compiler_command, toolchain::CompilerFamily};
+
+/// The extensions of Fortran sources, in fixed and free form, with the upper case ones
+/// preprocessed by the compiler.
+const EXTENSIONS: [&str; 10] = [
+    "f", "for", "f77", "f90", "f95", "f03", "f08", "F", "F90", "F95",
+];
+
+/// Returns whether `path` is a Fortran source, going by its extension.
+///
+/// # Examples
+///
+/// ```
+/// use morfo::fortran::is_fortran;
+/// use std::path::Path;
+///
+/// assert!(is_fortran(Path::new("solver.f90")));
+/// assert!(is_fortran(Path::new("legacy/blas.F")));
+/// assert!(!is_fortran(Path::new("main.c")));
+/// ```
+pub fn is_fortran(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| EXTENSIONS.contains(&extension))
+}
+
+/// Returns the command that compiles the Fortran `source` into the object file `object`.
+pub(crate) fn object_command(source: &Path, object: &Path, config: &Config) -> Command {
+    // gfortran and flang take the arguments of GCC
+    let family = CompilerFamily::Gcc;
+    let build_dir = layout::build_dir(config);
+    let mut compile_cmd = source_compiler_command(source, config);
+    compile_cmd.args(config.get_fflags());
+    if let Some(level) = config.get_opt_level() {
+        compile_cmd.arg(family.opt_arg(&level));
+    }
+    if config.get_debug() {
+        compile_cmd.arg(family.debug_arg());
+    }
+    for include in config.get_includes() {
+        compile_cmd.arg(family.include_arg(&include));
+    }
+    for (name, value) in config.get_defines() {
+        compile_cmd.arg(family.define_arg(&name, Some(&value)));
+    }
+    compile_cmd
+        .args(config.get_source_flags(source))
+        .arg("-J")
+        .arg(&build_dir)
+        .args(family.compile_args(source, object, &build_dir));
+    compile_cmd
+}
+
+/// Returns the Fortran sources of `act`.
+pub(crate) fn sources(act: &Act) -> Vec<PathBuf> {
+    act.sources()
+        .into_iter()
+        .filter(|source| is_fortran(source))
+        .collect()
+}
+
+/// Returns the arguments linking the runtime library of the Fortran compiler, if `act`
+/// has a Fortran source.
+pub(crate) fn link_args(act: &Act, config: &Config) -> Vec<OsString> {
+    if sources(act).is_empty() {
+        return Vec::new();
+    }
+    let family = config.get_family();
+    runtime_libs(&config.get_fc())
+        .iter()
+        .map(|name| family.library_arg(name).into())
+        .collect()
+}
+
+/// Returns the names of the runtime libraries of the Fortran compiler `fc`, such as
+/// `gfortran` for `x86_64-linux-gnu-gfortran-12`, or none for a compiler morfo does not
+/// know.
+fn runtime_libs(fc: &str) -> &'static [&'static str] {
+    let name = Path::new(fc)
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .unwrap_or(fc);
+    if name.contains("gfortran") {
+        &["gfortran"]
+    } else if name.contains("flang") {
+        &["FortranRuntime", "FortranDecimal"]
+    } else {
+        &[]
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    use super::*;
+
+    #[test]
+    fn compile_fortran() {
+        assert_eq!(runtime_libs("/usr/bin/gfortran-12"), ["gfortran"]);
+        assert_eq!(
+            runtime_libs("flang-new"),
+            ["FortranRuntime", "FortranDecimal"]
+        );
+        assert!(runtime_libs("ifx").is_empty());
+
+        // a stand-in for the Fortran compiler, as gfortran is not always installed
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let fc = dir.join("fc");
+        fs::write(&fc, "#!/bin/sh\nexec gcc -x c \"$@\"\n").unwrap();
+        fs::set_permissions(&fc, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(dir.join("solver.h"), "int solve(void);\n").unwrap();
+        fs::write(
+            dir.join("solver.f90"),
+            "int solve(void) { return ANSWER; }\n",
+        )
+        .unwrap();
+        let main_file = dir.join("main.c");
+        fs::write(
+            &main_file,
+            "#include \"solver.h\"\nint main(void) { return solve(); }\n",
+        )
+        .unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            cflags = ["-DANSWER=1"]
+            fc = "{}"
+            fflags = ["-DANSWER=42"]
+            builddir = "{}""#,
+            fc.display(),
+            dir.join(".out").display(),
+        ))
+        .unwrap();
+
+        let artifact = crate::compile(&main_file, &config).unwrap();
+        let outcome = crate::run(&artifact, crate::RunOptions::new(), &mut Vec::new()).unwrap();
+        assert_eq!(
+            outcome.exit_status.and_then(|status| status.code()),
+            Some(42)
+        );
+    }
+}
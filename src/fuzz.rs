@@ -0,0 +1,134 @@
+//! Fuzzing with libFuzzer.
+//!
+//! `morfo fuzz target.c` builds a fuzz target (a file defining `LLVMFuzzerTestOneInput`)
+//! with `-fsanitize=fuzzer,address` and runs it for a limited time. Everything the fuzzer
+//! keeps lives under `<builddir>/fuzz/<target>/`:
+//!
+//! * `corpus/` holds the inputs that reach new coverage and is reused across runs.
+//! * `crashes/` holds the inputs that crashed the target.
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    compile_act,
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    layout, lock, prepare,
+    toolchain::CompilerFamily,
+};
+
+/// The outcome of a fuzzing session.
+#[derive(Debug)]
+pub struct FuzzReport {
+    /// The instrumented fuzz target.
+    pub executable: PathBuf,
+    /// The corpus directory the fuzzer used.
+    pub corpus: PathBuf,
+    /// The crashing inputs found during this session.
+    pub new_crashes: Vec<PathBuf>,
+}
+
+impl FuzzReport {
+    /// Returns the command that reproduces `crash`.
+    pub fn repro_command(&self, crash: &Path) -> String {
+        format!("{} {}", self.executable.display(), crash.display())
+    }
+}
+
+/// Builds the fuzz target `main_file` and fuzzes it for `max_time` seconds.
+/// `fuzzer_args` are passed on to libFuzzer.
+///
+/// # Errors
+///
+/// If the compiler does not support libFuzzer, or the target cannot be built or run.
+pub fn fuzz(
+    main_file: PathBuf,
+    config: Config,
+    max_time: u64,
+    fuzzer_args: Vec<String>,
+) -> MorfoResult<FuzzReport> {
+    let config = config.detect_cc()?;
+    if config.get_family() == CompilerFamily::Gcc {
+        return Err(MorfoError::Unsupported(
+            "libFuzzer requires clang (set `cc = \"clang\"`)".to_owned(),
+        ));
+    }
+
+    let config = config.with_sanitizers(&["fuzzer", "address"]);
+    let _lock = lock::lock(&config)?;
+    let (act, config) = prepare(&main_file, config)?;
+    compile_act(&act, &config)?;
+
+    let dir = layout::fuzz_dir(&act.name, &config);
+    let corpus = dir.join("corpus");
+    let crashes = dir.join("crashes");
+    fs::create_dir_all(&corpus)?;
+    fs::create_dir_all(&crashes)?;
+    let known_crashes = list_files(&crashes)?;
+
+    let executable = layout::executable(&act.name, &config);
+    let mut artifact_prefix = crashes.into_os_string();
+    artifact_prefix.push(std::path::MAIN_SEPARATOR_STR);
+    let mut artifact_arg = std::ffi::OsString::from("-artifact_prefix=");
+    artifact_arg.push(&artifact_prefix);
+
+    // libFuzzer exits with a failure when it finds a crash, which is reported below instead
+    Command::new(&executable)
+        .arg(format!("-max_total_time={}", max_time))
+        .arg(artifact_arg)
+        .args(fuzzer_args)
+        .arg(&corpus)
+        .status()?;
+
+    let new_crashes = list_files(Path::new(&artifact_prefix))?
+        .difference(&known_crashes)
+        .cloned()
+        .collect();
+    Ok(FuzzReport {
+        executable,
+        corpus,
+        new_crashes,
+    })
+}
+
+fn list_files(dir: &Path) -> MorfoResult<BTreeSet<PathBuf>> {
+    let mut files = BTreeSet::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            files.insert(path);
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigBuilder;
+
+    #[test]
+    fn fuzz_gcc_unsupported() {
+        let config = ConfigBuilder::default().set_cc("gcc").build();
+        let result = fuzz(PathBuf::from("target.c"), config, 1, vec![]);
+        assert!(matches!(result, Err(MorfoError::Unsupported(_))));
+    }
+
+    #[test]
+    fn fuzz_repro_command() {
+        let report = FuzzReport {
+            executable: PathBuf::from(".out/target"),
+            corpus: PathBuf::from(".out/fuzz/target/corpus"),
+            new_crashes: vec![],
+        };
+        assert_eq!(
+            report.repro_command(Path::new(".out/fuzz/target/crashes/crash-1")),
+            ".out/target .out/fuzz/target/crashes/crash-1"
+        );
+    }
+}
@@ -0,0 +1,396 @@
+//! Garbage collection of the build directory and the object cache.
+//!
+//! Builds leave behind the objects of sources that were removed or renamed and the
+//! executables of main files that are gone, and the cache keeps an object for every
+//! compiler and flag ever tried. `morfo gc` removes from the build directory the objects
+//! no executable links anymore, and the executables none of whose sources exist anymore,
+//! there or in `bindir`, with the fingerprints and other files kept next to them and the
+//! manifest recording them. What was written after `morfo gc` started is kept, and every build directory
+//! under the build directory is locked first, so a build running at the same time keeps
+//! the objects it has not linked yet.
+//!
+//! `--older-than 30d` also removes the executables not built for that long, with their
+//! objects, and the cached objects not used for that long. `--max-size 5G` evicts the
+//! least recently used cached objects until the cache fits. The cached objects an
+//! executable of the project still links are kept by `--older-than` and evicted last by
+//! `--max-size`, since the next build of the project would copy them. The cache is left
+//! alone without either, as it is shared by every project.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use walkdir::WalkDir;
+
+use crate::{
+    cache,
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    fingerprint, layout, lock, manifest, utils,
+};
+
+/// What is recorded next to an artifact, by suffix, and removed with it.
+const SIDECARS: [&str; 3] = [".fingerprint", ".warnings", ".debug"];
+
+/// The extensions of objects, for GCC and Clang and for MSVC.
+const OBJECT_EXTENSIONS: [&str; 2] = ["o", "obj"];
+
+/// What `gc` removed, or would remove in a dry run.
+#[derive(Debug, PartialEq)]
+pub struct GcReport {
+    /// The objects no executable links anymore.
+    pub objects: usize,
+    /// The executables whose sources are gone, or not built for too long.
+    pub executables: usize,
+    /// The objects evicted from the cache.
+    pub cache_entries: usize,
+    /// The size of everything removed, in bytes.
+    pub freed: u64,
+    /// Whether nothing was removed, only reported.
+    pub dry_run: bool,
+}
+
+impl fmt::Display for GcReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} stale objects, {} orphaned executables and {} cache entries, freeing {}.",
+            if self.dry_run {
+                "Would remove"
+            } else {
+                "Removed"
+            },
+            self.objects,
+            self.executables,
+            self.cache_entries,
+            utils::format_size(self.freed)
+        )
+    }
+}
+
+/// Removes the stale objects and orphaned executables of the build directory of
+/// `config`, and with `older_than`, e.g. "30d", or `max_size`, e.g. "5G", prunes the
+/// cache too. In a dry run, nothing is removed.
+///
+/// # Errors
+///
+/// If the age or the size is invalid, the cache directory is unknown, or a file cannot
+/// be removed.
+pub fn gc(
+    config: &Config,
+    older_than: Option<&str>,
+    max_size: Option<&str>,
+) -> MorfoResult<GcReport> {
+    let older_than = older_than
+        .map(|age| {
+            utils::parse_duration(age)
+                .ok_or_else(|| MorfoError::InvlidConfig(format!("invalid age `{}`", age)))
+        })
+        .transpose()?;
+    let max_size = max_size
+        .map(|size| {
+            utils::parse_size(size)
+                .ok_or_else(|| MorfoError::InvlidConfig(format!("invalid cache size `{}`", size)))
+        })
+        .transpose()?;
+    let started = SystemTime::now();
+    let cutoff = older_than.map(|age| started.checked_sub(age).unwrap_or(SystemTime::UNIX_EPOCH));
+    let mut report = GcReport {
+        objects: 0,
+        executables: 0,
+        cache_entries: 0,
+        freed: 0,
+        dry_run: config.get_dry_run(),
+    };
+
+    let build_dir = config.get_build_dir();
+    let _locks = if report.dry_run {
+        Vec::new()
+    } else {
+        lock_all(&build_dir, config)?
+    };
+    let linked = collect_build_dir(&build_dir, config, cutoff, started, &mut report)?;
+    if older_than.is_some() || max_size.is_some() {
+        collect_cache(
+            &config.get_cache_dir()?,
+            &linked,
+            cutoff,
+            max_size,
+            &mut report,
+        )?;
+    }
+    Ok(report)
+}
+
+/// Locks every build directory under `build_dir`, which has a lock file.
+fn lock_all(build_dir: &Path, config: &Config) -> MorfoResult<Vec<lock::BuildLock>> {
+    let name = layout::lock_file(config).file_name().map(ToOwned::to_owned);
+    WalkDir::new(build_dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file() && Some(entry.file_name()) == name.as_deref())
+        .map(|entry| lock::lock_file(entry.path()))
+        .collect()
+}
+
+/// Removes the stale objects and orphaned executables of `build_dir`, and those not
+/// built since `cutoff`, and returns the SHA-256 of the objects left, by their size.
+fn collect_build_dir(
+    build_dir: &Path,
+    config: &Config,
+    cutoff: Option<SystemTime>,
+    started: SystemTime,
+    report: &mut GcReport,
+) -> MorfoResult<HashMap<u64, BTreeSet<String>>> {
+    let manifest_name = layout::manifest(config).file_name().map(ToOwned::to_owned);
+    let mut manifests = Vec::new();
+    let mut objects = Vec::new();
+    let mut executables = Vec::new();
+    // the executables of `bindir` link the objects of the build directory
+    let bin_dir = config
+        .get_bin_dir()
+        .filter(|bin_dir| !utils::normalize(bin_dir).starts_with(utils::normalize(build_dir)));
+    let entries = std::iter::once(build_dir.to_path_buf())
+        .chain(bin_dir)
+        .flat_map(|dir| WalkDir::new(dir).into_iter().filter_map(Result::ok));
+    for entry in entries {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let path = utils::normalize(entry.path());
+        if Some(entry.file_name()) == manifest_name.as_deref() {
+            if let Ok(manifest) = manifest::read(&path) {
+                let outputs: Vec<_> = manifest
+                    .outputs
+                    .keys()
+                    .map(|output| utils::normalize(output))
+                    .collect();
+                manifests.push((path, outputs));
+            }
+            continue;
+        }
+        let Some(inputs) = fingerprint::recorded_inputs(&path) else {
+            continue;
+        };
+        if is_object(&path) {
+            objects.push(path);
+        } else {
+            executables.push((path, inputs));
+        }
+    }
+
+    let mut linked = BTreeMap::new();
+    let mut removed = BTreeSet::new();
+    for (executable, (mut sources, executable_objects)) in executables {
+        for object in executable_objects.keys() {
+            if let Some((object_sources, _)) = fingerprint::recorded_inputs(object) {
+                sources.extend(object_sources);
+            }
+        }
+        let orphaned = !sources.is_empty() && !sources.iter().any(|source| source.exists());
+        let modified = modified(&executable);
+        let expired = cutoff.is_some_and(|cutoff| modified < cutoff);
+        if (orphaned || expired) && modified < started {
+            report.executables += 1;
+            report.freed += remove(&executable, report.dry_run)?;
+            removed.insert(executable);
+        } else {
+            linked.extend(
+                executable_objects
+                    .into_iter()
+                    .map(|(object, hash)| (utils::normalize(&object), hash)),
+            );
+        }
+    }
+
+    // a manifest of executables that are gone cannot be verified anymore
+    for (manifest, outputs) in manifests {
+        if !outputs.is_empty() && outputs.iter().all(|output| removed.contains(output)) {
+            report.freed += remove_file(&manifest, report.dry_run)?;
+        }
+    }
+
+    let mut live: HashMap<u64, BTreeSet<String>> = HashMap::new();
+    for object in objects {
+        if let Some(hash) = linked.get(&object) {
+            if let Ok(metadata) = fs::metadata(&object) {
+                live.entry(metadata.len()).or_default().insert(hash.clone());
+            }
+        } else if modified(&object) < started {
+            report.objects += 1;
+            report.freed += remove(&object, report.dry_run)?;
+        }
+    }
+    Ok(live)
+}
+
+/// Evicts the cached objects in `dir` not used since `cutoff` that are not `linked`, then
+/// the least recently used ones until the cache is no larger than `max_size`.
+fn collect_cache(
+    dir: &Path,
+    linked: &HashMap<u64, BTreeSet<String>>,
+    cutoff: Option<SystemTime>,
+    max_size: Option<u64>,
+    report: &mut GcReport,
+) -> MorfoResult<()> {
+    // only the objects as large as a linked one are worth hashing
+    let is_linked = |path: &Path, size: u64| {
+        linked
+            .get(&size)
+            .is_some_and(|hashes| utils::hash_file(path).is_ok_and(|hash| hashes.contains(&hash)))
+    };
+    let mut entries: Vec<_> = cache::entries(dir)
+        .into_iter()
+        .map(|(path, size, used)| {
+            let linked = is_linked(&path, size);
+            (path, size, used, linked)
+        })
+        .collect();
+    entries.sort_by_key(|(_, _, used, linked)| (*linked, *used));
+
+    let mut size: u64 = entries.iter().map(|(_, size, _, _)| size).sum();
+    for (path, entry_size, used, linked) in entries {
+        let expired = !linked && cutoff.is_some_and(|cutoff| used < cutoff);
+        let over = max_size.is_some_and(|max_size| size > max_size);
+        if !expired && !over {
+            continue;
+        }
+        remove_file(&path, report.dry_run)?;
+        size -= entry_size;
+        report.cache_entries += 1;
+        report.freed += entry_size;
+    }
+    Ok(())
+}
+
+fn is_object(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| OBJECT_EXTENSIONS.iter().any(|object| ext == *object))
+}
+
+fn modified(path: &Path) -> SystemTime {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Removes `artifact` with what is recorded next to it, and returns their size.
+fn remove(artifact: &Path, dry_run: bool) -> MorfoResult<u64> {
+    let mut files = vec![artifact.to_path_buf()];
+    files.extend(SIDECARS.iter().map(|suffix| {
+        let mut path = artifact.as_os_str().to_owned();
+        path.push(suffix);
+        PathBuf::from(path)
+    }));
+    // the debug information split out of an object
+    if is_object(artifact) {
+        files.push(artifact.with_extension("dwo"));
+    }
+    let mut freed = 0;
+    for file in files {
+        freed += remove_file(&file, dry_run)?;
+    }
+    Ok(freed)
+}
+
+/// Removes `path` if it exists, and returns its size.
+fn remove_file(path: &Path, dry_run: bool) -> MorfoResult<u64> {
+    let size = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    if !dry_run {
+        match fs::remove_file(path) {
+            Ok(()) => (),
+            // another gc got there first
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gc_build_dir_and_cache() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let out = dir.join(".out");
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            builddir = "{}"
+
+            [cache]
+            enabled = true
+            dir = "{}""#,
+            out.display(),
+            dir.join("cache").display()
+        ))
+        .unwrap();
+        let main_file = dir.join("main.c");
+        fs::write(dir.join("util.h"), "int util(void);\n").unwrap();
+        fs::write(dir.join("util.c"), "int util(void) { return 1; }\n").unwrap();
+        fs::write(
+            &main_file,
+            "#include \"util.h\"\nint main(void) { return util(); }\n",
+        )
+        .unwrap();
+        crate::compile(&main_file, &config).unwrap();
+        fs::write(dir.join("other.c"), "int main(void) { return 2; }\n").unwrap();
+        crate::compile(&dir.join("other.c"), &config).unwrap();
+
+        // util.c is dropped from main.c, and other.c removed
+        fs::write(&main_file, "int main(void) { return 0; }\n").unwrap();
+        fs::remove_file(dir.join("util.c")).unwrap();
+        fs::remove_file(dir.join("other.c")).unwrap();
+        crate::compile(&main_file, &config).unwrap();
+        assert_eq!(cache::entries(&config.get_cache_dir().unwrap()).len(), 4);
+        let main_bytes = fs::read(out.join("main.o")).unwrap();
+
+        let dry_run = gc(&config.clone().with_dry_run(true), None, None).unwrap();
+        assert_eq!((dry_run.objects, dry_run.executables), (2, 1));
+        assert!(dry_run.freed > 0);
+        assert!(out.join("util.o").exists());
+
+        let report = gc(&config, None, None).unwrap();
+        assert_eq!(
+            (report.objects, report.executables, report.cache_entries),
+            (2, 1, 0)
+        );
+        assert_eq!(report.freed, dry_run.freed);
+        for removed in ["util.o", "util.o.fingerprint", "other.o", "other"] {
+            assert!(!out.join(removed).exists(), "{} was not removed", removed);
+        }
+        for kept in ["main", "main.o", "manifest.json"] {
+            assert!(out.join(kept).exists(), "{} was removed", kept);
+        }
+
+        // the cached objects were last used two hours ago, and main still links one
+        let cache_dir = config.get_cache_dir().unwrap();
+        let two_hours_ago = SystemTime::now() - std::time::Duration::from_secs(2 * 3600);
+        for (entry, _, _) in cache::entries(&cache_dir) {
+            let file = fs::File::options().write(true).open(entry).unwrap();
+            file.set_modified(two_hours_ago).unwrap();
+        }
+        let report = gc(&config, Some("1h"), None).unwrap();
+        assert_eq!((report.objects, report.cache_entries), (0, 3));
+        let left = cache::entries(&cache_dir);
+        assert_eq!(left.len(), 1);
+        assert_eq!(fs::read(&left[0].0).unwrap(), main_bytes);
+        let report = gc(&config, None, Some("0")).unwrap();
+        assert_eq!(report.cache_entries, 1);
+
+        assert!(matches!(
+            gc(&config, Some("a month"), None),
+            Err(MorfoError::InvlidConfig(_))
+        ));
+    }
+}
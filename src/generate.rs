@@ -0,0 +1,246 @@
+//! Code generation with tools such as flex and bison.
+//!
+//! The `[generators]` section maps file patterns to the command that turns a matching
+//! file into C source. Before the dependency tree is built, every matching file under the
+//! project directory is run through its generator, and the outputs join the build as
+//! ordinary translation units. Outputs are written to `<builddir>/gen/`, which is also on
+//! the include path so that generated headers such as `parser.tab.h` can be included.
+//!
+//! A file is only regenerated when it is newer than its output or the command changed.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use globset::Glob;
+
+use crate::{
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    layout, utils,
+};
+
+/// A code generator, declared in the `[generators]` section of the config.
+///
+/// In `command` and `output`, `{in}` is replaced with the path of the input, `{out}` with
+/// the path of the output and `{stem}` with the input's file name without its extension.
+///
+/// # Examples
+///
+/// ```toml
+/// [generators."*.l"]
+/// command = "flex -o {out} {in}"
+/// output = "{stem}.yy.c"
+///
+/// [generators."*.y"]
+/// command = "bison -d -o {out} {in}"
+/// output = "{stem}.tab.c"
+/// ```
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+pub struct Generator {
+    command: String,
+    output: String,
+}
+
+impl Generator {
+    /// Returns the file name of the source generated from `input`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    /// use std::path::Path;
+    ///
+    /// let config: Config = toml::from_str(
+    ///     r#"
+    ///     [generators."*.y"]
+    ///     command = "bison -d -o {out} {in}"
+    ///     output = "{stem}.tab.c""#,
+    /// )
+    /// .unwrap();
+    /// let bison = &config.get_generators()["*.y"];
+    /// assert_eq!(bison.output(Path::new("src/parser.y")), "parser.tab.c");
+    /// ```
+    pub fn output(&self, input: &Path) -> String {
+        self.output.replace("{stem}", &utils::file_name(input))
+    }
+
+    /// Returns the command generating `output` from `input`, split into words.
+    pub fn command(&self, input: &Path, output: &Path) -> Vec<String> {
+        let input = input.to_string_lossy();
+        let output = output.to_string_lossy();
+        self.command
+            .split_whitespace()
+            .map(|word| {
+                word.replace("{in}", &input)
+                    .replace("{out}", &output)
+                    .replace("{stem}", &utils::file_name(Path::new(input.as_ref())))
+            })
+            .collect()
+    }
+}
+
+/// Runs the generators over the matching files under `project_dir` and returns the
+/// generated sources.
+///
+/// # Errors
+///
+/// If a generator pattern is invalid, or a generator cannot be run or fails.
+pub(crate) fn generate(config: &Config, project_dir: &Path) -> MorfoResult<Vec<PathBuf>> {
+    let generators = config.get_generators();
+    if generators.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let out_dir = layout::generated_dir(config);
+    if !config.get_dry_run() {
+        fs::create_dir_all(&out_dir)?;
+    }
+
+    let mut sources = Vec::new();
+    for (input, generator) in inputs(&generators, project_dir, config)? {
+        let output = out_dir.join(generator.output(&input));
+        let command = generator.command(&input, &output);
+        if config.get_dry_run() {
+            let words: Vec<_> = command
+                .iter()
+                .map(|word| utils::shell_quote(word))
+                .collect();
+            println!("{}", words.join(" "));
+        } else if is_stale(&input, &output, &command) {
+            run_generator(&command)?;
+            fs::write(stamp_path(&output), command.join(" "))?;
+        }
+        sources.push(output);
+    }
+    Ok(sources)
+}
+
+/// Finds the files under `project_dir` matching a generator pattern, skipping the build
+/// directory. A file matching several patterns uses the first in name order.
+fn inputs<'a>(
+    generators: &'a BTreeMap<String, Generator>,
+    project_dir: &Path,
+    config: &Config,
+) -> MorfoResult<Vec<(PathBuf, &'a Generator)>> {
+    let mut matchers = Vec::new();
+    for (pattern, generator) in generators {
+        let matcher = Glob::new(pattern)
+            .map_err(|e| MorfoError::InvlidConfig(format!("generator pattern: {}", e)))?
+            .compile_matcher();
+        matchers.push((matcher, generator));
+    }
+
+    let mut inputs = Vec::new();
+    for path in utils::project_files(project_dir, &config.get_build_dir()) {
+        if let Some((_, generator)) = matchers.iter().find(|(matcher, _)| matcher.is_match(&path)) {
+            inputs.push((path, *generator));
+        }
+    }
+    Ok(inputs)
+}
+
+/// The file recording the command that last generated `output`.
+fn stamp_path(output: &Path) -> PathBuf {
+    let mut stamp = output.as_os_str().to_owned();
+    stamp.push(".cmd");
+    PathBuf::from(stamp)
+}
+
+fn is_stale(input: &Path, output: &Path, command: &[String]) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    let newer_input = match (modified(input), modified(output)) {
+        (Some(input), Some(output)) => input > output,
+        _ => true,
+    };
+    let same_command =
+        fs::read_to_string(stamp_path(output)).is_ok_and(|stamp| stamp == command.join(" "));
+    newer_input || !same_command
+}
+
+fn run_generator(command: &[String]) -> MorfoResult<()> {
+    let Some((program, args)) = command.split_first() else {
+        return Ok(());
+    };
+
+    let mut generate_cmd = Command::new(program);
+    generate_cmd.args(args);
+    let status = utils::run_tool(&mut generate_cmd)?.status;
+    if !status.success() {
+        return Err(MorfoError::CommandFailure(command.join(" "), status.code()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn config(build_dir: &Path) -> Config {
+        toml::from_str(&format!(
+            r#"
+            builddir = "{}"
+
+            [generators."*.tmpl"]
+            command = "cp {{in}} {{out}}"
+            output = "{{stem}}_gen.c""#,
+            build_dir.display()
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn generate_regenerates_when_changed() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let project = tmp_dir.path();
+        let input = project.join("table.tmpl");
+        fs::write(&input, "int table[] = {1};\n").unwrap();
+        fs::write(project.join("main.c"), "").unwrap();
+        let config = config(&project.join(".out"));
+
+        let sources = generate(&config, project).unwrap();
+        let output = project.join(".out").join("gen").join("table_gen.c");
+        assert_eq!(sources, vec![output.clone()]);
+        assert_eq!(fs::read_to_string(&output).unwrap(), "int table[] = {1};\n");
+
+        // an unchanged input is not regenerated
+        fs::write(&output, "edited").unwrap();
+        generate(&config, project).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "edited");
+
+        // a newer input is
+        fs::File::options()
+            .write(true)
+            .open(&input)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(5))
+            .unwrap();
+        generate(&config, project).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "int table[] = {1};\n");
+    }
+
+    #[test]
+    fn generate_failing_generator() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            builddir = "{}"
+
+            [generators."*.y"]
+            command = "false {{in}}"
+            output = "{{stem}}.tab.c""#,
+            tmp_dir.path().join(".out").display()
+        ))
+        .unwrap();
+        fs::write(tmp_dir.path().join("parser.y"), "").unwrap();
+
+        assert!(matches!(
+            generate(&config, tmp_dir.path()).unwrap_err(),
+            MorfoError::CommandFailure(_, Some(1))
+        ));
+    }
+}
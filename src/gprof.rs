@@ -0,0 +1,135 @@
+//! Profiling with gprof.
+//!
+//! With `profiling = "gprof"`, usually set in a profile, the program is built with `-pg`.
+//! After it runs, gprof reads the `gmon.out` it wrote and morfo prints the flat profile.
+//! The profile data is written to `<builddir>/gmon.out.<pid>` where the C library
+//! supports `GMON_OUT_PREFIX` (glibc), and to `gmon.out` in the working directory elsewhere.
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    layout::{self, GMON_OUT},
+    toolchain::CompilerFamily,
+    utils,
+};
+
+/// Returns the flags that instrument the program for gprof, for both compiling and linking.
+pub(crate) fn flags(family: CompilerFamily) -> Vec<String> {
+    match family {
+        CompilerFamily::Gcc | CompilerFamily::Clang => vec!["-pg".to_owned()],
+        CompilerFamily::Msvc => Vec::new(),
+    }
+}
+
+/// Removes the profiles of earlier runs so that the report only covers the next one.
+///
+/// # Errors
+///
+/// If the compiler cannot build for gprof, or a stale profile cannot be removed.
+pub(crate) fn clean(config: &Config) -> MorfoResult<()> {
+    if config.get_family() == CompilerFamily::Msvc {
+        return Err(MorfoError::Unsupported(
+            "gprof profiling with MSVC".to_owned(),
+        ));
+    }
+    for profile in profiles(config)? {
+        fs::remove_file(profile)?;
+    }
+    Ok(())
+}
+
+/// Runs gprof on the profile written by the last run and writes the flat profile to `out`.
+///
+/// # Errors
+///
+/// If the program did not write a profile, or gprof fails.
+pub(crate) fn report<W: Write>(executable: &Path, config: &Config, out: &mut W) -> MorfoResult<()> {
+    let gmon = profiles(config)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| MorfoError::FileNotFound(PathBuf::from(GMON_OUT)))?;
+
+    let mut gprof_cmd = Command::new("gprof");
+    gprof_cmd
+        .args(["--brief", "--flat-profile"])
+        .arg(executable)
+        .arg(&gmon);
+    let output = utils::run_tool(&mut gprof_cmd)?;
+    if !output.status.success() {
+        return Err(MorfoError::CommandFailure(
+            "gprof".to_owned(),
+            output.status.code(),
+        ));
+    }
+    out.write_all(&output.stdout)?;
+    Ok(())
+}
+
+/// Returns the profiles in the build directory and the working directory, newest first.
+fn profiles(config: &Config) -> MorfoResult<Vec<PathBuf>> {
+    let prefix = format!("{}.", GMON_OUT);
+    let mut profiles = Vec::new();
+    if let Ok(entries) = fs::read_dir(layout::build_dir(config)) {
+        for entry in entries {
+            let path = entry?.path();
+            if path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(&prefix))
+            {
+                profiles.push(path);
+            }
+        }
+    }
+    if Path::new(GMON_OUT).is_file() {
+        profiles.push(PathBuf::from(GMON_OUT));
+    }
+
+    profiles.sort_by_cached_key(|path| {
+        std::cmp::Reverse(fs::metadata(path).and_then(|meta| meta.modified()).ok())
+    });
+    Ok(profiles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compile,
+        config::{ConfigBuilder, Profiling},
+        run, RunOptions,
+    };
+
+    #[test]
+    fn gprof_report() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let main_file = tmp_dir.path().join("main.c");
+        fs::write(
+            &main_file,
+            "int work(int n) { int s = 0; for (int i = 0; i < n; i++) s += i % 7; return s; }\n\
+             int main(void) { return work(1000000) == -1; }\n",
+        )
+        .unwrap();
+
+        let build_dir = tmp_dir.path().join(".out");
+        let config = ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir(build_dir.to_str().unwrap())
+            .build()
+            .with_profiling(Profiling::Gprof);
+        let artifact = compile(&main_file, &config).unwrap();
+
+        // the report follows the output of the run
+        let mut out = Vec::new();
+        run(&artifact, RunOptions::new(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Flat profile"), "{}", out);
+        assert!(out.contains("work"), "{}", out);
+    }
+}
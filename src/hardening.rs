@@ -0,0 +1,202 @@
+//! The hardened-build security preset.
+//!
+//! With `hardening = true` in the config or a profile, or `--hardened` on the command line,
+//! morfo compiles and links with a vetted set of exploit mitigations:
+//!
+//! | Mitigation          | GCC / Clang                          | MSVC        |
+//! |---------------------|--------------------------------------|-------------|
+//! | Stack protector     | `-fstack-protector-strong`           | `/GS`       |
+//! | Fortified libc      | `-D_FORTIFY_SOURCE=2`                | `/sdl`      |
+//! | Position independent| `-fPIE`, `-pie`                      | (default)   |
+//! | Read-only relocations | `-Wl,-z,relro,-z,now`              |             |
+//! | Control-flow guard  |                                      | `/guard:cf` |
+//!
+//! After linking, the executable is inspected with `readelf` to report which mitigations it
+//! actually ended up with, since a static library or a toolchain default can silently undo a flag.
+
+use std::{fmt, path::Path, process::Command};
+
+use crate::{
+    error::{MorfoError, MorfoResult},
+    toolchain::CompilerFamily,
+};
+
+/// Returns the hardening flags for compiling with `family`.
+pub(crate) fn compile_flags(family: CompilerFamily) -> Vec<String> {
+    let flags: &[&str] = match family {
+        CompilerFamily::Gcc | CompilerFamily::Clang => {
+            &["-fstack-protector-strong", "-D_FORTIFY_SOURCE=2", "-fPIE"]
+        }
+        CompilerFamily::Msvc => &["/GS", "/sdl", "/guard:cf"],
+    };
+    flags.iter().map(|flag| flag.to_string()).collect()
+}
+
+/// Returns the hardening flags for linking with `family`.
+pub(crate) fn link_flags(family: CompilerFamily) -> Vec<String> {
+    let flags: &[&str] = match family {
+        // Mach-O has no RELRO and PIE is the default on macOS
+        CompilerFamily::Gcc | CompilerFamily::Clang if cfg!(target_os = "macos") => &[],
+        CompilerFamily::Gcc | CompilerFamily::Clang => &["-pie", "-Wl,-z,relro,-z,now"],
+        CompilerFamily::Msvc => &["/guard:cf"],
+    };
+    flags.iter().map(|flag| flag.to_string()).collect()
+}
+
+/// How much of the relocation table is made read-only after loading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relro {
+    None,
+    Partial,
+    Full,
+}
+
+/// The exploit mitigations found in an ELF executable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mitigations {
+    pub pie: bool,
+    pub relro: Relro,
+    pub stack_protector: bool,
+    pub fortify: bool,
+    pub nx: bool,
+}
+
+impl fmt::Display for Mitigations {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let yes_no = |enabled: bool| if enabled { "yes" } else { "no" };
+        let relro = match self.relro {
+            Relro::None => "no",
+            Relro::Partial => "partial",
+            Relro::Full => "full",
+        };
+        write!(
+            f,
+            "PIE: {}, RELRO: {}, stack protector: {}, FORTIFY: {}, NX: {}",
+            yes_no(self.pie),
+            relro,
+            yes_no(self.stack_protector),
+            yes_no(self.fortify),
+            yes_no(self.nx)
+        )
+    }
+}
+
+/// Inspects the ELF executable at `executable` with `readelf`.
+///
+/// # Errors
+///
+/// If `readelf` cannot be run or does not recognise the executable.
+pub fn inspect(executable: &Path) -> MorfoResult<Mitigations> {
+    let output = Command::new("readelf")
+        .args([
+            "-W",
+            "--file-header",
+            "--program-headers",
+            "--dynamic",
+            "--syms",
+        ])
+        .arg(executable)
+        .output()?;
+    if !output.status.success() {
+        return Err(MorfoError::CommandFailure(
+            format!("readelf {}", executable.display()),
+            output.status.code(),
+        ));
+    }
+
+    Ok(parse_readelf(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Prints the mitigations of `executable`, or why they could not be determined.
+pub(crate) fn report(executable: &Path) {
+    match inspect(executable) {
+        Ok(mitigations) => println!("Hardening: {}", mitigations),
+        Err(e) => println!("Hardening: could not inspect the executable ({})", e),
+    }
+}
+
+fn parse_readelf(output: &str) -> Mitigations {
+    let mut mitigations = Mitigations {
+        pie: false,
+        relro: Relro::None,
+        stack_protector: false,
+        fortify: false,
+        nx: false,
+    };
+    let mut bind_now = false;
+
+    for line in output.lines() {
+        let line = line.trim();
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("Type:") => mitigations.pie = words.next() == Some("DYN"),
+            Some("GNU_RELRO") => mitigations.relro = Relro::Partial,
+            // the flags are the second to last column, e.g. `RW  0x10`
+            Some("GNU_STACK") => mitigations.nx = !line.contains("RWE"),
+            _ => {}
+        }
+        // full RELRO resolves every symbol at load time
+        if line.contains("BIND_NOW") || (line.contains("(FLAGS_1)") && line.contains(" NOW")) {
+            bind_now = true;
+        }
+        if line.contains("__stack_chk_fail") {
+            mitigations.stack_protector = true;
+        }
+        if line.contains("_chk@") || line.ends_with("_chk") {
+            mitigations.fortify = true;
+        }
+    }
+
+    if bind_now && mitigations.relro == Relro::Partial {
+        mitigations.relro = Relro::Full;
+    }
+    mitigations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hardening_flags() {
+        assert!(compile_flags(CompilerFamily::Gcc).contains(&"-fstack-protector-strong".to_owned()));
+        assert_eq!(link_flags(CompilerFamily::Msvc), vec!["/guard:cf"]);
+    }
+
+    #[test]
+    fn hardening_parse_readelf() {
+        let output = r#"
+ELF Header:
+  Type:                              DYN (Position-Independent Executable file)
+Program Headers:
+  Type           Offset   VirtAddr           PhysAddr           FileSiz  MemSiz   Flg Align
+  GNU_STACK      0x000000 0x0000000000000000 0x0000000000000000 0x000000 0x000000 RW  0x10
+  GNU_RELRO      0x002db8 0x0000000000003db8 0x0000000000003db8 0x000248 0x000248 R   0x1
+Dynamic section at offset 0x2dc8 contains 27 entries:
+ 0x000000000000001e (FLAGS)              BIND_NOW
+ 0x000000006ffffffb (FLAGS_1)            Flags: NOW PIE
+Symbol table '.dynsym' contains 8 entries:
+     2: 0000000000000000     0 FUNC    GLOBAL DEFAULT  UND __stack_chk_fail@GLIBC_2.4 (3)
+     3: 0000000000000000     0 FUNC    GLOBAL DEFAULT  UND __printf_chk@GLIBC_2.3.4 (4)
+"#;
+        assert_eq!(
+            parse_readelf(output),
+            Mitigations {
+                pie: true,
+                relro: Relro::Full,
+                stack_protector: true,
+                fortify: true,
+                nx: true,
+            }
+        );
+
+        let plain = r#"
+  Type:                              EXEC (Executable file)
+  GNU_STACK      0x000000 0x0000000000000000 0x0000000000000000 0x000000 0x000000 RWE 0x10
+"#;
+        let mitigations = parse_readelf(plain);
+        assert!(!mitigations.pie);
+        assert!(!mitigations.nx);
+        assert_eq!(mitigations.relro, Relro::None);
+    }
+}
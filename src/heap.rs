@@ -0,0 +1,406 @@
+//! Heap profiling.
+//!
+//! `morfo --heap-profile main.c` runs the program under heaptrack, or valgrind's massif
+//! tool when heaptrack is not installed, and reports the peak heap usage together with
+//! the allocation sites responsible for it. The raw profile is kept in `<builddir>/heap/`
+//! for the tool's own viewers (`heaptrack_gui`, `ms_print`).
+//!
+//! The `heap_budget` option turns the report into a check: the run is over budget when the
+//! peak exceeds it.
+
+use std::{
+    cmp::Reverse,
+    fmt, fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use regex::Regex;
+
+use crate::{
+    compile_act,
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    layout, lock, prepare, utils,
+};
+
+/// How many allocation sites are reported.
+const TOP_SITES: usize = 5;
+
+/// Where memory live at the peak was allocated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AllocationSite {
+    /// The allocating function and, if known, its source location.
+    pub location: String,
+    /// The bytes allocated here that were still live at the peak.
+    pub bytes: u64,
+}
+
+/// The outcome of a heap-profiled run.
+#[derive(Debug)]
+pub struct HeapProfile {
+    /// The raw profile written by the profiler.
+    pub profile: PathBuf,
+    /// The peak heap usage in bytes.
+    pub peak: u64,
+    /// The allocation sites holding the most memory at the peak, largest first.
+    pub sites: Vec<AllocationSite>,
+    /// The configured heap budget in bytes.
+    pub budget: Option<u64>,
+}
+
+impl HeapProfile {
+    /// Returns whether the peak heap usage exceeded the budget.
+    pub fn over_budget(&self) -> bool {
+        self.budget.is_some_and(|budget| self.peak > budget)
+    }
+}
+
+impl fmt::Display for HeapProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Peak heap usage: {}", utils::format_size(self.peak))?;
+        if let Some(budget) = self.budget {
+            write!(f, " (budget {})", utils::format_size(budget))?;
+        }
+        writeln!(f)?;
+        if !self.sites.is_empty() {
+            writeln!(f, "Top allocation sites:")?;
+        }
+        for site in &self.sites {
+            writeln!(
+                f,
+                "  {:>12}  {}",
+                utils::format_size(site.bytes),
+                site.location
+            )?;
+        }
+        write!(f, "Profile: {}", self.profile.display())
+    }
+}
+
+/// Builds `main_file` and runs it with `prog_args` under a heap profiler.
+///
+/// # Errors
+///
+/// If `heap_budget` is invalid, neither heaptrack nor valgrind is installed, or the
+/// build or profiler fails.
+pub fn heap_profile(
+    main_file: PathBuf,
+    config: Config,
+    prog_args: Vec<String>,
+) -> MorfoResult<HeapProfile> {
+    let budget = config.get_heap_budget()?;
+    let profiler = if utils::tool_available("heaptrack") {
+        Profiler::Heaptrack
+    } else if utils::tool_available("valgrind") {
+        Profiler::Massif
+    } else {
+        return Err(MorfoError::MissingTool("heaptrack or valgrind".to_owned()));
+    };
+
+    // debug information lets the profilers name the allocation sites
+    let _lock = lock::lock(&config)?;
+    let (act, config) = prepare(&main_file, config.with_frame_pointers())?;
+    compile_act(&act, &config)?;
+
+    let dir = layout::heap_dir(&config);
+    fs::create_dir_all(&dir)?;
+    let executable = layout::executable(&act.name, &config);
+    let (profile, peak, sites) = profiler.record(&executable, &prog_args, &dir)?;
+
+    Ok(HeapProfile {
+        profile,
+        peak,
+        sites,
+        budget,
+    })
+}
+
+enum Profiler {
+    Heaptrack,
+    Massif,
+}
+
+impl Profiler {
+    /// Runs `executable` under the profiler, writing the raw profile to `dir`.
+    fn record(
+        &self,
+        executable: &Path,
+        prog_args: &[String],
+        dir: &Path,
+    ) -> MorfoResult<(PathBuf, u64, Vec<AllocationSite>)> {
+        let name = utils::file_name(executable);
+        match self {
+            Profiler::Heaptrack => {
+                let prefix = format!("heaptrack.{}", name);
+                // heaptrack appends the compression format to the output name
+                remove_profiles(dir, &prefix)?;
+                let mut record_cmd = Command::new("heaptrack");
+                record_cmd
+                    .arg("-o")
+                    .arg(dir.join(&prefix))
+                    .arg(executable)
+                    .args(prog_args);
+                let status = utils::run_tool(inherit_stdio(&mut record_cmd))?.status;
+                let profile = find_profile(dir, &prefix).ok_or_else(|| {
+                    MorfoError::CommandFailure("heaptrack".to_owned(), status.code())
+                })?;
+
+                let mut print_cmd = Command::new("heaptrack_print");
+                print_cmd.arg(&profile);
+                let output = utils::run_tool(&mut print_cmd)?;
+                if !output.status.success() {
+                    return Err(MorfoError::CommandFailure(
+                        "heaptrack_print".to_owned(),
+                        output.status.code(),
+                    ));
+                }
+                let (peak, sites) = parse_heaptrack(&String::from_utf8_lossy(&output.stdout));
+                Ok((profile, peak, sites))
+            }
+            Profiler::Massif => {
+                let profile = dir.join(format!("massif.out.{}", name));
+                let mut record_cmd = Command::new("valgrind");
+                record_cmd
+                    .args(["--tool=massif", "--quiet"])
+                    .arg(format!("--massif-out-file={}", profile.display()))
+                    .arg(executable)
+                    .args(prog_args);
+                let status = utils::run_tool(inherit_stdio(&mut record_cmd))?.status;
+                // valgrind exits with the program's status, so only a missing profile is fatal
+                let Ok(text) = fs::read_to_string(&profile) else {
+                    return Err(MorfoError::CommandFailure(
+                        "valgrind --tool=massif".to_owned(),
+                        status.code(),
+                    ));
+                };
+                let (peak, sites) = parse_massif(&text);
+                Ok((profile, peak, sites))
+            }
+        }
+    }
+}
+
+fn inherit_stdio(cmd: &mut Command) -> &mut Command {
+    cmd.stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+}
+
+fn profiles(dir: &Path, prefix: &str) -> MorfoResult<Vec<PathBuf>> {
+    let mut profiles = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with(prefix))
+        {
+            profiles.push(path);
+        }
+    }
+    Ok(profiles)
+}
+
+fn remove_profiles(dir: &Path, prefix: &str) -> MorfoResult<()> {
+    for profile in profiles(dir, prefix)? {
+        fs::remove_file(profile)?;
+    }
+    Ok(())
+}
+
+fn find_profile(dir: &Path, prefix: &str) -> Option<PathBuf> {
+    profiles(dir, prefix).ok()?.into_iter().next()
+}
+
+/// Parses the peak and the peak memory consumers out of `heaptrack_print`'s report.
+fn parse_heaptrack(report: &str) -> (u64, Vec<AllocationSite>) {
+    let peak_re = Regex::new(r"^peak heap memory consumption: (\S+)").unwrap();
+    let site_re = Regex::new(r"^(\S+) peak memory consumed over \d+ calls from").unwrap();
+
+    let mut peak = 0;
+    let mut sites = Vec::new();
+    let mut in_consumers = false;
+    let mut lines = report.lines();
+    while let Some(line) = lines.next() {
+        if let Some(cap) = peak_re.captures(line) {
+            peak = utils::parse_size(&cap[1]).unwrap_or(0);
+        }
+        if line.starts_with("PEAK MEMORY CONSUMERS") {
+            in_consumers = true;
+        } else if line.chars().all(|c| c.is_ascii_uppercase() || c == ' ') && !line.is_empty() {
+            in_consumers = false;
+        }
+        if !in_consumers {
+            continue;
+        }
+        let Some(cap) = site_re.captures(line) else {
+            continue;
+        };
+        let function = lines.next().unwrap_or_default().trim();
+        let location = match lines.next().map(str::trim) {
+            Some(at) if at.starts_with("at ") => format!("{} ({})", function, &at[3..]),
+            _ => function.to_owned(),
+        };
+        sites.push(AllocationSite {
+            location,
+            bytes: utils::parse_size(&cap[1]).unwrap_or(0),
+        });
+    }
+
+    sites.truncate(TOP_SITES);
+    (peak, sites)
+}
+
+/// Parses the peak and the allocation sites at the peak out of a massif profile.
+fn parse_massif(profile: &str) -> (u64, Vec<AllocationSite>) {
+    // the root's children are the allocation sites, one space deep
+    let site_re = Regex::new(r"^ n\d+: (\d+) (?:0x[0-9A-Fa-f]+: )?(.*)$").unwrap();
+
+    let mut peak = 0;
+    let mut sites = Vec::new();
+    let mut in_peak_tree = false;
+    for line in profile.lines() {
+        if let Some(bytes) = line.strip_prefix("mem_heap_B=") {
+            peak = peak.max(bytes.parse().unwrap_or(0));
+        } else if line.starts_with("heap_tree=") {
+            in_peak_tree = line == "heap_tree=peak";
+        } else if line.starts_with('#') {
+            in_peak_tree = false;
+        } else if in_peak_tree {
+            let Some(cap) = site_re.captures(line) else {
+                continue;
+            };
+            // sites below massif's threshold are summarised rather than named
+            if cap[2].starts_with("in ") && cap[2].contains("below massif's threshold") {
+                continue;
+            }
+            sites.push(AllocationSite {
+                location: cap[2].to_owned(),
+                bytes: cap[1].parse().unwrap_or(0),
+            });
+        }
+    }
+
+    sites.sort_by_key(|site| Reverse(site.bytes));
+    sites.truncate(TOP_SITES);
+    (peak, sites)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heap_parse_massif() {
+        let profile = "\
+desc: (none)
+cmd: ./main
+time_unit: i
+#-----------
+snapshot=0
+#-----------
+time=0
+mem_heap_B=0
+mem_heap_extra_B=0
+mem_stacks_B=0
+heap_tree=empty
+#-----------
+snapshot=1
+#-----------
+time=2000
+mem_heap_B=5000
+mem_heap_extra_B=24
+mem_stacks_B=0
+heap_tree=peak
+n3: 5000 (heap allocation functions) malloc/new/new[], --alloc-fns, etc.
+ n1: 4000 0x10915E: make_buffer (main.c:5)
+  n0: 4000 0x109180: main (main.c:10)
+ n0: 1000 0x109190: main (main.c:11)
+ n0: 0 in 1 place, below massif's threshold (1.00%)
+#-----------
+snapshot=2
+#-----------
+time=3000
+mem_heap_B=1000
+mem_heap_extra_B=8
+mem_stacks_B=0
+heap_tree=empty
+";
+        let (peak, sites) = parse_massif(profile);
+        assert_eq!(peak, 5000);
+        assert_eq!(
+            sites,
+            vec![
+                AllocationSite {
+                    location: "make_buffer (main.c:5)".to_owned(),
+                    bytes: 4000
+                },
+                AllocationSite {
+                    location: "main (main.c:11)".to_owned(),
+                    bytes: 1000
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn heap_parse_heaptrack() {
+        let report = "\
+reading file \"heaptrack.main.1234.zst\" - please wait, this might take some time...
+Debuggee command was: ./main
+finished reading file, now analyzing data:
+
+MOST CALLS TO ALLOCATION FUNCTIONS
+2 calls to allocation functions with 1.00M peak consumption from
+make_buffer
+  at main.c:5
+  in ./main
+
+PEAK MEMORY CONSUMERS
+1.00M peak memory consumed over 1 calls from
+make_buffer
+  at main.c:5
+  in ./main
+1.00M consumed over 1 calls from:
+    main
+      at main.c:10
+      in ./main
+64B peak memory consumed over 1 calls from
+main
+  in ./main
+
+MEMORY LEAKS
+0B leaked over 0 calls from
+
+total runtime: 0.00s.
+peak heap memory consumption: 1.00M
+peak RSS (including heaptrack overhead): 3.45M
+";
+        let (peak, sites) = parse_heaptrack(report);
+        assert_eq!(peak, 1 << 20);
+        assert_eq!(sites.len(), 2);
+        assert_eq!(sites[0].location, "make_buffer (main.c:5)");
+        assert_eq!(sites[1].location, "main");
+        assert_eq!(sites[1].bytes, 64);
+    }
+
+    #[test]
+    fn heap_over_budget() {
+        let profile = HeapProfile {
+            profile: PathBuf::from("massif.out.main"),
+            peak: 2048,
+            sites: Vec::new(),
+            budget: Some(1024),
+        };
+        assert!(profile.over_budget());
+        assert_eq!(
+            profile.to_string(),
+            "Peak heap usage: 2.00 KiB (budget 1.00 KiB)\nProfile: massif.out.main"
+        );
+        assert!(!HeapProfile {
+            budget: None,
+            ..profile
+        }
+        .over_budget());
+    }
+}
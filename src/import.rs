@@ -0,0 +1,439 @@
+//! Importing the builds of other build systems.
+//!
+//! `morfo import compile_commands.json` writes a `morfo.toml` for a project built so far
+//! with CMake, Meson or make under Bear, from the compilation database they leave
+//! behind. The flags every source is compiled with become the settings of the config:
+//! the compiler, `includes`, `defines`, `std`, `opt_level`, `debug` and, for the rest,
+//! `cflags`. The flags only some of the sources are compiled with are kept in a
+//! `build.rhai` script setting them per file (see the [`script`] module), which the
+//! config then points at. The directories of the sources, but the directory of the
+//! config itself, become `src`, so that sources the build left out are not built.
+//!
+//! `morfo import Makefile` does the same for a project built by a single Makefile, from
+//! the commands `make -n -B` prints without running them. The commands compiling a C
+//! source are taken as the compilation database, and the libraries the program is
+//! linked with, with `-l`, become the `[libraries]` of the config. The other flags of the
+//! link, such as `-L` and `-Wl,`, are added to `cflags`, which are passed to the linker
+//! too. A Makefile building several programs is imported as one.
+//!
+//! The include directories under the directory of the config are written relative to
+//! it, like the sources of the script, and the others are kept absolute. The flags of
+//! the compilation itself, such as `-c`, `-o` and those writing dependency files, are
+//! left out, as morfo adds its own.
+//!
+//! [`script`]: crate::script
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    error::{MorfoError, MorfoResult},
+    utils,
+};
+
+/// A command of the compilation database.
+#[derive(Debug, serde::Deserialize)]
+struct Entry {
+    directory: PathBuf,
+    file: PathBuf,
+    arguments: Option<Vec<String>>,
+    command: Option<String>,
+}
+
+/// What `morfo import` writes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    /// The config, in TOML.
+    pub config: String,
+    /// The build script setting the flags of the sources compiled differently from the
+    /// others, if there are any.
+    pub script: Option<String>,
+}
+
+/// The flags of the compilation itself, followed by an argument, which morfo sets.
+const SKIPPED_WITH_ARG: [&str; 4] = ["-o", "-MF", "-MT", "-MQ"];
+/// The flags of the compilation itself, which morfo sets.
+const SKIPPED: [&str; 4] = ["-c", "-MD", "-MMD", "-MP"];
+
+/// The flags of a link that are not about the compilation.
+const LINK_PREFIXES: [&str; 3] = ["-l", "-L", "-Wl,"];
+
+/// Reconstructs a config, and a build script if needed, from `input`, a compilation
+/// database or a Makefile, for a config written to the directory `root`.
+///
+/// # Errors
+///
+/// If the database cannot be read, is not a compilation database, or is empty, or if
+/// make cannot be run or fails.
+pub fn import(input: &Path, root: &Path) -> MorfoResult<Import> {
+    let invalid = |msg: String| MorfoError::InvlidConfig(format!("{}: {}", input.display(), msg));
+    let (entries, link) = if is_makefile(input) {
+        dry_run(input)?
+    } else {
+        let entries: Vec<Entry> = serde_json::from_str(&fs::read_to_string(input)?)
+            .map_err(|e| invalid(e.to_string()))?;
+        (entries, Vec::new())
+    };
+    let root = utils::normalize(&std::path::absolute(root)?);
+
+    let mut cc = None;
+    let mut files: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for entry in &entries {
+        let args = match (&entry.arguments, &entry.command) {
+            (Some(arguments), _) => arguments.clone(),
+            (None, Some(command)) => utils::split_words(command),
+            (None, None) => return Err(invalid("an entry has no command".to_owned())),
+        };
+        let Some((compiler, args)) = args.split_first() else {
+            return Err(invalid("an entry has an empty command".to_owned()));
+        };
+        cc.get_or_insert_with(|| compiler.clone());
+        let file = relative(&entry.directory.join(&entry.file), &root);
+        files
+            .entry(file)
+            .or_insert_with(|| flags(args, entry, &root));
+    }
+    let Some(cc) = cc else {
+        return Err(invalid("no compile commands".to_owned()));
+    };
+
+    // the flags of the first source that every other source has too
+    let mut common = files.values().next().cloned().unwrap_or_default();
+    common.retain(|flag| files.values().all(|flags| flags.contains(flag)));
+    let overrides: BTreeMap<&String, Vec<&String>> = files
+        .iter()
+        .map(|(file, flags)| (file, flags.iter().filter(|f| !common.contains(f)).collect()))
+        .filter(|(_, flags): &(_, Vec<_>)| !flags.is_empty())
+        .collect();
+
+    let src: BTreeSet<_> = files
+        .keys()
+        .filter_map(|file| Path::new(file).parent())
+        .filter(|dir| !dir.as_os_str().is_empty() && dir.is_relative())
+        .map(|dir| format!("{}/", dir.display()))
+        .collect();
+    let mut libraries = Vec::new();
+    for flag in link {
+        let (flags, flag) = match flag.strip_prefix("-l") {
+            Some(library) => (&mut libraries, library.to_owned()),
+            None => match flag.strip_prefix("-L") {
+                Some(dir) => (
+                    &mut common,
+                    format!("-L{}", relative(&root.join(dir), &root)),
+                ),
+                None => (&mut common, flag),
+            },
+        };
+        if !flags.contains(&flag) {
+            flags.push(flag);
+        }
+    }
+
+    let mut config = format!("# Imported from {} by `morfo import`.\n\n", input.display());
+    config += &settings(&cc, &common);
+    if !src.is_empty() {
+        config += &format!("src = {}\n", toml::Value::from(Vec::from_iter(src)));
+    }
+    let script = (!overrides.is_empty()).then(|| {
+        config += "script = \"build.rhai\"\n";
+        script(&overrides)
+    });
+    if !libraries.is_empty() {
+        config += "\n[libraries]\n";
+        for library in libraries {
+            let bare = library
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            match bare {
+                true => config += &format!("{} = {{}}\n", library),
+                false => config += &format!("{} = {{}}\n", toml::Value::from(library)),
+            }
+        }
+    }
+    Ok(Import { config, script })
+}
+
+/// Returns whether `input` is a Makefile rather than a compilation database.
+fn is_makefile(input: &Path) -> bool {
+    let name = utils::file_name(input);
+    matches!(name.as_str(), "Makefile" | "makefile" | "GNUmakefile")
+        || input.extension().is_some_and(|extension| extension == "mk")
+}
+
+/// Runs `make -n -B` over `makefile` in its directory, and returns the compile commands
+/// it prints, with the flags of the links.
+fn dry_run(makefile: &Path) -> MorfoResult<(Vec<Entry>, Vec<String>)> {
+    let dir = match makefile.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let dir = utils::normalize(&std::path::absolute(dir)?);
+    let mut make = Command::new("make");
+    make.args(["-n", "-B", "-f"])
+        .arg(makefile.file_name().unwrap_or(makefile.as_os_str()))
+        .current_dir(&dir);
+    let output = make.output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => MorfoError::MissingTool("make".to_owned()),
+        _ => e.into(),
+    })?;
+    if !output.status.success() {
+        return Err(MorfoError::CommandFailure(
+            "make -n -B".to_owned(),
+            output.status.code(),
+        ));
+    }
+    Ok(parse_dry_run(
+        &String::from_utf8_lossy(&output.stdout),
+        &dir,
+    ))
+}
+
+/// Returns the compile commands of the output of `make -n` run in `dir`, one per C
+/// source, with the flags of the commands linking with the same compilers.
+fn parse_dry_run(output: &str, dir: &Path) -> (Vec<Entry>, Vec<String>) {
+    let is_source = |arg: &String| arg.ends_with(".c") && !arg.starts_with('-');
+    let is_link_flag = |arg: &String| LINK_PREFIXES.iter().any(|p| arg.starts_with(p));
+    let words: Vec<_> = output.lines().map(utils::split_words).collect();
+    let commands: Vec<_> = words
+        .iter()
+        .flat_map(|words| words.split(|word| matches!(word.as_str(), "&&" | "||" | ";")))
+        .filter(|command| command.len() > 1)
+        .collect();
+
+    // a compiler builds a C source into an object or an executable, which tells it
+    // apart from the `echo CC main.c` of a quiet Makefile
+    let mut compilers = BTreeSet::new();
+    let mut entries = Vec::new();
+    for command in &commands {
+        let sources: Vec<_> = command.iter().filter(|arg| is_source(arg)).collect();
+        let outputs = command
+            .iter()
+            .any(|arg| arg == "-c" || arg.starts_with("-o"));
+        if sources.is_empty() || !outputs {
+            continue;
+        }
+        compilers.insert(&command[0]);
+        for source in &sources {
+            let arguments = command
+                .iter()
+                .filter(|arg| !is_link_flag(arg) && (arg == source || !is_source(arg)))
+                .cloned()
+                .collect();
+            entries.push(Entry {
+                directory: dir.to_path_buf(),
+                file: PathBuf::from(source),
+                arguments: Some(arguments),
+                command: None,
+            });
+        }
+    }
+
+    let mut link = Vec::new();
+    for command in &commands {
+        if compilers.contains(&command[0]) && !command.iter().any(|arg| arg == "-c") {
+            link.extend(command.iter().filter(|arg| is_link_flag(arg)).cloned());
+        }
+    }
+    (entries, link)
+}
+
+/// Returns the flags of the compile command `args` of `entry` that are not about the
+/// compilation itself, with their arguments joined to them, and the include directories
+/// made relative to `root`.
+fn flags(args: &[String], entry: &Entry, root: &Path) -> Vec<String> {
+    let source = utils::normalize(&entry.directory.join(&entry.file));
+    let mut flags = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if SKIPPED.contains(&arg.as_str()) {
+            continue;
+        }
+        if SKIPPED_WITH_ARG.contains(&arg.as_str()) {
+            args.next();
+            continue;
+        }
+        if arg.starts_with("-o") || utils::normalize(&entry.directory.join(arg)) == source {
+            continue;
+        }
+        let flag = match arg.as_str() {
+            "-I" | "-D" | "-U" => match args.next() {
+                Some(value) => format!("{}{}", arg, value),
+                None => continue,
+            },
+            "-isystem" | "-include" => match args.next() {
+                Some(value) => format!("{} {}", arg, value),
+                None => continue,
+            },
+            _ => arg.clone(),
+        };
+        match flag.strip_prefix("-I") {
+            Some(dir) => flags.push(format!("-I{}", relative(&entry.directory.join(dir), root))),
+            None => flags.push(flag),
+        }
+    }
+    flags
+}
+
+/// Returns `path` relative to `root` if it is under it, or as it is otherwise.
+fn relative(path: &Path, root: &Path) -> String {
+    let path = utils::normalize(path);
+    match path.strip_prefix(root) {
+        Ok(relative) if relative.as_os_str().is_empty() => ".".to_owned(),
+        Ok(relative) => relative.to_string_lossy().into_owned(),
+        Err(_) => path.to_string_lossy().into_owned(),
+    }
+}
+
+/// Returns the settings of the config compiling with `cc` and the `common` flags.
+fn settings(cc: &str, common: &[String]) -> String {
+    let mut includes = Vec::new();
+    let mut defines = toml::Table::new();
+    let mut std = None;
+    let mut opt_level = None;
+    let mut debug = false;
+    let mut cflags = Vec::new();
+    for flag in common {
+        if let Some(dir) = flag.strip_prefix("-I") {
+            includes.push(toml::Value::from(dir));
+        } else if let Some(define) = flag.strip_prefix("-D") {
+            let (name, value) = define.split_once('=').unwrap_or((define, "1"));
+            defines.insert(name.to_owned(), toml::Value::from(value));
+        } else if let Some(standard) = flag.strip_prefix("-std=") {
+            std = Some(standard);
+        } else if let Some(level) = flag.strip_prefix("-O") {
+            opt_level = Some(if level.is_empty() { "1" } else { level });
+        } else if flag == "-g" {
+            debug = true;
+        } else {
+            cflags.push(toml::Value::from(flag.as_str()));
+        }
+    }
+
+    let mut settings = format!("cc = {}\n", toml::Value::from(cc));
+    if !cflags.is_empty() {
+        settings += &format!("cflags = {}\n", toml::Value::Array(cflags));
+    }
+    if !includes.is_empty() {
+        settings += &format!("includes = {}\n", toml::Value::Array(includes));
+    }
+    if !defines.is_empty() {
+        settings += &format!("defines = {}\n", toml::Value::Table(defines));
+    }
+    if let Some(std) = std {
+        settings += &format!("std = {}\n", toml::Value::from(std));
+    }
+    if let Some(level) = opt_level {
+        settings += &format!("opt_level = {}\n", toml::Value::from(level));
+    }
+    if debug {
+        settings += "debug = true\n";
+    }
+    settings
+}
+
+/// Returns the build script adding the flags of `overrides` to their sources.
+fn script(overrides: &BTreeMap<&String, Vec<&String>>) -> String {
+    let quote = |text: &str| serde_json::Value::from(text).to_string();
+    let mut script = String::from(
+        "// Imported by `morfo import`: the flags of the sources compiled differently\n\
+         // from the others.\n\
+         fn flags(file, build) {\n",
+    );
+    for (file, flags) in overrides {
+        let flags: Vec<_> = flags.iter().map(|flag| quote(flag)).collect();
+        script += &format!(
+            "    if file.ends_with({}) {{ return [{}]; }}\n",
+            quote(file),
+            flags.join(", ")
+        );
+    }
+    script += "}\n";
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn import_compile_commands() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let root = tmp_dir.path();
+        let build = root.join("build");
+        let database = root.join("compile_commands.json");
+        let entries = serde_json::json!([
+            {
+                "directory": build,
+                "file": "../src/main.c",
+                "arguments": [
+                    "/usr/bin/gcc", "-I../include", "-I", "/opt/lib/include", "-DNDEBUG",
+                    "-DVERSION=\"2\"", "-std=c11", "-O2", "-Wall", "-MD", "-MF",
+                    "main.c.d", "-o", "main.c.o", "-c", "../src/main.c"
+                ]
+            },
+            {
+                "directory": build,
+                "file": root.join("src/fast.c"),
+                "command": format!(
+                    "/usr/bin/gcc -I../include -I/opt/lib/include -DNDEBUG \
+                     '-DVERSION=\"2\"' -std=c11 -O2 -Wall -funroll-loops \
+                     -o fast.c.o -c {}",
+                    root.join("src/fast.c").display()
+                )
+            }
+        ]);
+        fs::write(&database, entries.to_string()).unwrap();
+
+        let import = import(&database, root).unwrap();
+        let config: Config = toml::from_str(&import.config).unwrap();
+        assert_eq!(config.get_cc(), "/usr/bin/gcc");
+        assert_eq!(config.get_cflags(), ["-Wall"]);
+        assert_eq!(config.get_includes(), ["include", "/opt/lib/include"]);
+        assert_eq!(config.get_defines()["NDEBUG"], "1");
+        assert_eq!(config.get_defines()["VERSION"], "\"2\"");
+        assert_eq!(config.get_std(), Some("c11".to_owned()));
+        assert_eq!(config.get_opt_level(), Some("2".to_owned()));
+        assert_eq!(config.get_src(), ["src/"]);
+        assert_eq!(config.get_script(), Some(PathBuf::from("build.rhai")));
+        assert_eq!(
+            import.script.unwrap().lines().nth(3).unwrap(),
+            r#"    if file.ends_with("src/fast.c") { return ["-funroll-loops"]; }"#
+        );
+    }
+
+    #[test]
+    fn import_makefile() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let root = tmp_dir.path();
+        fs::create_dir(root.join("util")).unwrap();
+        fs::write(root.join("main.c"), "int main(void) { return 0; }\n").unwrap();
+        fs::write(root.join("util/str.c"), "int str(void) { return 0; }\n").unwrap();
+        fs::write(
+            root.join("Makefile"),
+            "CFLAGS = -Wall -O2 -Iinclude\n\
+             app: main.o util/str.o\n\
+             \t@echo LD $@\n\
+             \t$(CC) -o $@ $^ -Llib -lm -lz\n\
+             %.o: %.c\n\
+             \t@echo CC $<\n\
+             \t$(CC) $(CFLAGS) -c -o $@ $<\n",
+        )
+        .unwrap();
+
+        let import = import(&root.join("Makefile"), root).unwrap();
+        let config: Config = toml::from_str(&import.config).unwrap();
+        assert_eq!(config.get_cc(), "cc");
+        assert_eq!(config.get_cflags(), ["-Wall", "-Llib"]);
+        assert_eq!(config.get_includes(), ["include"]);
+        assert_eq!(config.get_opt_level(), Some("2".to_owned()));
+        assert_eq!(config.get_src(), ["util/"]);
+        let libraries: Vec<_> = config.get_libraries().into_keys().collect();
+        assert_eq!(libraries, ["m", "z"]);
+        assert_eq!(import.script, None);
+    }
+}
@@ -0,0 +1,205 @@
+//! Ctrl+C handling.
+//!
+//! When morfo is interrupted (Ctrl+C, or `SIGTERM`/`SIGHUP` on Unix), it forwards
+//! `SIGINT` to the compiler, linker or program it is running and gives them a moment to
+//! exit. Whatever is still running after that is killed, the outputs they were writing
+//! are removed so that no half-written object or executable is left behind, and morfo
+//! exits with status 130.
+//!
+//! Only processes started through this module are tracked. A compiler runs in a process
+//! group of its own, so that the processes it starts (`cc1`, `as`, ...) are signalled
+//! with it. On Windows, the console delivers Ctrl+C to them directly, so nothing is
+//! forwarded.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::{self, Child, Command, ExitStatus},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::error::{MorfoError, MorfoResult};
+
+/// How long the running processes get to exit after the interrupt is forwarded.
+const GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// The exit status of a process interrupted by Ctrl+C.
+const INTERRUPTED_STATUS: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// The processes running, with the output each is writing, if any.
+static RUNNING: Mutex<Vec<Running>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, PartialEq)]
+struct Running {
+    pid: u32,
+    /// Whether the process leads a process group of its own, which is signalled whole.
+    group: bool,
+    output: Option<PathBuf>,
+}
+
+/// Installs the interrupt handler. It can only be installed once per process.
+///
+/// # Errors
+///
+/// If a handler is already installed.
+pub fn install() -> MorfoResult<()> {
+    ctrlc::set_handler(on_interrupt).map_err(|e| MorfoError::from(io::Error::other(e)))
+}
+
+fn on_interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    let interrupted = running();
+    for process in &interrupted {
+        signal(process.pid, process.group, Signal::Interrupt);
+    }
+
+    let deadline = Instant::now() + GRACE_PERIOD;
+    while !running().is_empty() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(20));
+    }
+    for process in running() {
+        signal(process.pid, process.group, Signal::Kill);
+    }
+    for output in interrupted
+        .iter()
+        .filter_map(|process| process.output.as_ref())
+    {
+        let _ = fs::remove_file(output);
+    }
+    process::exit(INTERRUPTED_STATUS);
+}
+
+fn running() -> Vec<Running> {
+    RUNNING.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+enum Signal {
+    Interrupt,
+    Kill,
+}
+
+/// Sends `signal` to the process `pid`, or to its whole process group if it leads one.
+#[cfg(unix)]
+fn signal(pid: u32, group: bool, signal: Signal) {
+    let signal = match signal {
+        Signal::Interrupt => libc::SIGINT,
+        Signal::Kill => libc::SIGKILL,
+    };
+    let pid = pid as libc::pid_t;
+    // SAFETY: kill has no memory safety requirements
+    unsafe {
+        libc::kill(if group { -pid } else { pid }, signal);
+    }
+}
+
+#[cfg(not(unix))]
+fn signal(_pid: u32, _group: bool, _signal: Signal) {}
+
+/// Spawns `cmd`, tracked so that an interrupt reaches it and removes `output`, which it
+/// writes. The child must be waited for with [`wait`].
+pub(crate) fn spawn(cmd: &mut Command, output: Option<&Path>) -> io::Result<Child> {
+    track(cmd, false, output)
+}
+
+/// Spawns `cmd` like [`spawn`], in a process group of its own, so that the processes it
+/// starts are interrupted, or killed by [`wait_timeout`], along with it.
+///
+/// The group is not the foreground one of the terminal, so this is only meant for
+/// processes that do not read it, such as compilers.
+pub(crate) fn spawn_group(cmd: &mut Command, output: Option<&Path>) -> io::Result<Child> {
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(cmd, 0);
+    track(cmd, cfg!(unix), output)
+}
+
+fn track(cmd: &mut Command, group: bool, output: Option<&Path>) -> io::Result<Child> {
+    halt_if_interrupted();
+    // the handler must not miss a process spawned while it runs
+    let mut running = RUNNING.lock().unwrap_or_else(|e| e.into_inner());
+    let child = cmd.spawn()?;
+    running.push(Running {
+        pid: child.id(),
+        group,
+        output: output.map(Path::to_path_buf),
+    });
+    Ok(child)
+}
+
+/// Waits for a child started with [`spawn`] to exit.
+pub(crate) fn wait(child: &mut Child) -> io::Result<ExitStatus> {
+    let status = child.wait();
+    untrack(child.id());
+    halt_if_interrupted();
+    status
+}
+
+/// Waits for a child started with [`spawn`] to exit for at most `timeout`, and kills it
+/// if it has not by then, returning `None`. A child started with [`spawn_group`] is
+/// killed with its whole process group.
+pub(crate) fn wait_timeout(child: &mut Child, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            untrack(child.id());
+            halt_if_interrupted();
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            let group = running()
+                .iter()
+                .any(|process| process.pid == child.id() && process.group);
+            if group {
+                signal(child.id(), true, Signal::Kill);
+            } else {
+                child.kill()?;
+            }
+            wait(child)?;
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn untrack(pid: u32) {
+    RUNNING
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .retain(|process| process.pid != pid);
+}
+
+/// Once interrupted, the handler cleans up and exits, so nothing else may be started and
+/// a process stopped by the interrupt must not be reported as a failure.
+fn halt_if_interrupted() {
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        loop {
+            thread::park();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interrupt_child_is_tracked_while_running() {
+        let output = PathBuf::from("main.o");
+        let mut child = spawn(&mut Command::new("true"), Some(&output)).unwrap();
+        let pid = child.id();
+        assert!(running().contains(&Running {
+            pid,
+            group: false,
+            output: Some(output),
+        }));
+
+        assert!(wait(&mut child).unwrap().success());
+        assert!(running().iter().all(|process| process.pid != pid));
+    }
+}
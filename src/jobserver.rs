@@ -0,0 +1,241 @@
+//! The jobserver of GNU make.
+//!
+//! When morfo is run by a recipe of `make -jN`, make shares `N` job slots between all
+//! the programs it runs through a jobserver it describes in `MAKEFLAGS`, as a pipe
+//! (`--jobserver-auth=R,W`, or `--jobserver-fds=R,W` before make 4.2) or, since make 4.4,
+//! a named FIFO (`--jobserver-auth=fifo:PATH`). Every program has one slot of its own,
+//! and must take a token from the jobserver for each other job it runs at the same time,
+//! and give it back after.
+//!
+//! morfo builds its first main file of a batch, or cell of a matrix, in its own slot,
+//! and the others only while it holds a token, so that `make -j8` runs at most eight jobs
+//! whichever of them morfo is building. The recipe must be marked with `+`, or make
+//! closes the pipe before running morfo, which then builds `--jobs` at a time as usual.
+//!
+//! The jobserver is only used on Unix.
+
+/// A job slot taken from the jobserver, given back when dropped.
+#[cfg(unix)]
+pub(crate) struct Token<'a> {
+    jobserver: &'a Jobserver,
+    byte: u8,
+}
+
+#[cfg(unix)]
+impl Drop for Token<'_> {
+    fn drop(&mut self) {
+        use std::io::Write;
+
+        // a token that cannot be given back is lost to make, which then runs fewer jobs
+        let _ = (&self.jobserver.write).write_all(&[self.byte]);
+    }
+}
+
+/// A job slot taken from the jobserver, which only exists on Unix.
+#[cfg(not(unix))]
+pub(crate) struct Token<'a>(std::marker::PhantomData<&'a Jobserver>);
+
+/// The jobserver of the make running morfo.
+pub(crate) struct Jobserver {
+    /// The end tokens are taken from, not blocking.
+    #[cfg(unix)]
+    read: std::fs::File,
+    /// The end tokens are given back to.
+    #[cfg(unix)]
+    write: std::fs::File,
+}
+
+/// Where the jobserver described by `MAKEFLAGS` is.
+#[derive(Debug, PartialEq, Eq)]
+enum Auth<'a> {
+    /// The ends of a pipe inherited from make.
+    Fds(i32, i32),
+    /// A named FIFO.
+    Fifo(&'a str),
+}
+
+/// Returns the jobserver of the make running morfo, if there is one.
+pub(crate) fn client() -> Option<&'static Jobserver> {
+    static CLIENT: std::sync::OnceLock<Option<Jobserver>> = std::sync::OnceLock::new();
+    CLIENT
+        .get_or_init(|| {
+            let makeflags = std::env::var("MAKEFLAGS").ok()?;
+            Jobserver::open(auth(&makeflags)?)
+        })
+        .as_ref()
+}
+
+/// Returns where the jobserver is according to `makeflags`. The last option wins, like
+/// make does when the flags of a sub-make are appended.
+fn auth(makeflags: &str) -> Option<Auth<'_>> {
+    makeflags.split_whitespace().rev().find_map(|flag| {
+        let value = flag
+            .strip_prefix("--jobserver-auth=")
+            .or_else(|| flag.strip_prefix("--jobserver-fds="))?;
+        if let Some(path) = value.strip_prefix("fifo:") {
+            return Some(Auth::Fifo(path));
+        }
+        let (read, write) = value.split_once(',')?;
+        Some(Auth::Fds(read.parse().ok()?, write.parse().ok()?))
+    })
+}
+
+#[cfg(unix)]
+impl Jobserver {
+    /// Opens the jobserver at `auth`, or returns `None` if it is not there, as when make
+    /// closed its pipe before running a recipe not marked with `+`.
+    fn open(auth: Auth) -> Option<Jobserver> {
+        use std::{fs::OpenOptions, os::unix::fs::OpenOptionsExt};
+
+        let open = |path: &str, write: bool| {
+            OpenOptions::new()
+                .read(!write)
+                .write(write)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(path)
+                .ok()
+        };
+        match auth {
+            Auth::Fifo(path) => Some(Jobserver {
+                read: open(path, false)?,
+                write: open(path, true)?,
+            }),
+            Auth::Fds(read, write) => {
+                // SAFETY: `fcntl` only checks that the descriptors are open
+                let valid = |fd| unsafe { libc::fcntl(fd, libc::F_GETFD) } != -1;
+                if !valid(read) || !valid(write) {
+                    return None;
+                }
+                // on Linux, the pipe is opened again so that it can be read without
+                // blocking, without changing the ends make and the other jobs share
+                #[cfg(target_os = "linux")]
+                if let (Some(read), Some(write)) = (
+                    open(&format!("/proc/self/fd/{}", read), false),
+                    open(&format!("/proc/self/fd/{}", write), true),
+                ) {
+                    return Some(Jobserver { read, write });
+                }
+                Some(Jobserver {
+                    read: duplicate(read)?,
+                    write: duplicate(write)?,
+                })
+            }
+        }
+    }
+
+    /// Takes a token, waiting for one while `wanted` returns `true`, or returns `None` if
+    /// it stopped waiting or the jobserver failed.
+    pub(crate) fn acquire_while(&self, wanted: impl Fn() -> bool) -> Option<Token<'_>> {
+        use std::{io::Read, os::fd::AsRawFd};
+
+        while wanted() {
+            let mut poll = libc::pollfd {
+                fd: self.read.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: `poll` is given one valid `pollfd`
+            if unsafe { libc::poll(&mut poll, 1, 100) } < 0 {
+                if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return None;
+            }
+            let mut byte = [0];
+            // another job may have taken the token announced by `poll` first
+            match (&self.read).read(&mut byte) {
+                Ok(1) => {
+                    return Some(Token {
+                        jobserver: self,
+                        byte: byte[0],
+                    })
+                }
+                Ok(_) => return None,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::Interrupted
+                    ) => {}
+                Err(_) => return None,
+            }
+        }
+        None
+    }
+}
+
+/// Returns a new descriptor of the open file `fd`.
+#[cfg(unix)]
+fn duplicate(fd: i32) -> Option<std::fs::File> {
+    use std::os::fd::FromRawFd;
+
+    // SAFETY: `fd` is open, and the duplicate is owned by the returned file alone
+    let duplicate = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+    (duplicate != -1).then(|| unsafe { std::fs::File::from_raw_fd(duplicate) })
+}
+
+#[cfg(not(unix))]
+impl Jobserver {
+    fn open(_auth: Auth) -> Option<Jobserver> {
+        None
+    }
+
+    /// Never takes a token, as there is no jobserver off Unix.
+    pub(crate) fn acquire_while(&self, _wanted: impl Fn() -> bool) -> Option<Token<'_>> {
+        None
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[test]
+    fn jobserver_limits_jobs() {
+        assert_eq!(
+            auth(" -j --jobserver-fds=3,4 --jobserver-auth=5,6"),
+            Some(Auth::Fds(5, 6))
+        );
+        assert_eq!(
+            auth("-j4 --jobserver-auth=fifo:/tmp/GMfifo1"),
+            Some(Auth::Fifo("/tmp/GMfifo1"))
+        );
+        assert_eq!(auth("-k"), None);
+
+        // a pipe holding two tokens, like `make -j3` with one slot for morfo
+        let mut fds = [0; 2];
+        // SAFETY: `fds` has room for both ends
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let jobserver = Jobserver::open(Auth::Fds(fds[0], fds[1])).unwrap();
+        (&jobserver.write).write_all(b"++").unwrap();
+
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = AtomicUsize::new(0);
+        let items: Vec<usize> = (0..12).collect();
+        crate::utils::parallel_map_with(&items, 8, Some(&jobserver), |_| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            most.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(20));
+            running.fetch_sub(1, Ordering::SeqCst);
+        });
+        assert_eq!(most.load(Ordering::SeqCst), 3);
+
+        // every token was given back
+        let mut tokens = [0; 8];
+        assert_eq!((&jobserver.read).read(&mut tokens).unwrap(), 2);
+
+        // SAFETY: the ends are closed once, after the jobserver is done with them
+        unsafe {
+            libc::close(fds[0]);
+            libc::close(fds[1]);
+        }
+    }
+}
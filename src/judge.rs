@@ -0,0 +1,232 @@
+//! Judging a solution against test cases, like the judges of programming contests.
+//!
+//! `morfo judge sol.c --cases cases/` builds the solution with the `release` profile,
+//! unless another is selected, and runs it once for every `<name>.in` in the cases
+//! directory, with the file as its stdin. Each run gets a verdict:
+//!
+//! | Verdict | Meaning                                                         |
+//! |---------|-----------------------------------------------------------------|
+//! | `AC`    | The output is the one in `<name>.out`                           |
+//! | `WA`    | The output is another                                           |
+//! | `TLE`   | The program was killed after running for longer than the limit  |
+//! | `RE`    | The program exited with a failure or was killed by a signal     |
+//!
+//! Like most judges, outputs are compared line by line, ignoring the whitespace at the
+//! end of every line and the blank lines at the end of the output.
+
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    config::Config,
+    error::{MorfoError, MorfoResult},
+};
+
+/// The verdict on a run of the solution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Accepted: the output is the expected one.
+    Accepted,
+    /// Wrong answer: the output is another.
+    WrongAnswer,
+    /// Time limit exceeded: the program was killed after running for too long.
+    TimeLimitExceeded,
+    /// Runtime error: the program exited with this code, or was killed by a signal.
+    RuntimeError(Option<i32>),
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            Verdict::Accepted => "AC",
+            Verdict::WrongAnswer => "WA",
+            Verdict::TimeLimitExceeded => "TLE",
+            Verdict::RuntimeError(_) => "RE",
+        })
+    }
+}
+
+/// A test case: the input of a run and the output expected from it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Case {
+    /// The name of the case, the file name of its input without `.in`.
+    pub name: String,
+    /// The file fed to the program as its stdin.
+    pub input: PathBuf,
+    /// The file holding the expected output.
+    pub expected: PathBuf,
+}
+
+/// How the solution did on a case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseResult {
+    /// The name of the case.
+    pub name: String,
+    /// The verdict on the run.
+    pub verdict: Verdict,
+    /// How long the program ran.
+    pub duration: Duration,
+}
+
+/// Returns the cases in `dir`, one for every `<name>.in` with its `<name>.out`, sorted
+/// by name.
+///
+/// # Errors
+///
+/// [`MorfoError::FileNotFound`] if `dir` cannot be read or has no cases, or a case has
+/// no expected output.
+pub fn cases(dir: &Path) -> MorfoResult<Vec<Case>> {
+    let entries = fs::read_dir(dir).map_err(|_| MorfoError::FileNotFound(dir.to_path_buf()))?;
+    let mut cases = Vec::new();
+    for entry in entries {
+        let input = entry?.path();
+        if input.extension().is_none_or(|ext| ext != "in") {
+            continue;
+        }
+        let expected = input.with_extension("out");
+        if !expected.is_file() {
+            return Err(MorfoError::FileNotFound(expected));
+        }
+        let name = input
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        cases.push(Case {
+            name,
+            input,
+            expected,
+        });
+    }
+    if cases.is_empty() {
+        return Err(MorfoError::FileNotFound(dir.join("*.in")));
+    }
+    cases.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(cases)
+}
+
+/// Builds `main_file` and runs it on every case in `cases_dir`, killing a run after
+/// `time_limit`, and returns the verdicts in the order of the cases.
+///
+/// # Errors
+///
+/// If the cases cannot be found, the build fails or the program cannot be run. A run
+/// that fails is not an error, but a [`Verdict`].
+pub fn judge(
+    main_file: &Path,
+    config: &Config,
+    cases_dir: &Path,
+    time_limit: Duration,
+) -> MorfoResult<Vec<CaseResult>> {
+    let cases = cases(cases_dir)?;
+    let artifact = crate::compile(main_file, config)?;
+    cases
+        .iter()
+        .map(|case| run_case(&artifact.executable_path, config, case, time_limit))
+        .collect()
+}
+
+fn run_case(
+    executable: &Path,
+    config: &Config,
+    case: &Case,
+    time_limit: Duration,
+) -> MorfoResult<CaseResult> {
+    let run = crate::capture_output(executable, config, Some(&case.input), Some(time_limit))?;
+    let verdict = match run.status {
+        None => Verdict::TimeLimitExceeded,
+        Some(status) if !status.success() => Verdict::RuntimeError(status.code()),
+        Some(_) if same_output(&run.stdout, &fs::read(&case.expected)?) => Verdict::Accepted,
+        Some(_) => Verdict::WrongAnswer,
+    };
+    Ok(CaseResult {
+        name: case.name.clone(),
+        verdict,
+        duration: run.duration,
+    })
+}
+
+/// Returns whether `actual` is `expected`, but for the whitespace at the end of the
+/// lines and the blank lines at the end.
+fn same_output(actual: &[u8], expected: &[u8]) -> bool {
+    let lines = |output: &[u8]| {
+        let output = String::from_utf8_lossy(output);
+        let mut lines: Vec<String> = output
+            .lines()
+            .map(|line| line.trim_end().to_owned())
+            .collect();
+        while lines.last().is_some_and(String::is_empty) {
+            lines.pop();
+        }
+        lines
+    };
+    lines(actual) == lines(expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigBuilder;
+
+    #[test]
+    fn judge_same_output() {
+        assert!(same_output(b"1 2\n3\n", b"1 2\n3"));
+        assert!(same_output(b"1 2  \r\n3\n\n\n", b"1 2\n3\n"));
+        assert!(!same_output(b"1  2\n3\n", b"1 2\n3\n"));
+        assert!(!same_output(b"\n1 2\n", b"1 2\n"));
+    }
+
+    #[test]
+    fn judge_verdicts() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(
+            dir.join("sol.c"),
+            "#include <stdio.h>\n\
+             int main(void) {\n\
+                 long n;\n\
+                 scanf(\"%ld\", &n);\n\
+                 if (n < 0) return 3;\n\
+                 if (n == 0) for (;;) {}\n\
+                 printf(\"%ld\\n\", n * 2);\n\
+             }\n",
+        )
+        .unwrap();
+        let cases_dir = dir.join("cases");
+        fs::create_dir(&cases_dir).unwrap();
+        for (name, input, output) in [
+            ("1", "21", "42\n"),
+            ("2", "2", "5\n"),
+            ("3", "0", "0\n"),
+            ("4", "-1", ""),
+        ] {
+            fs::write(cases_dir.join(format!("{}.in", name)), input).unwrap();
+            fs::write(cases_dir.join(format!("{}.out", name)), output).unwrap();
+        }
+        let config = ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir(dir.join(".out").to_str().unwrap())
+            .build();
+
+        let results = judge(
+            &dir.join("sol.c"),
+            &config,
+            &cases_dir,
+            Duration::from_millis(300),
+        )
+        .unwrap();
+        let verdicts: Vec<_> = results.iter().map(|result| result.verdict).collect();
+        assert_eq!(
+            verdicts,
+            [
+                Verdict::Accepted,
+                Verdict::WrongAnswer,
+                Verdict::TimeLimitExceeded,
+                Verdict::RuntimeError(Some(3)),
+            ]
+        );
+        assert!(results[2].duration >= Duration::from_millis(300));
+    }
+}
@@ -0,0 +1,377 @@
+//! Where the artifacts of a build go.
+//!
+//! Everything morfo writes lives in the build directory, `.out` unless `builddir` says
+//! otherwise. A build for a target goes to the subdirectory named after the target and a
+//! build with a profile to the subdirectory named after the profile, within the one of
+//! the target if both are selected, so switching between them does not throw away what
+//! the other built:
+//!
+//! ```text
+//! .out/
+//! ├── main                  the executable
+//! ├── main.o                an object for every source, mirroring the source tree
+//! ├── src/util.o
+//! ├── manifest.json
+//! ├── build-summary.json
+//! ├── stats.json            how many artifacts every build found up to date or built
+//! ├── modules/              the compiled interfaces of the C++ modules
+//! ├── rust/                 the headers generated for the Rust crates
+//! ├── package/              the files of the archives of `morfo package`
+//! ├── dist/                 the archives of `morfo package`, for every target and profile
+//! ├── release/              everything built with `--profile release`
+//! ├── arm-linux/debug/      everything built with `--target arm-linux --profile debug`
+//! └── submissions/alice.c/  everything built for one main file of `morfo build 'submissions/*.c'`
+//! ```
+//!
+//! With `bindir`, such as `bindir = "bin"`, the executables go to that directory instead,
+//! with the same subdirectories, so `bin/release/main` is built from the objects in
+//! `.out/release/`. The fingerprint and the split debug information of an executable are
+//! kept next to it.
+//!
+//! The functions here only say where each artifact goes. The build directory itself is
+//! created with [`create_build_dir`], and the steps writing the other directories
+//! create them.
+
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{act::Act, config::Config, error::MorfoResult, utils};
+
+/// The file name of the manifest in the build directory.
+const MANIFEST: &str = "manifest.json";
+
+/// The file name of the build summary in the build directory.
+const BUILD_SUMMARY: &str = "build-summary.json";
+
+/// The file name of the cached scans of the sources in the build directory.
+const SCAN_CACHE: &str = "scan.json";
+
+/// The file name of the socket of `morfo daemon` in the build directory.
+const DAEMON_SOCKET: &str = "daemon.sock";
+
+/// The file name of the hits and misses of the builds in the build directory.
+const BUILD_STATS: &str = "stats.json";
+
+/// The file name of the lock in the build directory.
+const LOCK: &str = ".lock";
+
+/// The file name the profiled program writes its profile to.
+pub(crate) const GMON_OUT: &str = "gmon.out";
+
+/// Returns the directory the artifacts of a build with `config` go to.
+///
+/// # Examples
+///
+/// ```
+/// use morfo::{config::ConfigBuilder, layout};
+/// use std::path::PathBuf;
+///
+/// let config = ConfigBuilder::default().set_cc("gcc").build();
+/// assert_eq!(layout::build_dir(&config), PathBuf::from(".out"));
+///
+/// let release = config.for_profile("release").unwrap();
+/// assert_eq!(layout::build_dir(&release), PathBuf::from(".out/release"));
+/// ```
+pub fn build_dir(config: &Config) -> PathBuf {
+    within(config.get_build_dir(), config)
+}
+
+/// Returns the directory the executables of a build with `config` go to: the build
+/// directory unless `bindir` is set, which has the same subdirectories for the targets,
+/// profiles and batches.
+///
+/// # Examples
+///
+/// ```
+/// use morfo::{config::Config, layout};
+/// use std::path::PathBuf;
+///
+/// let config: Config = toml::from_str("cc = \"gcc\"\nbindir = \"bin\"").unwrap();
+/// assert_eq!(layout::bin_dir(&config), PathBuf::from("bin"));
+///
+/// let release = config.for_profile("release").unwrap();
+/// assert_eq!(layout::bin_dir(&release), PathBuf::from("bin/release"));
+/// assert_eq!(layout::build_dir(&release), PathBuf::from(".out/release"));
+/// ```
+pub fn bin_dir(config: &Config) -> PathBuf {
+    match config.get_bin_dir() {
+        Some(dir) => within(dir, config),
+        None => build_dir(config),
+    }
+}
+
+/// Returns the subdirectory of `dir` for the target, profile and batch of `config`.
+fn within(mut dir: PathBuf, config: &Config) -> PathBuf {
+    if let Some(target) = config.get_target() {
+        dir.push(target);
+    }
+    if let Some(profile) = config.get_profile() {
+        dir.push(profile);
+    }
+    if let Some(batch_dir) = config.get_batch_dir() {
+        dir.push(batch_dir);
+    }
+    dir
+}
+
+/// Creates the build directory of `config`, and the directory of the executables if it
+/// is another, with their parents, and returns the build directory.
+///
+/// # Errors
+///
+/// If a directory cannot be created.
+pub fn create_build_dir(config: &Config) -> MorfoResult<PathBuf> {
+    let dir = build_dir(config);
+    fs::create_dir_all(&dir)?;
+    fs::create_dir_all(bin_dir(config))?;
+    Ok(dir)
+}
+
+/// Returns where the executable built from `main_file` goes.
+pub fn executable(main_file: &Path, config: &Config) -> PathBuf {
+    let name = utils::file_name(main_file) + config.get_exe_suffix().as_str();
+    bin_dir(config).join(name)
+}
+
+/// Returns every source of `act` with the object it is compiled to.
+///
+/// The objects mirror the sources under the build directory, so `a/util.c` and
+/// `b/util.c` are compiled to `a/util.o` and `b/util.o`. Sources generated into the
+/// build directory get their objects next to them, and sources outside the project go
+/// to `external/`, in a directory named by a hash of theirs. A source named like one
+/// before it but for the extension, such as `math.cpp` after the module interface
+/// `math.cppm`, keeps its extension in the name of its object, `math.cpp.o`.
+pub fn objects(act: &Act, config: &Config) -> Vec<(PathBuf, PathBuf)> {
+    let root = utils::normalize(&config.get_build_dir());
+    let build_dir = utils::normalize(&build_dir(config));
+    let project_dir = utils::normalize(crate::project_dir(&act.name));
+    let extension = config.get_family().object_extension();
+    let mut objects: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for source in act.sources() {
+        let normalized = utils::normalize(&source);
+        let within = |dir: &Path| {
+            let relative = if dir == Path::new(".") {
+                Some(normalized.as_path()).filter(|path| path.is_relative())
+            } else {
+                normalized.strip_prefix(dir).ok()
+            };
+            relative.filter(|path| {
+                !matches!(path.components().next(), Some(Component::ParentDir) | None)
+            })
+        };
+        let base = if within(&root).is_some() {
+            normalized.clone()
+        } else if let Some(relative) = within(&project_dir) {
+            build_dir.join(relative)
+        } else {
+            let dir = normalized.parent().unwrap_or(Path::new(""));
+            let hash = format!("{:x}", Sha256::digest(dir.to_string_lossy().as_bytes()));
+            let name = normalized.file_name().unwrap_or_default();
+            build_dir.join("external").join(&hash[..8]).join(name)
+        };
+        let mut object = base.with_extension(extension);
+        if objects.iter().any(|(_, taken)| *taken == object) {
+            let source_extension = base.extension().unwrap_or_default().to_string_lossy();
+            object = base.with_extension(format!("{}.{}", source_extension, extension));
+        }
+        objects.push((source, object));
+    }
+    objects
+}
+
+/// Returns where the program read from standard input by `morfo run -` is written.
+pub fn stdin_source(config: &Config) -> PathBuf {
+    build_dir(config).join("stdin.c")
+}
+
+/// Returns where the source of the C code blocks of `markdown_file` is written, of the
+/// `block`th alone if there is one.
+pub fn markdown_source(markdown_file: &Path, block: Option<usize>, config: &Config) -> PathBuf {
+    let name = match block {
+        Some(block) => format!("{}-{}.c", utils::file_name(markdown_file), block),
+        None => format!("{}.c", utils::file_name(markdown_file)),
+    };
+    build_dir(config).join("markdown").join(name)
+}
+
+/// Returns where the unity source including every source of `main_file` goes.
+pub fn unity_source(main_file: &Path, config: &Config) -> PathBuf {
+    build_dir(config).join(format!("{}.unity.c", utils::file_name(main_file)))
+}
+
+/// Returns the directory holding headers generated by morfo, such as `config.h`.
+pub fn include_dir(config: &Config) -> PathBuf {
+    build_dir(config).join("include")
+}
+
+/// Returns the directory holding the sources and `embed.h` generated for `embed`.
+pub fn embed_dir(config: &Config) -> PathBuf {
+    build_dir(config).join("embed")
+}
+
+/// Returns the directory the compiled interfaces of the C++ modules are written to.
+pub fn modules_dir(config: &Config) -> PathBuf {
+    build_dir(config).join("modules")
+}
+
+/// Returns the directory `morfo package` gathers the files of an archive in.
+pub fn package_dir(config: &Config) -> PathBuf {
+    build_dir(config).join("package")
+}
+
+/// Returns the directory `morfo package` writes the archives to, shared by every target
+/// and profile.
+pub fn dist_dir(config: &Config) -> PathBuf {
+    config.get_build_dir().join("dist")
+}
+
+/// Returns the directory the headers of the Rust crates are generated in.
+pub fn rust_dir(config: &Config) -> PathBuf {
+    build_dir(config).join("rust")
+}
+
+/// Returns the directory the code generators write their sources to.
+pub fn generated_dir(config: &Config) -> PathBuf {
+    build_dir(config).join("gen")
+}
+
+/// Returns the directory the profiles of profile-guided optimization are collected in.
+pub fn pgo_dir(config: &Config) -> PathBuf {
+    build_dir(config).join("pgo")
+}
+
+/// Returns the prefix the program profiled with gprof writes its profile to.
+pub fn gmon_prefix(config: &Config) -> PathBuf {
+    build_dir(config).join(GMON_OUT)
+}
+
+/// Returns the directory the flame graphs of `morfo profile` go to.
+pub fn flamegraph_dir(config: &Config) -> PathBuf {
+    build_dir(config).join("profile")
+}
+
+/// Returns the directory the heap profiles go to.
+pub fn heap_dir(config: &Config) -> PathBuf {
+    build_dir(config).join("heap")
+}
+
+/// Returns the directory holding the corpus and crashes of fuzzing `main_file`.
+pub fn fuzz_dir(main_file: &Path, config: &Config) -> PathBuf {
+    build_dir(config)
+        .join("fuzz")
+        .join(utils::file_name(main_file))
+}
+
+/// Returns where the manifest of the build is written.
+pub fn manifest(config: &Config) -> PathBuf {
+    build_dir(config).join(MANIFEST)
+}
+
+/// Returns where the summary of the build is written, `--summary-path` if it is set.
+pub fn build_summary(config: &Config) -> PathBuf {
+    match config.get_summary_path() {
+        Some(path) => path.to_path_buf(),
+        None => build_dir(config).join(BUILD_SUMMARY),
+    }
+}
+
+/// Returns where the includes found in the sources are cached between runs.
+pub fn scan_cache(config: &Config) -> PathBuf {
+    build_dir(config).join(SCAN_CACHE)
+}
+
+/// Returns the socket `morfo daemon` listens on, in the build directory shared by every
+/// target and profile.
+pub fn daemon_socket(config: &Config) -> PathBuf {
+    config.get_build_dir().join(DAEMON_SOCKET)
+}
+
+/// Returns where the artifacts found up to date and built again by every build of the
+/// project are counted, in the build directory shared by every target and profile.
+pub fn build_stats(config: &Config) -> PathBuf {
+    config.get_build_dir().join(BUILD_STATS)
+}
+
+/// Returns the lock file taken while building.
+pub(crate) fn lock_file(config: &Config) -> PathBuf {
+    build_dir(config).join(LOCK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigBuilder;
+
+    #[test]
+    fn layout_target_and_profile() {
+        let config = ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir("target/morfo/out")
+            .build();
+        let config = config
+            .for_target("wasm32-emscripten")
+            .unwrap()
+            .for_profile("debug")
+            .unwrap();
+        assert_eq!(
+            build_dir(&config),
+            PathBuf::from("target/morfo/out/wasm32-emscripten/debug")
+        );
+        assert_eq!(
+            executable(Path::new("app/main.c"), &config),
+            PathBuf::from("target/morfo/out/wasm32-emscripten/debug/main.js")
+        );
+    }
+
+    #[test]
+    fn layout_objects_mirror_sources() {
+        let mut act = Act {
+            name: PathBuf::from("app/main.c"),
+            header: None,
+            linkers: Vec::new(),
+            dependencies: Vec::new(),
+            headers: Vec::new(),
+            module: None,
+            imports: Vec::new(),
+        };
+        for source in ["app/./src/util.c", ".out/embed/logo.c", "vendor/util.c"] {
+            act.add_generated(Path::new(source));
+        }
+        let config = ConfigBuilder::default().set_cc("gcc").build();
+
+        let objects: Vec<_> = objects(&act, &config)
+            .into_iter()
+            .map(|(_, object)| object)
+            .collect();
+        let hash = format!("{:x}", Sha256::digest(b"vendor"));
+        assert_eq!(
+            objects,
+            [
+                ".out/src/util.o".to_owned(),
+                ".out/embed/logo.o".to_owned(),
+                format!(".out/external/{}/util.o", &hash[..8]),
+                ".out/main.o".to_owned(),
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn layout_create_nested_build_dir() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let root = tmp_dir.path().join("target/morfo/out");
+        let config = ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir(root.to_str().unwrap())
+            .build()
+            .for_profile("release")
+            .unwrap();
+
+        assert_eq!(create_build_dir(&config).unwrap(), root.join("release"));
+        assert!(root.join("release").is_dir());
+    }
+}
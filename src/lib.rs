@@ -10,67 +10,1102 @@
 //!
 //! fn main() {
 //!    let config = ConfigBuilder::default().build();
-//!    execute("main.c".into(), config, &mut std::io::stdout(), vec![]);
+//!    let outcome = execute("main.c".into(), config, &mut std::io::stdout(), vec![]).unwrap();
+//!    println!("exited with {:?} after {:?}", outcome.exit_status, outcome.duration);
+//! }
+//! ```
+//!
+//! To build once and run the program many times, say with different inputs:
+//!
+//! ```no_run
+//! use morfo::config::ConfigBuilder;
+//! use morfo::{compile, run, RunOptions};
+//!
+//! fn main() {
+//!    let config = ConfigBuilder::default().build();
+//!    let artifact = compile("main.c".as_ref(), &config).unwrap();
+//!    for input in ["1 2", "3 4"] {
+//!        let options = RunOptions::new().with_stdin(input);
+//!        run(&artifact, options, &mut std::io::stdout()).unwrap();
+//!    }
 //! }
 //! ```
 
 use std::{
-    env,
-    fs::create_dir,
-    io::Write,
+    collections::BTreeMap,
+    ffi::OsString,
+    fs,
+    io::{self, BufRead, Read, Write},
     path::{Path, PathBuf},
-    process::{Command, Stdio},
+    process::{Command, ExitStatus, Stdio},
+    thread,
+    time::{Duration, Instant},
 };
 
-use act::ACT;
-use config::Config;
+use act::{
+    dirinfo::{DirInfo, Exclude},
+    Act, IncludePaths,
+};
+use config::{Config, Linkage, Profiling};
+use diagnostic::MessageFormat;
 use error::{MorfoError, MorfoResult};
+use plugin::PluginEvent;
+use toolchain::{CompilerFamily, Language};
 
-mod act;
+pub mod act;
+pub mod batch;
+pub mod buildinfo;
+pub mod cache;
 pub mod config;
+pub mod container;
+#[cfg(unix)]
+pub mod daemon;
+pub mod diagnostic;
+pub mod difftest;
+pub mod distributed;
+pub mod embed;
 pub mod error;
+pub mod fingerprint;
+pub mod flamegraph;
+pub mod flash;
+pub mod fortran;
+pub mod fuzz;
+pub mod gc;
+pub mod generate;
+mod gprof;
+pub mod hardening;
+pub mod heap;
+pub mod import;
+pub mod interrupt;
+mod jobserver;
+pub mod judge;
+pub mod layout;
+pub mod libraries;
+pub mod lint;
+mod lock;
+pub mod manifest;
+pub mod markdown;
+pub mod matrix;
+pub mod modules;
+mod notify;
+pub mod objc;
+pub mod package;
+pub mod pgo;
+pub mod plugin;
+mod priority;
+mod privilege;
+pub mod probe;
+#[cfg(unix)]
+mod pty;
+pub mod remote;
+pub mod repro;
+mod rpath;
+pub mod rustlib;
+pub mod sarif;
+pub mod sbom;
+pub mod script;
+pub mod session;
+pub mod snapshot;
+mod splitdebug;
+pub mod summary;
+pub mod symbols;
+mod tee;
+pub mod template;
+pub mod timetrace;
+pub mod toolchain;
 mod utils;
+mod warm;
+pub mod warnings;
+
+/// What happened when a program was built and run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunOutcome {
+    /// How the program exited, or `None` if it was not run because of `--dry-run`.
+    pub exit_status: Option<ExitStatus>,
+    /// How long the program ran.
+    pub duration: Duration,
+    /// What the build before the run did.
+    pub compile_stats: CompileStats,
+    /// The executable that was run.
+    pub executable_path: PathBuf,
+}
 
+/// A program built by [`compile`], ready to be [`run`] any number of times.
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    /// The executable that was built.
+    pub executable_path: PathBuf,
+    /// What the build did.
+    pub compile_stats: CompileStats,
+    /// The effective config the program was built with, which also decides how it runs.
+    config: Config,
+}
+
+/// How to [`run`] an [`Artifact`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunOptions {
+    args: Vec<String>,
+    stdin: Option<Vec<u8>>,
+}
+
+impl RunOptions {
+    /// Returns options running the program without arguments, reading morfo's stdin.
+    pub fn new() -> RunOptions {
+        RunOptions::default()
+    }
+
+    /// Passes `args` to the program.
+    ///
+    /// ```
+    /// use morfo::RunOptions;
+    ///
+    /// let options = RunOptions::new().with_args(vec!["--verbose".to_string()]);
+    /// ```
+    pub fn with_args(mut self, args: Vec<String>) -> RunOptions {
+        self.args = args;
+        self
+    }
+
+    /// Feeds `input` to the program as its stdin, instead of morfo's stdin. The program
+    /// then runs with pipes, even with `--tty`.
+    ///
+    /// ```
+    /// use morfo::RunOptions;
+    ///
+    /// let options = RunOptions::new().with_stdin("3 4\n");
+    /// ```
+    pub fn with_stdin(mut self, input: impl Into<Vec<u8>>) -> RunOptions {
+        self.stdin = Some(input.into());
+        self
+    }
+}
+
+/// What a build did.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CompileStats {
+    /// The objects compiled and the executable linked, in that order.
+    pub built: Vec<PathBuf>,
+    /// The artifacts that were up to date, and not built again.
+    pub fresh: Vec<PathBuf>,
+    /// How long the build took.
+    pub duration: Duration,
+    /// How many warnings the compiler printed for the sources of the program, the last
+    /// time each was compiled.
+    pub warnings: usize,
+    /// How many of the objects compiled were copied from the object cache.
+    pub cache_hits: u64,
+}
+
+/// Builds `main_file` and runs it with `prog_args`, writing its output to `out`.
+///
+/// # Errors
+///
+/// If any step of the build fails, or the program cannot be run. A program that runs
+/// but fails is not an error; its exit status is in the [`RunOutcome`].
 pub fn execute<W: Write>(
     main_file: PathBuf,
     config: Config,
     out: &mut W,
     prog_args: Vec<String>,
+) -> MorfoResult<RunOutcome> {
+    let artifact = compile(&main_file, &config)?;
+    run(&artifact, RunOptions::new().with_args(prog_args), out)
+}
+
+/// Builds `main_file` without running it. The build directory is only locked while
+/// building, so the artifact can be run while another build runs.
+///
+/// # Errors
+///
+/// If any step of the build fails.
+pub fn compile(main_file: &Path, config: &Config) -> MorfoResult<Artifact> {
+    let _lock = lock::lock(config)?;
+    let start = Instant::now();
+    plugin::emit(
+        config,
+        PluginEvent::BuildStarted,
+        serde_json::json!({ "main_file": main_file }),
+    );
+    let result = prepare(main_file, config.clone()).and_then(|(act, config)| {
+        let compile_stats = compile_act(&act, &config)?;
+        Ok(Artifact {
+            executable_path: layout::executable(&act.name, &config),
+            compile_stats,
+            config,
+        })
+    });
+    if !config.get_dry_run() {
+        let summary = summary::BuildSummary::new(main_file, &result, start.elapsed());
+        // a failed build reports its own error rather than the summary's
+        let written = summary::write(&summary, &layout::build_summary(config));
+        notify::build_finished(main_file, &result, start.elapsed(), config);
+        plugin::emit(
+            config,
+            PluginEvent::BuildFinished,
+            serde_json::to_value(&summary).unwrap_or_default(),
+        );
+        if result.is_ok() {
+            written?;
+        }
+    }
+    result
+}
+
+/// Runs a built `artifact` with `options`, writing its output to `out`. With `--profile
+/// gprof`, the profile report follows the output.
+///
+/// # Errors
+///
+/// If the program cannot be run. A program that runs but fails is not an error; its
+/// exit status is in the [`RunOutcome`].
+pub fn run<W: Write>(
+    artifact: &Artifact,
+    options: RunOptions,
+    out: &mut W,
+) -> MorfoResult<RunOutcome> {
+    let config = &artifact.config;
+    let gprof = config.get_profiling() == Some(Profiling::Gprof) && !config.get_dry_run();
+    if gprof {
+        gprof::clean(config)?;
+    }
+
+    let start = Instant::now();
+    let exit_status = run_executable(&artifact.executable_path, config, out, options)?;
+    let duration = start.elapsed();
+    if gprof {
+        gprof::report(&artifact.executable_path, config, out)?;
+    }
+    plugin::emit(
+        config,
+        PluginEvent::RunFinished,
+        serde_json::json!({
+            "executable": artifact.executable_path,
+            "exit_code": exit_status.and_then(|status| status.code()),
+            "duration": duration.as_secs_f64(),
+        }),
+    );
+    Ok(RunOutcome {
+        exit_status,
+        duration,
+        compile_stats: artifact.compile_stats.clone(),
+        executable_path: artifact.executable_path.clone(),
+    })
+}
+
+/// Builds and runs `main_file` like [`execute`], for callers that have no use for the
+/// [`RunOutcome`].
+///
+/// # Errors
+///
+/// The same as [`execute`].
+#[deprecated(since = "0.4.0", note = "use `execute`, which returns a `RunOutcome`")]
+pub fn execute_simple<W: Write>(
+    main_file: PathBuf,
+    config: Config,
+    out: &mut W,
+    prog_args: Vec<String>,
 ) -> MorfoResult<()> {
-    let dirinfo = act::dirinfo::get_dir_info(&main_file);
+    execute(main_file, config, out, prog_args).map(|_| ())
+}
+
+/// Builds `main_file` without running it and returns the path of the executable.
+///
+/// # Errors
+///
+/// If any step of the build fails.
+pub fn build(main_file: PathBuf, config: Config) -> MorfoResult<PathBuf> {
+    compile(&main_file, &config).map(|artifact| artifact.executable_path)
+}
 
-    let act = ACT::build(&main_file, &dirinfo);
-    compile(&act, &config)?;
+/// Discovers the dependency tree of `main_file` without building anything, though
+/// generated and embedded sources are written so that they are part of it.
+///
+/// # Errors
+///
+/// If the tree cannot be built, e.g. because an included source cannot be read.
+pub fn dependencies(main_file: &Path, config: Config) -> MorfoResult<Act> {
+    let _lock = lock::lock(&config)?;
+    prepare(main_file, config).map(|(act, _)| act)
+}
 
-    run(act, &config, out, prog_args)?;
-    Ok(())
+/// Returns the sources and headers of the project that `main_file` does not depend on,
+/// so they are never compiled, sorted by path.
+///
+/// # Errors
+///
+/// Like [`dependencies`].
+pub fn unused_files(main_file: &Path, config: Config) -> MorfoResult<Vec<PathBuf>> {
+    let _lock = lock::lock(&config)?;
+    let (act, config) = prepare(main_file, config)?;
+    let used: Vec<PathBuf> = act
+        .files()
+        .iter()
+        .map(|file| utils::normalize(file))
+        .collect();
+
+    let dirinfo = dir_info(project_dir(main_file), &config)?;
+    let mut unused: Vec<PathBuf> = dirinfo
+        .c_files
+        .into_iter()
+        .chain(dirinfo.fortran_files)
+        .chain(dirinfo.objc_files)
+        .chain(dirinfo.cpp_files)
+        .chain(dirinfo.header_files)
+        .filter(|file| !used.contains(&utils::normalize(file)))
+        .collect();
+    unused.sort();
+    Ok(unused)
 }
 
-fn compile(act: &ACT, config: &Config) -> MorfoResult<()> {
-    // create .out directory if it doesn't exist
-    if !Path::new(&config.get_build_dir()).exists() {
-        create_dir(config.get_build_dir())?;
+/// Resolves the effective config and discovers the dependency tree of `main_file`.
+fn prepare(main_file: &Path, config: Config) -> MorfoResult<(Act, Config)> {
+    let config = config.detect_cc()?;
+    let project_dir = project_dir(main_file);
+    let config = if config.get_reproducible() {
+        repro::apply(config, project_dir)
+    } else {
+        config
+    };
+    let config = if config.get_build_info() {
+        buildinfo::apply(config, project_dir)
+    } else {
+        config
+    };
+    let config = rustlib::apply(config)?;
+    let dirinfo = dir_info(project_dir, &config)?;
+
+    // generated before the tree is built, so the headers generated with them are found
+    let generated = generate::generate(&config, project_dir)?;
+    let embedded = embed::embed(&config, project_dir)?;
+    let mut generated_headers = generated.clone();
+    if !config.get_features().is_empty() {
+        generated_headers.push(layout::include_dir(&config).join("config.h"));
     }
+    if !config.get_embed().is_empty() {
+        generated_headers.push(layout::embed_dir(&config).join("embed.h"));
+    }
+
+    let (macros, system) = preprocessor(main_file, &config);
+    let paths = IncludePaths {
+        dirs: config.get_includes().iter().map(PathBuf::from).collect(),
+        angled: config.get_angle_includes(),
+        system,
+        generated: generated_headers,
+    };
 
-    for dependency in &act.dependencies {
-        compile(dependency, config)?;
+    // only the files changed since the last run are scanned for includes again
+    let cache_path = layout::scan_cache(&config);
+    let mut cache = warm::scan_cache(&cache_path);
+    let mut act = Act::build_cached(main_file, &dirinfo, &paths, &macros, &mut cache)?;
+    if !config.get_dry_run() {
+        layout::create_build_dir(&config)?;
+        cache.save(&cache_path)?;
+        warm::keep_scan_cache(&cache_path, cache);
     }
+    for source in generated.iter().chain(&embedded) {
+        act.add_generated(source);
+    }
+    let config = script::apply(&mut act, config)?;
+    let config = objc::apply(&act, config);
+    let config = modules::apply(&act, config)?;
+    Ok((act, config))
+}
 
-    // use command to print pwd
-    let mut compile_cmd = Command::new(config.get_cc());
-    if !config.get_cflags().is_empty() {
-        compile_cmd.arg(config.get_cflags().join(" ").as_str());
+/// Finds the sources and headers of the project in `project_dir`.
+fn dir_info(project_dir: &Path, config: &Config) -> MorfoResult<DirInfo> {
+    let exclude = Exclude::new(project_dir, &config.get_build_dir(), &config.get_exclude())?;
+    let roots = config.get_src();
+    let mut dirinfo = if roots.is_empty() {
+        act::dirinfo::get_dir_info(project_dir, &exclude)
+    } else {
+        let roots = roots.iter().map(PathBuf::from).collect::<Vec<_>>();
+        act::dirinfo::get_roots_info(project_dir, &roots, &exclude)?
+    };
+    dirinfo.header_sources = config
+        .get_header_sources()
+        .into_iter()
+        .map(|(header, sources)| {
+            let sources = sources.iter().map(|s| utils::normalize(Path::new(s)));
+            (utils::normalize(Path::new(&header)), sources.collect())
+        })
+        .collect();
+    Ok(dirinfo)
+}
+
+/// Returns the directory of `main_file`, where the project lives.
+fn project_dir(main_file: &Path) -> &Path {
+    main_file
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
+
+fn compile_act(act: &Act, config: &Config) -> MorfoResult<CompileStats> {
+    let start = Instant::now();
+    if config.get_dry_run() {
+        dry_run(act, config)?;
+        return Ok(CompileStats {
+            duration: start.elapsed(),
+            ..CompileStats::default()
+        });
     }
+
+    if config.get_time_trace() {
+        if config.get_family() != CompilerFamily::Clang {
+            return Err(MorfoError::Unsupported(
+                "--time-trace with a compiler other than Clang".to_owned(),
+            ));
+        }
+        if config.get_unity() {
+            return Err(MorfoError::Unsupported(
+                "--time-trace with a unity build".to_owned(),
+            ));
+        }
+    }
+
+    layout::create_build_dir(config)?;
+
+    if !config.get_features().is_empty() {
+        probe::write_config_header(config, &layout::include_dir(config).join("config.h"))?;
+    }
+
+    if config.get_linkage() == Some(Linkage::Static) {
+        libraries::check_static(config)?;
+    }
+
+    // the executable is only fresh if every object is
+    let fresh = fresh_artifacts(act, config)?;
+    let executable = layout::executable(&act.name, config);
+    let mut built = Vec::new();
+    let mut cache_hits = 0;
+    if !fresh.contains(&executable) {
+        let objects = if config.get_unity() {
+            compile_unity(act, config)?;
+            Vec::new()
+        } else {
+            let objects;
+            (objects, cache_hits) = compile_objects(act, &fresh, config)?;
+            if config.get_symbol_check() {
+                check_symbols(act, config)?;
+            }
+            link(act, &objects, config)?;
+            objects
+        };
+        built.extend(
+            objects
+                .iter()
+                .filter(|object| !fresh.contains(object))
+                .cloned(),
+        );
+        built.push(executable.clone());
+
+        if config.get_split_debug() {
+            splitdebug::separate(&executable, config.get_family())?;
+        }
+        if config.get_bundle_libs() {
+            rpath::bundle(&executable)?;
+        }
+        fingerprint::record_executable(act, &objects, config)?;
+    }
+    if config.get_hardening() {
+        hardening::report(&executable);
+    }
+    if config.get_time_trace() {
+        let objects = layout::objects(act, config);
+        print!(
+            "{}",
+            timetrace::report(&objects, config.get_time_trace_output())?
+        );
+    }
+    manifest::write(act, config)?;
+    cache::record_build(config, fresh.len(), built.len());
+
+    // a unity build compiles every source into the executable at once
+    let mut artifacts = vec![executable];
+    if !config.get_unity() {
+        artifacts.extend(
+            layout::objects(act, config)
+                .into_iter()
+                .map(|(_, object)| object),
+        );
+    }
+    let (warnings, warned) = warnings::total(&artifacts);
+    if warnings > 0 {
+        eprintln!(
+            "{} warning(s) from {} of the {} compiler runs",
+            warnings,
+            warned,
+            artifacts.len()
+        );
+    }
+    Ok(CompileStats {
+        built,
+        fresh,
+        duration: start.elapsed(),
+        warnings,
+        cache_hits,
+    })
+}
+
+/// Returns the artifacts of `act` that are up to date, or none when a rebuild is forced.
+fn fresh_artifacts(act: &Act, config: &Config) -> MorfoResult<Vec<PathBuf>> {
+    if config.get_rebuild() {
+        return Ok(Vec::new());
+    }
+    Ok(fingerprint::artifacts(act, config)?
+        .into_iter()
+        .filter(fingerprint::Artifact::is_fresh)
+        .map(|artifact| artifact.path)
+        .collect())
+}
+
+/// Compiles the objects of `act` that are not `fresh` and returns every object, with how
+/// many were copied from the object cache.
+fn compile_objects(
+    act: &Act,
+    fresh: &[PathBuf],
+    config: &Config,
+) -> MorfoResult<(Vec<PathBuf>, u64)> {
+    let mut objects = Vec::new();
+    let mut jobs: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for (source, object) in layout::objects(act, config) {
+        if !fresh.contains(&object) {
+            if let Some(dir) = object.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            jobs.push((source, object.clone()));
+        }
+        objects.push(object);
+    }
+
+    // the traces of the objects are only written by compiling them here
+    let mut cache = if config.get_time_trace() {
+        None
+    } else {
+        cache::ObjectCache::open(config)?
+    };
+    let result = if distributed::enabled(config) && !config.get_time_trace() {
+        distributed::compile_objects(&jobs, config, cache.as_mut())
+    } else {
+        let mut failed = Vec::new();
+        let result = jobs.iter().try_for_each(|(source, object)| {
+            let result = match &mut cache {
+                Some(cache) => cache.compile(source, object, config),
+                None => compile_object(source, object, config),
+            };
+            keep_going(result, source, config, &mut failed)
+        });
+        if result.is_ok() && !failed.is_empty() {
+            Err(MorfoError::CompilationFailures(failed))
+        } else {
+            result
+        }
+    };
+    let cache_hits = cache.map_or(0, cache::ObjectCache::finish);
+    result?;
+    fingerprint::record_objects(act, &jobs, config)?;
+    Ok((objects, cache_hits))
+}
+
+/// Fails when two objects of `act` define the same strong symbol.
+fn check_symbols(act: &Act, config: &Config) -> MorfoResult<()> {
+    if config.get_family() == CompilerFamily::Msvc {
+        return Err(MorfoError::Unsupported(
+            "the symbol check with MSVC".to_owned(),
+        ));
+    }
+    let duplicates = symbols::duplicates(&layout::objects(act, config))?;
+    if duplicates.is_empty() {
+        Ok(())
+    } else {
+        Err(MorfoError::DuplicateSymbols(duplicates))
+    }
+}
+
+/// Links `objects` into the executable for `act`.
+fn link(act: &Act, objects: &[PathBuf], config: &Config) -> MorfoResult<()> {
+    let executable = layout::executable(&act.name, config);
+    if config.get_linker().as_deref() == Some("auto") {
+        let linker = linker(config).unwrap_or("the default linker".to_owned());
+        eprintln!("linking with {} (linker = \"auto\")", linker);
+    }
+    run_compiler(
+        &mut link_command(act, objects, config)?,
+        &executable,
+        &executable,
+        config,
+    )
+}
+
+fn link_command(act: &Act, objects: &[PathBuf], config: &Config) -> MorfoResult<Command> {
+    // link every object into the executable, dependents before their dependencies
+    // and the libraries last, in the order of `libraries::order`
+    let languages: Vec<Language> = act.sources().iter().map(|s| Language::of(s)).collect();
+    let linker = linker(config);
+    let fuse_ld = linker
+        .as_deref()
+        .and_then(|linker| config.get_family().fuse_ld_arg(linker));
+    let mut link_cmd = match linker {
+        Some(linker) if fuse_ld.is_none() => compiler_command(&config.clone().with_cc(&linker)),
+        _ if languages
+            .iter()
+            .any(|language| matches!(language, Language::Cxx | Language::Cuda)) =>
+        {
+            compiler_command(&config.clone().with_cc(&config.get_compiler(Language::Cxx)))
+        }
+        _ => compiler_command(config),
+    };
+    link_cmd.args(fuse_ld);
+    if languages.contains(&Language::Cuda) {
+        link_cmd.arg(config.get_family().library_arg("cudart"));
+    }
+    link_cmd
+        .args(config.get_cflags())
+        .args(objects.iter().rev())
+        .args(link_flags(config))
+        .args(libraries::link_args(config)?)
+        .args(fortran::link_args(act, config))
+        .args(objc::link_args(act, config))
+        .args(
+            config
+                .get_family()
+                .link_output_args(&layout::executable(&act.name, config)),
+        );
+    Ok(link_cmd)
+}
+
+/// Returns the linker of `config`, with `linker = "auto"` resolved: the fastest of mold
+/// and lld the compiler links with for an unoptimized build, and the default linker for
+/// an optimized one, where the link is a small part of the build.
+fn linker(config: &Config) -> Option<String> {
+    let linker = config.get_linker()?;
+    if linker != "auto" {
+        return Some(linker);
+    }
+    // the linkers installed here say nothing about the ones in the image
+    let optimized = config.get_opt_level().is_some_and(|level| level != "0");
+    if optimized || config.get_container().is_some() {
+        return None;
+    }
+    toolchain::fast_linker(config.get_cc(), config.get_family()).map(str::to_owned)
+}
+
+/// Compiles `source` into the object file `object`.
+fn compile_object(source: &Path, object: &Path, config: &Config) -> MorfoResult<()> {
+    run_compiler(
+        &mut object_command(source, object, config),
+        source,
+        object,
+        config,
+    )
+}
+
+fn object_command(source: &Path, object: &Path, config: &Config) -> Command {
+    if fortran::is_fortran(source) {
+        return fortran::object_command(source, object, config);
+    }
+    let mut compile_cmd = source_compiler_command(source, config);
+    compile_cmd.args(object_flags(source, object, config));
+    compile_cmd.args(objc::language_args(source));
+    compile_cmd.args(
+        config
+            .get_family()
+            .compile_args(source, object, &layout::build_dir(config)),
+    );
+    compile_cmd
+}
+
+/// Prints the commands that compile and link the stale artifacts instead of running them.
+/// Feature checks and the steps after linking are skipped.
+fn dry_run(act: &Act, config: &Config) -> MorfoResult<()> {
+    let fresh = fresh_artifacts(act, config)?;
+    let commands = if fresh.contains(&layout::executable(&act.name, config)) {
+        Vec::new()
+    } else if config.get_unity() {
+        vec![unity_command(act, config)?]
+    } else {
+        let mut commands = Vec::new();
+        let mut objects = Vec::new();
+        for (source, object) in layout::objects(act, config) {
+            if !fresh.contains(&object) {
+                commands.push(object_command(&source, &object, config));
+            }
+            objects.push(object);
+        }
+        commands.push(link_command(act, &objects, config)?);
+        commands
+    };
+    for cmd in commands {
+        println!("{}", utils::format_command(&cmd));
+    }
+    Ok(())
+}
+
+/// Builds the executable from a single generated source that includes every translation unit,
+/// compiling and linking it in one invocation.
+fn compile_unity(act: &Act, config: &Config) -> MorfoResult<()> {
+    let mut unity = String::from("/* Generated by morfo for a unity build. Do not edit. */\n");
+    for source in act.sources() {
+        let source = fs::canonicalize(&source)?;
+        unity.push_str(&format!("#include \"{}\"\n", source.display()));
+    }
+    let unity_source = layout::unity_source(&act.name, config);
+    fs::write(&unity_source, unity)?;
+
+    run_compiler(
+        &mut unity_command(act, config)?,
+        &unity_source,
+        &layout::executable(&act.name, config),
+        config,
+    )
+}
+
+/// Returns the command that compiles and links the unity source of `act`.
+fn unity_command(act: &Act, config: &Config) -> MorfoResult<Command> {
+    if !fortran::sources(act).is_empty() {
+        return Err(MorfoError::Unsupported(
+            "unity builds of programs with Fortran sources".to_owned(),
+        ));
+    }
+    if !objc::sources(act).is_empty() {
+        return Err(MorfoError::Unsupported(
+            "unity builds of programs with Objective-C sources".to_owned(),
+        ));
+    }
+    if !modules::interfaces(act).is_empty() {
+        return Err(MorfoError::Unsupported(
+            "unity builds of programs with C++ modules".to_owned(),
+        ));
+    }
+    let mut compile_cmd = compiler_command(config);
     compile_cmd
-        .arg(&act.name)
-        .arg("-o")
-        .arg(config.get_build_dir().join(utils::file_name(&act.name)));
+        .args(config.get_cflags())
+        .args(compile_flags(config))
+        .arg(layout::unity_source(&act.name, config))
+        .args(link_flags(config))
+        .args(libraries::link_args(config)?)
+        .args(
+            config
+                .get_family()
+                .link_output_args(&layout::executable(&act.name, config)),
+        );
+    Ok(compile_cmd)
+}
 
-    if env::var("VERBOSITY").unwrap_or_default() == "1" {
-        println!("{}", format!("{:?}", compile_cmd).replace('\"', ""));
+/// Returns a command invoking the configured compiler in the environment the config asks for.
+fn compiler_command(config: &Config) -> Command {
+    if let Some(container) = config.get_container() {
+        let mut cmd = container::compiler_command(config, &container);
+        priority::apply(&mut cmd, config.get_build_priority());
+        return cmd;
+    }
+    let mut cmd = Command::new(config.get_cc());
+    for (name, value) in config.get_build_env() {
+        match value {
+            Some(value) => cmd.env(name, value),
+            None => cmd.env_remove(name),
+        };
+    }
+    if let Some(epoch) = config.get_source_date_epoch() {
+        cmd.env("SOURCE_DATE_EPOCH", epoch.to_string());
     }
+    priority::apply(&mut cmd, config.get_build_priority());
+    cmd
+}
+
+/// Returns a command invoking the compiler of the language of `source`, as chosen by
+/// [`Config::get_compiler`].
+fn source_compiler_command(source: &Path, config: &Config) -> Command {
+    let compiler = config.get_compiler(Language::of(source));
+    if compiler == *config.get_cc() {
+        compiler_command(config)
+    } else {
+        compiler_command(&config.clone().with_cc(&compiler))
+    }
+}
 
-    let status = compile_cmd.status()?;
+/// Returns what identifies the compiler: its command, the version it reports and the
+/// image it runs in, since the same command names a different compiler in every image.
+fn compiler_identity(config: &Config) -> [String; 3] {
+    let version = toolchain::probe(config.get_cc())
+        .map(|compiler| compiler.version)
+        .unwrap_or_default();
+    let image = config
+        .get_container()
+        .and_then(|container| container.get_image().map(str::to_owned))
+        .unwrap_or_default();
+    [config.get_cc().clone(), version, image]
+}
+
+/// Returns the object-like macros defined when compiling `main_file`, as the compiler
+/// reports them with the flags and defines of `config`, and the directories it searches
+/// for headers. If it cannot report them, only the configured defines are known, and
+/// none of the directories.
+fn preprocessor(
+    main_file: &Path,
+    config: &Config,
+) -> (BTreeMap<String, String>, Option<Vec<PathBuf>>) {
+    let cpp = Language::of(main_file) == Language::Cxx;
+    let Some(args) = config.get_family().predefine_args(cpp) else {
+        return (config.get_defines(), None);
+    };
+    let mut cmd = source_compiler_command(main_file, config);
+    cmd.args(config.get_cflags())
+        .args(compile_flags(config))
+        .args(args)
+        .arg("-v")
+        .stdin(Stdio::null());
+    let predefines = warm::predefines(format!("{:?}", cmd), || {
+        let output = cmd.output().ok().filter(|output| output.status.success())?;
+        let macros = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.strip_prefix("#define "))
+            .filter_map(|definition| {
+                let (name, value) = definition.split_once(' ').unwrap_or((definition, ""));
+                (!name.contains('(')).then(|| (name.to_owned(), value.to_owned()))
+            })
+            .collect();
+        Some((
+            macros,
+            search_dirs(&String::from_utf8_lossy(&output.stderr)),
+        ))
+    });
+    predefines.unwrap_or_else(|| (config.get_defines(), None))
+}
+
+/// Returns the directories listed between `#include "..." search starts here:` and
+/// `End of search list.` in the verbose output of GCC and Clang, if there are any.
+fn search_dirs(verbose: &str) -> Option<Vec<PathBuf>> {
+    let mut lines = verbose.lines();
+    lines.find(|line| line.starts_with("#include \"...\" search starts here:"))?;
+    let dirs = lines
+        .take_while(|line| !line.starts_with("End of search list."))
+        .filter(|line| line.starts_with(' '))
+        // Clang on macOS marks the directories of frameworks
+        .map(|line| line.trim().trim_end_matches(" (framework directory)"))
+        .map(PathBuf::from)
+        .collect();
+    Some(dirs)
+}
+
+/// Records `source` as failed instead of failing with its compilation error when the
+/// build keeps going, so the remaining sources are still compiled.
+fn keep_going(
+    result: MorfoResult<()>,
+    source: &Path,
+    config: &Config,
+    failed: &mut Vec<PathBuf>,
+) -> MorfoResult<()> {
+    match result {
+        Err(MorfoError::CompilationFailure(_)) if config.get_keep_going() => {
+            failed.push(source.to_path_buf());
+            Ok(())
+        }
+        // the compiler said nothing about why it was killed
+        Err(e @ MorfoError::CompileTimeout(..)) if config.get_keep_going() => {
+            eprintln!("{}", e);
+            failed.push(source.to_path_buf());
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Returns every flag used to compile `source` to `object`, apart from the files.
+fn object_flags(source: &Path, object: &Path, config: &Config) -> Vec<String> {
+    let mut flags = config.get_cflags();
+    flags.extend(compile_flags(config));
+    flags.extend(config.get_source_flags(source));
+    if config.get_reproducible() {
+        flags.extend(repro::object_flags(config.get_family(), object));
+    }
+    flags
+}
+
+/// The options that only matter to the preprocessor and take a value, attached or as the
+/// next argument.
+const PREPROCESSOR_OPTIONS: [&str; 14] = [
+    "-I",
+    "/I",
+    "-isystem",
+    "-iquote",
+    "-idirafter",
+    "-include",
+    "-imacros",
+    "-D",
+    "/D",
+    "-U",
+    "/U",
+    "-MF",
+    "-MT",
+    "-MQ",
+];
+
+/// The options that only matter to the preprocessor and take no value.
+const PREPROCESSOR_SWITCHES: [&str; 6] = ["-M", "-MM", "-MD", "-MMD", "-MP", "-MG"];
+
+/// Returns `flags` without the options that only matter to the preprocessor, along with
+/// their values, for compiling a source that is already preprocessed.
+fn compile_only_flags(flags: &[String]) -> Vec<String> {
+    let mut kept = Vec::new();
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        if PREPROCESSOR_SWITCHES.contains(&flag.as_str()) {
+            continue;
+        }
+        match PREPROCESSOR_OPTIONS
+            .iter()
+            .find(|option| flag.starts_with(*option))
+        {
+            Some(option) if flag == option => {
+                flags.next();
+            }
+            Some(_) => (),
+            None => kept.push(flag.clone()),
+        }
+    }
+    kept
+}
+
+/// Returns the flags morfo adds to every compilation on top of `cflags`.
+fn compile_flags(config: &Config) -> Vec<String> {
+    let family = config.get_family();
+    let mut flags = Vec::new();
+
+    if let Some(level) = config.get_opt_level() {
+        flags.push(family.opt_arg(&level));
+    }
+    if let Some(std) = config.get_std() {
+        flags.push(family.std_arg(&std));
+    }
+    if config.get_debug() || config.get_split_debug() {
+        flags.push(family.debug_arg().to_owned());
+    }
+    if config.get_split_debug() {
+        flags.extend(splitdebug::compile_flags(family));
+    }
+    if config.get_frame_pointers() {
+        flags.push(family.frame_pointer_arg().to_owned());
+    }
+    if let Some(linkage) = config.get_linkage() {
+        flags.extend(family.linkage_args(linkage).0);
+    }
+    if config.get_hardening() {
+        flags.extend(hardening::compile_flags(family));
+    }
+    if let Some(policy) = config.get_warnings() {
+        flags.extend(warnings::compile_flags(family, &policy));
+    }
+    if !config.get_sanitizers().is_empty() {
+        flags.push(family.sanitize_arg(&config.get_sanitizers()));
+    }
+    if let Some(max_errors) = config.get_max_errors() {
+        flags.extend(family.max_errors_arg(max_errors));
+    }
+    for include in config.get_includes() {
+        flags.push(family.include_arg(&include));
+    }
+    if !config.get_features().is_empty() {
+        flags.push(family.include_arg(&layout::include_dir(config).to_string_lossy()));
+    }
+    if !config.get_generators().is_empty() {
+        flags.push(family.include_arg(&layout::generated_dir(config).to_string_lossy()));
+    }
+    if !config.get_embed().is_empty() {
+        flags.push(family.include_arg(&layout::embed_dir(config).to_string_lossy()));
+    }
+    for (name, value) in config.get_defines() {
+        flags.push(family.define_arg(&name, Some(&value)));
+    }
+    if let Some(phase) = config.get_pgo_phase() {
+        flags.extend(pgo::flags(config, phase));
+    }
+    if config.get_profiling() == Some(Profiling::Gprof) {
+        flags.extend(gprof::flags(family));
+    }
+    if config.get_reproducible() {
+        flags.extend(repro::compile_flags(family));
+    }
+    if config.get_time_trace() && family == CompilerFamily::Clang {
+        flags.push("-ftime-trace".to_owned());
+    }
+
+    flags
+}
+
+/// Returns the flags morfo adds when linking on top of `cflags`.
+fn link_flags(config: &Config) -> Vec<String> {
+    let mut flags = Vec::new();
+
+    if config.get_hardening() {
+        flags.extend(hardening::link_flags(config.get_family()));
+    }
+    if let Some(linkage) = config.get_linkage() {
+        flags.extend(config.get_family().linkage_args(linkage).1);
+    }
+    flags.extend(rpath::link_flags(config));
+    if config.get_reproducible() {
+        flags.extend(repro::link_flags(config.get_family()));
+    }
+    if !config.get_sanitizers().is_empty() {
+        flags.push(config.get_family().sanitize_arg(&config.get_sanitizers()));
+    }
+    if let Some(phase) = config.get_pgo_phase() {
+        flags.extend(pgo::flags(config, phase));
+    }
+    if config.get_profiling() == Some(Profiling::Gprof) {
+        flags.extend(gprof::flags(config.get_family()));
+    }
+
+    flags
+}
+
+/// Runs a compiler or linker invocation building `unit` into `output`, failing if it
+/// does not exit successfully or runs for longer than the compile timeout.
+fn run_compiler(cmd: &mut Command, unit: &Path, output: &Path, config: &Config) -> MorfoResult<()> {
+    if config.get_env_var("VERBOSITY").as_deref() == Some("1") {
+        println!("{}", format!("{:?}", cmd).replace('\"', ""));
+    }
+
+    // the diagnostics are passed on as they come, unless they are printed short once the
+    // compiler is done, and their warnings counted
+    cmd.stderr(Stdio::piped());
+    let mut child = interrupt::spawn_group(cmd, Some(output))?;
+    let stderr = child.stderr.take();
+    let message_format = config.get_message_format();
+    let tee = thread::spawn(move || {
+        let mut diagnostics = String::new();
+        let Some(stderr) = stderr else {
+            return diagnostics;
+        };
+        for line in io::BufReader::new(stderr).split(b'\n') {
+            let Ok(line) = line else {
+                break;
+            };
+            let line = String::from_utf8_lossy(&line);
+            if message_format != MessageFormat::Short {
+                eprintln!("{}", line);
+            }
+            diagnostics.push_str(&line);
+            diagnostics.push('\n');
+        }
+        diagnostics
+    });
+    let status = match config.get_compile_timeout() {
+        Some(timeout) => match interrupt::wait_timeout(&mut child, timeout)? {
+            Some(status) => status,
+            None => {
+                let _ = tee.join();
+                let _ = fs::remove_file(output);
+                return Err(MorfoError::CompileTimeout(unit.to_path_buf(), timeout));
+            }
+        },
+        None => interrupt::wait(&mut child)?,
+    };
+    let diagnostics = tee.join().unwrap_or_default();
+    if message_format != MessageFormat::Human || config.get_annotations().is_some() {
+        diagnostic::report(&diagnostics, message_format, config.get_annotations());
+    }
     match status.code() {
         Some(code) => {
             if code != 0 {
@@ -80,35 +1115,664 @@ fn compile(act: &ACT, config: &Config) -> MorfoResult<()> {
         None => return Err(MorfoError::CompilationFailure(Option::None)),
     }
 
+    warnings::record(output, warnings::count(&diagnostics))?;
     Ok(())
 }
 
-fn run<W: Write>(
-    act: ACT,
+/// Returns the command invoking `executable`, wrapped in the runner if one is configured,
+/// and in a network namespace of its own if the config turns the network off. It runs as
+/// the user and with the umask of the `[run]` section, if set.
+///
+/// # Errors
+///
+/// If the network is turned off anywhere but on Linux, or the user or the umask cannot
+/// be used.
+fn executable_command(executable: &Path, config: &Config) -> MorfoResult<Command> {
+    let mut words: Vec<OsString> = Vec::new();
+    if !config.get_run_net() {
+        if !cfg!(target_os = "linux") {
+            return Err(MorfoError::Unsupported(
+                "`net = false` off Linux".to_owned(),
+            ));
+        }
+        // in a user namespace of its own, making the network namespace needs no root
+        words.extend(["unshare", "--user", "--net"].map(OsString::from));
+    }
+    words.extend(config.get_runner().into_iter().map(OsString::from));
+    words.push(executable.into());
+    let mut cmd = Command::new(&words[0]);
+    cmd.args(&words[1..]);
+    privilege::apply(&mut cmd, config)?;
+    Ok(cmd)
+}
+
+/// How a program fed a file ran, when its output is captured rather than shown.
+struct Captured {
+    /// How the program exited, or `None` if it was killed for running too long.
+    status: Option<ExitStatus>,
+    /// What the program wrote to its stdout.
+    stdout: Vec<u8>,
+    /// How long the program ran.
+    duration: Duration,
+}
+
+/// Runs `executable` with `input` as its stdin, or none, capturing its stdout and
+/// discarding its stderr, and kills it after `time_limit` if there is one.
+fn capture_output(
+    executable: &Path,
+    config: &Config,
+    input: Option<&Path>,
+    time_limit: Option<Duration>,
+) -> MorfoResult<Captured> {
+    let mut cmd = executable_command(executable, config)?;
+    let stdin = match input {
+        Some(input) => Stdio::from(fs::File::open(input)?),
+        None => Stdio::null(),
+    };
+    cmd.stdin(stdin)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    let start = Instant::now();
+    let mut child = interrupt::spawn(&mut cmd, None)?;
+    let mut stdout = child.stdout.take();
+    let read_stdout = thread::spawn(move || -> io::Result<Vec<u8>> {
+        let mut output = Vec::new();
+        if let Some(stdout) = &mut stdout {
+            stdout.read_to_end(&mut output)?;
+        }
+        Ok(output)
+    });
+    let status = match time_limit {
+        Some(time_limit) => interrupt::wait_timeout(&mut child, time_limit)?,
+        None => Some(interrupt::wait(&mut child)?),
+    };
+    let duration = start.elapsed();
+    let stdout = read_stdout.join().unwrap_or_else(|_| Ok(Vec::new()))?;
+    Ok(Captured {
+        status,
+        stdout,
+        duration,
+    })
+}
+
+/// Runs `executable` with `options`, and returns how it exited, or `None`
+/// in a dry run.
+fn run_executable<W: Write>(
+    executable: &Path,
     config: &Config,
     out: &mut W,
-    prog_args: Vec<String>,
-) -> MorfoResult<()> {
-    let executable = config.get_build_dir().join(utils::file_name(&act.name));
-    if !executable.exists() {
-        return Err(MorfoError::MissingExecutable);
+    options: RunOptions,
+) -> MorfoResult<Option<ExitStatus>> {
+    let mut run_cmd = executable_command(executable, config)?;
+    run_cmd.args(options.args);
+    if options.stdin.is_none() {
+        run_cmd.stdin(Stdio::inherit());
+    }
+    if config.get_profiling() == Some(Profiling::Gprof) {
+        run_cmd.env("GMON_OUT_PREFIX", layout::gmon_prefix(config));
     }
 
-    // use command to invoke the executable
-    let mut run_cmd = Command::new(executable);
-    for arg in prog_args {
-        run_cmd.arg(arg);
+    if config.get_dry_run() {
+        println!("{}", utils::format_command(&run_cmd));
+        return Ok(None);
+    }
+    if !executable.exists() {
+        return Err(MorfoError::MissingExecutable);
     }
-    run_cmd.stdin(Stdio::inherit());
 
-    if env::var("VERBOSITY").unwrap_or_default() == "1" {
+    if config.get_env_var("VERBOSITY").as_deref() == Some("1") {
         println!("{}", format!("{:?}", run_cmd).replace('\"', ""));
     }
     println!();
 
-    // pipe the output to out
-    let run_project = run_cmd.output()?;
-    out.write_all(&run_project.stdout)?;
+    let mut out = tee::Tee::new(out, config)?;
+    #[cfg(unix)]
+    if config.get_tty() && options.stdin.is_none() {
+        return pty::run(&mut run_cmd, &mut out).map(Some);
+    }
+    tee::run(&mut run_cmd, options.stdin, &mut out).map(Some)
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    /// Builds a project whose `main.c` and `bad.gen` do not compile and `good.gen` does.
+    /// Every `.gen` file is copied to a C source by a generator.
+    fn build_broken(keep_going: bool) -> (tempfile::TempDir, MorfoResult<()>) {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(dir.join("main.c"), "int main(void) { return missing; }\n").unwrap();
+        fs::write(dir.join("bad.gen"), "int bad(void) { return missing; }\n").unwrap();
+        fs::write(dir.join("good.gen"), "int good(void) { return 0; }\n").unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            builddir = "{}"
+
+            [generators."*.gen"]
+            command = "cp {{in}} {{out}}"
+            output = "{{stem}}.c""#,
+            dir.join(".out").display()
+        ))
+        .unwrap();
+
+        let main_file = dir.join("main.c");
+        let result = prepare(&main_file, config.with_keep_going(keep_going))
+            .and_then(|(act, config)| compile_act(&act, &config).map(|_| ()));
+        (tmp_dir, result)
+    }
+
+    #[test]
+    fn compile_only_flags_skip_preprocessor_values() {
+        let flags: Vec<String> = [
+            "-O2", "-I", "include", "-Isrc", "-isystem", "sys", "-include", "config.h", "-DX=1",
+            "-U", "Y", "-MMD", "-MF", "main.d", "-Wall",
+        ]
+        .map(String::from)
+        .to_vec();
+        assert_eq!(compile_only_flags(&flags), vec!["-O2", "-Wall"]);
+    }
+
+    #[test]
+    fn compile_stops_at_first_failure() {
+        let (_tmp_dir, result) = build_broken(false);
+        assert_eq!(result, Err(MorfoError::CompilationFailure(Some(1))));
+    }
+
+    #[test]
+    fn compile_keep_going() {
+        let (tmp_dir, result) = build_broken(true);
+        let Err(MorfoError::CompilationFailures(mut failed)) = result else {
+            panic!("expected every failure, got {:?}", result);
+        };
+        failed.sort();
+        let out = tmp_dir.path().join(".out");
+        assert_eq!(
+            failed,
+            vec![out.join("gen").join("bad.c"), tmp_dir.path().join("main.c")]
+        );
+        assert!(out.join("gen").join("good.o").exists());
+        assert!(!out.join("main").exists());
+    }
+
+    #[test]
+    fn compile_only_stale_objects() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(dir.join("main.c"), "int main(void) { return 0; }\n").unwrap();
+        let build = |cc: &str, cflags: &str| {
+            let config = config::ConfigBuilder::default()
+                .set_cc(cc)
+                .add_cflag(cflags)
+                .set_build_dir(dir.join(".out").to_str().unwrap())
+                .build();
+            let (act, config) = prepare(&dir.join("main.c"), config).unwrap();
+            compile_act(&act, &config).unwrap();
+            fs::metadata(dir.join(".out").join("main.o"))
+                .and_then(|metadata| metadata.modified())
+                .unwrap()
+        };
+
+        let built = build("gcc", "-O1");
+        assert_eq!(build("gcc", "-O1"), built);
+        let built_with_flags = build("gcc", "-O2");
+        assert_ne!(built_with_flags, built);
+        assert_ne!(build("cc", "-O2"), built_with_flags);
+    }
+
+    #[test]
+    fn execute_outcome() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(dir.join("main.c"), "int main(void) { return 3; }\n").unwrap();
+        let config = config::ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir(dir.join(".out").to_str().unwrap())
+            .build();
+        let out = dir.join(".out");
+        let execute = || {
+            execute(
+                dir.join("main.c"),
+                config.clone(),
+                &mut io::sink(),
+                Vec::new(),
+            )
+            .unwrap()
+        };
+
+        let outcome = execute();
+        assert_eq!(
+            outcome.exit_status.and_then(|status| status.code()),
+            Some(3)
+        );
+        assert_eq!(outcome.executable_path, out.join("main"));
+        assert_eq!(
+            outcome.compile_stats.built,
+            vec![out.join("main.o"), out.join("main")]
+        );
+        assert_eq!(
+            execute().compile_stats.fresh,
+            vec![out.join("main.o"), out.join("main")]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn run_without_network() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(
+            dir.join("main.c"),
+            "#include <arpa/inet.h>\n#include <stdlib.h>\n#include <sys/socket.h>\n\
+             int main(int argc, char **argv) {\n\
+             struct sockaddr_in addr = { .sin_family = AF_INET, .sin_port = htons(atoi(argv[1])) };\n\
+             addr.sin_addr.s_addr = htonl(INADDR_LOOPBACK);\n\
+             int fd = socket(AF_INET, SOCK_STREAM, 0);\n\
+             return connect(fd, (struct sockaddr *)&addr, sizeof addr) != 0;\n}\n",
+        )
+        .unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port().to_string();
+        let connect = |net: bool| {
+            let config: Config = toml::from_str(&format!(
+                "cc = \"gcc\"\nbuilddir = \"{}\"\n[run]\nnet = {}",
+                dir.join(".out").display(),
+                net
+            ))
+            .unwrap();
+            execute(
+                dir.join("main.c"),
+                config,
+                &mut io::sink(),
+                vec![port.clone()],
+            )
+            .unwrap()
+            .exit_status
+            .and_then(|status| status.code())
+        };
+
+        assert_eq!(connect(true), Some(0));
+        assert_eq!(connect(false), Some(1));
+    }
+
+    #[test]
+    fn run_artifact_with_inputs() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(
+            dir.join("main.c"),
+            "#include <stdio.h>\n\
+             int main(int argc, char **argv) {\n\
+                 int a, b;\n\
+                 if (scanf(\"%d %d\", &a, &b) != 2) return 1;\n\
+                 printf(\"%s %d\", argv[1], a + b);\n\
+                 return 0;\n\
+             }\n",
+        )
+        .unwrap();
+        let config = config::ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir(dir.join(".out").to_str().unwrap())
+            .build();
+
+        let artifact = compile(&dir.join("main.c"), &config).unwrap();
+        assert_eq!(artifact.executable_path, dir.join(".out").join("main"));
+        for (input, expected) in [("1 2", "sum 3"), ("40 2\n", "sum 42")] {
+            let options = RunOptions::new()
+                .with_args(vec!["sum".to_owned()])
+                .with_stdin(input);
+            let mut out = Vec::new();
+            let outcome = run(&artifact, options, &mut out).unwrap();
+            assert!(outcome.exit_status.unwrap().success());
+            assert_eq!(String::from_utf8(out).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn include_dirs_are_searched() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::create_dir(dir.join("inc")).unwrap();
+        fs::write(
+            dir.join("main.c"),
+            "#include \"api.h\"\nint main(void) { return answer(); }\n",
+        )
+        .unwrap();
+        fs::write(dir.join("inc").join("api.h"), "int answer(void);\n").unwrap();
+        fs::write(
+            dir.join("inc").join("api.c"),
+            "#include \"api.h\"\nint answer(void) { return 42; }\n",
+        )
+        .unwrap();
+        let config = config::ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir(dir.join(".out").to_str().unwrap())
+            .add_include(dir.join("inc").to_str().unwrap())
+            .build();
+
+        let outcome = execute(dir.join("main.c"), config, &mut io::sink(), Vec::new()).unwrap();
+        assert_eq!(
+            outcome.exit_status.and_then(|status| status.code()),
+            Some(42)
+        );
+    }
+
+    #[test]
+    fn cflags_are_separate_arguments() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(
+            dir.join("main.c"),
+            "#include <string.h>\nint main(void) { return strlen(GREETING) + LEVEL; }\n",
+        )
+        .unwrap();
+        let config = config::ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir(dir.join(".out").to_str().unwrap())
+            .add_cflag("-Wall -O2")
+            .build()
+            .with_cflags(r#"-DGREETING='"hello world"' -D LEVEL=30"#);
+
+        let outcome = execute(dir.join("main.c"), config, &mut io::sink(), Vec::new()).unwrap();
+        assert_eq!(
+            outcome.exit_status.and_then(|status| status.code()),
+            Some(41)
+        );
+    }
+
+    #[test]
+    fn same_name_sources_get_their_own_objects() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        for (name, value) in [("a", 1), ("b", 2)] {
+            fs::create_dir(dir.join(name)).unwrap();
+            fs::write(
+                dir.join(name).join("util.h"),
+                format!("int {}(void);\n", name),
+            )
+            .unwrap();
+            fs::write(
+                dir.join(name).join("util.c"),
+                format!("int {}(void) {{ return {}; }}\n", name, value),
+            )
+            .unwrap();
+        }
+        fs::write(
+            dir.join("main.c"),
+            "#include \"a/util.h\"\n#include \"b/util.h\"\n\
+             int main(void) { return a() * 10 + b(); }\n",
+        )
+        .unwrap();
+        let config = config::ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir(dir.join(".out").to_str().unwrap())
+            .build();
+
+        let outcome = execute(dir.join("main.c"), config, &mut io::sink(), Vec::new()).unwrap();
+        assert_eq!(
+            outcome.exit_status.and_then(|status| status.code()),
+            Some(12)
+        );
+        assert_eq!(outcome.compile_stats.built.len(), 4);
+        assert!(dir.join(".out/a/util.o").exists());
+        assert!(dir.join(".out/b/util.o").exists());
+    }
+
+    #[test]
+    fn dry_run_builds_nothing() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(dir.join("main.c"), "int main(void) { return missing; }\n").unwrap();
+        let config = config::ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir(dir.join(".out").to_str().unwrap())
+            .build()
+            .with_dry_run(true);
+
+        let executable = build(dir.join("main.c"), config).unwrap();
+        assert_eq!(executable, dir.join(".out").join("main"));
+        assert!(!dir.join(".out").exists());
+    }
+
+    #[test]
+    fn unused_files_are_orphans() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(dir.join("main.c"), "#include \"util.h\"\n").unwrap();
+        fs::write(dir.join("util.h"), "").unwrap();
+        fs::write(dir.join("util.c"), "#include \"util.h\"\n").unwrap();
+        fs::write(dir.join("old.h"), "").unwrap();
+        fs::write(dir.join("old.c"), "#include \"old.h\"\n").unwrap();
+        let config = config::ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir(dir.join(".out").to_str().unwrap())
+            .build();
+
+        let unused = unused_files(&dir.join("main.c"), config).unwrap();
+        assert_eq!(unused, [dir.join("old.c"), dir.join("old.h")]);
+    }
+
+    #[test]
+    fn symbol_check_names_both_definitions() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(
+            dir.join("main.c"),
+            "#include \"util.h\"\nint main(void) { return 0; }\n",
+        )
+        .unwrap();
+        fs::write(dir.join("util.h"), "").unwrap();
+        fs::write(dir.join("util.c"), "int main(void) { return 1; }\n").unwrap();
+        let config = config::ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir(dir.join(".out").to_str().unwrap())
+            .build()
+            .with_symbol_check(true);
+
+        let Err(MorfoError::DuplicateSymbols(duplicates)) = build(dir.join("main.c"), config)
+        else {
+            panic!("expected the duplicate main");
+        };
+        assert_eq!(
+            duplicates,
+            [symbols::Duplicate {
+                symbol: "main".to_owned(),
+                first: dir.join("util.c").display().to_string(),
+                second: dir.join("main.c").display().to_string(),
+            }]
+        );
+        assert!(!dir.join(".out").join("main").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn compile_timeout_names_the_source() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(dir.join("main.c"), "int main(void) { return 0; }\n").unwrap();
+        // a compiler that hangs on compiling in a process of its own, like cc1, but
+        // answers the probes
+        let cc = dir.join("hangcc");
+        fs::write(
+            &cc,
+            format!(
+                "#!/bin/sh\ncase \" $* \" in *\" -c \"*) echo $$ > {}; sleep 10; exit 1;; esac\nexec gcc \"$@\"\n",
+                dir.join("pgid").display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&cc, fs::Permissions::from_mode(0o755)).unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "{}"
+            family = "gcc"
+            builddir = "{}"
+            compile_timeout = 1"#,
+            cc.display(),
+            dir.join(".out").display()
+        ))
+        .unwrap();
+
+        let start = Instant::now();
+        assert_eq!(
+            build(dir.join("main.c"), config),
+            Err(MorfoError::CompileTimeout(
+                dir.join("main.c"),
+                Duration::from_secs(1)
+            ))
+        );
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(!dir.join(".out").join("main.o").exists());
+        // the compiler led its process group, so nothing it started is left running
+        let pgid = fs::read_to_string(dir.join("pgid")).unwrap();
+        #[cfg(target_os = "linux")]
+        for entry in fs::read_dir("/proc").unwrap().flatten() {
+            let Ok(stat) = fs::read_to_string(entry.path().join("stat")) else {
+                continue;
+            };
+            // the fields after the command are the state, the parent and the group;
+            // a killed process its reaper has not waited for yet is a zombie
+            let fields: Vec<&str> = stat[stat.rfind(')').unwrap() + 1..]
+                .split_whitespace()
+                .collect();
+            assert!(fields[2] != pgid.trim() || fields[0] == "Z", "{}", stat);
+        }
+    }
+
+    #[test]
+    fn compilers_per_language() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(
+            dir.join("main.c"),
+            "#include \"counter.h\"\nint main(void) { return count(\"hello\"); }\n",
+        )
+        .unwrap();
+        fs::write(dir.join("counter.h"), "int count(const char *text);\n").unwrap();
+        fs::write(
+            dir.join("counter.cpp"),
+            "#include <string>\nextern \"C\" int count(const char *text) {\n    \
+             return std::string(text).size() + 37;\n}\n",
+        )
+        .unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            builddir = "{out}"
+            header_sources = {{ "{header}" = ["{source}"] }}
+
+            [compilers]
+            c = "gcc"
+            "#,
+            out = dir.join(".out").display(),
+            header = dir.join("counter.h").display(),
+            source = dir.join("counter.cpp").display(),
+        ))
+        .unwrap();
+        assert_eq!(config.get_compiler(Language::Cxx), "g++");
+
+        // the C++ source is compiled with g++, and the program linked with its library
+        let outcome = execute(dir.join("main.c"), config, &mut io::sink(), Vec::new()).unwrap();
+        assert_eq!(
+            outcome.exit_status.and_then(|status| status.code()),
+            Some(42)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn linker_override() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let main_file = dir.join("main.c");
+        fs::write(&main_file, "int main(void) { return 42; }\n").unwrap();
+        // a link driver that records it linked, then links with gcc
+        let linker = dir.join("linker");
+        fs::write(
+            &linker,
+            format!(
+                "#!/bin/sh\ntouch {}\nexec gcc \"$@\"\n",
+                dir.join("linked").display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&linker, fs::Permissions::from_mode(0o755)).unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            linker = "{}"
+            builddir = "{}""#,
+            linker.display(),
+            dir.join(".out").display()
+        ))
+        .unwrap();
+
+        let outcome = execute(main_file.clone(), config, &mut io::sink(), Vec::new()).unwrap();
+        assert_eq!(
+            outcome.exit_status.and_then(|status| status.code()),
+            Some(42)
+        );
+        assert!(dir.join("linked").exists());
+
+        // a linker name is passed to the compiler
+        let config: Config = toml::from_str(&format!(
+            "cc = \"gcc\"\nlinker = \"ld.bfd\"\nbuilddir = \"{}\"",
+            dir.join(".out").display()
+        ))
+        .unwrap();
+        let (act, config) = prepare(&main_file, config).unwrap();
+        let link_cmd = link_command(&act, &[], &config).unwrap();
+        assert_eq!(link_cmd.get_program(), "gcc");
+        assert!(link_cmd.get_args().any(|arg| arg == "-fuse-ld=bfd"));
+    }
+
+    #[test]
+    fn bin_dir_keeps_executables_apart() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let main_file = dir.join("main.c");
+        fs::write(dir.join("util.h"), "int util(void);\n").unwrap();
+        fs::write(dir.join("util.c"), "int util(void) { return 42; }\n").unwrap();
+        fs::write(
+            &main_file,
+            "#include \"util.h\"\nint main(void) { return util(); }\n",
+        )
+        .unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            builddir = "{}"
+            bindir = "{}""#,
+            dir.join(".out").display(),
+            dir.join("bin").display()
+        ))
+        .unwrap();
+
+        let outcome = execute(
+            main_file.clone(),
+            config.clone(),
+            &mut io::sink(),
+            Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            outcome.exit_status.and_then(|status| status.code()),
+            Some(42)
+        );
+        assert_eq!(outcome.executable_path, dir.join("bin").join("main"));
+        assert!(dir.join(".out").join("util.o").is_file());
+        assert!(!dir.join(".out").join("main").exists());
+
+        // the objects the executable in `bindir` links are not garbage
+        let report = gc::gc(&config, None, None).unwrap();
+        assert_eq!((report.objects, report.executables), (0, 0));
+        let stats = compile(&main_file, &config).unwrap().compile_stats;
+        assert!(stats.built.is_empty());
+    }
 }
@@ -0,0 +1,376 @@
+//! Libraries linked into the program, and the order they are linked in.
+//!
+//! Single-pass linkers such as GNU ld only resolve a symbol from an archive if it is
+//! already undefined when the archive is read, so a library must come after everything
+//! that uses it. morfo derives that order from the `deps` declared in the `[libraries]`
+//! section instead of relying on the order the libraries are written in: the objects come
+//! first, dependents before their dependencies, then each library before the libraries
+//! it depends on.
+//!
+//! Libraries that depend on each other cannot be ordered. Setting `link_group = true`
+//! wraps the libraries in `--start-group`/`--end-group`, which makes the linker search
+//! them repeatedly until nothing new is resolved.
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    compiler_command,
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    toolchain::CompilerFamily,
+    utils,
+};
+
+/// A library to link, declared in the `[libraries]` section of the config.
+///
+/// A library with a `path` is linked from that file. Otherwise it is looked up by
+/// name on the linker's search path, like `-lm`.
+///
+/// `version`, `license` and `sources` only describe the library for `morfo sbom`.
+/// `sources` are patterns for the vendored files of the library, relative to the
+/// project directory.
+///
+/// # Examples
+///
+/// ```toml
+/// [libraries]
+/// parse = { path = "vendor/libparse.a", deps = ["util"], version = "2.1.0", license = "MIT" }
+/// util = { path = "vendor/libutil.a", deps = ["m"], sources = ["vendor/util/**"] }
+/// m = {}
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize)]
+pub struct Library {
+    path: Option<String>,
+    deps: Option<Vec<String>>,
+    version: Option<String>,
+    license: Option<String>,
+    sources: Option<Vec<String>>,
+}
+
+impl Library {
+    /// Returns the file the library is linked from, if it is not found by name.
+    pub fn get_path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// Returns a library linked from the archive at `path`, before the libraries of `deps`.
+    pub(crate) fn archive(path: &Path, deps: Vec<String>) -> Library {
+        Library {
+            path: Some(path.to_string_lossy().into_owned()),
+            deps: Some(deps),
+            ..Library::default()
+        }
+    }
+
+    /// Returns the names of the libraries this library depends on.
+    pub fn get_deps(&self) -> Vec<String> {
+        self.deps.clone().unwrap_or_default()
+    }
+
+    /// Returns the version of the library, if declared.
+    pub fn get_version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// Returns the SPDX license expression of the library, if declared.
+    pub fn get_license(&self) -> Option<&str> {
+        self.license.as_deref()
+    }
+
+    /// Returns the patterns matching the vendored sources of the library.
+    pub fn get_sources(&self) -> Vec<String> {
+        self.sources.clone().unwrap_or_default()
+    }
+}
+
+/// Returns the names of `libraries` in link order: every library before its dependencies.
+/// Libraries that do not depend on each other keep their name order.
+///
+/// # Errors
+///
+/// If a library depends on one that is not declared, or the libraries depend on each
+/// other in a cycle and `allow_cycles` is not set.
+pub fn order(
+    libraries: &BTreeMap<String, Library>,
+    allow_cycles: bool,
+) -> MorfoResult<Vec<String>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        Visiting,
+        Done,
+    }
+
+    fn visit(
+        name: &str,
+        libraries: &BTreeMap<String, Library>,
+        allow_cycles: bool,
+        states: &mut BTreeMap<String, State>,
+        stack: &mut Vec<String>,
+        post_order: &mut Vec<String>,
+    ) -> MorfoResult<()> {
+        match states.get(name) {
+            Some(State::Done) => return Ok(()),
+            Some(State::Visiting) if allow_cycles => return Ok(()),
+            Some(State::Visiting) => {
+                let start = stack.iter().position(|n| n == name).unwrap_or(0);
+                let mut cycle = stack[start..].to_vec();
+                cycle.push(name.to_owned());
+                return Err(MorfoError::InvlidConfig(format!(
+                    "libraries depend on each other ({}); set `link_group = true` to link them as a group",
+                    cycle.join(" -> ")
+                )));
+            }
+            None => (),
+        }
+        let library = libraries.get(name).ok_or_else(|| {
+            MorfoError::InvlidConfig(format!(
+                "library `{}` depends on undeclared library `{}`",
+                stack.last().map(String::as_str).unwrap_or_default(),
+                name
+            ))
+        })?;
+
+        states.insert(name.to_owned(), State::Visiting);
+        stack.push(name.to_owned());
+        for dep in library.get_deps() {
+            visit(&dep, libraries, allow_cycles, states, stack, post_order)?;
+        }
+        stack.pop();
+        states.insert(name.to_owned(), State::Done);
+        post_order.push(name.to_owned());
+        Ok(())
+    }
+
+    let mut states = BTreeMap::new();
+    let mut post_order = Vec::new();
+    // visiting in reverse name order and reversing the result keeps independent libraries in name order
+    for name in libraries.keys().rev() {
+        visit(
+            name,
+            libraries,
+            allow_cycles,
+            &mut states,
+            &mut Vec::new(),
+            &mut post_order,
+        )?;
+    }
+    post_order.reverse();
+    Ok(post_order)
+}
+
+/// Checks that a static archive exists for the C library and every library linked by name,
+/// so that a static link fails with the missing library rather than a wall of linker errors.
+/// Only GCC and Clang can be asked where their archives are.
+///
+/// # Errors
+///
+/// If an archive cannot be found.
+pub(crate) fn check_static(config: &Config) -> MorfoResult<()> {
+    if config.get_family() == CompilerFamily::Msvc {
+        return Ok(());
+    }
+
+    let by_name = config
+        .get_libraries()
+        .into_iter()
+        .filter(|(_, library)| library.path.is_none())
+        .map(|(name, _)| name);
+    for name in std::iter::once("c".to_owned()).chain(by_name) {
+        let archive = format!("lib{}.a", name);
+        let mut print_cmd = compiler_command(config);
+        print_cmd.arg(format!("-print-file-name={}", archive));
+        let output = utils::run_tool(&mut print_cmd)?;
+        // the compiler echoes the name back when it cannot find the file
+        if String::from_utf8_lossy(&output.stdout).trim() == archive {
+            return Err(MorfoError::MissingStaticLibrary(archive));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the archives of the libraries linked from a file, which the executable is
+/// linked again after they change.
+pub(crate) fn archives(config: &Config) -> Vec<PathBuf> {
+    config
+        .get_libraries()
+        .into_values()
+        .filter_map(|library| library.path.map(PathBuf::from))
+        .collect()
+}
+
+/// Returns the linker arguments for the configured libraries, in link order.
+///
+/// # Errors
+///
+/// If the libraries cannot be ordered.
+pub(crate) fn link_args(config: &Config) -> MorfoResult<Vec<OsString>> {
+    let libraries = config.get_libraries();
+    if libraries.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let family = config.get_family();
+    let mut args: Vec<OsString> = order(&libraries, config.get_link_group())?
+        .iter()
+        .map(|name| match &libraries[name].path {
+            Some(path) => path.into(),
+            None => family.library_arg(name).into(),
+        })
+        .collect();
+
+    // the MSVC and Apple linkers search libraries repeatedly already
+    let groups = family != CompilerFamily::Msvc && !cfg!(target_os = "macos");
+    if config.get_link_group() && groups {
+        args.insert(0, "-Wl,--start-group".into());
+        args.push("-Wl,--end-group".into());
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::Path, process::Command};
+
+    use crate::{compile, run, RunOptions};
+
+    fn libraries(toml: &str) -> BTreeMap<String, Library> {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn libraries_order() {
+        let libraries = libraries(
+            r#"
+            app = { deps = ["parse", "util"] }
+            parse = { deps = ["util"] }
+            util = { deps = ["m"] }
+            m = {}
+            z = {}"#,
+        );
+        assert_eq!(
+            order(&libraries, false).unwrap(),
+            vec!["app", "parse", "util", "m", "z"]
+        );
+    }
+
+    #[test]
+    fn libraries_order_cycle() {
+        let libraries = libraries(
+            r#"
+            a = { deps = ["b"] }
+            b = { deps = ["a"] }"#,
+        );
+        assert_eq!(
+            order(&libraries, false).unwrap_err(),
+            MorfoError::InvlidConfig(
+                "libraries depend on each other (b -> a -> b); set `link_group = true` to link them as a group"
+                    .to_owned()
+            )
+        );
+        assert_eq!(order(&libraries, true).unwrap(), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn libraries_order_undeclared() {
+        let libraries = libraries(r#"a = { deps = ["missing"] }"#);
+        assert_eq!(
+            order(&libraries, false).unwrap_err(),
+            MorfoError::InvlidConfig(
+                "library `a` depends on undeclared library `missing`".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn libraries_link_args_group() {
+        let config: Config = toml::from_str(
+            r#"
+            cc = "gcc"
+            link_group = true
+
+            [libraries]
+            a = { path = "liba.a", deps = ["b"] }
+            b = { deps = ["a"] }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            link_args(&config).unwrap(),
+            vec!["-Wl,--start-group", "-lb", "liba.a", "-Wl,--end-group"]
+        );
+    }
+
+    #[test]
+    fn libraries_check_static() {
+        let config: Config = toml::from_str(
+            r#"
+            cc = "gcc"
+
+            [libraries]
+            morfo_no_such_library = {}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            check_static(&config).unwrap_err(),
+            MorfoError::MissingStaticLibrary("libmorfo_no_such_library.a".to_owned())
+        );
+    }
+
+    fn archive(dir: &Path, name: &str, source: &str) {
+        let c_file = dir.join(format!("{}.c", name));
+        let object = dir.join(format!("{}.o", name));
+        fs::write(&c_file, source).unwrap();
+        assert!(Command::new("gcc")
+            .arg("-c")
+            .arg(&c_file)
+            .arg("-o")
+            .arg(&object)
+            .status()
+            .unwrap()
+            .success());
+        assert!(Command::new("ar")
+            .arg("rcs")
+            .arg(dir.join(format!("lib{}.a", name)))
+            .arg(&object)
+            .status()
+            .unwrap()
+            .success());
+    }
+
+    #[test]
+    fn libraries_link_in_dependency_order() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        // `a` sorts first but depends on `b`, so name order would fail to link
+        archive(dir, "a", "int b(void);\nint a(void) { return b() + 1; }\n");
+        archive(dir, "b", "int b(void) { return 41; }\n");
+        let main_file = dir.join("main.c");
+        fs::write(
+            &main_file,
+            "#include <stdio.h>\nint a(void);\nint main(void) { printf(\"%d\", a()); return 0; }\n",
+        )
+        .unwrap();
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            builddir = "{0}/.out"
+
+            [libraries]
+            a = {{ path = "{0}/liba.a", deps = ["b"] }}
+            b = {{ path = "{0}/libb.a" }}"#,
+            dir.display()
+        ))
+        .unwrap();
+        let artifact = compile(&main_file, &config).unwrap();
+
+        let mut out = Vec::new();
+        run(&artifact, RunOptions::new(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "42");
+    }
+}
@@ -0,0 +1,136 @@
+//! Static analysis of the discovered sources.
+//!
+//! `morfo lint` runs one or more analyzer backends over every translation unit reachable
+//! from the main file, with the project's include paths and defines, and merges what they
+//! report into a single list of [`Diagnostic`]s.
+//!
+//! [`Diagnostic`]: ../diagnostic/struct.Diagnostic.html
+
+use std::{path::PathBuf, process::Command, str::FromStr};
+
+use crate::{
+    compile_flags,
+    config::Config,
+    diagnostic::{self, Diagnostic},
+    error::MorfoResult,
+    fortran, layout, lock, prepare, utils,
+};
+
+/// An analyzer morfo can run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    ClangTidy,
+    Cppcheck,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clang-tidy" => Ok(Backend::ClangTidy),
+            "cppcheck" => Ok(Backend::Cppcheck),
+            _ => Err(format!(
+                "unknown backend `{}`, expected `clang-tidy` or `cppcheck`",
+                s
+            )),
+        }
+    }
+}
+
+/// Analyzes `main_file` and its dependencies with each of `backends`.
+/// The diagnostics are sorted by location and de-duplicated.
+///
+/// # Errors
+///
+/// If a backend is not installed or cannot be run.
+pub fn lint(
+    main_file: PathBuf,
+    config: Config,
+    backends: &[Backend],
+) -> MorfoResult<Vec<Diagnostic>> {
+    let _lock = lock::lock(&config)?;
+    let (act, config) = prepare(&main_file, config)?;
+    // the analyzers only know C
+    let sources: Vec<PathBuf> = act
+        .sources()
+        .into_iter()
+        .filter(|source| !fortran::is_fortran(source))
+        .collect();
+
+    let mut diagnostics = Vec::new();
+    for backend in backends {
+        let mut cmd = match backend {
+            Backend::ClangTidy => clang_tidy(&config, &sources),
+            Backend::Cppcheck => cppcheck(&config, &sources),
+        };
+        // both analyzers exit successfully even when they find problems
+        let output = utils::run_tool(&mut cmd)?;
+        let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+        text.push_str(&String::from_utf8_lossy(&output.stderr));
+        diagnostics.extend(diagnostic::parse(&text));
+    }
+
+    diagnostic::dedup(&mut diagnostics);
+    Ok(diagnostics)
+}
+
+fn clang_tidy(config: &Config, sources: &[PathBuf]) -> Command {
+    let mut cmd = Command::new("clang-tidy");
+    cmd.arg("--quiet")
+        .args(sources)
+        .arg("--")
+        .args(config.get_cflags())
+        .args(compile_flags(config));
+    cmd
+}
+
+fn cppcheck(config: &Config, sources: &[PathBuf]) -> Command {
+    let mut cmd = Command::new("cppcheck");
+    cmd.args([
+        "--quiet",
+        "--enable=warning,style,performance,portability",
+        "--template={file}:{line}:{column}: {severity}: {message} [{id}]",
+    ]);
+    for include in config.get_includes() {
+        cmd.arg(format!("-I{}", include));
+    }
+    if !config.get_features().is_empty() {
+        cmd.arg(format!("-I{}", layout::include_dir(config).display()));
+    }
+    if !config.get_generators().is_empty() {
+        cmd.arg(format!("-I{}", layout::generated_dir(config).display()));
+    }
+    if !config.get_embed().is_empty() {
+        cmd.arg(format!("-I{}", layout::embed_dir(config).display()));
+    }
+    for (name, value) in config.get_defines() {
+        cmd.arg(format!("-D{}={}", name, value));
+    }
+    cmd.args(sources);
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigBuilder;
+
+    #[test]
+    fn lint_backend_from_str() {
+        assert_eq!("cppcheck".parse(), Ok(Backend::Cppcheck));
+        assert_eq!("clang-tidy".parse(), Ok(Backend::ClangTidy));
+        assert!("splint".parse::<Backend>().is_err());
+    }
+
+    #[test]
+    fn lint_cppcheck_command() {
+        let config = ConfigBuilder::default()
+            .add_include("include")
+            .add_define("DEBUG", "1")
+            .build();
+        let cmd = cppcheck(&config, &[PathBuf::from("main.c")]);
+        let args: Vec<_> = cmd.get_args().map(|arg| arg.to_str().unwrap()).collect();
+        assert_eq!(args[3..], ["-Iinclude", "-DDEBUG=1", "main.c"]);
+    }
+}
@@ -0,0 +1,114 @@
+//! Build directory locking.
+//!
+//! Two morfo runs building into the same directory would overwrite each other's objects
+//! halfway through. Every build takes an advisory lock on `<builddir>/.lock` first, and
+//! a second run waits for the first to finish. The lock file holds the PID of the run
+//! holding the lock, so the waiting run can say who it is waiting for. The lock is
+//! released when the run exits, even if it crashes.
+
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::{Read, Seek, Write},
+    path::Path,
+    process,
+};
+
+use colored::Colorize;
+
+use crate::{config::Config, error::MorfoResult, layout};
+
+/// A held lock on the build directory, released when dropped.
+#[derive(Debug)]
+pub(crate) struct BuildLock {
+    _file: File,
+}
+
+/// Locks the build directory for the rest of the build, waiting for another run holding
+/// it to finish. Nothing is locked with `--no-lock` or in a dry run.
+///
+/// # Errors
+///
+/// If the build directory or the lock file cannot be created, or the lock cannot be taken.
+pub(crate) fn lock(config: &Config) -> MorfoResult<Option<BuildLock>> {
+    if config.get_no_lock() || config.get_dry_run() {
+        return Ok(None);
+    }
+    layout::create_build_dir(config)?;
+    lock_file(&layout::lock_file(config)).map(Some)
+}
+
+/// Locks the build directory whose lock file is at `path`, waiting for another run
+/// holding it to finish.
+///
+/// # Errors
+///
+/// If the lock file cannot be created, or the lock cannot be taken.
+pub(crate) fn lock_file(path: &Path) -> MorfoResult<BuildLock> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+
+    match file.try_lock() {
+        Ok(()) => (),
+        Err(TryLockError::WouldBlock) => {
+            let mut holder = String::new();
+            file.read_to_string(&mut holder)?;
+            let holder = match holder.trim() {
+                "" => "another process".to_owned(),
+                pid => format!("PID {}", pid),
+            };
+            eprintln!(
+                "{}",
+                format!(
+                    "Waiting for lock on {} held by {}...",
+                    path.display(),
+                    holder
+                )
+                .yellow()
+            );
+            file.lock()?;
+        }
+        Err(TryLockError::Error(e)) => return Err(e.into()),
+    }
+
+    file.set_len(0)?;
+    file.rewind()?;
+    write!(file, "{}", process::id())?;
+    Ok(BuildLock { _file: file })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread, time::Duration};
+
+    use super::*;
+    use crate::config::ConfigBuilder;
+
+    #[test]
+    fn lock_waits_for_holder() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let config = ConfigBuilder::default()
+            .set_build_dir(tmp_dir.path().join(".out").to_str().unwrap())
+            .build();
+
+        let held = lock(&config).unwrap().unwrap();
+        assert_eq!(
+            fs::read_to_string(layout::lock_file(&config)).unwrap(),
+            process::id().to_string()
+        );
+
+        let waiter = {
+            let config = config.clone();
+            thread::spawn(move || lock(&config).unwrap().is_some())
+        };
+        thread::sleep(Duration::from_millis(100));
+        assert!(!waiter.is_finished());
+        drop(held);
+        assert!(waiter.join().unwrap());
+
+        assert!(lock(&config.with_no_lock(true)).unwrap().is_none());
+    }
+}
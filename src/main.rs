@@ -1,54 +1,1566 @@
-use std::{env, io, path::PathBuf, process};
+use std::{
+    env, fs,
+    io::{self, IsTerminal, Read},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process, thread,
+    time::Duration,
+};
 
-use clap::Parser;
+use clap::{Args, CommandFactory, Parser, Subcommand};
 use colored::Colorize;
 use morfo::{
-    config::{find_config_file, parse_config_file},
-    execute,
+    batch, cache,
+    config::{find_config_file, parse_config_file_with_vars, BuildPriority, Config, Linkage},
+    diagnostic::{self, Annotations, MessageFormat, Severity},
+    difftest,
+    error::{MorfoError, MorfoResult},
+    fingerprint, flamegraph, flash, fuzz, gc, heap, interrupt,
+    judge::{self, Verdict},
+    layout,
+    lint::{self, Backend},
+    manifest, markdown, matrix, package, pgo, plugin, remote, repro, sarif,
+    sbom::{self, Format},
+    session::{self, Stream},
+    snapshot::{self, Status},
+    toolchain, Artifact, RunOptions,
 };
 
+#[cfg(unix)]
+use morfo::daemon;
+
 #[derive(Debug, Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Cli {
-    /// The main file to execute
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// The main file to execute, `-` to read the program from standard input, or a
+    /// Markdown file to run its C code blocks
     #[arg(value_name = "main")]
-    main: PathBuf,
+    main: Option<PathBuf>,
+
+    /// Run only this C code block of the Markdown file, counting from 1
+    #[arg(long, value_name = "N")]
+    block: Option<usize>,
 
     /// The arguments to pass to the main file
     #[arg(value_name = "args")]
     args: Vec<String>,
 
+    /// Run the main file under a heap profiler and report its peak heap usage
+    #[arg(long)]
+    heap_profile: bool,
+
+    /// Print the commands that would be run instead of running them
+    #[arg(long, conflicts_with = "heap_profile")]
+    dry_run: bool,
+
+    /// Run the program in a pseudo-terminal. The default when the output is a terminal
+    #[arg(long, conflicts_with = "no_tty")]
+    tty: bool,
+
+    /// Run the program with pipes instead of a pseudo-terminal
+    #[arg(long)]
+    no_tty: bool,
+
+    /// Also write the output of the program to this file, byte for byte
+    #[arg(long, value_name = "path")]
+    output_file: Option<PathBuf>,
+
+    /// Only write the output of the program to the output file
+    #[arg(long, requires = "output_file")]
+    no_echo: bool,
+
+    /// Build and run on this host over SSH, such as `user@host`, with its compiler
+    #[arg(long, value_name = "host", conflicts_with_all = ["heap_profile", "output_file"])]
+    on: Option<String>,
+
+    #[command(flatten)]
+    build: BuildArgs,
+
     /// The config file to use
-    #[arg(long, value_name = "config")]
+    #[arg(long, value_name = "config", global = true)]
     config: Option<PathBuf>,
 
+    /// Set a variable of the `[vars]` section of the config, as `vars.name=value`
+    #[arg(long, value_name = "vars.name=value", global = true)]
+    set: Vec<String>,
+
     /// Display all the build steps
-    #[arg(short, long, default_value = "false")]
+    #[arg(short, long, default_value = "false", global = true)]
     verbose: bool,
 }
 
+#[derive(Debug, Subcommand)]
+enum Commands {
+    /// Build and execute the main file
+    Run(RunArgs),
+
+    /// Build the main file without running it
+    Build(BuildCommandArgs),
+
+    /// Build the main file with profile-guided optimization
+    Pgo(PgoArgs),
+
+    /// Build a libFuzzer target and fuzz it
+    Fuzz(FuzzArgs),
+
+    /// Run static analyzers over the main file and its dependencies
+    Lint(LintArgs),
+
+    /// Profile the main file and render a flamegraph
+    Profile(ProfileArgs),
+
+    /// Build the main file reproducibly twice and compare the executables
+    VerifyRepro(VerifyReproArgs),
+
+    /// Check that the sources and executable still match the manifest of a build
+    Verify(VerifyArgs),
+
+    /// Report which artifacts of the main file are stale and why
+    Explain(ExplainArgs),
+
+    /// Print the sources and headers the main file depends on
+    Deps(DepsArgs),
+
+    /// Run the main file on test cases and give a verdict on each, like a contest judge
+    Judge(JudgeArgs),
+
+    /// Test the output of the main file against recorded snapshots
+    Test(TestArgs),
+
+    /// Run the main file, recording what is typed to it and what it prints in a session
+    Record(RecordArgs),
+
+    /// Run the main file of a recorded session again and compare what it prints
+    Replay(ReplayArgs),
+
+    /// Build the main file two ways, run both on the same inputs and report where they differ
+    Difftest(DifftestArgs),
+
+    /// Write a bill of materials listing the third-party libraries of the program
+    Sbom(SbomArgs),
+
+    /// Build the release profile and archive the executable with its licenses and assets
+    Package(PackageArgs),
+
+    /// Write a morfo.toml reconstructed from a compile_commands.json or a Makefile
+    Import(ImportArgs),
+
+    /// Inspect the compilers morfo can use
+    #[command(subcommand)]
+    Toolchain(ToolchainCommands),
+
+    /// Inspect and trim the object cache shared by every project
+    #[command(subcommand)]
+    Cache(CacheCommands),
+
+    /// Remove stale objects and orphaned executables from the build directory, and prune the cache
+    Gc(GcArgs),
+
+    /// Keep the project warm in memory and build for `morfo run` and `morfo build`
+    Daemon(DaemonArgs),
+
+    /// Build the firmware and write it to the board of the `[flash]` section
+    Flash(FlashArgs),
+}
+
+#[derive(Debug, Args)]
+struct BuildCommandArgs {
+    /// The main file to build, or a directory or glob of main files to build one by one
+    #[arg(value_name = "main")]
+    main: PathBuf,
+
+    /// Print the commands that would be run instead of running them
+    #[arg(long)]
+    dry_run: bool,
+
+    /// How many main files of a directory or glob, or cells of the matrix, to build at
+    /// once, by default one per CPU, and never more than the jobserver of `make -jN` allows
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<usize>,
+
+    /// Build with every combination of the compilers and C standards under `[matrix]`
+    /// and report which of them the program builds with
+    #[arg(long)]
+    matrix: bool,
+
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+#[derive(Debug, Args)]
+struct PgoArgs {
+    /// The main file to build
+    #[arg(value_name = "main")]
+    main: PathBuf,
+
+    #[command(flatten)]
+    build: BuildArgs,
+
+    /// The arguments to train the instrumented program with, unless `[pgo] train` is set
+    #[arg(value_name = "args")]
+    args: Vec<String>,
+
+    /// Record a new profile even if the sources, headers and compile flags have not changed
+    #[arg(long)]
+    retrain: bool,
+}
+
+#[derive(Debug, Args)]
+struct FuzzArgs {
+    /// The fuzz target, defining `LLVMFuzzerTestOneInput`
+    #[arg(value_name = "target")]
+    main: PathBuf,
+
+    /// The arguments to pass to libFuzzer
+    #[arg(value_name = "args")]
+    args: Vec<String>,
+
+    /// How long to fuzz for, in seconds
+    #[arg(long, value_name = "secs", default_value = "60")]
+    max_time: u64,
+
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+#[derive(Debug, Args)]
+struct LintArgs {
+    /// The main file whose sources to analyze
+    #[arg(value_name = "main")]
+    main: PathBuf,
+
+    /// The analyzers to run: `clang-tidy` or `cppcheck`. Can be repeated.
+    #[arg(long = "backend", value_name = "backend", default_value = "clang-tidy")]
+    backends: Vec<Backend>,
+
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+#[derive(Debug, Args)]
+struct VerifyReproArgs {
+    /// The main file to build
+    #[arg(value_name = "main")]
+    main: PathBuf,
+
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+#[derive(Debug, Args)]
+struct VerifyArgs {
+    /// The manifest to check. Defaults to `manifest.json` in the build directory.
+    #[arg(long, value_name = "path")]
+    manifest: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct ExplainArgs {
+    /// The main file to explain
+    #[arg(value_name = "main")]
+    main: PathBuf,
+
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+#[derive(Debug, Args)]
+struct JudgeArgs {
+    /// The solution to judge
+    #[arg(value_name = "main")]
+    main: PathBuf,
+
+    /// The directory of the cases, every `<name>.in` with the expected `<name>.out`
+    #[arg(long, value_name = "dir")]
+    cases: PathBuf,
+
+    /// How long a run may take, in seconds
+    #[arg(long, value_name = "seconds", default_value_t = 2.0)]
+    time_limit: f64,
+
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+#[derive(Debug, Args)]
+struct TestArgs {
+    /// The main file to test
+    #[arg(value_name = "main")]
+    main: PathBuf,
+
+    /// Compare the output for every `<name>.in` with the snapshot `<name>.snap`,
+    /// recording those that are missing
+    #[arg(long, required = true)]
+    snapshot: bool,
+
+    /// The directory of the inputs and snapshots
+    #[arg(long, value_name = "dir", default_value = "snapshots")]
+    dir: PathBuf,
+
+    /// Record the output as the snapshot when it changed
+    #[arg(long)]
+    accept: bool,
+
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+#[derive(Debug, Args)]
+struct RecordArgs {
+    /// The main file to run
+    #[arg(value_name = "main")]
+    main: PathBuf,
+
+    /// The name of the session, saved in `sessions/<name>.json`
+    #[arg(long, value_name = "name")]
+    session: String,
+
+    /// The arguments to pass to the main file
+    #[arg(value_name = "args")]
+    args: Vec<String>,
+
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+#[derive(Debug, Args)]
+struct ReplayArgs {
+    /// The name of the session, saved in `sessions/<name>.json`
+    #[arg(long, value_name = "name")]
+    session: String,
+
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+#[derive(Debug, Args)]
+struct DifftestArgs {
+    /// The main file to test
+    #[arg(value_name = "main")]
+    main: PathBuf,
+
+    /// A setting to change for the second build, e.g. `cc=clang` or `opt_level=3`.
+    /// Can be repeated.
+    #[arg(long, value_name = "key=value", required = true)]
+    against: Vec<String>,
+
+    /// The directory of the inputs, every `<name>.in` fed to both builds. By default they
+    /// run once with no input.
+    #[arg(long, value_name = "dir")]
+    inputs: Option<PathBuf>,
+
+    /// How long a run may take, in seconds
+    #[arg(long, value_name = "seconds", default_value_t = 2.0)]
+    time_limit: f64,
+
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+#[derive(Debug, Args)]
+struct DepsArgs {
+    /// The main file of the program
+    #[arg(value_name = "main")]
+    main: PathBuf,
+
+    /// Print the translation units as an indented tree, the default
+    #[arg(long, conflicts_with_all = ["json", "flat", "unused"])]
+    tree: bool,
+
+    /// Print the dependency tree as JSON
+    #[arg(long, conflicts_with_all = ["flat", "unused"])]
+    json: bool,
+
+    /// Print every source and header, one per line
+    #[arg(long, conflicts_with = "unused")]
+    flat: bool,
+
+    /// Print the sources and headers of the project the main file does not depend on
+    #[arg(long)]
+    unused: bool,
+
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+#[derive(Debug, Args)]
+struct SbomArgs {
+    /// The main file of the program
+    #[arg(value_name = "main")]
+    main: PathBuf,
+
+    /// The format of the document: `cyclonedx` or `spdx`
+    #[arg(long, value_name = "format", default_value = "cyclonedx")]
+    format: Format,
+
+    /// Write the document to this file instead of standard output
+    #[arg(short, long, value_name = "path")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct PackageArgs {
+    /// The main file of the program
+    #[arg(value_name = "main")]
+    main: PathBuf,
+
+    /// The format of the archive, `tar.gz` or `zip`, in place of the one of `[package]`
+    #[arg(long, value_name = "format")]
+    format: Option<package::Format>,
+
+    /// Sign the archive and the checksums of the archives with the command of `[release]`
+    #[arg(long)]
+    sign: bool,
+
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+#[derive(Debug, Args)]
+struct ImportArgs {
+    /// The compilation database to import, or a Makefile to import from `make -n`
+    #[arg(value_name = "database", default_value = "compile_commands.json")]
+    database: PathBuf,
+
+    /// Write the config here; the build script, if any, is written next to it
+    #[arg(short, long, value_name = "path", default_value = "morfo.toml")]
+    output: PathBuf,
+
+    /// Overwrite the config and the build script if they exist
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Debug, Args)]
+struct GcArgs {
+    /// Also remove the executables not built and the cached objects not used for this
+    /// long, e.g. `30d` or `12h`
+    #[arg(long, value_name = "age")]
+    older_than: Option<String>,
+
+    /// Evict the least recently used cached objects until the cache fits in this size,
+    /// e.g. `5G`
+    #[arg(long, value_name = "size")]
+    max_size: Option<String>,
+
+    /// Report what would be removed without removing it
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+struct DaemonArgs {
+    /// Also serve an HTTP API to trigger builds at this address, e.g. `127.0.0.1:7878`
+    #[arg(long, value_name = "addr")]
+    http: Option<SocketAddr>,
+}
+
+#[derive(Debug, Args)]
+struct FlashArgs {
+    /// The main file of the firmware
+    #[arg(value_name = "main")]
+    main: PathBuf,
+
+    /// Then print what the board sends on its serial port, until Ctrl+C
+    #[arg(long)]
+    monitor: bool,
+
+    /// Print the commands that would be run instead of running them
+    #[arg(long, conflicts_with = "monitor")]
+    dry_run: bool,
+
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+#[derive(Debug, Subcommand)]
+enum ToolchainCommands {
+    /// List the compilers found on the PATH, marking the one used when `cc` is not set
+    List,
+}
+
+#[derive(Debug, Subcommand)]
+enum CacheCommands {
+    /// Show the size, the largest entries and the hit rate of the build directory and the cache
+    Stats {
+        /// How many of the largest entries to list
+        #[arg(long, value_name = "N", default_value_t = 5)]
+        top: usize,
+    },
+
+    /// Evict the least recently used objects until the cache fits in the given size
+    Gc {
+        /// The largest the cache may be, e.g. `2G` or `500M`
+        #[arg(long, value_name = "size")]
+        max_size: String,
+    },
+}
+
+#[derive(Debug, Args)]
+struct RunArgs {
+    /// The main file to execute, `-` to read the program from standard input, or a
+    /// Markdown file to run its C code blocks
+    #[arg(value_name = "main")]
+    main: PathBuf,
+
+    /// Run only this C code block of the Markdown file, counting from 1
+    #[arg(long, value_name = "N")]
+    block: Option<usize>,
+
+    /// The arguments to pass to the main file
+    #[arg(value_name = "args")]
+    args: Vec<String>,
+
+    /// Run the main file under a heap profiler and report its peak heap usage
+    #[arg(long)]
+    heap_profile: bool,
+
+    /// Print the commands that would be run instead of running them
+    #[arg(long, conflicts_with = "heap_profile")]
+    dry_run: bool,
+
+    /// Run the program in a pseudo-terminal. The default when the output is a terminal
+    #[arg(long, conflicts_with = "no_tty")]
+    tty: bool,
+
+    /// Run the program with pipes instead of a pseudo-terminal
+    #[arg(long)]
+    no_tty: bool,
+
+    /// Also write the output of the program to this file, byte for byte
+    #[arg(long, value_name = "path")]
+    output_file: Option<PathBuf>,
+
+    /// Only write the output of the program to the output file
+    #[arg(long, requires = "output_file")]
+    no_echo: bool,
+
+    /// Build and run on this host over SSH, such as `user@host`, with its compiler
+    #[arg(long, value_name = "host", conflicts_with_all = ["heap_profile", "output_file"])]
+    on: Option<String>,
+
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+#[derive(Debug, Args)]
+struct ProfileArgs {
+    /// The main file to profile
+    #[arg(value_name = "main")]
+    main: PathBuf,
+
+    /// The arguments to pass to the main file
+    #[arg(value_name = "args")]
+    args: Vec<String>,
+
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+/// Options that change how the main file is built, shared by the commands that build.
+#[derive(Debug, Args)]
+struct BuildArgs {
+    /// The target declared in the config (or built in) to build and run for
+    #[arg(long, value_name = "target")]
+    target: Option<String>,
+
+    /// The profile declared in the config (or built in) to build with
+    #[arg(long, value_name = "profile")]
+    profile: Option<String>,
+
+    /// Apply the hardened-build security flags and report the mitigations of the executable
+    #[arg(long)]
+    hardened: bool,
+
+    /// Before linking, stop when two sources define the same symbol
+    #[arg(long)]
+    check_symbols: bool,
+
+    /// Define a preprocessor macro, overriding the config
+    #[arg(short = 'D', value_name = "NAME[=VALUE]")]
+    defines: Vec<String>,
+
+    /// Extra compiler flags, split like a shell would, after those of the config and `CFLAGS`
+    #[arg(long, value_name = "flags", allow_hyphen_values = true)]
+    cflags: Vec<String>,
+
+    /// Compile every source as a single translation unit
+    #[arg(long)]
+    unity: bool,
+
+    /// Link statically, including the C library
+    #[arg(long = "static")]
+    static_linkage: bool,
+
+    /// Build reproducibly, so the same sources always produce the same bytes
+    #[arg(long)]
+    reproducible: bool,
+
+    /// Compile and link in a container of this image, e.g. `ubuntu:24.04`
+    #[arg(long, value_name = "image")]
+    in_container: Option<String>,
+
+    /// Keep compiling the other sources when one fails, and report every failure at the end
+    #[arg(short = 'k', long)]
+    keep_going: bool,
+
+    /// Stop reporting errors for a source after this many
+    #[arg(long, value_name = "N")]
+    max_errors: Option<u32>,
+
+    /// Build every artifact again, even the ones that are up to date
+    #[arg(long)]
+    rebuild: bool,
+
+    /// Do not lock the build directory against other runs of morfo
+    #[arg(long)]
+    no_lock: bool,
+
+    /// Run the compilers at this priority: `normal`, `low` or `idle`, overriding the config
+    #[arg(long, value_name = "priority")]
+    build_priority: Option<BuildPriority>,
+
+    /// Have Clang trace every compilation and report where the time went
+    #[arg(long)]
+    time_trace: bool,
+
+    /// Also merge the time traces into this Chrome trace file
+    #[arg(long, value_name = "path")]
+    time_trace_out: Option<PathBuf>,
+
+    /// How to print diagnostics: `human`, `short` for one `file:line:col: severity:
+    /// message` line each, that editors can jump from, or `sarif` to also write a SARIF
+    /// log on stdout
+    #[arg(long, value_name = "format", default_value = "human")]
+    message_format: MessageFormat,
+
+    /// Also print the diagnostics as annotations: `github`, the default on GitHub Actions
+    #[arg(long, value_name = "kind")]
+    annotations: Option<Annotations>,
+
+    /// Show a desktop notification when the build finishes
+    #[arg(long)]
+    notify: bool,
+
+    /// Write the summary of the build here instead of `build-summary.json` in the build directory
+    #[arg(long, value_name = "path")]
+    summary_path: Option<PathBuf>,
+}
+
 fn main() {
+    run_external_subcommand();
     let args = Cli::parse();
+    interrupt::install().unwrap_or_else(|e| exit_with(e));
 
     if args.verbose {
         env::set_var("VERBOSITY", "1");
     }
 
-    let config_path = args.config.unwrap_or_else(|| {
-        find_config_file().unwrap_or_else(|e| {
-            eprintln!("{}", format!("{:?}", e).red());
+    // importing writes the config, so it must not need one
+    let command = match args.command {
+        Some(Commands::Import(import_args)) => return run_import(import_args),
+        command => command,
+    };
+
+    let config_path = args
+        .config
+        .unwrap_or_else(|| find_config_file().unwrap_or_else(|e| exit_with(e)));
+
+    let config =
+        parse_config_file_with_vars(&config_path, &args.set).unwrap_or_else(|e| exit_with(e));
+
+    // `morfo <main>` is shorthand for `morfo run <main>`
+    let command = command.or(args.main.map(|main| {
+        Commands::Run(RunArgs {
+            main,
+            block: args.block,
+            args: args.args,
+            heap_profile: args.heap_profile,
+            dry_run: args.dry_run,
+            tty: args.tty,
+            no_tty: args.no_tty,
+            output_file: args.output_file,
+            no_echo: args.no_echo,
+            on: args.on,
+            build: args.build,
+        })
+    }));
+    match command {
+        Some(Commands::Run(run_args)) => run(run_args, config),
+        Some(Commands::Build(build_args)) => build(build_args, config),
+        Some(Commands::Pgo(pgo_args)) => run_pgo(pgo_args, config),
+        Some(Commands::Fuzz(fuzz_args)) => run_fuzz(fuzz_args, config),
+        Some(Commands::Lint(lint_args)) => run_lint(lint_args, config),
+        Some(Commands::Profile(profile_args)) => run_profile(profile_args, config),
+        Some(Commands::VerifyRepro(verify_args)) => verify_repro(verify_args, config),
+        Some(Commands::Verify(verify_args)) => verify(verify_args, config),
+        Some(Commands::Explain(explain_args)) => explain(explain_args, config),
+        Some(Commands::Deps(deps_args)) => deps(deps_args, config),
+        Some(Commands::Judge(judge_args)) => judge(judge_args, config),
+        Some(Commands::Test(test_args)) => test(test_args, config),
+        Some(Commands::Record(record_args)) => record(record_args, config),
+        Some(Commands::Replay(replay_args)) => replay(replay_args, config),
+        Some(Commands::Difftest(difftest_args)) => run_difftest(difftest_args, config),
+        Some(Commands::Sbom(sbom_args)) => write_sbom(sbom_args, config),
+        Some(Commands::Package(args)) => run_package(args, config),
+        Some(Commands::Import(_)) => unreachable!("imported before the config is read"),
+        Some(Commands::Toolchain(ToolchainCommands::List)) => toolchain_list(config),
+        Some(Commands::Cache(CacheCommands::Stats { top })) => cache_stats(config, top),
+        Some(Commands::Cache(CacheCommands::Gc { max_size })) => cache_gc(config, max_size),
+        Some(Commands::Gc(args)) => run_gc(args, config),
+        Some(Commands::Daemon(args)) => run_daemon(args, config),
+        Some(Commands::Flash(args)) => run_flash(args, config),
+        None => {
+            eprintln!("{}", "No main file given. See `morfo --help`.".red());
             process::exit(1);
+        }
+    }
+}
+
+/// Runs `morfo-<name>` from the `PATH` for `morfo <name>`, and exits with its status, if
+/// `<name>` is neither a subcommand nor a file.
+fn run_external_subcommand() {
+    let mut args = env::args_os().skip(1);
+    let Some(name) = args.next().and_then(|name| name.into_string().ok()) else {
+        return;
+    };
+    if name.starts_with('-')
+        || Cli::command().find_subcommand(&name).is_some()
+        || Path::new(&name).exists()
+    {
+        return;
+    }
+    if let Some(executable) = plugin::find_external(&name) {
+        let status = plugin::run_external(&executable, args).unwrap_or_else(|e| exit_with(e));
+        process::exit(status.code().unwrap_or(1));
+    }
+}
+
+impl BuildArgs {
+    /// Applies the options to `config`, exiting if a target or profile does not exist.
+    fn apply(&self, config: Config) -> Config {
+        self.try_apply(config).unwrap_or_else(|e| exit_with(e))
+    }
+
+    /// Applies the options to `config`.
+    ///
+    /// # Errors
+    ///
+    /// If a target or profile does not exist.
+    fn try_apply(&self, config: Config) -> MorfoResult<Config> {
+        let config = match &self.target {
+            Some(target) => config.for_target(target)?,
+            None => config,
+        };
+        let config = match &self.profile {
+            Some(profile) => config.for_profile(profile)?,
+            None => config,
+        };
+        // the flags of `CFLAGS` go after those of the config and `--cflags` last, so they win
+        let cflags = config
+            .get_env_var("CFLAGS")
+            .into_iter()
+            .chain(self.cflags.clone());
+        let config = cflags.fold(config, |config, cflags| config.with_cflags(&cflags));
+        // `-D NAME` defines the macro to 1, like the compilers do
+        let config = self.defines.iter().fold(config, |config, define| {
+            let (name, value) = define.split_once('=').unwrap_or((define, "1"));
+            config.with_define(name, value)
+        });
+        let config = if self.unity {
+            config.with_unity(true)
+        } else {
+            config
+        };
+        let config = if self.reproducible {
+            config.with_reproducible(true)
+        } else {
+            config
+        };
+        let config = if self.static_linkage {
+            config.with_linkage(Linkage::Static)
+        } else {
+            config
+        };
+        let config = match &self.in_container {
+            Some(image) => config.with_container_image(image),
+            None => config,
+        };
+        let config = if self.keep_going {
+            config.with_keep_going(true)
+        } else {
+            config
+        };
+        let config = match self.max_errors {
+            Some(max_errors) => config.with_max_errors(max_errors),
+            None => config,
+        };
+        let config = if self.rebuild {
+            config.with_rebuild(true)
+        } else {
+            config
+        };
+        let config = if self.no_lock {
+            config.with_no_lock(true)
+        } else {
+            config
+        };
+        let config = match self.build_priority {
+            Some(priority) => config.with_build_priority(priority),
+            None => config,
+        };
+        let config = if self.check_symbols {
+            config.with_symbol_check(true)
+        } else {
+            config
+        };
+        let config = if self.time_trace || self.time_trace_out.is_some() {
+            config.with_time_trace(self.time_trace_out.as_deref())
+        } else {
+            config
+        };
+        let config = config.with_message_format(self.message_format);
+        let annotations = self.annotations.or_else(|| {
+            (config.get_env_var("GITHUB_ACTIONS").as_deref() == Some("true"))
+                .then_some(Annotations::Github)
+        });
+        let config = match annotations {
+            Some(annotations) => config.with_annotations(annotations),
+            None => config,
+        };
+        let config = if self.notify {
+            config.with_notify(true)
+        } else {
+            config
+        };
+        let config = match &self.summary_path {
+            Some(path) => config.with_summary_path(path),
+            None => config,
+        };
+        Ok(if self.hardened {
+            config.with_hardening(true)
+        } else {
+            config
         })
-    });
+    }
+}
 
-    let config = parse_config_file(&config_path).unwrap_or_else(|e| {
-        eprintln!("{}", format!("{:?}", e).red());
-        process::exit(1);
-    });
+fn exit_with(e: MorfoError) -> ! {
+    eprintln!("{}", format!("{:?}", e).red());
+    process::exit(1);
+}
+
+fn run(mut args: RunArgs, config: Config) {
+    let tty = args.tty || (!args.no_tty && io::stdout().is_terminal());
+    let config = args
+        .build
+        .apply(config)
+        .with_dry_run(args.dry_run)
+        .with_tty(tty)
+        .with_no_echo(args.no_echo);
+    let config = match &args.output_file {
+        Some(path) => config.with_output_file(path),
+        None => config,
+    };
+    if args.main == Path::new("-") {
+        args.main = read_stdin_main(&config).unwrap_or_else(|e| exit_with(e));
+    } else if markdown::is_markdown(&args.main) {
+        args.main =
+            markdown::extract(&args.main, args.block, &config).unwrap_or_else(|e| exit_with(e));
+    } else if args.block.is_some() {
+        exit_with(MorfoError::Unsupported(
+            "--block with a main file that is not Markdown".to_owned(),
+        ));
+    }
+    if args.heap_profile {
+        return run_heap_profile(args, config);
+    }
+    if let Some(host) = &args.on {
+        if let Err(e) = remote::run(host, &args.main, config, &args.args) {
+            exit_with(e);
+        }
+        return;
+    }
 
-    let result = execute(args.main, config, &mut io::stdout(), args.args);
+    let result = compile(&args.main, &config).and_then(|artifact| {
+        morfo::run(
+            &artifact,
+            RunOptions::new().with_args(args.args),
+            &mut io::stdout(),
+        )
+    });
     if result.is_err() {
         eprintln!("{}", format!("Error executing: {:?}", result).red());
         process::exit(1);
     }
 }
+
+/// Builds `main` with the daemon listening on the build directory if there is one, or
+/// here otherwise.
+fn compile(main: &Path, config: &Config) -> MorfoResult<Artifact> {
+    #[cfg(unix)]
+    if !config.get_dry_run() {
+        let result = daemon::Request::current(main)
+            .ok()
+            .and_then(|request| daemon::compile(config, &request));
+        if let Some(result) = result {
+            return result;
+        }
+    }
+    morfo::compile(main, config)
+}
+
+#[cfg(unix)]
+fn run_daemon(args: DaemonArgs, config: Config) {
+    daemon::serve(&config, args.http, daemon_build).unwrap_or_else(|e| exit_with(e));
+}
+
+#[cfg(not(unix))]
+fn run_daemon(_args: DaemonArgs, _config: Config) {
+    exit_with(MorfoError::Unsupported("morfo daemon off Unix".to_owned()));
+}
+
+/// Builds what `morfo run` or `morfo build` asked the daemon to, with the config file
+/// and the options of their command line.
+#[cfg(unix)]
+fn daemon_build(request: &daemon::Request) -> MorfoResult<Artifact> {
+    let args =
+        Cli::try_parse_from(&request.args).map_err(|e| MorfoError::DaemonBuild(e.to_string()))?;
+    let config_path = match args.config {
+        Some(path) => path,
+        None => find_config_file()?,
+    };
+    let config =
+        parse_config_file_with_vars(&config_path, &args.set)?.with_build_env(request.build_env());
+    // the options come from HTTP clients too, so a wrong one must not stop the daemon
+    let config = match args.command {
+        Some(Commands::Run(run_args)) => run_args.build.try_apply(config)?,
+        Some(Commands::Build(build_args)) => build_args.build.try_apply(config)?,
+        None => args.build.try_apply(config)?,
+        Some(_) => {
+            return Err(MorfoError::DaemonBuild(
+                "only `morfo run` and `morfo build` are built by the daemon".to_owned(),
+            ))
+        }
+    };
+    morfo::compile(&request.main, &config)
+}
+
+fn run_flash(args: FlashArgs, config: Config) {
+    let config = args.build.apply(config).with_dry_run(args.dry_run);
+    let artifact = morfo::compile(&args.main, &config).unwrap_or_else(|e| exit_with(e));
+    flash::flash(&artifact.executable_path, &config).unwrap_or_else(|e| exit_with(e));
+    if args.monitor {
+        flash::monitor(&config).unwrap_or_else(|e| exit_with(e));
+    }
+}
+
+/// Writes the program read from standard input to the build directory, where it is built
+/// like any main file. The program itself then finds its standard input at its end.
+fn read_stdin_main(config: &Config) -> MorfoResult<PathBuf> {
+    let mut source = Vec::new();
+    io::stdin().read_to_end(&mut source)?;
+    layout::create_build_dir(config)?;
+    let main = layout::stdin_source(config);
+    fs::write(&main, source)?;
+    Ok(main)
+}
+
+fn build(args: BuildCommandArgs, config: Config) {
+    let sarif = args.build.message_format == MessageFormat::Sarif;
+    if sarif && (args.matrix || batch::is_batch(&args.main)) {
+        exit_with(MorfoError::Unsupported(
+            "--message-format sarif with --matrix or a batch".to_owned(),
+        ));
+    }
+    // every main file or cell writes its own summary
+    if args.build.summary_path.is_some() && (args.matrix || batch::is_batch(&args.main)) {
+        exit_with(MorfoError::Unsupported(
+            "--summary-path with --matrix or a batch".to_owned(),
+        ));
+    }
+    if args.matrix {
+        return build_matrix(args, config);
+    }
+    if batch::is_batch(&args.main) {
+        return build_batch(args, config);
+    }
+    let config = args.build.apply(config).with_dry_run(args.dry_run);
+    let dry_run = config.get_dry_run();
+    // the diagnostics of the log are those reported in this process
+    let result = if sarif {
+        morfo::build(args.main, config)
+    } else {
+        compile(&args.main, &config).map(|artifact| artifact.executable_path)
+    };
+    // the log is written whether the build failed or not, it is what it is for
+    if sarif {
+        let log = sarif::sarif(&diagnostic::reported()).unwrap_or_else(|e| exit_with(e));
+        print!("{}", log);
+    }
+    let executable = result.unwrap_or_else(|e| exit_with(e));
+    if !dry_run && !sarif {
+        println!("{}", executable.display());
+    }
+}
+
+fn build_batch(args: BuildCommandArgs, config: Config) {
+    let config = args.build.apply(config).with_dry_run(args.dry_run);
+    let main_files = batch::main_files(&args.main).unwrap_or_else(|e| exit_with(e));
+    // the commands of a dry run would interleave
+    let jobs = match args.jobs {
+        _ if args.dry_run => 1,
+        Some(jobs) => jobs,
+        None => thread::available_parallelism().map_or(1, usize::from),
+    };
+    let builds = batch::build_all(&main_files, &config, jobs);
+    if args.dry_run {
+        return;
+    }
+
+    let width = builds
+        .iter()
+        .map(|build| build.main_file.display().to_string().len())
+        .max()
+        .unwrap_or_default();
+    let mut failed = 0;
+    for build in &builds {
+        let main_file = build.main_file.display().to_string();
+        match &build.result {
+            Ok(executable) => println!(
+                "{:width$}  {}  {}",
+                main_file,
+                format!("{:6}", "ok").green(),
+                executable.display()
+            ),
+            Err(e) => {
+                failed += 1;
+                println!("{:width$}  {}  {}", main_file, "FAILED".red(), e);
+            }
+        }
+    }
+    println!("{} built, {} failed", builds.len() - failed, failed);
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+fn build_matrix(args: BuildCommandArgs, config: Config) {
+    if batch::is_batch(&args.main) {
+        exit_with(MorfoError::Unsupported(
+            "--matrix with a directory or glob of main files".to_owned(),
+        ));
+    }
+    let config = args.build.apply(config).with_dry_run(args.dry_run);
+    let jobs = match args.jobs {
+        _ if args.dry_run => 1,
+        Some(jobs) => jobs,
+        None => thread::available_parallelism().map_or(1, usize::from),
+    };
+    let builds = matrix::build_matrix(&args.main, &config, jobs).unwrap_or_else(|e| exit_with(e));
+    if args.dry_run {
+        return;
+    }
+
+    // a row for every compiler and a column for every standard
+    let row_of =
+        |build: &matrix::MatrixBuild| build.cc.clone().unwrap_or_else(|| config.get_cc().clone());
+    let mut rows: Vec<String> = Vec::new();
+    let mut columns: Vec<String> = Vec::new();
+    for build in &builds {
+        let row = row_of(build);
+        let column = build.std.clone().unwrap_or_else(|| "build".to_owned());
+        if !rows.contains(&row) {
+            rows.push(row);
+        }
+        if !columns.contains(&column) {
+            columns.push(column);
+        }
+    }
+    let width = rows.iter().map(String::len).max().unwrap_or_default();
+    let column_width = columns
+        .iter()
+        .map(String::len)
+        .max()
+        .unwrap_or_default()
+        .max(6);
+    print!("{:width$}", "");
+    for column in &columns {
+        print!("  {:column_width$}", column);
+    }
+    println!();
+    for (row, cells) in rows.iter().zip(builds.chunks(columns.len())) {
+        print!("{:width$}", row);
+        for cell in cells {
+            match cell.result {
+                Ok(_) => print!("  {}", format!("{:column_width$}", "ok").green()),
+                Err(_) => print!("  {}", format!("{:column_width$}", "FAILED").red()),
+            }
+        }
+        println!();
+    }
+
+    let mut failed = 0;
+    for build in &builds {
+        if let Err(e) = &build.result {
+            failed += 1;
+            match &build.std {
+                Some(std) => eprintln!("{} {}: {}", row_of(build), std, e),
+                None => eprintln!("{}: {}", row_of(build), e),
+            }
+        }
+    }
+    println!("{} built, {} failed", builds.len() - failed, failed);
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+fn run_heap_profile(args: RunArgs, config: Config) {
+    let profile = heap::heap_profile(args.main, config, args.args).unwrap_or_else(|e| exit_with(e));
+
+    println!("{}", profile);
+    if profile.over_budget() {
+        eprintln!("{}", "Peak heap usage exceeds the heap budget.".red());
+        process::exit(1);
+    }
+}
+
+fn run_pgo(args: PgoArgs, config: Config) {
+    let config = args.build.apply(config);
+    match pgo::pgo(args.main, config, args.args, args.retrain) {
+        Ok(executable) => println!("Optimized executable: {}", executable.display()),
+        Err(e) => {
+            eprintln!("{}", format!("Error executing: {:?}", e).red());
+            process::exit(1);
+        }
+    }
+}
+
+fn run_fuzz(args: FuzzArgs, config: Config) {
+    let config = args.build.apply(config);
+    let report =
+        fuzz::fuzz(args.main, config, args.max_time, args.args).unwrap_or_else(|e| exit_with(e));
+
+    println!("Corpus: {}", report.corpus.display());
+    if report.new_crashes.is_empty() {
+        println!("{}", "No new crashes found.".green());
+        return;
+    }
+    println!(
+        "{}",
+        format!("{} new crash(es) found:", report.new_crashes.len()).red()
+    );
+    for crash in &report.new_crashes {
+        println!("  {}", report.repro_command(crash));
+    }
+    process::exit(1);
+}
+
+fn run_lint(args: LintArgs, config: Config) {
+    let config = args.build.apply(config);
+    let message_format = config.get_message_format();
+    let annotations = config.get_annotations();
+    let diagnostics =
+        lint::lint(args.main, config, &args.backends).unwrap_or_else(|e| exit_with(e));
+
+    if message_format == MessageFormat::Sarif {
+        let log = sarif::sarif(&diagnostics).unwrap_or_else(|e| exit_with(e));
+        print!("{}", log);
+    } else if annotations == Some(Annotations::Github) {
+        for diagnostic in &diagnostics {
+            println!("{}", diagnostic.github_annotation());
+        }
+    }
+    for diagnostic in &diagnostics {
+        match message_format {
+            MessageFormat::Human => println!("{}", diagnostic),
+            MessageFormat::Short => println!("{}", diagnostic.short()),
+            MessageFormat::Sarif => eprintln!("{}", diagnostic),
+        }
+    }
+    if diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+    {
+        process::exit(1);
+    }
+}
+
+fn run_profile(args: ProfileArgs, config: Config) {
+    let config = args.build.apply(config);
+    let graph = flamegraph::profile(args.main, config, args.args).unwrap_or_else(|e| exit_with(e));
+
+    println!("Collapsed stacks: {}", graph.folded.display());
+    println!("Flamegraph: {}", graph.svg.display());
+}
+
+fn verify_repro(args: VerifyReproArgs, config: Config) {
+    let config = args.build.apply(config);
+    let report = repro::verify(args.main, config).unwrap_or_else(|e| exit_with(e));
+
+    match report.first_difference {
+        None => println!(
+            "{}",
+            format!("{} is reproducible.", report.executable.display()).green()
+        ),
+        Some(offset) => {
+            eprintln!(
+                "{}",
+                format!(
+                    "{} and {} differ from byte {}.",
+                    report.first.display(),
+                    report.executable.display(),
+                    offset
+                )
+                .red()
+            );
+            process::exit(1);
+        }
+    }
+}
+
+fn verify(args: VerifyArgs, config: Config) {
+    let path = args.manifest.unwrap_or_else(|| layout::manifest(&config));
+    let manifest = manifest::read(&path).unwrap_or_else(|e| exit_with(e));
+    let report = manifest::verify(&manifest);
+
+    if report.is_ok() {
+        println!(
+            "{}",
+            format!("Every file matches {}.", path.display()).green()
+        );
+        return;
+    }
+    for file in &report.changed {
+        eprintln!("{}", format!("changed: {}", file.display()).red());
+    }
+    for file in &report.missing {
+        eprintln!("{}", format!("missing: {}", file.display()).red());
+    }
+    process::exit(1);
+}
+
+fn explain(args: ExplainArgs, config: Config) {
+    let config = args.build.apply(config);
+    let artifacts = fingerprint::explain(args.main, config).unwrap_or_else(|e| exit_with(e));
+    for artifact in artifacts {
+        if artifact.is_fresh() {
+            println!("{}", artifact.to_string().green());
+        } else {
+            println!("{}", artifact.to_string().yellow());
+        }
+    }
+}
+
+fn judge(args: JudgeArgs, config: Config) {
+    // solutions are judged optimized, unless another profile is selected
+    let config = match &args.build.profile {
+        Some(_) => args.build.apply(config),
+        None => args
+            .build
+            .apply(config)
+            .for_profile("release")
+            .unwrap_or_else(|e| exit_with(e)),
+    };
+    let time_limit = Duration::try_from_secs_f64(args.time_limit)
+        .unwrap_or_else(|e| exit_with(MorfoError::InvlidConfig(format!("--time-limit: {}", e))));
+    let results =
+        judge::judge(&args.main, &config, &args.cases, time_limit).unwrap_or_else(|e| exit_with(e));
+
+    let width = results
+        .iter()
+        .map(|result| result.name.len())
+        .max()
+        .unwrap_or_default();
+    for result in &results {
+        let verdict = format!("{:3}", result.verdict);
+        let verdict = match result.verdict {
+            Verdict::Accepted => verdict.green(),
+            _ => verdict.red(),
+        };
+        let note = match result.verdict {
+            Verdict::TimeLimitExceeded => format!(">{:.3}s", time_limit.as_secs_f64()),
+            Verdict::RuntimeError(Some(code)) => {
+                format!("{:.3}s  exit code {}", result.duration.as_secs_f64(), code)
+            }
+            Verdict::RuntimeError(None) => {
+                format!("{:.3}s  killed by a signal", result.duration.as_secs_f64())
+            }
+            _ => format!("{:.3}s", result.duration.as_secs_f64()),
+        };
+        println!("{:width$}  {}  {}", result.name, verdict, note);
+    }
+
+    let accepted = results
+        .iter()
+        .filter(|result| result.verdict == Verdict::Accepted)
+        .count();
+    println!("{}/{} accepted", accepted, results.len());
+    if accepted < results.len() {
+        process::exit(1);
+    }
+}
+
+fn test(args: TestArgs, config: Config) {
+    let config = args.build.apply(config);
+    let results = snapshot::check(&args.main, &config, &args.dir, args.accept)
+        .unwrap_or_else(|e| exit_with(e));
+
+    let mut changed = 0;
+    for result in &results {
+        let snapshot = result.snapshot.display();
+        match &result.status {
+            Status::Recorded => println!("{}  {}", "recorded".yellow(), snapshot),
+            Status::Matched => println!("{}  {}", "ok      ".green(), snapshot),
+            Status::Accepted => println!("{}  {}", "accepted".yellow(), snapshot),
+            Status::Changed(diff) => {
+                changed += 1;
+                println!("{}  {}", "changed ".red(), snapshot);
+                for line in diff.lines() {
+                    let line = match line.chars().next() {
+                        Some('-') => line.red(),
+                        _ => line.green(),
+                    };
+                    println!("    {}", line);
+                }
+            }
+        }
+    }
+    if changed > 0 {
+        println!(
+            "{} snapshot(s) changed; run again with --accept to record the new output",
+            changed
+        );
+        process::exit(1);
+    }
+}
+
+fn record(args: RecordArgs, config: Config) {
+    let config = args.build.apply(config);
+    let path = session::session_path(&args.session);
+    session::record(&args.main, &config, args.args, &path).unwrap_or_else(|e| exit_with(e));
+    eprintln!("{}  {}", "recorded".yellow(), path.display());
+}
+
+fn replay(args: ReplayArgs, config: Config) {
+    let config = args.build.apply(config);
+    let path = session::session_path(&args.session);
+    let replay = session::replay(&path, &config).unwrap_or_else(|e| exit_with(e));
+
+    for (name, stream) in [("stdout", Stream::Stdout), ("stderr", Stream::Stderr)] {
+        let Some(diff) = replay.diff(stream) else {
+            println!("{}  {}", "ok      ".green(), name);
+            continue;
+        };
+        println!("{}  {}", "changed ".red(), name);
+        for line in diff.lines() {
+            let line = match line.chars().next() {
+                Some('-') => line.red(),
+                _ => line.green(),
+            };
+            println!("    {}", line);
+        }
+    }
+    // a program killed by a signal has no exit code
+    let exit = |code: Option<i32>| code.map_or("a signal".to_owned(), |code| code.to_string());
+    let (recorded, replayed) = (replay.recorded.exit_code, replay.replayed.exit_code);
+    if recorded == replayed {
+        println!("{}  exit {}", "ok      ".green(), exit(replayed));
+    } else {
+        println!(
+            "{}  exit {}, recorded {}",
+            "changed ".red(),
+            exit(replayed),
+            exit(recorded)
+        );
+    }
+    if !replay.matches() {
+        process::exit(1);
+    }
+}
+
+fn run_difftest(args: DifftestArgs, config: Config) {
+    let base = args.build.apply(config);
+    let against = args
+        .against
+        .iter()
+        .try_fold(base.clone(), |config, setting| {
+            difftest::with_setting(config, setting)
+        })
+        .unwrap_or_else(|e| exit_with(e));
+    let time_limit = Duration::try_from_secs_f64(args.time_limit)
+        .unwrap_or_else(|e| exit_with(MorfoError::InvlidConfig(format!("--time-limit: {}", e))));
+    let comparisons = difftest::difftest(
+        &args.main,
+        &base,
+        &against,
+        args.inputs.as_deref(),
+        time_limit,
+    )
+    .unwrap_or_else(|e| exit_with(e));
+
+    let names: Vec<String> = comparisons
+        .iter()
+        .map(|comparison| match &comparison.input {
+            Some(input) => input.display().to_string(),
+            None => "(no input)".to_owned(),
+        })
+        .collect();
+    let width = names.iter().map(String::len).max().unwrap_or_default();
+    let mut diverged = 0;
+    for (name, comparison) in names.iter().zip(&comparisons) {
+        if !comparison.diverges() {
+            println!("{:width$}  {}", name, "same".green());
+            continue;
+        }
+        diverged += 1;
+        println!("{:width$}  {}", name, "DIVERGES".red());
+        if comparison.base.stdout != comparison.against.stdout {
+            for line in comparison.stdout_diff().lines() {
+                let line = match line.chars().next() {
+                    Some('-') => line.red(),
+                    _ => line.green(),
+                };
+                println!("    {}", line);
+            }
+        }
+        let (base, against) = (comparison.base.exit, comparison.against.exit);
+        if base != against {
+            println!("    {} vs {}", base, against);
+        }
+    }
+    println!(
+        "{}/{} inputs agree between the builds (- base, + against)",
+        comparisons.len() - diverged,
+        comparisons.len()
+    );
+    if diverged > 0 {
+        process::exit(1);
+    }
+}
+
+fn deps(args: DepsArgs, config: Config) {
+    let config = args.build.apply(config);
+    if args.unused {
+        let unused = morfo::unused_files(&args.main, config).unwrap_or_else(|e| exit_with(e));
+        for file in unused {
+            println!("{}", file.display());
+        }
+        return;
+    }
+
+    let act = morfo::dependencies(&args.main, config).unwrap_or_else(|e| exit_with(e));
+    if args.json {
+        let json = serde_json::to_string_pretty(&act)
+            .unwrap_or_else(|e| exit_with(MorfoError::InvlidConfig(e.to_string())));
+        println!("{}", json);
+    } else if args.flat {
+        for file in act.files() {
+            println!("{}", file.display());
+        }
+    } else {
+        print!("{}", act.tree());
+    }
+}
+
+fn write_sbom(args: SbomArgs, config: Config) {
+    let document = sbom::sbom(&args.main, &config, args.format).unwrap_or_else(|e| exit_with(e));
+    match args.output {
+        Some(path) => fs::write(&path, document).unwrap_or_else(|e| exit_with(e.into())),
+        None => print!("{}", document),
+    }
+}
+
+fn run_package(args: PackageArgs, config: Config) {
+    let config = args.build.apply(config);
+    // a package is a release unless another profile is asked for
+    let config = match config.get_profile() {
+        Some(_) => config,
+        None => config
+            .for_profile("release")
+            .unwrap_or_else(|e| exit_with(e)),
+    };
+    let artifact = morfo::compile(&args.main, &config).unwrap_or_else(|e| exit_with(e));
+    let archive = package::package(&args.main, &artifact.executable_path, &config, args.format)
+        .unwrap_or_else(|e| exit_with(e));
+    if args.sign {
+        package::sign(&archive, &config).unwrap_or_else(|e| exit_with(e));
+    }
+    println!("{}", archive.display());
+}
+
+fn run_import(args: ImportArgs) {
+    let dir = args.output.parent().unwrap_or(Path::new(""));
+    let root = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let import = morfo::import::import(&args.database, root).unwrap_or_else(|e| exit_with(e));
+    let script = import.script.map(|script| (dir.join("build.rhai"), script));
+    let mut files = vec![(args.output, import.config)];
+    files.extend(script);
+    if !args.force {
+        if let Some((existing, _)) = files.iter().find(|(path, _)| path.exists()) {
+            eprintln!(
+                "{}",
+                format!(
+                    "{} already exists. Pass --force to overwrite it.",
+                    existing.display()
+                )
+                .red()
+            );
+            process::exit(1);
+        }
+    }
+    for (path, contents) in files {
+        fs::write(&path, contents).unwrap_or_else(|e| exit_with(e.into()));
+        println!("{} {}", "Wrote".green().bold(), path.display());
+    }
+}
+
+fn cache_stats(config: Config, top: usize) {
+    let build_dir = cache::build_dir_stats(&config, top);
+    let cache = cache::stats(&config, top).unwrap_or_else(|e| exit_with(e));
+    println!("{}\n{}\n", "Build directory".bold(), build_dir);
+    println!("{}\n{}", "Object cache".bold(), cache);
+}
+
+fn cache_gc(config: Config, max_size: String) {
+    let report = cache::gc(&config, &max_size).unwrap_or_else(|e| exit_with(e));
+    println!("{}", report);
+}
+
+fn run_gc(args: GcArgs, config: Config) {
+    let config = config.with_dry_run(args.dry_run);
+    let report = gc::gc(
+        &config,
+        args.older_than.as_deref(),
+        args.max_size.as_deref(),
+    )
+    .unwrap_or_else(|e| exit_with(e));
+    println!("{}", report);
+}
+
+fn toolchain_list(config: Config) {
+    let compilers = toolchain::list(&config.get_cc_candidates());
+    if compilers.is_empty() {
+        eprintln!("{}", "No compilers found on the PATH.".red());
+        process::exit(1);
+    }
+
+    for (i, compiler) in compilers.iter().enumerate() {
+        let marker = if i == 0 { "*" } else { " " };
+        println!(
+            "{} {:<10} {:<6} {}",
+            marker,
+            compiler.command,
+            format!("{:?}", compiler.family).to_lowercase(),
+            compiler.version
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Build manifests.
+//!
+//! After every build, morfo writes `<builddir>/manifest.json` recording what went into
+//! the executable and what came out: the hash of every source and header, the compiler and its
+//! version, the flags, and the hash of the executable. `morfo verify` checks that the
+//! files on disk still match a manifest, e.g. that a submission was built from the
+//! sources handed in.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    act::Act,
+    compile_flags,
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    layout, libraries, link_flags,
+    toolchain::{self, CompilerFamily},
+    utils,
+};
+
+/// What a build used and produced.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Manifest {
+    /// The version of morfo that ran the build.
+    pub morfo_version: String,
+    /// The compiler command.
+    pub cc: String,
+    /// The compiler family.
+    pub family: CompilerFamily,
+    /// The first line the compiler reports about its version, if it could be run.
+    pub cc_version: Option<String>,
+    /// Every argument passed when compiling, apart from the files.
+    pub compile_flags: Vec<String>,
+    /// Every argument passed when linking, apart from the files.
+    pub link_flags: Vec<String>,
+    /// The SHA-256 of every source and header, by path.
+    pub inputs: BTreeMap<PathBuf, String>,
+    /// The SHA-256 of every output, by path.
+    pub outputs: BTreeMap<PathBuf, String>,
+}
+
+/// Writes the manifest of the build of `act` into the build directory.
+///
+/// # Errors
+///
+/// If a file cannot be hashed or the manifest cannot be written.
+pub(crate) fn write(act: &Act, config: &Config) -> MorfoResult<()> {
+    let mut inputs = BTreeMap::new();
+    for file in act.files() {
+        inputs.insert(file.clone(), utils::hash_file(&file)?);
+    }
+    let executable = layout::executable(&act.name, config);
+    let mut outputs = BTreeMap::new();
+    outputs.insert(executable.clone(), utils::hash_file(&executable)?);
+
+    let mut link = link_flags(config);
+    link.extend(
+        libraries::link_args(config)?
+            .into_iter()
+            .map(|arg| arg.to_string_lossy().into_owned()),
+    );
+    let manifest = Manifest {
+        morfo_version: env!("CARGO_PKG_VERSION").to_owned(),
+        cc: config.get_cc().clone(),
+        family: config.get_family(),
+        cc_version: toolchain::probe(config.get_cc()).map(|compiler| compiler.version),
+        compile_flags: config
+            .get_cflags()
+            .into_iter()
+            .chain(compile_flags(config))
+            .collect(),
+        link_flags: link,
+        inputs,
+        outputs,
+    };
+
+    let json = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| MorfoError::InvlidConfig(e.to_string()))?;
+    fs::write(layout::manifest(config), json + "\n")?;
+    Ok(())
+}
+
+/// Reads the manifest at `path`.
+///
+/// # Errors
+///
+/// If the manifest does not exist or is not valid.
+pub fn read(path: &Path) -> MorfoResult<Manifest> {
+    let json = fs::read_to_string(path).map_err(|_| MorfoError::FileNotFound(path.into()))?;
+    serde_json::from_str(&json).map_err(|e| MorfoError::InvlidConfig(e.to_string()))
+}
+
+/// The files that no longer match a manifest.
+#[derive(Debug, Default, PartialEq)]
+pub struct VerifyReport {
+    /// Files whose contents changed since the build.
+    pub changed: Vec<PathBuf>,
+    /// Files that no longer exist.
+    pub missing: Vec<PathBuf>,
+}
+
+impl VerifyReport {
+    /// Returns whether every file still matches the manifest.
+    pub fn is_ok(&self) -> bool {
+        self.changed.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// Checks the inputs and outputs recorded in `manifest` against the files on disk.
+pub fn verify(manifest: &Manifest) -> VerifyReport {
+    let mut report = VerifyReport::default();
+    for (path, hash) in manifest.inputs.iter().chain(&manifest.outputs) {
+        match utils::hash_file(path) {
+            Ok(current) if &current == hash => (),
+            Ok(_) => report.changed.push(path.clone()),
+            Err(_) => report.missing.push(path.clone()),
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile_act, prepare};
+
+    #[test]
+    fn manifest_write_and_verify() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let main_file = tmp_dir.path().join("main.c");
+        fs::write(&main_file, "int main(void) { return 0; }\n").unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            builddir = "{}"
+            opt_level = "2""#,
+            tmp_dir.path().join(".out").display()
+        ))
+        .unwrap();
+
+        let (act, config) = prepare(&main_file, config).unwrap();
+        compile_act(&act, &config).unwrap();
+
+        let manifest = read(&layout::manifest(&config)).unwrap();
+        assert_eq!(manifest.cc, "gcc");
+        assert!(manifest.compile_flags.contains(&"-O2".to_owned()));
+        assert_eq!(manifest.inputs.keys().collect::<Vec<_>>(), vec![&main_file]);
+        assert!(verify(&manifest).is_ok());
+
+        fs::write(&main_file, "int main(void) { return 1; }\n").unwrap();
+        fs::remove_file(layout::executable(&act.name, &config)).unwrap();
+        assert_eq!(
+            verify(&manifest),
+            VerifyReport {
+                changed: vec![main_file],
+                missing: vec![layout::executable(&act.name, &config)],
+            }
+        );
+    }
+
+    #[test]
+    fn manifest_verify_changed_header() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let main_file = tmp_dir.path().join("main.c");
+        let header = tmp_dir.path().join("answer.h");
+        fs::write(
+            &main_file,
+            "#include \"answer.h\"\nint main(void) { return ANSWER; }\n",
+        )
+        .unwrap();
+        fs::write(&header, "#define ANSWER 0\n").unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            builddir = "{}""#,
+            tmp_dir.path().join(".out").display()
+        ))
+        .unwrap();
+
+        let (act, config) = prepare(&main_file, config).unwrap();
+        compile_act(&act, &config).unwrap();
+
+        let manifest = read(&layout::manifest(&config)).unwrap();
+        assert!(manifest.inputs.contains_key(&header));
+
+        fs::write(&header, "#define ANSWER 1\n").unwrap();
+        assert_eq!(
+            verify(&manifest),
+            VerifyReport {
+                changed: vec![header],
+                missing: vec![],
+            }
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Running the C code blocks of a Markdown file.
+//!
+//! `morfo run notes.md` compiles every fenced code block marked `c` in the file, in
+//! order, as one program and runs it, and `--block 2` selects the second of them alone:
+//!
+//! ````markdown
+//! ```c
+//! #include <stdio.h>
+//! int main(void) { puts("hello"); }
+//! ```
+//! ````
+//!
+//! The source is written to `<builddir>/markdown/`, with a `#line` directive where each
+//! block starts, so the compiler reports errors at their lines in the Markdown file.
+
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    layout,
+};
+
+/// A fenced code block of a Markdown file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeBlock {
+    /// The line of the Markdown file the code starts on, from 1.
+    pub line: usize,
+    /// The code, every line ending with a newline.
+    pub code: String,
+}
+
+/// Returns whether `path` is a Markdown file, by its extension.
+///
+/// # Examples
+///
+/// ```
+/// use morfo::markdown::is_markdown;
+/// use std::path::Path;
+///
+/// assert!(is_markdown(Path::new("README.md")));
+/// assert!(!is_markdown(Path::new("main.c")));
+/// ```
+pub fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == "md" || ext == "markdown")
+}
+
+/// Returns the fenced code blocks of `markdown` whose language is C, in order. A block
+/// left open runs to the end of the file, like CommonMark has it.
+pub fn c_blocks(markdown: &str) -> Vec<CodeBlock> {
+    // the fence character, its length and whether it opens a C block, and the block
+    let mut open: Option<(char, usize, bool, CodeBlock)> = None;
+    let mut blocks = Vec::new();
+    for (index, line) in markdown.lines().enumerate() {
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        let fence = (indent <= 3).then(|| &line[indent..]);
+        match &mut open {
+            None => {
+                let Some(fence) = fence else {
+                    continue;
+                };
+                let Some(c) = fence.chars().next().filter(|c| *c == '`' || *c == '~') else {
+                    continue;
+                };
+                let len = fence.chars().take_while(|&f| f == c).count();
+                let info = &fence[len..];
+                if len < 3 || (c == '`' && info.contains('`')) {
+                    continue;
+                }
+                let language = info.split_whitespace().next().unwrap_or_default();
+                let block = CodeBlock {
+                    line: index + 2,
+                    code: String::new(),
+                };
+                open = Some((c, len, language.eq_ignore_ascii_case("c"), block));
+            }
+            Some((c, len, is_c, block)) => {
+                let closing = fence.map(str::trim_end).filter(|fence| {
+                    fence.chars().all(|f| f == *c) && fence.chars().count() >= *len
+                });
+                if closing.is_none() {
+                    block.code.push_str(line);
+                    block.code.push('\n');
+                    continue;
+                }
+                if *is_c {
+                    blocks.push(block.clone());
+                }
+                open = None;
+            }
+        }
+    }
+    if let Some((_, _, true, block)) = open {
+        blocks.push(block);
+    }
+    blocks
+}
+
+/// Writes the C source of the `block`th C code block of `markdown_file`, counting from
+/// 1, or of every C code block if `block` is `None`, and returns where it went.
+///
+/// # Errors
+///
+/// If `markdown_file` cannot be read or the source cannot be written, and
+/// [`MorfoError::MissingCodeBlock`] if there is no such block.
+pub fn extract(
+    markdown_file: &Path,
+    block: Option<usize>,
+    config: &Config,
+) -> MorfoResult<PathBuf> {
+    let markdown = fs::read_to_string(markdown_file).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => MorfoError::FileNotFound(markdown_file.to_path_buf()),
+        _ => e.into(),
+    })?;
+    let blocks = c_blocks(&markdown);
+    let selected = match block {
+        Some(n) => blocks.get(n.wrapping_sub(1)).into_iter().collect(),
+        None => blocks.iter().collect::<Vec<_>>(),
+    };
+    if selected.is_empty() {
+        return Err(MorfoError::MissingCodeBlock(
+            markdown_file.to_path_buf(),
+            block,
+        ));
+    }
+
+    let name = markdown_file
+        .to_string_lossy()
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"");
+    let source: String = selected
+        .iter()
+        .map(|block| format!("#line {} \"{}\"\n{}", block.line, name, block.code))
+        .collect();
+    let path = layout::markdown_source(markdown_file, block, config);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, source)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigBuilder;
+
+    const NOTES: &str = "# Notes\n\
+                         \n\
+                         ```c\n\
+                         int answer(void) { return 42; }\n\
+                         ```\n\
+                         \n\
+                         ```sh\n\
+                         morfo run notes.md\n\
+                         ```\n\
+                         \n\
+                         ~~~~ C\n\
+                         ```\n\
+                         int main(void) { return answer(); }\n\
+                         ~~~~\n";
+
+    #[test]
+    fn markdown_c_blocks() {
+        assert_eq!(
+            c_blocks(NOTES),
+            [
+                CodeBlock {
+                    line: 4,
+                    code: "int answer(void) { return 42; }\n".to_owned(),
+                },
+                CodeBlock {
+                    line: 12,
+                    code: "```\nint main(void) { return answer(); }\n".to_owned(),
+                },
+            ]
+        );
+        assert_eq!(c_blocks("```c\nint x;\n")[0].code, "int x;\n");
+    }
+
+    #[test]
+    fn markdown_extract_blocks() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let notes = tmp_dir.path().join("notes.md");
+        fs::write(&notes, NOTES).unwrap();
+        let config = ConfigBuilder::default()
+            .set_build_dir(tmp_dir.path().join(".out").to_str().unwrap())
+            .build();
+
+        let first = extract(&notes, Some(1), &config).unwrap();
+        assert_eq!(
+            fs::read_to_string(first).unwrap(),
+            format!(
+                "#line 4 \"{}\"\nint answer(void) {{ return 42; }}\n",
+                notes.display()
+            )
+        );
+        let all = fs::read_to_string(extract(&notes, None, &config).unwrap()).unwrap();
+        assert_eq!(all.matches("#line").count(), 2);
+        assert_eq!(
+            extract(&notes, Some(3), &config),
+            Err(MorfoError::MissingCodeBlock(notes.clone(), Some(3)))
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! Building a program with every combination of compilers and C standards.
+//!
+//! `morfo build main.c --matrix` builds the main file once for every pair of a compiler
+//! and a standard listed under `[matrix]`, a few at a time, to tell which of them the
+//! program builds with:
+//!
+//! ```toml
+//! [matrix]
+//! cc = ["gcc", "clang"]
+//! std = ["c99", "c17"]
+//! ```
+//!
+//! Leaving out `cc` or `std` builds with the one of the config. Each cell puts its
+//! artifacts in a subdirectory of the build directory, `matrix/<cc>-<std>`, so the
+//! builds never share an object.
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    utils,
+};
+
+/// The outcome of building one cell of the matrix.
+#[derive(Debug)]
+pub struct MatrixBuild {
+    /// The compiler of the cell, or `None` for the one of the config.
+    pub cc: Option<String>,
+    /// The C standard of the cell, or `None` for the one of the config.
+    pub std: Option<String>,
+    /// The executable built, or why it could not be.
+    pub result: MorfoResult<PathBuf>,
+}
+
+/// Returns the cells of the matrix of `config`, every compiler with every standard,
+/// row by row.
+///
+/// # Errors
+///
+/// [`MorfoError::InvlidConfig`] if the config lists no compilers and no standards.
+pub fn cells(config: &Config) -> MorfoResult<Vec<(Option<String>, Option<String>)>> {
+    let ccs = config.get_matrix_cc();
+    let stds = config.get_matrix_std();
+    if ccs.is_empty() && stds.is_empty() {
+        return Err(MorfoError::InvlidConfig(
+            "--matrix needs `cc` or `std` under `[matrix]`".to_owned(),
+        ));
+    }
+
+    let column = |values: Vec<String>| -> Vec<Option<String>> {
+        if values.is_empty() {
+            vec![None]
+        } else {
+            values.into_iter().map(Some).collect()
+        }
+    };
+    let stds = column(stds);
+    Ok(column(ccs)
+        .into_iter()
+        .flat_map(|cc| stds.iter().map(move |std| (cc.clone(), std.clone())))
+        .collect())
+}
+
+/// Builds `main_file` in every cell of the matrix of `config`, `jobs` at a time, and
+/// returns how each went, row by row.
+///
+/// # Errors
+///
+/// If the config has no matrix. A cell that fails to build is not an error, but a
+/// [`MatrixBuild`].
+pub fn build_matrix(
+    main_file: &Path,
+    config: &Config,
+    jobs: usize,
+) -> MorfoResult<Vec<MatrixBuild>> {
+    let cells = cells(config)?;
+    Ok(utils::parallel_map(&cells, jobs, |(cc, std)| {
+        let mut cell = config.clone().with_batch_dir(&cell_dir(cc, std));
+        if let Some(cc) = cc {
+            cell = cell.with_cc(cc);
+        }
+        if let Some(std) = std {
+            cell = cell.with_std(std);
+        }
+        MatrixBuild {
+            cc: cc.clone(),
+            std: std.clone(),
+            result: crate::build(main_file.to_path_buf(), cell),
+        }
+    }))
+}
+
+/// Returns the subdirectory of the build directory for a cell, `matrix/<cc>-<std>` with
+/// the file name of the compiler, leaving out what the cell does not change.
+fn cell_dir(cc: &Option<String>, std: &Option<String>) -> PathBuf {
+    let name: Vec<String> = cc
+        .iter()
+        .map(|cc| utils::file_name(Path::new(cc)))
+        .chain(std.iter().cloned())
+        .collect();
+    Path::new("matrix").join(name.join("-"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn matrix_builds_every_cell() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let main_file = dir.join("main.c");
+        fs::write(
+            &main_file,
+            "int main(void) {\n\
+                 int sum = 0;\n\
+                 for (int i = 0; i < 3; i++) sum += i;\n\
+                 return sum - 3;\n\
+             }\n",
+        )
+        .unwrap();
+        let out = dir.join(".out");
+        let config: Config = toml::from_str(&format!(
+            "cc = \"gcc\"\n\
+             builddir = \"{}\"\n\
+             [matrix]\n\
+             cc = [\"/usr/bin/gcc\"]\n\
+             std = [\"c89\", \"c99\"]\n",
+            out.display()
+        ))
+        .unwrap();
+
+        let builds = build_matrix(&main_file, &config, 2).unwrap();
+        let grid: Vec<_> = builds
+            .iter()
+            .map(|build| (build.cc.as_deref(), build.std.as_deref()))
+            .collect();
+        assert_eq!(
+            grid,
+            [
+                (Some("/usr/bin/gcc"), Some("c89")),
+                (Some("/usr/bin/gcc"), Some("c99"))
+            ]
+        );
+        assert!(builds[0].result.is_err());
+        assert_eq!(builds[1].result, Ok(out.join("matrix/gcc-c99/main")));
+
+        let config: Config = toml::from_str("cc = \"gcc\"").unwrap();
+        assert!(cells(&config).is_err());
+        assert_eq!(
+            cell_dir(&None, &Some("c11".to_owned())),
+            Path::new("matrix/c11")
+        );
+    }
+}
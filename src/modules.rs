@@ -0,0 +1,203 @@
+//! C++20 modules.
+//!
+//! A C++ source of the project declaring `export module math;` is the interface unit of
+//! the module `math`, which the sources that `import math;` depend on, just as a source
+//! depends on the source next to a header it includes. Compiling an interface unit, or a
+//! partition such as `export module math:ops;`, writes the compiled interface of the
+//! module (its BMI) to `<builddir>/modules/`, where the units importing it read it, so the
+//! interfaces are compiled before their importers. The implementation units of a module,
+//! declaring `module math;`, are built whenever the module is imported.
+//!
+//! GCC is told where each compiled interface goes with a module mapper file, and given
+//! `-fmodules-ts`. Clang finds them in the directory with `-fprebuilt-module-path`. The
+//! sources with an extension the compilers do not know as C++, such as `.cppm` or `.ixx`,
+//! are compiled as C++ explicitly. MSVC is not supported. The C++ standard is still set
+//! with `std`, such as `std = "c++20"`.
+//!
+//! An object importing a module is rebuilt whenever the interface of the module is, and
+//! neither the objects of the module units nor those importing modules are cached or
+//! compiled on other hosts, as their compilation depends on more than their source.
+//! Unity builds of programs with modules are not supported.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    act::Act,
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    layout,
+    toolchain::CompilerFamily,
+};
+
+/// The file name of the module mapper of GCC in the modules directory.
+const MAPPER: &str = "mapper";
+
+/// The flags telling the compilers where the compiled interfaces of the modules are.
+const FLAGS: [&str; 2] = ["-fmodules-ts", "-fprebuilt-module-path="];
+
+/// Returns the config compiling the module units of `act`, and the units importing
+/// modules, with the flags that write and read the compiled interfaces, and writes the
+/// module mapper of GCC.
+///
+/// # Errors
+///
+/// [`Unsupported`] if the compiler is MSVC, and an I/O error if the module mapper cannot
+/// be written.
+///
+/// [`Unsupported`]: MorfoError::Unsupported
+pub(crate) fn apply(act: &Act, config: Config) -> MorfoResult<Config> {
+    let units: Vec<&Act> = act
+        .nodes()
+        .filter(|node| node.module.is_some() || !node.imports.is_empty())
+        .collect();
+    if units.is_empty() {
+        return Ok(config);
+    }
+    let family = config.get_family();
+    if family == CompilerFamily::Msvc {
+        return Err(MorfoError::Unsupported("C++ modules with MSVC".to_owned()));
+    }
+    let dir = std::path::absolute(layout::modules_dir(&config))?;
+    if !config.get_dry_run() {
+        fs::create_dir_all(&dir)?;
+    }
+
+    let mut config = config;
+    if family == CompilerFamily::Gcc {
+        let mapper = dir.join(MAPPER);
+        let mut lines = String::new();
+        for (module, _) in interfaces(act) {
+            let bmi = dir.join(bmi_name(&module, family));
+            lines.push_str(&format!("{} {}\n", module, bmi.display()));
+        }
+        if !config.get_dry_run() && fs::read_to_string(&mapper).ok() != Some(lines.clone()) {
+            fs::write(&mapper, lines)?;
+        }
+        for unit in units {
+            let mut flags = vec![
+                FLAGS[0].to_owned(),
+                format!("-fmodule-mapper={}", mapper.display()),
+            ];
+            if !is_cpp_extension(&unit.name) {
+                flags.extend(["-x".to_owned(), "c++".to_owned()]);
+            }
+            config = config.with_source_flags(&unit.name, flags);
+        }
+    } else {
+        for unit in units {
+            let mut flags = vec![format!("{}{}", FLAGS[1], dir.display())];
+            if let Some(module) = &unit.module {
+                let bmi = dir.join(bmi_name(module, family));
+                flags.extend(["-x".to_owned(), "c++-module".to_owned()]);
+                flags.push(format!("-fmodule-output={}", bmi.display()));
+            }
+            config = config.with_source_flags(&unit.name, flags);
+        }
+    }
+    Ok(config)
+}
+
+/// Returns whether `flags` compile a source against the compiled interfaces of modules.
+pub(crate) fn uses_modules(flags: &[String]) -> bool {
+    flags
+        .iter()
+        .any(|flag| FLAGS.iter().any(|modules| flag.starts_with(modules)))
+}
+
+/// Returns the source declaring the interface of every module of `act`, by module.
+pub(crate) fn interfaces(act: &Act) -> BTreeMap<String, PathBuf> {
+    act.nodes()
+        .filter_map(|node| Some((node.module.clone()?, node.name.clone())))
+        .collect()
+}
+
+/// Returns the path of the compiled interface of `module`.
+pub(crate) fn bmi(module: &str, config: &Config) -> PathBuf {
+    layout::modules_dir(config).join(bmi_name(module, config.get_family()))
+}
+
+/// Returns the file name of the compiled interface of `module`, with the partitions
+/// named `math-ops` for `math:ops` as Clang looks them up.
+fn bmi_name(module: &str, family: CompilerFamily) -> String {
+    let extension = match family {
+        CompilerFamily::Clang => "pcm",
+        _ => "gcm",
+    };
+    format!("{}.{}", module.replace(':', "-"), extension)
+}
+
+/// Returns whether the compilers take `source` for C++ going by its extension, unlike
+/// the extensions of module interfaces such as `.cppm`.
+fn is_cpp_extension(source: &Path) -> bool {
+    source.extension().is_some_and(|extension| {
+        ["cpp", "cc", "cxx", "c++", "C"].contains(&&*extension.to_string_lossy())
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_modules() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(
+            dir.join("math.cppm"),
+            "module;\n#include <cstdio>\nexport module math;\nexport import :ops;\n\
+             export int answer();\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("ops.cppm"),
+            "export module math:ops;\nexport int add(int a, int b) { return a + b; }\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("math.cpp"),
+            "module math;\nint answer() { return add(40, 1); }\n",
+        )
+        .unwrap();
+        let main_file = dir.join("main.cpp");
+        fs::write(
+            &main_file,
+            "import math;\nint main() { return answer() + ANSWER; }\n",
+        )
+        .unwrap();
+        fs::write(dir.join("unused.cpp"), "module unused;\n").unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "g++"
+            std = "c++20"
+            defines = {{ ANSWER = "1" }}
+            builddir = "{}""#,
+            dir.join(".out").display()
+        ))
+        .unwrap();
+
+        let artifact = crate::compile(&main_file, &config).unwrap();
+        let outcome = crate::run(&artifact, crate::RunOptions::new(), &mut Vec::new()).unwrap();
+        assert_eq!(
+            outcome.exit_status.and_then(|status| status.code()),
+            Some(42)
+        );
+        assert!(bmi("math:ops", &config).is_file());
+
+        // changing an interface rebuilds the units importing it
+        fs::write(
+            dir.join("ops.cppm"),
+            "export module math:ops;\nexport int add(int a, int b) { return a + b + 1; }\n",
+        )
+        .unwrap();
+        let artifact = crate::compile(&main_file, &config).unwrap();
+        let outcome = crate::run(&artifact, crate::RunOptions::new(), &mut Vec::new()).unwrap();
+        assert_eq!(
+            outcome.exit_status.and_then(|status| status.code()),
+            Some(43)
+        );
+    }
+}
@@ -0,0 +1,75 @@
+//! Desktop notifications of finished builds.
+//!
+//! With `--notify`, or `notify = true` in the config, morfo shows a desktop notification
+//! when a build finishes, saying whether it succeeded and how long it took, so that one
+//! can work on something else during a slow build. `notify_after` leaves out the
+//! builds shorter than that many seconds. Without a notification server, e.g. over
+//! SSH, nothing is shown.
+
+use std::{path::Path, time::Duration};
+
+use notify_rust::{Notification, Timeout};
+
+use crate::{config::Config, error::MorfoResult, Artifact};
+
+/// Shows the notification of building `main_file` into `result` in `duration`, if the
+/// config asks for one and the build took long enough.
+pub(crate) fn build_finished(
+    main_file: &Path,
+    result: &MorfoResult<Artifact>,
+    duration: Duration,
+    config: &Config,
+) {
+    if !config.get_notify() || duration < config.get_notify_after() {
+        return;
+    }
+    let (summary, body) = message(main_file, result, duration);
+    // a notification that cannot be shown is not worth failing the build for
+    let _ = Notification::new()
+        .appname("morfo")
+        .summary(&summary)
+        .body(&body)
+        .timeout(Timeout::Milliseconds(10_000))
+        .show();
+}
+
+/// Returns the summary and the body of the notification.
+fn message(
+    main_file: &Path,
+    result: &MorfoResult<Artifact>,
+    duration: Duration,
+) -> (String, String) {
+    let name = main_file.display();
+    match result {
+        Ok(artifact) => (
+            format!("{} built in {:.1}s", name, duration.as_secs_f64()),
+            format!(
+                "{} warning(s)\n{}",
+                artifact.compile_stats.warnings,
+                artifact.executable_path.display()
+            ),
+        ),
+        Err(e) => (
+            format!("{} failed after {:.1}s", name, duration.as_secs_f64()),
+            e.to_string(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::MorfoError;
+
+    #[test]
+    fn notify_message() {
+        let result = Err(MorfoError::CompilationFailure(Some(1)));
+        let (summary, body) = message(
+            Path::new("src/main.c"),
+            &result,
+            Duration::from_millis(12_340),
+        );
+        assert_eq!(summary, "src/main.c failed after 12.3s");
+        assert_eq!(body, MorfoError::CompilationFailure(Some(1)).to_string());
+    }
+}
@@ -0,0 +1,114 @@
+//! Objective-C translation units.
+//!
+//! Sources with the `.m` extension are translation units of the program like the C
+//! sources, found from a header of the same name next to them, or built as the main file
+//! itself. They are compiled as Objective-C with `-x objective-c`, and the program is
+//! linked with the Objective-C runtime, `libobjc`. As GCC rarely ships with Objective-C,
+//! a program with an Objective-C source is compiled and linked with `clang` unless `cc`
+//! is already a Clang.
+//!
+//! The `frameworks` of the config, such as `Foundation`, are linked with
+//! `-framework <name>`, which only the macOS linker knows. They are linked into C
+//! programs too, for CoreFoundation and the like.
+//!
+//! Unity builds of programs with Objective-C sources are not supported.
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use crate::{act::Act, config::Config, toolchain::CompilerFamily};
+
+/// Returns whether `path` is an Objective-C source, going by its extension.
+///
+/// # Examples
+///
+/// ```
+/// use morfo::objc::is_objc;
+/// use std::path::Path;
+///
+/// assert!(is_objc(Path::new("AppDelegate.m")));
+/// assert!(!is_objc(Path::new("main.c")));
+/// ```
+pub fn is_objc(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "m")
+}
+
+/// Returns the Objective-C sources of `act`.
+pub(crate) fn sources(act: &Act) -> Vec<PathBuf> {
+    act.sources()
+        .into_iter()
+        .filter(|source| is_objc(source))
+        .collect()
+}
+
+/// Returns the config compiling `act` with `clang` if it has an Objective-C source and
+/// the compiler is not a Clang.
+pub(crate) fn apply(act: &Act, config: Config) -> Config {
+    if sources(act).is_empty() || config.get_family() == CompilerFamily::Clang {
+        config
+    } else {
+        config.with_cc("clang")
+    }
+}
+
+/// Returns the arguments selecting the language of `source`, which come before it.
+pub(crate) fn language_args(source: &Path) -> Vec<&'static str> {
+    if is_objc(source) {
+        vec!["-x", "objective-c"]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Returns the arguments linking the frameworks of `config`, and the Objective-C runtime
+/// if `act` has an Objective-C source.
+pub(crate) fn link_args(act: &Act, config: &Config) -> Vec<OsString> {
+    let mut args: Vec<OsString> = Vec::new();
+    if !sources(act).is_empty() {
+        args.push(config.get_family().library_arg("objc").into());
+    }
+    for framework in config.get_frameworks() {
+        args.push("-framework".into());
+        args.push(framework.into());
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn objc_with_clang() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(dir.join("greeter.h"), "void greet(void);\n").unwrap();
+        fs::write(dir.join("greeter.m"), "void greet(void) {}\n").unwrap();
+        let main_file = dir.join("main.c");
+        fs::write(
+            &main_file,
+            "#include \"greeter.h\"\nint main(void) { greet(); return 0; }\n",
+        )
+        .unwrap();
+        let config: Config = toml::from_str(&format!(
+            "cc = \"gcc\"\nbuilddir = \"{}\"\nframeworks = [\"Foundation\"]",
+            dir.join(".out").display()
+        ))
+        .unwrap();
+
+        let (act, config) = crate::prepare(&main_file, config).unwrap();
+        assert_eq!(sources(&act), [dir.join("greeter.m")]);
+        assert_eq!(config.get_cc(), "clang");
+        assert_eq!(config.get_family(), CompilerFamily::Clang);
+        assert_eq!(
+            link_args(&act, &config),
+            ["-lobjc", "-framework", "Foundation"]
+        );
+        assert_eq!(language_args(&dir.join("greeter.m")), ["-x", "objective-c"]);
+        assert!(language_args(&main_file).is_empty());
+    }
+}
@@ -0,0 +1,535 @@
+//! Distributable archives of the program.
+//!
+//! `morfo package` builds the program with the `release` profile, unless another is
+//! selected, and gathers the executable, the licenses and the assets of the `[package]`
+//! section into a directory named `{name}-{version}-{target}`: the name and the version
+//! of the `[project]` section, the name defaulting to that of the main file, and the
+//! target selected with `--target` or else the platform morfo runs on, such as
+//! `x86_64-linux`. The directory is archived with `tar` as a `.tar.gz`, or with `zip` as
+//! a `.zip`, into `<builddir>/dist/`.
+//!
+//! The directory has a `SHA256SUMS` file with the SHA-256 of every other file in it, and
+//! the SHA-256 of the archive is written next to it in `<archive>.sha256`, both as
+//! `sha256sum` prints them, so a download can be checked with `sha256sum -c`. The
+//! `SHA256SUMS` of `dist/` lists every archive in it, for a release of several targets.
+//!
+//! With `--sign`, the archive and the `SHA256SUMS` of `dist/` are signed with the
+//! command of the `[release]` section, such as minisign or gpg, which writes the
+//! signatures next to them: `.minisig` files for minisign and `.asc` files for gpg.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+use globset::GlobBuilder;
+use walkdir::WalkDir;
+
+use crate::{
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    layout, project_dir, utils,
+};
+
+/// The file listing the SHA-256 of the files of the package.
+const CHECKSUMS: &str = "SHA256SUMS";
+
+/// The format of an archive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Deserialize)]
+pub enum Format {
+    #[default]
+    #[serde(rename = "tar.gz")]
+    TarGz,
+    #[serde(rename = "zip")]
+    Zip,
+}
+
+impl Format {
+    /// Returns the extension of the archives in this format.
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::TarGz => "tar.gz",
+            Format::Zip => "zip",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tar.gz" => Ok(Format::TarGz),
+            "zip" => Ok(Format::Zip),
+            _ => Err(format!(
+                "unknown format `{}`, expected `tar.gz` or `zip`",
+                s
+            )),
+        }
+    }
+}
+
+/// What goes into the archive besides the executable, declared in the `[package]`
+/// section of the config.
+///
+/// `licenses` are files put at the top of the archive, and `include` patterns for the
+/// assets, relative to the project directory, which keep their path in the archive.
+/// `format` is `tar.gz` (the default) or `zip`.
+///
+/// # Examples
+///
+/// ```toml
+/// [package]
+/// licenses = ["LICENSE", "NOTICE"]
+/// include = ["README.md", "assets/**"]
+/// format = "zip"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize)]
+pub struct Package {
+    licenses: Option<Vec<String>>,
+    include: Option<Vec<String>>,
+    format: Option<Format>,
+}
+
+impl Package {
+    /// Returns the license files of the package.
+    pub fn get_licenses(&self) -> Vec<String> {
+        self.licenses.clone().unwrap_or_default()
+    }
+
+    /// Returns the patterns matching the assets of the package.
+    pub fn get_include(&self) -> Vec<String> {
+        self.include.clone().unwrap_or_default()
+    }
+
+    /// Returns the format of the archive. Defaults to `tar.gz`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::{config::Config, package::Format};
+    ///
+    /// let config: Config = toml::from_str("[package]\nformat = \"zip\"").unwrap();
+    /// assert_eq!(config.get_package().get_format(), Format::Zip);
+    /// ```
+    pub fn get_format(&self) -> Format {
+        self.format.unwrap_or_default()
+    }
+}
+
+/// Archives `executable`, built from `main_file`, with the licenses and assets of the
+/// package, in `format` or else the one of the config, and returns the archive.
+///
+/// # Errors
+///
+/// If the project has no version, a license or an asset pattern matches no file, a file
+/// cannot be copied, or `tar` or `zip` cannot be run or fail.
+pub fn package(
+    main_file: &Path,
+    executable: &Path,
+    config: &Config,
+    format: Option<Format>,
+) -> MorfoResult<PathBuf> {
+    let package = config.get_package();
+    let format = format.unwrap_or(package.get_format());
+    let version = config.get_project_version().ok_or_else(|| {
+        MorfoError::InvlidConfig("`morfo package` needs a version in [project]".to_owned())
+    })?;
+    let name = config
+        .get_project_name()
+        .unwrap_or_else(|| utils::file_name(main_file));
+    let stem = format!("{}-{}-{}", name, version, target(config));
+
+    let staging = layout::package_dir(config).join(&stem);
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)?;
+    copy(executable, &staging)?;
+    let project_dir = project_dir(main_file);
+    for license in package.get_licenses() {
+        let path = project_dir.join(&license);
+        if !path.is_file() {
+            return Err(MorfoError::FileNotFound(path));
+        }
+        copy(&path, &staging)?;
+    }
+    for asset in assets(&package.get_include(), project_dir, config)? {
+        let dest = staging.join(&asset);
+        if let Some(dir) = dest.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::copy(project_dir.join(&asset), dest)?;
+    }
+    write_checksums(&staging)?;
+
+    let dist = layout::dist_dir(config);
+    fs::create_dir_all(&dist)?;
+    let archive = std::path::absolute(dist.join(format!("{}.{}", stem, format.extension())))?;
+    if archive.exists() {
+        fs::remove_file(&archive)?;
+    }
+    let mut archive_cmd = match format {
+        Format::TarGz => {
+            let mut tar = Command::new("tar");
+            tar.arg("-czf").arg(&archive).arg(&stem);
+            tar
+        }
+        Format::Zip => {
+            let mut zip = Command::new("zip");
+            zip.arg("-qr").arg(&archive).arg(&stem);
+            zip
+        }
+    };
+    archive_cmd.current_dir(layout::package_dir(config));
+    let output = utils::run_tool(&mut archive_cmd)?;
+    if !output.status.success() {
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        return Err(MorfoError::CommandFailure(
+            utils::format_command(&archive_cmd),
+            output.status.code(),
+        ));
+    }
+
+    let mut checksum = archive.as_os_str().to_owned();
+    checksum.push(".sha256");
+    fs::write(
+        checksum,
+        format!(
+            "{}  {}.{}\n",
+            utils::hash_file(&archive)?,
+            stem,
+            format.extension()
+        ),
+    )?;
+    write_dist_checksums(&dist)?;
+    Ok(archive)
+}
+
+/// How `morfo package --sign` signs the archives, declared in the `[release]` section of
+/// the config.
+///
+/// `sign` is `minisign`, `gpg`, or a command run on every file to sign with `{file}`
+/// replaced by its path, such as `ssh-keygen -Y sign -f release.key -n file {file}`.
+/// `key` is the secret key file of minisign or the user ID of the gpg key. Without it,
+/// the tools sign with their default key.
+///
+/// # Examples
+///
+/// ```toml
+/// [release]
+/// sign = "minisign"
+/// key = "~/.minisign/release.key"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize)]
+pub struct Release {
+    sign: Option<String>,
+    key: Option<String>,
+}
+
+impl Release {
+    /// Returns the signing tool or command, if set.
+    pub fn get_sign(&self) -> Option<&str> {
+        self.sign.as_deref().filter(|sign| !sign.trim().is_empty())
+    }
+
+    /// Returns the key the files are signed with, if set.
+    pub fn get_key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+}
+
+/// Signs `archive`, and the `SHA256SUMS` of the archives next to it, with the command of
+/// the `[release]` section, which writes the signatures next to them.
+///
+/// # Errors
+///
+/// If no signing command is configured, or it cannot be run or fails.
+pub fn sign(archive: &Path, config: &Config) -> MorfoResult<()> {
+    let release = config.get_release();
+    let dist_checksums = archive.with_file_name(CHECKSUMS);
+    for file in [archive, dist_checksums.as_path()] {
+        let mut sign_cmd = sign_command(&release, file)?;
+        let status = utils::run_tool(
+            sign_cmd
+                .stdin(Stdio::inherit())
+                .stdout(Stdio::inherit())
+                .stderr(Stdio::inherit()),
+        )?
+        .status;
+        if !status.success() {
+            return Err(MorfoError::CommandFailure(
+                utils::format_command(&sign_cmd),
+                status.code(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the command signing `file` as `release` says.
+fn sign_command(release: &Release, file: &Path) -> MorfoResult<Command> {
+    let sign = release.get_sign().ok_or_else(|| {
+        MorfoError::InvlidConfig("`--sign` needs a sign command in [release]".to_owned())
+    })?;
+    let cmd = match sign {
+        "minisign" => {
+            let mut minisign = Command::new("minisign");
+            minisign.arg("-S");
+            if let Some(key) = release.get_key() {
+                minisign.args(["-s", key]);
+            }
+            minisign.arg("-m").arg(file);
+            minisign
+        }
+        "gpg" => {
+            let mut gpg = Command::new("gpg");
+            gpg.args(["--yes", "--detach-sign", "--armor"]);
+            if let Some(key) = release.get_key() {
+                gpg.args(["--local-user", key]);
+            }
+            gpg.arg(file);
+            gpg
+        }
+        _ => {
+            let file = file.to_string_lossy();
+            let words: Vec<String> = utils::split_words(sign)
+                .iter()
+                .map(|word| word.replace("{file}", &file))
+                .collect();
+            let Some((program, args)) = words.split_first() else {
+                return Err(MorfoError::InvlidConfig(
+                    "empty sign command in [release]".to_owned(),
+                ));
+            };
+            let mut custom = Command::new(program);
+            custom.args(args);
+            custom
+        }
+    };
+    Ok(cmd)
+}
+
+/// Returns the target the program is built for: the one selected, or else the
+/// architecture and the operating system morfo runs on.
+fn target(config: &Config) -> String {
+    config
+        .get_target()
+        .unwrap_or_else(|| format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS))
+}
+
+/// Returns the files of the project matching `patterns`, relative to `project_dir`.
+fn assets(patterns: &[String], project_dir: &Path, config: &Config) -> MorfoResult<Vec<PathBuf>> {
+    let files = utils::project_files(project_dir, &config.get_build_dir());
+    let mut assets: Vec<PathBuf> = Vec::new();
+    for pattern in patterns {
+        let matcher = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| MorfoError::InvlidConfig(format!("package include: {}", e)))?
+            .compile_matcher();
+        let matched: Vec<_> = files
+            .iter()
+            .filter_map(|file| file.strip_prefix(project_dir).ok())
+            .filter(|rel| matcher.is_match(rel))
+            .collect();
+        if matched.is_empty() {
+            return Err(MorfoError::FileNotFound(project_dir.join(pattern)));
+        }
+        for rel in matched {
+            if !assets.iter().any(|asset| asset == rel) {
+                assets.push(rel.to_path_buf());
+            }
+        }
+    }
+    Ok(assets)
+}
+
+/// Copies the file at `path` into `dir`, keeping its name and permissions.
+fn copy(path: &Path, dir: &Path) -> MorfoResult<()> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| MorfoError::FileNotFound(path.to_path_buf()))?;
+    fs::copy(path, dir.join(name))?;
+    Ok(())
+}
+
+/// Writes the checksums of every archive of `dist`, from their `.sha256` files, to its
+/// `SHA256SUMS`, in name order.
+fn write_dist_checksums(dist: &Path) -> MorfoResult<()> {
+    let mut checksums: Vec<PathBuf> = fs::read_dir(dist)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "sha256")
+        })
+        .collect();
+    checksums.sort();
+    let mut lines = String::new();
+    for checksum in checksums {
+        lines.push_str(&fs::read_to_string(checksum)?);
+    }
+    fs::write(dist.join(CHECKSUMS), lines)?;
+    Ok(())
+}
+
+/// Writes the SHA-256 of every file under `dir` to its `SHA256SUMS`, in path order.
+fn write_checksums(dir: &Path) -> MorfoResult<()> {
+    let mut lines = String::new();
+    for entry in WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry.map_err(std::io::Error::from)?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let rel = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+        let rel = rel.to_string_lossy().replace('\\', "/");
+        lines.push_str(&format!("{}  {}\n", utils::hash_file(entry.path())?, rel));
+    }
+    fs::write(dir.join(CHECKSUMS), lines)?;
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn package_tar_gz() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let main_file = dir.join("main.c");
+        fs::write(&main_file, "int main(void) { return 0; }\n").unwrap();
+        fs::write(dir.join("LICENSE"), "MIT\n").unwrap();
+        fs::create_dir_all(dir.join("assets/fonts")).unwrap();
+        fs::write(dir.join("assets/fonts/mono.ttf"), "font").unwrap();
+        fs::write(dir.join("assets/notes.txt"), "notes").unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            builddir = "{}"
+
+            [project]
+            name = "hello"
+            version = "1.2.0"
+
+            [package]
+            licenses = ["LICENSE"]
+            include = ["assets/**/*.ttf"]"#,
+            dir.join(".out").display()
+        ))
+        .unwrap();
+        let config = config.for_profile("release").unwrap();
+
+        let artifact = crate::compile(&main_file, &config).unwrap();
+        let archive = package(&main_file, &artifact.executable_path, &config, None).unwrap();
+        let stem = format!("hello-1.2.0-{}", target(&config));
+        assert_eq!(
+            archive,
+            std::path::absolute(dir.join(".out/dist").join(format!("{}.tar.gz", stem))).unwrap()
+        );
+
+        let listing = Command::new("tar")
+            .arg("-tzf")
+            .arg(&archive)
+            .output()
+            .unwrap();
+        let mut listing: Vec<String> = String::from_utf8_lossy(&listing.stdout)
+            .lines()
+            .filter(|line| !line.ends_with('/'))
+            .map(str::to_owned)
+            .collect();
+        listing.sort();
+        let expected: Vec<String> = ["LICENSE", "SHA256SUMS", "assets/fonts/mono.ttf", "main"]
+            .iter()
+            .map(|file| format!("{}/{}", stem, file))
+            .collect();
+        assert_eq!(listing, expected);
+
+        let sums =
+            fs::read_to_string(layout::package_dir(&config).join(&stem).join(CHECKSUMS)).unwrap();
+        assert!(sums.contains(&format!(
+            "{}  LICENSE\n",
+            utils::hash_file(&dir.join("LICENSE")).unwrap()
+        )));
+        let checksum = fs::read_to_string(format!("{}.sha256", archive.display())).unwrap();
+        assert_eq!(
+            checksum,
+            format!("{}  {}.tar.gz\n", utils::hash_file(&archive).unwrap(), stem)
+        );
+
+        // an asset pattern matching nothing is an error
+        let config: Config = toml::from_str(&format!(
+            "cc = \"gcc\"\nbuilddir = \"{}\"\n[project]\nversion = \"1.0.0\"\n\
+             [package]\ninclude = [\"missing/*\"]",
+            dir.join(".out").display()
+        ))
+        .unwrap();
+        assert_eq!(
+            package(&main_file, &artifact.executable_path, &config, None),
+            Err(MorfoError::FileNotFound(dir.join("missing/*")))
+        );
+    }
+
+    #[test]
+    fn sign_archives() {
+        let release: Release =
+            toml::from_str("sign = \"minisign\"\nkey = \"release.key\"").unwrap();
+        let minisign = sign_command(&release, Path::new("a.tar.gz")).unwrap();
+        assert_eq!(
+            minisign.get_args().collect::<Vec<_>>(),
+            ["-S", "-s", "release.key", "-m", "a.tar.gz"]
+        );
+        let release: Release = toml::from_str("sign = \"gpg\"").unwrap();
+        let gpg = sign_command(&release, Path::new("a.zip")).unwrap();
+        assert_eq!(
+            gpg.get_args().collect::<Vec<_>>(),
+            ["--yes", "--detach-sign", "--armor", "a.zip"]
+        );
+        assert!(sign_command(&Release::default(), Path::new("a.zip")).is_err());
+
+        // a stand-in for the signing tool
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let main_file = dir.join("tool.c");
+        let executable = dir.join("tool");
+        fs::write(&executable, "tool").unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            builddir = "{}"
+
+            [project]
+            version = "2.0.0"
+
+            [release]
+            sign = "cp {{file}} '{{file}}.sig'""#,
+            dir.join(".out").display()
+        ))
+        .unwrap();
+
+        let wasm = config.for_target("wasm32-emscripten").unwrap();
+        let archive = package(&main_file, &executable, &wasm, Some(Format::TarGz)).unwrap();
+        let musl = config.for_target("musl").unwrap();
+        let other = package(&main_file, &executable, &musl, None).unwrap();
+        let dist = layout::dist_dir(&config);
+        assert_eq!(
+            fs::read_to_string(dist.join(CHECKSUMS)).unwrap(),
+            format!(
+                "{}  {}\n{}  {}\n",
+                utils::hash_file(&other).unwrap(),
+                other.file_name().unwrap().to_string_lossy(),
+                utils::hash_file(&archive).unwrap(),
+                archive.file_name().unwrap().to_string_lossy(),
+            )
+        );
+
+        sign(&archive, &config).unwrap();
+        assert_eq!(
+            fs::read(format!("{}.sig", archive.display())).unwrap(),
+            fs::read(&archive).unwrap()
+        );
+        assert!(dist.join("SHA256SUMS.sig").is_file());
+    }
+}
@@ -0,0 +1,228 @@
+//! Profile-guided optimization.
+//!
+//! `morfo pgo` runs the classic two-phase flow:
+//!
+//! 1. Build the program instrumented to record a profile (`-fprofile-generate`).
+//! 2. Run it, or the `train` command from the `[pgo]` section, to collect the profile.
+//! 3. Rebuild the program optimized for the profile (`-fprofile-use`).
+//!
+//! The profile is kept in `<builddir>/pgo` together with a hash of the sources, headers
+//! and compile flags it was recorded for. Training is skipped while they are unchanged.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    act::Act,
+    compile_act, compile_flags,
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    layout, lock, prepare, run_compiler, run_executable,
+    toolchain::CompilerFamily,
+    utils, RunOptions,
+};
+
+/// The phase of a profile-guided build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PgoPhase {
+    /// Instrument the program to record a profile.
+    Generate,
+    /// Optimize the program using the recorded profile.
+    Use,
+}
+
+/// Builds `main_file` with profile-guided optimization and returns the path of the optimized executable.
+///
+/// The instrumented program is trained with `prog_args` unless a `train` command is configured.
+/// Training output is discarded. Set `retrain` to record a new profile even if the sources, headers
+/// and compile flags are unchanged.
+///
+/// # Errors
+///
+/// If the compiler does not support profile-guided optimization through morfo, or any build or
+/// training step fails.
+pub fn pgo(
+    main_file: PathBuf,
+    config: Config,
+    prog_args: Vec<String>,
+    retrain: bool,
+) -> MorfoResult<PathBuf> {
+    let config = config.detect_cc()?;
+    if config.get_family() == CompilerFamily::Msvc {
+        return Err(MorfoError::Unsupported(
+            "profile-guided optimization with MSVC".to_owned(),
+        ));
+    }
+    let _lock = lock::lock(&config)?;
+    let (act, config) = prepare(&main_file, config)?;
+
+    let dir = layout::pgo_dir(&config);
+    let hash_path = dir.join("sources.sha256");
+    let hash = profile_hash(&act, &config)?;
+    let trained = fs::read_to_string(&hash_path).is_ok_and(|stored| stored == hash);
+
+    if retrain || !trained {
+        // stale profiles make the optimizing build warn or fail, so start afresh
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+
+        let instrumented = config.clone().with_pgo_phase(PgoPhase::Generate);
+        compile_act(&act, &instrumented)?;
+        match instrumented.get_pgo_train(&layout::executable(&act.name, &instrumented)) {
+            Some(train) => train_with(&train)?,
+            None => {
+                run_executable(
+                    &layout::executable(&act.name, &instrumented),
+                    &instrumented,
+                    &mut io::sink(),
+                    RunOptions::new().with_args(prog_args),
+                )?;
+            }
+        }
+        if config.get_family() == CompilerFamily::Clang {
+            merge_clang_profiles(&dir, &config)?;
+        }
+        fs::write(&hash_path, hash)?;
+    }
+
+    let optimized = config.with_pgo_phase(PgoPhase::Use);
+    compile_act(&act, &optimized)?;
+    Ok(layout::executable(&act.name, &optimized))
+}
+
+/// Returns the hash of the sources and headers of `act` and of the flags they are compiled
+/// with, apart from those of the phase, which a profile is recorded for.
+fn profile_hash(act: &Act, config: &Config) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(utils::hash_files(&act.files())?);
+    for flag in config.get_cflags().into_iter().chain(compile_flags(config)) {
+        hasher.update([0]);
+        hasher.update(flag);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Returns the flags for building in `phase` with the configured compiler.
+pub(crate) fn flags(config: &Config, phase: PgoPhase) -> Vec<String> {
+    let dir = layout::pgo_dir(config);
+    match (config.get_family(), phase) {
+        (CompilerFamily::Msvc, _) => Vec::new(),
+        (_, PgoPhase::Generate) => vec![format!("-fprofile-generate={}", dir.display())],
+        (CompilerFamily::Gcc, PgoPhase::Use) => vec![
+            format!("-fprofile-use={}", dir.display()),
+            "-fprofile-correction".to_owned(),
+        ],
+        (CompilerFamily::Clang, PgoPhase::Use) => {
+            vec![format!("-fprofile-use={}", clang_profdata(&dir).display())]
+        }
+    }
+}
+
+fn clang_profdata(dir: &Path) -> PathBuf {
+    dir.join("default.profdata")
+}
+
+fn train_with(train: &[String]) -> MorfoResult<()> {
+    let Some((program, args)) = train.split_first() else {
+        return Ok(());
+    };
+
+    let status = Command::new(program).args(args).status()?;
+    if !status.success() {
+        return Err(MorfoError::CommandFailure(train.join(" "), status.code()));
+    }
+    Ok(())
+}
+
+/// Clang writes raw profiles that must be merged with `llvm-profdata` before they can be used.
+fn merge_clang_profiles(dir: &Path, config: &Config) -> MorfoResult<()> {
+    let mut raw_profiles = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "profraw") {
+            raw_profiles.push(path);
+        }
+    }
+
+    let profdata = clang_profdata(dir);
+    let mut merge_cmd = Command::new("llvm-profdata");
+    merge_cmd
+        .arg("merge")
+        .arg(format!("-output={}", profdata.display()))
+        .args(raw_profiles);
+    run_compiler(&mut merge_cmd, &profdata, &profdata, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigBuilder;
+
+    #[test]
+    fn pgo_flags() {
+        let gcc = ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir(".out")
+            .build();
+        assert_eq!(
+            flags(&gcc, PgoPhase::Generate),
+            vec!["-fprofile-generate=.out/pgo"]
+        );
+        assert_eq!(
+            flags(&gcc, PgoPhase::Use),
+            vec!["-fprofile-use=.out/pgo", "-fprofile-correction"]
+        );
+
+        let clang = ConfigBuilder::default()
+            .set_cc("clang")
+            .set_build_dir(".out")
+            .build();
+        assert_eq!(
+            flags(&clang, PgoPhase::Use),
+            vec!["-fprofile-use=.out/pgo/default.profdata"]
+        );
+    }
+
+    #[test]
+    fn pgo_profile_hash_covers_headers_and_flags() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let main_file = tmp_dir.path().join("main.c");
+        let header = tmp_dir.path().join("answer.h");
+        fs::write(
+            &main_file,
+            "#include \"answer.h\"\nint main(void) { return ANSWER; }\n",
+        )
+        .unwrap();
+        fs::write(&header, "#define ANSWER 0\n").unwrap();
+        let config = ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir(tmp_dir.path().join(".out").to_str().unwrap())
+            .build();
+
+        let (act, config) = prepare(&main_file, config).unwrap();
+        let hash = profile_hash(&act, &config).unwrap();
+
+        fs::write(&header, "#define ANSWER 1\n").unwrap();
+        let edited = profile_hash(&act, &config).unwrap();
+        assert_ne!(edited, hash);
+
+        let optimized = config.clone().with_cflags("-O3");
+        assert_ne!(profile_hash(&act, &optimized).unwrap(), edited);
+    }
+
+    #[test]
+    fn pgo_msvc_unsupported() {
+        let config = ConfigBuilder::default().set_cc("cl").build();
+        assert_eq!(
+            pgo(PathBuf::from("main.c"), config, vec![], false).unwrap_err(),
+            MorfoError::Unsupported("profile-guided optimization with MSVC".to_owned())
+        );
+    }
+}
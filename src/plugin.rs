@@ -0,0 +1,249 @@
+//! Plugins.
+//!
+//! morfo is extended in two ways, neither of which needs a fork:
+//!
+//! * `morfo <name>`, where `<name>` is neither a subcommand of morfo nor a file, runs the
+//!   executable `morfo-<name>` found on the `PATH` with the rest of the arguments, like
+//!   cargo and git do. The `MORFO` environment variable holds the path of morfo itself,
+//!   so that the plugin can call it back.
+//! * The plugins declared in the `[plugins]` section are run when something happens in
+//!   a build, and read what happened from their stdin as a JSON object whose `event` is
+//!   `build-started`, `build-finished` or `run-finished`. The `build-finished` event
+//!   holds the summary of the build, as in `build-summary.json`. Plugins run one after
+//!   the other, in the order of their names, and morfo waits for each. A plugin that
+//!   fails is reported as a warning, but does not fail the build.
+//!
+//! Nothing is sent to the plugins in a dry run.
+
+use std::{
+    env,
+    ffi::OsString,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+};
+
+use colored::Colorize;
+
+use crate::{
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    utils,
+};
+
+/// Something that happens in a build, sent to the plugins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PluginEvent {
+    /// A build is about to start, with the `main_file` to build.
+    BuildStarted,
+    /// A build finished, successfully or not, with its summary.
+    BuildFinished,
+    /// A program built by morfo exited, with its `executable`, its `exit_code` and the
+    /// `duration` of the run in seconds.
+    RunFinished,
+}
+
+/// A plugin, declared in the `[plugins]` section of the config.
+///
+/// `command` is split into words like a shell would, and run in the directory morfo
+/// runs in. `events` are the events the plugin is run for, every event by default.
+///
+/// # Examples
+///
+/// ```toml
+/// [plugins.deploy]
+/// command = "./scripts/deploy.sh --env staging"
+/// events = ["build-finished"]
+/// ```
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+pub struct Plugin {
+    command: String,
+    events: Option<Vec<PluginEvent>>,
+}
+
+impl Plugin {
+    /// Returns the command of the plugin, split into words.
+    pub fn get_command(&self) -> Vec<String> {
+        utils::split_words(&self.command)
+    }
+
+    /// Returns whether the plugin is run for `event`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::{config::Config, plugin::PluginEvent};
+    ///
+    /// let config: Config = toml::from_str(
+    ///     r#"
+    ///     [plugins.deploy]
+    ///     command = "./deploy.sh"
+    ///     events = ["build-finished"]"#,
+    /// )
+    /// .unwrap();
+    /// let deploy = &config.get_plugins()["deploy"];
+    /// assert!(deploy.wants(PluginEvent::BuildFinished));
+    /// assert!(!deploy.wants(PluginEvent::RunFinished));
+    /// ```
+    pub fn wants(&self, event: PluginEvent) -> bool {
+        self.events
+            .as_ref()
+            .is_none_or(|events| events.contains(&event))
+    }
+}
+
+/// Runs the plugins of `config` that want `event`, writing `payload`, a JSON object,
+/// with the `event` added to it on their stdin.
+pub(crate) fn emit(config: &Config, event: PluginEvent, payload: serde_json::Value) {
+    let plugins = config.get_plugins();
+    if config.get_dry_run() || plugins.is_empty() {
+        return;
+    }
+    let mut payload = payload;
+    if let Some(object) = payload.as_object_mut() {
+        object.insert("event".to_owned(), serde_json::json!(event));
+    }
+    let line = payload.to_string() + "\n";
+    for (name, plugin) in plugins.iter().filter(|(_, plugin)| plugin.wants(event)) {
+        if let Err(e) = run_plugin(plugin, &line) {
+            eprintln!(
+                "{}",
+                format!("warning: plugin `{}` failed: {}", name, e).yellow()
+            );
+        }
+    }
+}
+
+fn run_plugin(plugin: &Plugin, line: &str) -> MorfoResult<()> {
+    let words = plugin.get_command();
+    let Some((program, args)) = words.split_first() else {
+        return Err(MorfoError::InvlidConfig("empty plugin command".to_owned()));
+    };
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => MorfoError::MissingTool(program.clone()),
+            _ => e.into(),
+        })?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // a plugin that does not read the event is not a failure
+        let _ = stdin.write_all(line.as_bytes());
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(MorfoError::CommandFailure(
+            plugin.command.clone(),
+            status.code(),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the executable of the external subcommand `name`, `morfo-<name>` on the
+/// `PATH`, if there is one.
+pub fn find_external(name: &str) -> Option<PathBuf> {
+    find_external_in(name, &env::var_os("PATH")?)
+}
+
+fn find_external_in(name: &str, path: &OsString) -> Option<PathBuf> {
+    let file_name = format!("morfo-{}{}", name, env::consts::EXE_SUFFIX);
+    env::split_paths(path)
+        .map(|dir| dir.join(&file_name))
+        .find(|candidate| is_executable(candidate))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Runs the external subcommand at `executable` with `args`, and returns how it exited.
+///
+/// # Errors
+///
+/// If it cannot be run.
+pub fn run_external(
+    executable: &Path,
+    args: impl IntoIterator<Item = OsString>,
+) -> MorfoResult<ExitStatus> {
+    let mut cmd = Command::new(executable);
+    cmd.args(args);
+    if let Ok(morfo) = env::current_exe() {
+        cmd.env("MORFO", morfo);
+    }
+    Ok(cmd.status()?)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    use super::*;
+
+    #[test]
+    fn plugin_events() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let main_file = dir.join("main.c");
+        fs::write(&main_file, "int main(void) { return 3; }\n").unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            builddir = "{out}"
+
+            [plugins.log]
+            command = "sh -c 'cat >> {dir}/events'"
+
+            [plugins.runs]
+            command = "sh -c 'cat >> {dir}/runs'"
+            events = ["run-finished"]
+
+            [plugins.broken]
+            command = "false""#,
+            out = dir.join(".out").display(),
+            dir = dir.display(),
+        ))
+        .unwrap();
+
+        let artifact = crate::compile(&main_file, &config).unwrap();
+        let outcome = crate::run(&artifact, crate::RunOptions::new(), &mut Vec::new()).unwrap();
+        assert_eq!(
+            outcome.exit_status.and_then(|status| status.code()),
+            Some(3)
+        );
+
+        let events: Vec<serde_json::Value> = fs::read_to_string(dir.join("events"))
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let names: Vec<_> = events.iter().map(|event| &event["event"]).collect();
+        assert_eq!(names, ["build-started", "build-finished", "run-finished"]);
+        assert_eq!(events[1]["success"], true);
+        assert_eq!(events[2]["exit_code"], 3);
+        let runs = fs::read_to_string(dir.join("runs")).unwrap();
+        assert_eq!(runs.lines().count(), 1);
+
+        let bin = dir.join("bin");
+        fs::create_dir(&bin).unwrap();
+        let hello = bin.join("morfo-hello");
+        fs::write(&hello, "#!/bin/sh\ntest \"$1\" = world\n").unwrap();
+        fs::set_permissions(&hello, fs::Permissions::from_mode(0o755)).unwrap();
+        let path = env::join_paths([dir.join("missing"), bin]).unwrap();
+        assert_eq!(find_external_in("hello", &path), Some(hello.clone()));
+        assert_eq!(find_external_in("bye", &path), None);
+        let status = run_external(&hello, [OsString::from("world")]).unwrap();
+        assert!(status.success());
+    }
+}
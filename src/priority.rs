@@ -0,0 +1,127 @@
+//! The scheduling priority of the compilers.
+//!
+//! With `build_priority = "low"`, or `--build-priority low`, the compiler and the linker
+//! run niced by 10, and on Linux in the lowest best-effort IO class too, so that a big
+//! parallel build leaves the editor and the browser responsive. `"idle"` nices them by
+//! 19 and, on Linux, only lets them do IO when the disks are otherwise idle. On Windows,
+//! they get the below-normal and idle priority classes. morfo itself keeps its priority.
+
+use std::process::Command;
+
+use crate::config::BuildPriority;
+
+/// How much the priority of the compilers is lowered on Unix, as a nice increment.
+#[cfg(unix)]
+fn nice_increment(priority: BuildPriority) -> i32 {
+    match priority {
+        BuildPriority::Normal => 0,
+        BuildPriority::Low => 10,
+        BuildPriority::Idle => 19,
+    }
+}
+
+/// Makes `cmd` run with `priority`.
+#[cfg(unix)]
+pub(crate) fn apply(cmd: &mut Command, priority: BuildPriority) {
+    use std::os::unix::process::CommandExt;
+
+    if priority == BuildPriority::Normal {
+        return;
+    }
+    let increment = nice_increment(priority);
+    // SAFETY: `nice` and `ioprio_set` are plain system calls, safe to make after a fork
+    unsafe {
+        cmd.pre_exec(move || {
+            // a priority that cannot be lowered is no reason not to build
+            libc::nice(increment);
+            #[cfg(target_os = "linux")]
+            set_io_priority(priority);
+            Ok(())
+        });
+    }
+}
+
+/// Puts the calling process in the IO class of `priority`.
+#[cfg(target_os = "linux")]
+fn set_io_priority(priority: BuildPriority) {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    const IOPRIO_CLASS_BE: libc::c_int = 2;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+
+    let ioprio = match priority {
+        BuildPriority::Normal => return,
+        // the lowest level of the best-effort class
+        BuildPriority::Low => IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT | 7,
+        BuildPriority::Idle => IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+    };
+    // SAFETY: `ioprio_set` only reads its integer arguments
+    unsafe {
+        libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio);
+    }
+}
+
+/// Makes `cmd` run with `priority`.
+#[cfg(windows)]
+pub(crate) fn apply(cmd: &mut Command, priority: BuildPriority) {
+    use std::os::windows::process::CommandExt;
+
+    const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x0000_4000;
+    const IDLE_PRIORITY_CLASS: u32 = 0x0000_0040;
+
+    match priority {
+        BuildPriority::Normal => (),
+        BuildPriority::Low => {
+            cmd.creation_flags(BELOW_NORMAL_PRIORITY_CLASS);
+        }
+        BuildPriority::Idle => {
+            cmd.creation_flags(IDLE_PRIORITY_CLASS);
+        }
+    }
+}
+
+/// Leaves `cmd` as it is, as there is no priority to lower off Unix and Windows.
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn apply(_cmd: &mut Command, _priority: BuildPriority) {}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    use crate::config::Config;
+
+    #[test]
+    fn build_priority_nices_the_compiler() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(dir.join("main.c"), "int main(void) { return 0; }\n").unwrap();
+        // a compiler recording its niceness before compiling
+        let cc = dir.join("cc");
+        fs::write(
+            &cc,
+            format!(
+                "#!/bin/sh\nnice >> {}\nexec gcc \"$@\"\n",
+                dir.join("niceness").display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&cc, fs::Permissions::from_mode(0o755)).unwrap();
+        let niceness = |priority: &str| -> i32 {
+            let _ = fs::remove_file(dir.join("niceness"));
+            let config: Config = toml::from_str(&format!(
+                "cc = \"{}\"\nbuilddir = \"{}\"\nbuild_priority = \"{}\"",
+                cc.display(),
+                dir.join(".out").display(),
+                priority
+            ))
+            .unwrap();
+            crate::compile(&dir.join("main.c"), &config.with_rebuild(true)).unwrap();
+            let niceness = fs::read_to_string(dir.join("niceness")).unwrap();
+            niceness.lines().next().unwrap().parse().unwrap()
+        };
+
+        let normal = niceness("normal");
+        assert_eq!(niceness("low"), (normal + 10).min(19));
+        assert_eq!(niceness("idle"), 19);
+    }
+}
@@ -0,0 +1,189 @@
+//! Running programs with reduced privileges.
+//!
+//! With `user = "nobody"` under `[run]`, the programs morfo runs are started as that
+//! user and its primary group, without the supplementary groups of morfo, so that a
+//! service can be tried the way it is deployed. The variables of the environment that
+//! change how programs are loaded, such as `LD_PRELOAD`, are removed, and `HOME`, `USER`
+//! and `LOGNAME` are those of the user. Only root can run programs as another user, and
+//! the user must be able to read the executable and the build directory.
+//!
+//! `umask = "027"` sets the file mode creation mask of the programs, whoever runs them.
+//!
+//! Both are only supported on Unix.
+
+use std::process::Command;
+
+use crate::{
+    config::Config,
+    error::{MorfoError, MorfoResult},
+};
+
+/// The variables of the environment that change how a program is loaded.
+#[cfg(unix)]
+const LOADER_ENV: [&str; 7] = [
+    "LD_PRELOAD",
+    "LD_LIBRARY_PATH",
+    "LD_AUDIT",
+    "LD_DEBUG_OUTPUT",
+    "DYLD_INSERT_LIBRARIES",
+    "DYLD_LIBRARY_PATH",
+    "DYLD_FRAMEWORK_PATH",
+];
+
+/// Makes `cmd` run as the user and with the umask of the `[run]` section of `config`.
+///
+/// # Errors
+///
+/// If the user does not exist, morfo is not root and the user is another, or the umask
+/// is not an octal number.
+#[cfg(unix)]
+pub(crate) fn apply(cmd: &mut Command, config: &Config) -> MorfoResult<()> {
+    use std::os::unix::process::CommandExt;
+
+    if let Some(umask) = config.get_run_umask()? {
+        // SAFETY: `umask` is async-signal-safe and cannot fail
+        unsafe {
+            cmd.pre_exec(move || {
+                libc::umask(umask as libc::mode_t);
+                Ok(())
+            });
+        }
+    }
+
+    let Some(name) = config.get_run_user() else {
+        return Ok(());
+    };
+    let user = lookup(&name)
+        .ok_or_else(|| MorfoError::InvlidConfig(format!("unknown user `{}` under [run]", name)))?;
+    // SAFETY: `geteuid` cannot fail
+    let euid = unsafe { libc::geteuid() };
+    if euid != 0 && euid != user.uid {
+        return Err(MorfoError::Unsupported(format!(
+            "running programs as `{}` without being root",
+            name
+        )));
+    }
+    // the supplementary groups are dropped by the standard library when root sets a uid
+    cmd.uid(user.uid).gid(user.gid);
+    for name in LOADER_ENV {
+        cmd.env_remove(name);
+    }
+    cmd.env("HOME", user.home)
+        .env("USER", &name)
+        .env("LOGNAME", &name);
+    Ok(())
+}
+
+/// Fails if the `[run]` section of `config` asks for a user or a umask, as only Unix
+/// has them.
+///
+/// # Errors
+///
+/// [`MorfoError::Unsupported`] if a user or a umask is set.
+#[cfg(not(unix))]
+pub(crate) fn apply(_cmd: &mut Command, config: &Config) -> MorfoResult<()> {
+    if config.get_run_user().is_some() || config.get_run_umask()?.is_some() {
+        return Err(MorfoError::Unsupported(
+            "`user` and `umask` under [run] off Unix".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+/// A user of the system.
+#[cfg(unix)]
+struct User {
+    uid: u32,
+    gid: u32,
+    home: std::ffi::OsString,
+}
+
+/// Returns the user called `name`, if there is one.
+#[cfg(unix)]
+fn lookup(name: &str) -> Option<User> {
+    use std::{
+        ffi::{CStr, CString, OsStr},
+        os::unix::ffi::OsStrExt,
+        ptr,
+    };
+
+    let c_name = CString::new(name).ok()?;
+    let mut buffer = vec![0 as libc::c_char; 16 * 1024];
+    // SAFETY: `passwd` is plain data filled in by `getpwnam_r`
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut found = ptr::null_mut();
+    // SAFETY: every pointer is valid for the length given with it
+    let status = unsafe {
+        libc::getpwnam_r(
+            c_name.as_ptr(),
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut found,
+        )
+    };
+    if status != 0 || found.is_null() {
+        return None;
+    }
+    // SAFETY: `pw_dir` points into `buffer`, which is still alive
+    let home = unsafe { CStr::from_ptr(passwd.pw_dir) };
+    Some(User {
+        uid: passwd.pw_uid,
+        gid: passwd.pw_gid,
+        home: OsStr::from_bytes(home.to_bytes()).to_owned(),
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    use super::*;
+
+    #[test]
+    fn run_as_user_with_umask() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(
+            dir.join("main.c"),
+            "#include <stdio.h>\n#include <stdlib.h>\n#include <string.h>\n\
+             int main(int argc, char **argv) {\n\
+             FILE *file = fopen(\"created\", \"w\");\n\
+             return file == NULL || strcmp(getenv(\"LOGNAME\"), argv[1]) != 0;\n}\n",
+        )
+        .unwrap();
+        // SAFETY: `getpwuid` returns the entry of the user running the tests
+        let name = unsafe {
+            let passwd = libc::getpwuid(libc::geteuid());
+            std::ffi::CStr::from_ptr((*passwd).pw_name)
+                .to_string_lossy()
+                .into_owned()
+        };
+        let config = |user: &str| -> Config {
+            toml::from_str(&format!(
+                "cc = \"gcc\"\nbuilddir = \"{}\"\n[run]\nuser = \"{}\"\numask = \"027\"",
+                dir.join(".out").display(),
+                user
+            ))
+            .unwrap()
+        };
+        let run = |config: Config| {
+            let artifact = crate::compile(&dir.join("main.c"), &config)?;
+            let mut cmd = crate::executable_command(&artifact.executable_path, &config)?;
+            let status = cmd.current_dir(dir).arg(&name).status()?;
+            MorfoResult::Ok(status.code())
+        };
+
+        assert_eq!(run(config(&name)).unwrap(), Some(0));
+        let mode = fs::metadata(dir.join("created"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o640);
+
+        assert!(matches!(
+            run(config("no-such-user-of-morfo")),
+            Err(MorfoError::InvlidConfig(_))
+        ));
+    }
+}
@@ -0,0 +1,219 @@
+//! Feature checks performed by compiling tiny test programs.
+//!
+//! Each check writes a small source file into a temporary directory and asks the
+//! configured compiler to build it, the same way autoconf does. A successful build
+//! means the feature is available with the current compiler and flags.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use morfo::config::ConfigBuilder;
+//! use morfo::probe;
+//!
+//! let config = ConfigBuilder::default().set_cc("gcc").build();
+//! if probe::has_header(&config, "sys/epoll.h").unwrap() {
+//!     println!("epoll is available");
+//! }
+//! ```
+
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::{compiler_command, config::Config, error::MorfoResult, toolchain::CompilerFamily};
+
+/// A single feature check, declared in the `[features]` section of the config.
+///
+/// # Examples
+///
+/// ```toml
+/// [features]
+/// HAVE_EPOLL = { header = "sys/epoll.h" }
+/// HAVE_STRLCPY = { function = "strlcpy" }
+/// HAVE_STACK_CLASH_PROTECTION = { flag = "-fstack-clash-protection" }
+/// ```
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Check {
+    Header(String),
+    Function(String),
+    Flag(String),
+}
+
+impl Check {
+    /// Performs the check with the configured compiler.
+    ///
+    /// # Errors
+    ///
+    /// If the temporary directory for the test compile cannot be created.
+    pub fn run(&self, config: &Config) -> MorfoResult<bool> {
+        match self {
+            Check::Header(header) => has_header(config, header),
+            Check::Function(function) => has_function(config, function),
+            Check::Flag(flag) => flag_supported(config, flag),
+        }
+    }
+}
+
+/// Runs every check in the `[features]` section and writes the results to the header at `path`.
+/// Each passing check becomes `#define NAME 1`.
+///
+/// The header is only rewritten when its contents change, so it does not look modified
+/// to anything watching it.
+///
+/// # Errors
+///
+/// If a check cannot be performed or the header cannot be written.
+pub fn write_config_header(config: &Config, path: &Path) -> MorfoResult<()> {
+    let mut header = String::from(
+        "/* Generated by morfo from the [features] section of the config. Do not edit. */\n\
+         #ifndef MORFO_CONFIG_H\n\
+         #define MORFO_CONFIG_H\n\n",
+    );
+    for (name, check) in config.get_features() {
+        if check.run(config)? {
+            header.push_str(&format!("#define {} 1\n", name));
+        } else {
+            header.push_str(&format!("/* #undef {} */\n", name));
+        }
+    }
+    header.push_str("\n#endif /* MORFO_CONFIG_H */\n");
+
+    if fs::read_to_string(path).ok().as_deref() == Some(header.as_str()) {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, header)?;
+    Ok(())
+}
+
+/// Returns whether `header` can be included.
+///
+/// # Errors
+///
+/// If the temporary directory for the test compile cannot be created.
+pub fn has_header(config: &Config, header: &str) -> MorfoResult<bool> {
+    let source = format!("#include <{}>\nint main(void) {{ return 0; }}\n", header);
+    try_build(config, &source, &[], false)
+}
+
+/// Returns whether `function` can be linked against.
+///
+/// The function is declared with a dummy prototype so that no header is needed,
+/// which is how autoconf checks for functions.
+///
+/// # Errors
+///
+/// If the temporary directory for the test compile cannot be created.
+pub fn has_function(config: &Config, function: &str) -> MorfoResult<bool> {
+    let source = format!(
+        "char {0}(void);\nint main(void) {{ return (int){0}(); }}\n",
+        function
+    );
+    try_build(config, &source, &[], true)
+}
+
+/// Returns whether the compiler accepts `flag`.
+/// Warnings are promoted to errors so that flags the compiler merely ignores are rejected.
+///
+/// # Errors
+///
+/// If the temporary directory for the test compile cannot be created.
+pub fn flag_supported(config: &Config, flag: &str) -> MorfoResult<bool> {
+    let werror = match config.get_family() {
+        CompilerFamily::Gcc | CompilerFamily::Clang => "-Werror",
+        CompilerFamily::Msvc => "/WX",
+    };
+    let source = "int main(void) { return 0; }\n";
+    try_build(config, source, &[flag, werror], false)
+}
+
+/// Compiles `source` with the configured compiler and flags plus `extra_args`,
+/// linking it into an executable if `link` is set.
+fn try_build(config: &Config, source: &str, extra_args: &[&str], link: bool) -> MorfoResult<bool> {
+    let dir = tempfile::tempdir()?;
+    let family = config.get_family();
+    let source_path = dir.path().join("probe.c");
+    let object = dir
+        .path()
+        .join(format!("probe.{}", family.object_extension()));
+    fs::write(&source_path, source)?;
+
+    let mut compile_cmd = compiler_command(config);
+    compile_cmd
+        .args(config.get_cflags())
+        .args(extra_args)
+        .args(family.compile_args(&source_path, &object, dir.path()));
+    if !succeeds(&mut compile_cmd) {
+        return Ok(false);
+    }
+    if !link {
+        return Ok(true);
+    }
+
+    let mut link_cmd = compiler_command(config);
+    link_cmd
+        .arg(&object)
+        .args(family.link_output_args(&dir.path().join("probe")));
+    Ok(succeeds(&mut link_cmd))
+}
+
+fn succeeds(cmd: &mut Command) -> bool {
+    cmd.stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigBuilder;
+
+    fn config() -> Config {
+        ConfigBuilder::default().set_cc("cc").build()
+    }
+
+    #[test]
+    fn probe_has_header() {
+        assert!(has_header(&config(), "stdio.h").unwrap());
+        assert!(!has_header(&config(), "morfo/no_such_header.h").unwrap());
+    }
+
+    #[test]
+    fn probe_has_function() {
+        assert!(has_function(&config(), "strlen").unwrap());
+        assert!(!has_function(&config(), "morfo_no_such_function").unwrap());
+    }
+
+    #[test]
+    fn probe_write_config_header() {
+        let config: Config = toml::from_str(
+            r#"
+            cc = "cc"
+
+            [features]
+            HAVE_STDIO = { header = "stdio.h" }
+            HAVE_NO_SUCH_FUNCTION = { function = "morfo_no_such_function" }"#,
+        )
+        .unwrap();
+
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = tmp_dir.path().join("include").join("config.h");
+        write_config_header(&config, &path).unwrap();
+
+        let header = fs::read_to_string(&path).unwrap();
+        assert!(header.contains("/* #undef HAVE_NO_SUCH_FUNCTION */\n#define HAVE_STDIO 1\n"));
+    }
+
+    #[test]
+    fn probe_flag_supported() {
+        assert!(flag_supported(&config(), "-Wall").unwrap());
+        assert!(!flag_supported(&config(), "-fmorfo-no-such-flag").unwrap());
+    }
+}
@@ -0,0 +1,230 @@
+//! Pseudo-terminals.
+//!
+//! With `--tty`, the default when morfo's output is a terminal, the program runs with a
+//! pseudo-terminal as its stdin, stdout and stderr instead of pipes, so `isatty()` holds,
+//! colors and prompts are shown, and curses interfaces work as if the program was run
+//! directly. Everything the program writes to the terminal is passed on to the writer,
+//! and everything typed is passed on to the program. While it runs, morfo's own terminal
+//! is put into raw mode, so keys such as Ctrl+C reach the program's terminal untouched.
+//!
+//! Pseudo-terminals are only available on Unix. Elsewhere the program runs with pipes.
+
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::process::CommandExt,
+    },
+    process::{Command, ExitStatus, Stdio},
+    ptr,
+};
+
+use crate::{error::MorfoResult, interrupt};
+
+/// Runs `cmd` in a new pseudo-terminal, copying its output to `out` and the input of
+/// morfo to it, until it exits.
+///
+/// # Errors
+///
+/// If the pseudo-terminal cannot be opened, the program cannot be run or its output
+/// cannot be written.
+pub(crate) fn run<W: Write>(cmd: &mut Command, out: &mut W) -> MorfoResult<ExitStatus> {
+    let (mut master, slave) = open()?;
+    cmd.stdin(Stdio::from(slave.try_clone()?))
+        .stdout(Stdio::from(slave.try_clone()?))
+        .stderr(Stdio::from(slave));
+    // SAFETY: only async-signal-safe functions are called between fork and exec
+    unsafe {
+        cmd.pre_exec(|| {
+            // a new session, with the pseudo-terminal as its controlling terminal
+            if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+
+    let raw_mode = RawMode::enable();
+    let mut child = interrupt::spawn(cmd, None)?;
+    // the terminal only reports the end of the output once no one else holds it open
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    relay(&mut master, out)?;
+    let status = interrupt::wait(&mut child)?;
+    drop(raw_mode);
+    Ok(status)
+}
+
+/// Copies what is written to the terminal at `master` to `out`, and the input of morfo
+/// to the terminal, until the program closes it. Nothing is left reading the input
+/// afterwards, so what is typed next goes to whatever runs next.
+fn relay<W: Write>(master: &mut File, out: &mut W) -> io::Result<()> {
+    // the program may not read its input while it writes, so what is typed is only
+    // written to the terminal when it has room, and never blocks the output
+    // SAFETY: fcntl has no memory safety requirements
+    unsafe {
+        let flags = libc::fcntl(master.as_raw_fd(), libc::F_GETFL);
+        if flags == -1
+            || libc::fcntl(master.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK) == -1
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    let mut input = Vec::new();
+    let mut stdin_open = true;
+    let mut buf = [0; 4096];
+    loop {
+        let mut fds = [
+            libc::pollfd {
+                fd: master.as_raw_fd(),
+                events: if input.is_empty() {
+                    libc::POLLIN
+                } else {
+                    libc::POLLIN | libc::POLLOUT
+                },
+                revents: 0,
+            },
+            libc::pollfd {
+                // a negative descriptor is ignored
+                fd: if stdin_open && input.is_empty() {
+                    libc::STDIN_FILENO
+                } else {
+                    -1
+                },
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        // SAFETY: fds is valid for the duration of the call
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } == -1 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        }
+
+        if fds[0].revents & libc::POLLOUT != 0 {
+            match master.write(&input) {
+                Ok(n) => {
+                    input.drain(..n);
+                }
+                Err(e) if is_retry(&e) => (),
+                // the program closed the terminal, which the read below reports
+                Err(_) => input.clear(),
+            }
+        }
+        if fds[0].revents & (libc::POLLIN | libc::POLLHUP | libc::POLLERR) != 0 {
+            match master.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    out.write_all(&buf[..n])?;
+                    out.flush()?;
+                }
+                Err(e) if is_retry(&e) => (),
+                // Linux fails with EIO once the program closed the terminal
+                Err(_) => break,
+            }
+        }
+        if fds[1].revents != 0 {
+            // stdin is read directly, as the buffer of io::stdin() would keep what it
+            // read ahead from the next reader
+            // SAFETY: buf is valid for writes of its length
+            let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+            match n {
+                0 => stdin_open = false,
+                n if n > 0 => input.extend_from_slice(&buf[..n as usize]),
+                _ => stdin_open = is_retry(&io::Error::last_os_error()),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_retry(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock
+    )
+}
+
+/// Opens a pseudo-terminal the size of morfo's terminal and returns its master and
+/// slave ends.
+fn open() -> io::Result<(File, OwnedFd)> {
+    let (mut master, mut slave) = (0, 0);
+    // SAFETY: winsize is plain data that TIOCGWINSZ fills in
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    // SAFETY: TIOCGWINSZ writes a winsize, and size stays all zeros if it fails
+    let sized = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    let size: *mut libc::winsize = if sized { &mut size } else { ptr::null_mut() };
+
+    // SAFETY: openpty writes the two descriptors, which are owned from here on
+    unsafe {
+        if libc::openpty(
+            &mut master,
+            &mut slave,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            size,
+        ) == -1
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok((File::from_raw_fd(master), OwnedFd::from_raw_fd(slave)))
+    }
+}
+
+/// Puts morfo's terminal into raw mode until dropped, if stdin is a terminal.
+struct RawMode {
+    original: Option<libc::termios>,
+}
+
+impl RawMode {
+    fn enable() -> RawMode {
+        // SAFETY: termios is plain data that tcgetattr fills in
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        // SAFETY: the termios passed are valid for the duration of the calls
+        unsafe {
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return RawMode { original: None };
+            }
+            let mut raw = original;
+            libc::cfmakeraw(&mut raw);
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw);
+        }
+        RawMode {
+            original: Some(original),
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if let Some(original) = &self.original {
+            // SAFETY: original is a valid termios read by tcgetattr
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pty_program_sees_a_terminal() {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "test -t 0 && test -t 1 && test -t 2 && printf tty"]);
+        let mut out = Vec::new();
+
+        let status = run(&mut cmd, &mut out).unwrap();
+        assert!(status.success());
+        assert_eq!(out, b"tty");
+    }
+}
@@ -0,0 +1,322 @@
+//! Remote runs.
+//!
+//! `morfo run --on user@host main.c` builds and runs the program on another machine over
+//! SSH, for programs that need hardware that is not at hand, such as a GPU box or an ARM
+//! board. The sources and headers of the dependency tree of the main file are copied
+//! with rsync to `~/.cache/morfo/remote/` on the host, only those that changed since the
+//! last run, and compiled there in one invocation with the compiler of the host, under
+//! the name of the configured one, and the flags of the config. The program then runs
+//! on the host in the copy of the project, with its output streamed back.
+//!
+//! Every file of the tree must be under the directory of the main file, which is copied
+//! as a whole. Both rsync and ssh must be installed locally, and rsync on the host too.
+
+use std::{
+    ffi::OsString,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    compile_flags,
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    libraries, link_flags, lock, prepare, project_dir,
+    toolchain::CompilerFamily,
+    utils,
+};
+
+/// The exit status of `ssh` when the connection fails.
+const SSH_FAILURE: i32 = 255;
+
+/// The commands reaching the host.
+struct Tools {
+    ssh: Vec<String>,
+    rsync: String,
+}
+
+impl Default for Tools {
+    fn default() -> Self {
+        Tools {
+            ssh: vec!["ssh".to_owned()],
+            rsync: "rsync".to_owned(),
+        }
+    }
+}
+
+/// Copies the tree of `main_file` to `host`, then builds it and runs it there with
+/// `prog_args`, and returns how the program exited, or `None` in a dry run, which prints
+/// the commands instead.
+///
+/// # Errors
+///
+/// If a file of the tree is outside the directory of the main file, the host cannot be
+/// reached or the program does not compile there.
+pub fn run(
+    host: &str,
+    main_file: &Path,
+    config: Config,
+    prog_args: &[String],
+) -> MorfoResult<Option<ExitStatus>> {
+    run_with(&Tools::default(), host, main_file, config, prog_args)
+}
+
+fn run_with(
+    tools: &Tools,
+    host: &str,
+    main_file: &Path,
+    config: Config,
+    prog_args: &[String],
+) -> MorfoResult<Option<ExitStatus>> {
+    let (act, config) = {
+        let _lock = lock::lock(&config)?;
+        prepare(main_file, config)?
+    };
+    if config.get_family() == CompilerFamily::Msvc {
+        return Err(MorfoError::Unsupported("--on with MSVC".to_owned()));
+    }
+    let root = fs::canonicalize(project_dir(main_file))?;
+    let relative = |file: &Path| -> MorfoResult<PathBuf> {
+        let file = fs::canonicalize(file)?;
+        file.strip_prefix(&root)
+            .map(Path::to_path_buf)
+            .map_err(|_| {
+                MorfoError::Unsupported(format!(
+                    "--on with {}, outside the directory of the main file",
+                    file.display()
+                ))
+            })
+    };
+    let files = act
+        .files()
+        .iter()
+        .map(|file| relative(file))
+        .collect::<MorfoResult<Vec<_>>>()?;
+    let sources = act
+        .sources()
+        .iter()
+        .map(|source| relative(source))
+        .collect::<MorfoResult<Vec<_>>>()?;
+
+    let dir = remote_dir(&root);
+    let executable = format!(".morfo/{}", utils::file_name(&act.name));
+    let mut sync_cmd = Command::new(&tools.rsync);
+    sync_cmd
+        .args(["--archive", "--files-from=-"])
+        .arg(format!("--rsync-path=mkdir -p {} && rsync", dir))
+        .arg("--rsh")
+        .arg(tools.ssh.join(" "))
+        .arg(".")
+        .arg(format!("{}:{}", host, dir))
+        .current_dir(&root);
+    let build_cmd = ssh_command(
+        tools,
+        host,
+        false,
+        &format!(
+            "cd {} && mkdir -p .morfo && {}",
+            dir,
+            compile_command(&config, &root, &sources, &executable)?
+        ),
+    );
+    let mut program = vec![format!("./{}", executable)];
+    program.extend(prog_args.iter().cloned());
+    let run_cmd = ssh_command(
+        tools,
+        host,
+        config.get_tty(),
+        &format!("cd {} && exec {}", dir, quote_all(&program)),
+    );
+
+    if config.get_dry_run() {
+        for cmd in [&sync_cmd, &build_cmd, &run_cmd] {
+            println!("{}", utils::format_command(cmd));
+        }
+        return Ok(None);
+    }
+
+    let file_list: Vec<String> = files
+        .iter()
+        .map(|file| file.to_string_lossy().into_owned())
+        .collect();
+    let mut sync = sync_cmd
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => MorfoError::MissingTool(tools.rsync.clone()),
+            _ => e.into(),
+        })?;
+    if let Some(mut stdin) = sync.stdin.take() {
+        stdin.write_all((file_list.join("\n") + "\n").as_bytes())?;
+    }
+    let status = sync.wait()?;
+    if !status.success() {
+        return Err(MorfoError::CommandFailure(
+            tools.rsync.clone(),
+            status.code(),
+        ));
+    }
+
+    let status = utils::run_tool(&mut { build_cmd })?.status;
+    match status.code() {
+        Some(0) => (),
+        Some(SSH_FAILURE) => {
+            return Err(MorfoError::CommandFailure(
+                tools.ssh.join(" "),
+                status.code(),
+            ))
+        }
+        code => return Err(MorfoError::CompilationFailure(code)),
+    }
+    let status = utils::run_tool(&mut { run_cmd })?.status;
+    Ok(Some(status))
+}
+
+/// Returns the directory the project at `root` is copied to on the host, relative to
+/// the home directory there, so that two projects of the same name do not share one.
+fn remote_dir(root: &Path) -> String {
+    let name = root
+        .file_name()
+        .map_or("project".into(), |name| name.to_string_lossy());
+    let hash = format!("{:x}", Sha256::digest(root.to_string_lossy().as_bytes()));
+    utils::shell_quote(&format!(".cache/morfo/remote/{}-{}", name, &hash[..12]))
+}
+
+/// Returns the shell command compiling and linking `sources` into `executable` on the
+/// host, with the paths under `root` made relative to it.
+fn compile_command(
+    config: &Config,
+    root: &Path,
+    sources: &[PathBuf],
+    executable: &str,
+) -> MorfoResult<String> {
+    let mut words: Vec<OsString> = vec![config.get_cc().into()];
+    words.extend(config.get_cflags().into_iter().map(OsString::from));
+    words.extend(compile_flags(config).into_iter().map(OsString::from));
+    words.extend(sources.iter().map(OsString::from));
+    words.extend(link_flags(config).into_iter().map(OsString::from));
+    words.extend(libraries::link_args(config)?);
+    words.extend(config.get_family().link_output_args(Path::new(executable)));
+    // the include directories of the project are found under the copy on the host
+    let prefix = format!("{}/", root.display());
+    let words: Vec<String> = words
+        .iter()
+        .map(|word| word.to_string_lossy().replace(&prefix, ""))
+        .collect();
+    Ok(quote_all(&words))
+}
+
+/// Returns the command running `script` on `host`, in a terminal there if `tty`.
+fn ssh_command(tools: &Tools, host: &str, tty: bool, script: &str) -> Command {
+    let mut cmd = Command::new(&tools.ssh[0]);
+    cmd.args(&tools.ssh[1..]);
+    if tty {
+        cmd.arg("-t");
+    }
+    cmd.arg(host).arg(script);
+    cmd.stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit());
+    cmd
+}
+
+fn quote_all(words: &[String]) -> String {
+    words
+        .iter()
+        .map(|word| utils::shell_quote(word))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn script(path: &Path, body: &str) {
+        fs::write(path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn remote_run() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let project = dir.join("project");
+        let home = dir.join("home");
+        fs::create_dir_all(project.join("include")).unwrap();
+        fs::create_dir_all(&home).unwrap();
+        fs::write(
+            project.join("main.c"),
+            "#include <stdio.h>\n#include \"answer.h\"\n#include \"util.h\"\n\
+             int main(int argc, char **argv) { printf(\"%s\\n\", argv[1]); return twice(HALF); }\n",
+        )
+        .unwrap();
+        fs::write(project.join("include/answer.h"), "#define HALF 21\n").unwrap();
+        fs::write(project.join("util.h"), "int twice(int x);\n").unwrap();
+        fs::write(
+            project.join("util.c"),
+            "int twice(int x) { return 2 * x; }\n",
+        )
+        .unwrap();
+        fs::write(project.join("unused.c"), "int unused;\n").unwrap();
+
+        // stand-ins for ssh, running the command in `home`, and rsync, copying the
+        // listed files from the working directory to `home`
+        let ssh = dir.join("ssh");
+        script(
+            &ssh,
+            &format!("shift\ncd {}\nexec sh -c \"$1\"", home.display()),
+        );
+        let rsync = dir.join("rsync");
+        script(
+            &rsync,
+            &format!(
+                "for arg; do dest=$arg; done\n\
+                 dest={}/${{dest#*:}}\n\
+                 while read -r file; do\n\
+                 mkdir -p \"$dest/$(dirname \"$file\")\" && cp \"$file\" \"$dest/$file\"\n\
+                 done",
+                home.display()
+            ),
+        );
+        let tools = Tools {
+            ssh: vec![ssh.to_string_lossy().into_owned()],
+            rsync: rsync.to_string_lossy().into_owned(),
+        };
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            builddir = "{}/.out"
+            includes = ["{}"]"#,
+            dir.display(),
+            project.join("include").display()
+        ))
+        .unwrap();
+        let status = run_with(
+            &tools,
+            "board",
+            &project.join("main.c"),
+            config,
+            &["hello".to_owned()],
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(status.code(), Some(42));
+
+        let remote = fs::read_dir(home.join(".cache/morfo/remote"))
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        assert!(remote.join("include/answer.h").exists());
+        assert!(remote.join(".morfo/main").exists());
+        assert!(!remote.join("unused.c").exists());
+    }
+}
@@ -0,0 +1,181 @@
+//! Reproducible builds.
+//!
+//! With `--reproducible` (or `reproducible = true`), building the same sources twice
+//! produces the same bytes:
+//!
+//! * `SOURCE_DATE_EPOCH` is passed to the compiler, so `__DATE__` and `__TIME__` (and
+//!   `MORFO_BUILD_TIME`) are fixed. If it is not set, the time of the last git commit is
+//!   used, or the Unix epoch outside git.
+//! * The working directory is mapped to `.` in debug information and `__FILE__`.
+//! * Randomly seeded names are seeded from the object file name, and the build ID is a
+//!   hash of the contents.
+//!
+//! `morfo verify-repro` builds the program twice and compares the executables.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    compile_act, config::Config, error::MorfoResult, layout, lock, prepare,
+    toolchain::CompilerFamily,
+};
+
+/// Returns `SOURCE_DATE_EPOCH` from the environment of the build, if it is set to a valid
+/// time.
+pub fn env_source_date_epoch(config: &Config) -> Option<u64> {
+    config.get_env_var("SOURCE_DATE_EPOCH")?.trim().parse().ok()
+}
+
+/// Returns the time of the build in seconds since the Unix epoch: the fixed time of a
+/// reproducible build, then `SOURCE_DATE_EPOCH`, then the current time.
+pub(crate) fn build_time(config: &Config) -> u64 {
+    config
+        .get_source_date_epoch()
+        .or_else(|| env_source_date_epoch(config))
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default()
+        })
+}
+
+/// Returns the config with the build time fixed for a reproducible build of the project in `project_dir`.
+pub(crate) fn apply(config: Config, project_dir: &Path) -> Config {
+    let epoch = env_source_date_epoch(&config)
+        .or_else(|| last_commit_time(project_dir))
+        .unwrap_or(0);
+    config.with_source_date_epoch(epoch)
+}
+
+fn last_commit_time(dir: &Path) -> Option<u64> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["log", "-1", "--format=%ct"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+/// Returns the flags that remove nondeterminism from every compilation.
+pub(crate) fn compile_flags(family: CompilerFamily) -> Vec<String> {
+    match family {
+        CompilerFamily::Gcc | CompilerFamily::Clang => env::current_dir()
+            .map(|dir| vec![format!("-ffile-prefix-map={}=.", dir.display())])
+            .unwrap_or_default(),
+        CompilerFamily::Msvc => vec!["/Brepro".to_owned()],
+    }
+}
+
+/// Returns the flags that remove nondeterminism from compiling `object`.
+pub(crate) fn object_flags(family: CompilerFamily, object: &Path) -> Vec<String> {
+    match family {
+        CompilerFamily::Gcc => vec![format!(
+            "-frandom-seed={}",
+            object.file_name().unwrap_or_default().to_string_lossy()
+        )],
+        CompilerFamily::Clang | CompilerFamily::Msvc => Vec::new(),
+    }
+}
+
+/// Returns the flags that remove nondeterminism from linking.
+pub(crate) fn link_flags(family: CompilerFamily) -> Vec<String> {
+    match family {
+        CompilerFamily::Gcc | CompilerFamily::Clang if !cfg!(target_os = "macos") => {
+            vec!["-Wl,--build-id=sha1".to_owned()]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// The outcome of `morfo verify-repro`.
+#[derive(Debug)]
+pub struct ReproReport {
+    /// The executable of the second build.
+    pub executable: PathBuf,
+    /// The executable of the first build, kept for comparing with a tool such as diffoscope.
+    pub first: PathBuf,
+    /// The offset of the first byte that differs between the builds, if they differ.
+    pub first_difference: Option<usize>,
+}
+
+impl ReproReport {
+    /// Returns whether both builds produced the same executable.
+    pub fn is_reproducible(&self) -> bool {
+        self.first_difference.is_none()
+    }
+}
+
+/// Builds `main_file` reproducibly twice and compares the executables.
+///
+/// # Errors
+///
+/// If either build fails or the executables cannot be read.
+pub fn verify(main_file: PathBuf, config: Config) -> MorfoResult<ReproReport> {
+    // both builds must compile everything, or there is nothing to compare
+    let config = config.with_reproducible(true).with_rebuild(true);
+    let _lock = lock::lock(&config)?;
+    let (act, config) = prepare(&main_file, config)?;
+    let executable = layout::executable(&act.name, &config);
+
+    compile_act(&act, &config)?;
+    let mut first = executable.as_os_str().to_owned();
+    first.push(".first");
+    let first = PathBuf::from(first);
+    fs::rename(&executable, &first)?;
+
+    // let the clock move on, so that a timestamp leaking into the build shows up
+    thread::sleep(Duration::from_secs(1));
+    compile_act(&act, &config)?;
+    let (a, b) = (fs::read(&first)?, fs::read(&executable)?);
+    let first_difference = a
+        .iter()
+        .zip(&b)
+        .position(|(a, b)| a != b)
+        .or_else(|| (a.len() != b.len()).then(|| a.len().min(b.len())));
+
+    Ok(ReproReport {
+        executable,
+        first,
+        first_difference,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repro_verify() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let main_file = tmp_dir.path().join("main.c");
+        fs::write(
+            &main_file,
+            "#include <stdio.h>\n\
+             int main(void) { printf(\"%s %s %s\\n\", __DATE__, __TIME__, MORFO_BUILD_TIME); return 0; }\n",
+        )
+        .unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            builddir = "{}"
+            cflags = ["-g"]
+            build_info = true"#,
+            tmp_dir.path().join(".out").display()
+        ))
+        .unwrap();
+
+        let report = verify(main_file, config).unwrap();
+        assert!(report.is_reproducible(), "{:?}", report);
+        assert!(report.first.exists());
+    }
+}
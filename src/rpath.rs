@@ -0,0 +1,187 @@
+//! Runtime library search paths and relocatable bundles.
+//!
+//! `rpath` entries are embedded into the executable so the dynamic loader finds shared
+//! libraries that are not installed system-wide. `$ORIGIN` stands for the directory of
+//! the executable and is translated to `@loader_path` on macOS. Windows has no rpath;
+//! its loader always searches the executable's directory.
+//!
+//! With `bundle_libs = true`, the shared libraries the executable needs that are not part
+//! of the system are copied next to it, so the build directory can be moved or archived
+//! as a whole. Pair it with `rpath = ["$ORIGIN"]`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{config::Config, error::MorfoResult, toolchain::CompilerFamily, utils};
+
+/// Directories whose libraries are provided by the system and never bundled.
+const SYSTEM_DIRS: [&str; 5] = ["/lib", "/lib64", "/usr/lib", "/usr/lib64", "/System/"];
+
+/// Returns the linker flags embedding the configured `rpath` entries.
+pub(crate) fn link_flags(config: &Config) -> Vec<String> {
+    if config.get_family() == CompilerFamily::Msvc {
+        return Vec::new();
+    }
+    config
+        .get_rpath()
+        .iter()
+        .map(|path| {
+            let path = if cfg!(target_os = "macos") {
+                path.replace("$ORIGIN", "@loader_path")
+            } else {
+                path.clone()
+            };
+            format!("-Wl,-rpath,{}", path)
+        })
+        .collect()
+}
+
+/// Copies the non-system shared libraries `executable` needs into its directory and
+/// returns the copies.
+///
+/// # Errors
+///
+/// If the libraries cannot be listed or copied.
+pub(crate) fn bundle(executable: &Path) -> MorfoResult<Vec<PathBuf>> {
+    let libraries = if cfg!(target_os = "macos") {
+        let mut otool_cmd = Command::new("otool");
+        otool_cmd.arg("-L").arg(executable);
+        parse_otool(&String::from_utf8_lossy(
+            &utils::run_tool(&mut otool_cmd)?.stdout,
+        ))
+    } else if cfg!(windows) {
+        // the DLLs the program needs are not listed without extra tools
+        return Ok(Vec::new());
+    } else {
+        let mut ldd_cmd = Command::new("ldd");
+        ldd_cmd.arg(executable);
+        parse_ldd(&String::from_utf8_lossy(
+            &utils::run_tool(&mut ldd_cmd)?.stdout,
+        ))
+    };
+
+    let dir = executable.parent().unwrap_or(Path::new("."));
+    let mut bundled = Vec::new();
+    for library in libraries {
+        if is_system(&library) {
+            continue;
+        }
+        let Some(name) = library.file_name() else {
+            continue;
+        };
+        let copy = dir.join(name);
+        if copy != library {
+            fs::copy(&library, &copy)?;
+        }
+        bundled.push(copy);
+    }
+    Ok(bundled)
+}
+
+fn is_system(library: &Path) -> bool {
+    let library = library.to_string_lossy();
+    SYSTEM_DIRS.iter().any(|dir| {
+        library.starts_with(dir) && (dir.ends_with('/') || library[dir.len()..].starts_with('/'))
+    })
+}
+
+/// Parses the resolved libraries out of `ldd`'s output, e.g.
+/// `libfoo.so.1 => /opt/foo/lib/libfoo.so.1 (0x00007f...)`.
+fn parse_ldd(output: &str) -> Vec<PathBuf> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (_, resolved) = line.split_once("=>")?;
+            let path = resolved.split_whitespace().next()?;
+            path.starts_with('/').then(|| PathBuf::from(path))
+        })
+        .collect()
+}
+
+/// Parses the libraries out of `otool -L`'s output. The first line names the executable.
+fn parse_otool(output: &str) -> Vec<PathBuf> {
+    output
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let path = line.trim().split(" (").next()?;
+            path.starts_with('/').then(|| PathBuf::from(path))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rpath_link_flags() {
+        let config: Config = toml::from_str(
+            r#"
+            cc = "gcc"
+            rpath = ["$ORIGIN/../lib", "/opt/foo/lib"]"#,
+        )
+        .unwrap();
+        let origin = if cfg!(target_os = "macos") {
+            "@loader_path"
+        } else {
+            "$ORIGIN"
+        };
+        assert_eq!(
+            link_flags(&config),
+            vec![
+                format!("-Wl,-rpath,{}/../lib", origin),
+                "-Wl,-rpath,/opt/foo/lib".to_owned()
+            ]
+        );
+
+        let msvc: Config = toml::from_str(
+            r#"
+            cc = "cl"
+            rpath = ["$ORIGIN"]"#,
+        )
+        .unwrap();
+        assert!(link_flags(&msvc).is_empty());
+    }
+
+    #[test]
+    fn rpath_parse_ldd() {
+        let output = "\
+\tlinux-vdso.so.1 (0x00007ffd4b7e2000)
+\tlibfoo.so.1 => /opt/foo/lib/libfoo.so.1 (0x00007f0c3c400000)
+\tlibbar.so => not found
+\tlibc.so.6 => /lib/x86_64-linux-gnu/libc.so.6 (0x00007f0c3c000000)
+\t/lib64/ld-linux-x86-64.so.2 (0x00007f0c3c6f0000)
+";
+        let libraries = parse_ldd(output);
+        assert_eq!(
+            libraries,
+            vec![
+                PathBuf::from("/opt/foo/lib/libfoo.so.1"),
+                PathBuf::from("/lib/x86_64-linux-gnu/libc.so.6")
+            ]
+        );
+        assert!(!is_system(&libraries[0]));
+        assert!(is_system(&libraries[1]));
+        assert!(!is_system(Path::new("/library/libfoo.so")));
+    }
+
+    #[test]
+    fn rpath_parse_otool() {
+        let output = "\
+.out/main:
+\t/usr/local/opt/foo/lib/libfoo.1.dylib (compatibility version 1.0.0, current version 1.2.0)
+\t/usr/lib/libSystem.B.dylib (compatibility version 1.0.0, current version 1319.0.0)
+";
+        assert_eq!(
+            parse_otool(output),
+            vec![
+                PathBuf::from("/usr/local/opt/foo/lib/libfoo.1.dylib"),
+                PathBuf::from("/usr/lib/libSystem.B.dylib")
+            ]
+        );
+    }
+}
@@ -0,0 +1,335 @@
+//! Rust crates linked into C programs.
+//!
+//! A crate declared under `[dependencies.rust]` is built with cargo before every build,
+//! and linked into the program like a library of the `[libraries]` section, with the
+//! system libraries the Rust standard library needs. Its `Cargo.toml` must have
+//! `crate-type = ["staticlib"]`. cargo finds the crate up to date when nothing changed,
+//! and the program is only linked again when the archive it built did change.
+//!
+//! The C side of the crate is found in its `include` directory or, with `cbindgen = true`,
+//! in a header generated by [cbindgen] from the crate and named after it, such as
+//! `mycrate.h` in `<builddir>/rust/`. Either is put on the include path.
+//!
+//! The `ar` and `ranlib` of the config are passed to cargo as `AR` and `RANLIB`, which
+//! the build scripts compiling C code with the `cc` crate archive it with.
+//!
+//! In a dry run, the commands building the crates are printed instead.
+//!
+//! [cbindgen]: https://github.com/mozilla/cbindgen
+
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    layout,
+    libraries::Library,
+    utils,
+};
+
+/// A Rust crate, declared in the `[dependencies.rust]` section of the config.
+///
+/// `path` is the directory of the crate. It is built in release mode with `release`,
+/// with the cargo `features`, and its header is generated with `cbindgen` or found in the
+/// `include` directory.
+///
+/// # Examples
+///
+/// ```toml
+/// [dependencies.rust.mycrate]
+/// path = "rust/mycrate"
+/// release = true
+/// features = ["simd"]
+/// cbindgen = true
+/// ```
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+pub struct RustCrate {
+    path: String,
+    release: Option<bool>,
+    features: Option<Vec<String>>,
+    cbindgen: Option<bool>,
+    include: Option<String>,
+}
+
+impl RustCrate {
+    /// Returns the directory of the crate.
+    pub fn get_path(&self) -> &Path {
+        Path::new(&self.path)
+    }
+
+    /// Returns whether the crate is built in release mode. Defaults to false.
+    pub fn get_release(&self) -> bool {
+        self.release.unwrap_or(false)
+    }
+
+    /// Returns the cargo features the crate is built with.
+    pub fn get_features(&self) -> Vec<String> {
+        self.features.clone().unwrap_or_default()
+    }
+
+    /// Returns whether the header of the crate is generated with cbindgen.
+    /// Defaults to false.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::config::Config;
+    ///
+    /// let config: Config = toml::from_str(
+    ///     r#"
+    ///     [dependencies.rust.mycrate]
+    ///     path = "rust/mycrate"
+    ///     cbindgen = true"#,
+    /// )
+    /// .unwrap();
+    /// let mycrate = &config.get_rust_crates()["mycrate"];
+    /// assert!(mycrate.get_cbindgen());
+    /// assert!(!mycrate.get_release());
+    /// ```
+    pub fn get_cbindgen(&self) -> bool {
+        self.cbindgen.unwrap_or(false)
+    }
+
+    /// Returns the directory holding the headers of the crate, if set.
+    pub fn get_include(&self) -> Option<&str> {
+        self.include.as_deref()
+    }
+
+    /// Returns the cargo command building the crate, with the archiver of `config` for
+    /// the build scripts that compile C code.
+    fn cargo_command(&self, config: &Config) -> Command {
+        let mut cargo = Command::new(std::env::var_os("CARGO").unwrap_or("cargo".into()));
+        if let Some(ar) = config.get_ar() {
+            cargo.env("AR", ar);
+        }
+        if let Some(ranlib) = config.get_ranlib() {
+            cargo.env("RANLIB", ranlib);
+        }
+        cargo
+            .args(["rustc", "--lib", "--message-format=json", "--manifest-path"])
+            .arg(self.get_path().join("Cargo.toml"));
+        if self.get_release() {
+            cargo.arg("--release");
+        }
+        let features = self.get_features();
+        if !features.is_empty() {
+            cargo.args(["--features", &features.join(",")]);
+        }
+        // the system libraries of the standard library are printed as a note
+        cargo.args(["--", "--print=native-static-libs"]);
+        cargo
+    }
+}
+
+/// What cargo reported building a crate.
+#[derive(Debug, Default)]
+struct Built {
+    archive: Option<PathBuf>,
+    native_libs: Vec<String>,
+}
+
+/// Builds the Rust crates of `config`, and returns the config linking them and including
+/// their headers.
+///
+/// # Errors
+///
+/// If cargo or cbindgen cannot be run or fail, or a crate builds no static library.
+pub(crate) fn apply(config: Config) -> MorfoResult<Config> {
+    let mut config = config;
+    for (name, rust_crate) in config.get_rust_crates() {
+        if let Some(include) = rust_crate.get_include() {
+            config = config.with_include(include);
+        }
+        if rust_crate.get_cbindgen() {
+            let dir = layout::rust_dir(&config);
+            config = config.with_include(&dir.to_string_lossy());
+        }
+        if config.get_dry_run() {
+            println!(
+                "{}",
+                utils::format_command(&rust_crate.cargo_command(&config))
+            );
+            continue;
+        }
+
+        let built = build(&name, &rust_crate, &config)?;
+        let archive = built.archive.ok_or_else(|| {
+            MorfoError::InvlidConfig(format!(
+                "crate `{}` builds no static library; set `crate-type = [\"staticlib\"]`",
+                name
+            ))
+        })?;
+        let libraries = config.get_libraries();
+        for native in &built.native_libs {
+            if !libraries.contains_key(native) {
+                config = config.with_library(native, Library::default());
+            }
+        }
+        config = config.with_library(&name, Library::archive(&archive, built.native_libs));
+
+        if rust_crate.get_cbindgen() {
+            let header = layout::rust_dir(&config).join(format!("{}.h", name));
+            generate_header(&rust_crate, &header)?;
+        }
+    }
+    Ok(config)
+}
+
+/// Builds `rust_crate` with cargo, printing the diagnostics of rustc.
+fn build(name: &str, rust_crate: &RustCrate, config: &Config) -> MorfoResult<Built> {
+    let mut cargo = rust_crate.cargo_command(config);
+    let command = utils::format_command(&cargo);
+    let output = cargo.output().map_err(|e| match e.kind() {
+        ErrorKind::NotFound => MorfoError::MissingTool("cargo".to_owned()),
+        _ => e.into(),
+    })?;
+
+    let mut built = Built::default();
+    for message in String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+    {
+        match message["reason"].as_str() {
+            Some("compiler-message") => {
+                let text = message["message"]["message"].as_str().unwrap_or_default();
+                match text.strip_prefix("native-static-libs:") {
+                    Some(libs) => built.native_libs = native_libs(libs),
+                    // the notes are about linking the archive, which morfo does
+                    None if message["message"]["level"] == "note" => (),
+                    None => {
+                        if let Some(rendered) = message["message"]["rendered"].as_str() {
+                            eprint!("{}", rendered);
+                        }
+                    }
+                }
+            }
+            Some("compiler-artifact") => {
+                let is_staticlib = message["target"]["crate_types"]
+                    .as_array()
+                    .is_some_and(|types| types.iter().any(|t| t == "staticlib"));
+                if is_staticlib {
+                    built.archive = message["filenames"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|file| file.as_str())
+                        .find(|file| file.ends_with(".a") || file.ends_with(".lib"))
+                        .map(PathBuf::from);
+                }
+            }
+            _ => (),
+        }
+    }
+    if !output.status.success() {
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        return Err(MorfoError::CommandFailure(
+            format!("{} (crate `{}`)", command, name),
+            output.status.code(),
+        ));
+    }
+    Ok(built)
+}
+
+/// Returns the names of the libraries of the `native-static-libs` note of rustc, such as
+/// `-lgcc_s -lc` or `kernel32.lib ntdll.lib`.
+fn native_libs(libs: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for lib in libs.split_whitespace() {
+        let name = lib
+            .strip_prefix("-l")
+            .or_else(|| lib.strip_suffix(".lib"))
+            .map(str::to_owned);
+        if let Some(name) = name.filter(|name| !names.contains(name)) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Generates the header of `rust_crate` with cbindgen at `header`, which is only written
+/// when it changes so that the sources including it are not rebuilt every time.
+fn generate_header(rust_crate: &RustCrate, header: &Path) -> MorfoResult<()> {
+    let mut cbindgen = Command::new("cbindgen");
+    cbindgen.args(["--lang", "c"]).arg(rust_crate.get_path());
+    let output = cbindgen.output().map_err(|e| match e.kind() {
+        ErrorKind::NotFound => MorfoError::MissingTool("cbindgen".to_owned()),
+        _ => e.into(),
+    })?;
+    if !output.status.success() {
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        return Err(MorfoError::CommandFailure(
+            utils::format_command(&cbindgen),
+            output.status.code(),
+        ));
+    }
+    if fs::read(header).is_ok_and(|old| old == output.stdout) {
+        return Ok(());
+    }
+    if let Some(dir) = header.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(header, output.stdout)?;
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn link_rust_staticlib() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let crate_dir = dir.join("answer");
+        fs::create_dir_all(crate_dir.join("src")).unwrap();
+        fs::create_dir_all(crate_dir.join("include")).unwrap();
+        fs::write(
+            crate_dir.join("Cargo.toml"),
+            "[package]\nname = \"answer\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+             [lib]\ncrate-type = [\"staticlib\"]\n\n[workspace]\n",
+        )
+        .unwrap();
+        fs::write(
+            crate_dir.join("src/lib.rs"),
+            "#[no_mangle]\npub extern \"C\" fn answer() -> i32 {\n    \
+             std::env::args().count() as i32 + 40\n}\n",
+        )
+        .unwrap();
+        fs::write(crate_dir.join("include/answer.h"), "int answer(void);\n").unwrap();
+        let main_file = dir.join("main.c");
+        fs::write(
+            &main_file,
+            "#include \"answer.h\"\nint main(void) { return answer(); }\n",
+        )
+        .unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            builddir = "{out}"
+
+            [dependencies.rust.answer]
+            path = "{path}"
+            include = "{path}/include""#,
+            out = dir.join(".out").display(),
+            path = crate_dir.display(),
+        ))
+        .unwrap();
+
+        let artifact = crate::compile(&main_file, &config).unwrap();
+        let outcome = crate::run(&artifact, crate::RunOptions::new(), &mut Vec::new()).unwrap();
+        assert_eq!(
+            outcome.exit_status.and_then(|status| status.code()),
+            Some(41)
+        );
+
+        assert_eq!(
+            native_libs(" -lgcc_s -lutil -lc -lgcc_s"),
+            ["gcc_s", "util", "c"]
+        );
+    }
+}
@@ -0,0 +1,156 @@
+//! Diagnostics as SARIF, for code scanning dashboards.
+//!
+//! `morfo build main.c --message-format sarif > morfo.sarif` writes the diagnostics of
+//! the compiler as a SARIF 2.1.0 log on stdout once the build is done, failed or not,
+//! and `morfo lint main.c --message-format sarif` those of the analyzers, so that GitHub
+//! code scanning and other dashboards can ingest them. The diagnostics are still printed
+//! on stderr as the compiler prints them.
+//!
+//! Every result is located in its file, relative to the working directory when it is
+//! under it, as the dashboards expect paths relative to the root of the repository, and
+//! the warning options and checks that produced the results are listed as the rules.
+
+use std::{
+    collections::BTreeSet,
+    env,
+    path::{Path, PathBuf},
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    diagnostic::Diagnostic,
+    error::{MorfoError, MorfoResult},
+};
+
+/// The schema of the logs.
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Returns `diagnostics` as a SARIF log of a run of morfo.
+///
+/// # Errors
+///
+/// If the log cannot be serialized.
+///
+/// # Examples
+///
+/// ```
+/// use morfo::{diagnostic::parse, sarif::sarif};
+///
+/// let diagnostics = parse("main.c:3:5: warning: unused variable 'x' [-Wunused-variable]");
+/// let log = sarif(&diagnostics).unwrap();
+/// assert!(log.contains("\"ruleId\": \"-Wunused-variable\""));
+/// ```
+pub fn sarif(diagnostics: &[Diagnostic]) -> MorfoResult<String> {
+    let rules: BTreeSet<&str> = diagnostics
+        .iter()
+        .filter_map(|diagnostic| diagnostic.check.as_deref())
+        .collect();
+    let cwd = env::current_dir().unwrap_or_default();
+    let results: Vec<Value> = diagnostics
+        .iter()
+        .map(|diagnostic| result(diagnostic, &cwd))
+        .collect();
+
+    let log = json!({
+        "$schema": SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "morfo",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_HOMEPAGE"),
+                    "rules": rules.iter().map(|id| json!({ "id": id })).collect::<Vec<_>>(),
+                },
+            },
+            "results": results,
+        }],
+    });
+    serde_json::to_string_pretty(&log)
+        .map(|json| json + "\n")
+        .map_err(|e| MorfoError::InvlidConfig(e.to_string()))
+}
+
+fn result(diagnostic: &Diagnostic, cwd: &Path) -> Value {
+    let mut region = json!({ "startLine": diagnostic.line.max(1) });
+    if diagnostic.column > 0 {
+        region["startColumn"] = json!(diagnostic.column);
+    }
+    let mut result = json!({
+        "level": diagnostic.severity.to_string(),
+        "message": { "text": diagnostic.message },
+        "locations": [{
+            "physicalLocation": {
+                "artifactLocation": { "uri": uri(&diagnostic.file, cwd) },
+                "region": region,
+            },
+        }],
+    });
+    if let Some(check) = &diagnostic.check {
+        result["ruleId"] = json!(check);
+    }
+    result
+}
+
+/// Returns the URI of `file`, relative to `cwd` if it is under it.
+fn uri(file: &Path, cwd: &Path) -> String {
+    let file: PathBuf = match file.strip_prefix(cwd) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => file.to_path_buf(),
+    };
+    let path = file.to_string_lossy().replace('\\', "/");
+    if file.is_absolute() {
+        format!(
+            "file://{}{}",
+            if path.starts_with('/') { "" } else { "/" },
+            path
+        )
+    } else {
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::parse;
+
+    #[test]
+    fn sarif_log() {
+        let cwd = env::current_dir().unwrap();
+        let output = format!(
+            "{}/src/main.c:4:9: warning: Value stored to 'x' is never read [deadcode]\n\
+             /usr/include/stdio.h:12: note: declared here\n\
+             util.c:2:1: error: expected ';'\n",
+            cwd.display()
+        );
+        let log: Value = serde_json::from_str(&sarif(&parse(&output)).unwrap()).unwrap();
+        let run = &log["runs"][0];
+        assert_eq!(log["version"], "2.1.0");
+        assert_eq!(
+            run["tool"]["driver"]["rules"],
+            json!([{ "id": "deadcode" }])
+        );
+
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0]["ruleId"], "deadcode");
+        assert_eq!(results[0]["level"], "warning");
+        let location = &results[0]["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "src/main.c");
+        assert_eq!(
+            location["region"],
+            json!({ "startLine": 4, "startColumn": 9 })
+        );
+
+        let location = &results[1]["locations"][0]["physicalLocation"];
+        assert_eq!(
+            location["artifactLocation"]["uri"],
+            "file:///usr/include/stdio.h"
+        );
+        assert_eq!(location["region"], json!({ "startLine": 12 }));
+        assert_eq!(results[2]["level"], "error");
+        assert!(results[2].get("ruleId").is_none());
+    }
+}
@@ -0,0 +1,429 @@
+//! Software bills of materials.
+//!
+//! `morfo sbom` lists the third-party code that goes into the program: every library in
+//! the `[libraries]` section with its version, license, dependencies and the hashes of
+//! the archive and vendored sources it is built from. The document is written as
+//! CycloneDX 1.5 or SPDX 2.3 JSON.
+
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use globset::GlobBuilder;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    repro, utils,
+};
+
+/// The format of a bill of materials.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    CycloneDx,
+    Spdx,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cyclonedx" => Ok(Format::CycloneDx),
+            "spdx" => Ok(Format::Spdx),
+            _ => Err(format!(
+                "unknown format `{}`, expected `cyclonedx` or `spdx`",
+                s
+            )),
+        }
+    }
+}
+
+/// A third-party library that goes into the program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Component {
+    pub name: String,
+    pub version: Option<String>,
+    /// The SPDX license expression.
+    pub license: Option<String>,
+    /// The SHA-256 of the file the library is linked from, unless it is found by name.
+    pub hash: Option<String>,
+    /// The vendored sources of the library and their SHA-256.
+    pub files: Vec<(PathBuf, String)>,
+    /// The names of the libraries it depends on.
+    pub deps: Vec<String>,
+}
+
+/// Returns the libraries of the project in `project_dir` as components.
+///
+/// # Errors
+///
+/// If a library file cannot be read, or a source pattern is invalid or matches no files.
+pub fn components(config: &Config, project_dir: &Path) -> MorfoResult<Vec<Component>> {
+    let files = utils::project_files(project_dir, &config.get_build_dir());
+    let mut components = Vec::new();
+    for (name, library) in config.get_libraries() {
+        let hash = match library.get_path() {
+            Some(path) => Some(
+                utils::hash_file(Path::new(path))
+                    .map_err(|_| MorfoError::FileNotFound(path.into()))?,
+            ),
+            None => None,
+        };
+
+        let mut sources: Vec<PathBuf> = Vec::new();
+        for pattern in library.get_sources() {
+            let matcher = GlobBuilder::new(&pattern)
+                .literal_separator(true)
+                .build()
+                .map_err(|e| MorfoError::InvlidConfig(format!("library sources: {}", e)))?
+                .compile_matcher();
+            let matched: Vec<_> = files
+                .iter()
+                .filter_map(|file| file.strip_prefix(project_dir).ok())
+                .filter(|rel| matcher.is_match(rel))
+                .collect();
+            if matched.is_empty() {
+                return Err(MorfoError::FileNotFound(project_dir.join(pattern)));
+            }
+            for rel in matched {
+                if !sources.iter().any(|source| source == rel) {
+                    sources.push(rel.to_path_buf());
+                }
+            }
+        }
+        sources.sort();
+        let files = sources
+            .into_iter()
+            .map(|rel| Ok((rel.clone(), utils::hash_file(&project_dir.join(rel))?)))
+            .collect::<MorfoResult<_>>()?;
+
+        components.push(Component {
+            version: library.get_version().map(str::to_owned),
+            license: library.get_license().map(str::to_owned),
+            hash,
+            files,
+            deps: library.get_deps(),
+            name,
+        });
+    }
+    Ok(components)
+}
+
+/// Returns the bill of materials of the program built from `main_file` as JSON.
+///
+/// The program is named after the `[project]` section, or the main file.
+///
+/// # Errors
+///
+/// If the components cannot be collected.
+pub fn sbom(main_file: &Path, config: &Config, format: Format) -> MorfoResult<String> {
+    let project_dir = match main_file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let components = components(config, project_dir)?;
+    let name = config.get_project_name().unwrap_or_else(|| {
+        main_file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "main".to_owned())
+    });
+    let version = config.get_project_version();
+    let timestamp = utils::format_utc(repro::build_time(config));
+
+    let document = match format {
+        Format::CycloneDx => cyclonedx(&name, version.as_deref(), &timestamp, &components),
+        Format::Spdx => spdx(&name, version.as_deref(), &timestamp, &components),
+    };
+    serde_json::to_string_pretty(&document)
+        .map(|json| json + "\n")
+        .map_err(|e| MorfoError::InvlidConfig(e.to_string()))
+}
+
+fn cyclonedx(
+    name: &str,
+    version: Option<&str>,
+    timestamp: &str,
+    components: &[Component],
+) -> Value {
+    let mut application = json!({ "type": "application", "bom-ref": name, "name": name });
+    if let Some(version) = version {
+        application["version"] = json!(version);
+    }
+
+    let entries: Vec<_> = components
+        .iter()
+        .map(|component| {
+            let mut entry = json!({
+                "type": "library",
+                "bom-ref": component.name,
+                "name": component.name,
+            });
+            if let Some(version) = &component.version {
+                entry["version"] = json!(version);
+            }
+            if let Some(license) = &component.license {
+                entry["licenses"] = json!([{ "expression": license }]);
+            }
+            if let Some(hash) = &component.hash {
+                entry["hashes"] = json!([{ "alg": "SHA-256", "content": hash }]);
+            }
+            if !component.files.is_empty() {
+                let files: Vec<_> = component
+                    .files
+                    .iter()
+                    .map(|(path, hash)| {
+                        json!({
+                            "type": "file",
+                            "name": path.to_string_lossy(),
+                            "hashes": [{ "alg": "SHA-256", "content": hash }],
+                        })
+                    })
+                    .collect();
+                entry["components"] = json!(files);
+            }
+            entry
+        })
+        .collect();
+
+    let mut dependencies = vec![json!({
+        "ref": name,
+        "dependsOn": components.iter().map(|c| &c.name).collect::<Vec<_>>(),
+    })];
+    dependencies.extend(
+        components
+            .iter()
+            .map(|c| json!({ "ref": c.name, "dependsOn": c.deps })),
+    );
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "timestamp": timestamp,
+            "tools": [{ "name": "morfo", "version": env!("CARGO_PKG_VERSION") }],
+            "component": application,
+        },
+        "components": entries,
+        "dependencies": dependencies,
+    })
+}
+
+fn spdx(name: &str, version: Option<&str>, timestamp: &str, components: &[Component]) -> Value {
+    let application_id = spdx_id("Package", name);
+    let mut application = json!({
+        "name": name,
+        "SPDXID": application_id,
+        "downloadLocation": "NOASSERTION",
+        "filesAnalyzed": false,
+    });
+    if let Some(version) = version {
+        application["versionInfo"] = json!(version);
+    }
+
+    let mut packages = vec![application];
+    let mut files = Vec::new();
+    let mut relationships = vec![relationship(
+        "SPDXRef-DOCUMENT",
+        "DESCRIBES",
+        &application_id,
+    )];
+    for component in components {
+        let id = spdx_id("Package", &component.name);
+        let license = component.license.as_deref().unwrap_or("NOASSERTION");
+        let mut package = json!({
+            "name": component.name,
+            "SPDXID": id,
+            "downloadLocation": "NOASSERTION",
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": license,
+            "filesAnalyzed": false,
+        });
+        if let Some(version) = &component.version {
+            package["versionInfo"] = json!(version);
+        }
+        if let Some(hash) = &component.hash {
+            package["checksums"] = json!([{ "algorithm": "SHA256", "checksumValue": hash }]);
+        }
+        packages.push(package);
+
+        relationships.push(relationship(&application_id, "DEPENDS_ON", &id));
+        for dep in &component.deps {
+            relationships.push(relationship(&id, "DEPENDS_ON", &spdx_id("Package", dep)));
+        }
+        for (path, hash) in &component.files {
+            let file_id = spdx_id("File", &path.to_string_lossy());
+            files.push(json!({
+                "fileName": format!("./{}", path.to_string_lossy()),
+                "SPDXID": file_id,
+                "checksums": [{ "algorithm": "SHA256", "checksumValue": hash }],
+                "licenseConcluded": license,
+            }));
+            relationships.push(relationship(&id, "CONTAINS", &file_id));
+        }
+    }
+
+    // the namespace must be unique to the document, so it is derived from its contents
+    let mut hasher = Sha256::new();
+    hasher.update(json!([packages, files]).to_string());
+    let namespace = format!("https://spdx.org/spdxdocs/{}-{:x}", name, hasher.finalize());
+
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": name,
+        "documentNamespace": namespace,
+        "creationInfo": {
+            "created": timestamp,
+            "creators": [format!("Tool: morfo-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": packages,
+        "files": files,
+        "relationships": relationships,
+    })
+}
+
+/// Returns an SPDX identifier for `name`, which may only contain letters, digits, `.` and `-`.
+fn spdx_id(kind: &str, name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("SPDXRef-{}-{}", kind, name)
+}
+
+fn relationship(from: &str, kind: &str, to: &str) -> Value {
+    json!({
+        "spdxElementId": from,
+        "relationshipType": kind,
+        "relatedSpdxElement": to,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn project() -> (tempfile::TempDir, Config) {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(dir.join("main.c"), "int main(void) { return 0; }\n").unwrap();
+        fs::create_dir_all(dir.join("vendor/parse")).unwrap();
+        fs::write(dir.join("vendor/parse/parse.c"), "int parse(void);\n").unwrap();
+        fs::write(dir.join("vendor/libparse.a"), "!<arch>\n").unwrap();
+        let config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            builddir = "{0}/.out"
+
+            [project]
+            name = "calc"
+            version = "1.2.0"
+
+            [libraries]
+            parse = {{ path = "{0}/vendor/libparse.a", deps = ["m"], version = "2.1.0", license = "MIT", sources = ["vendor/parse/*.c"] }}
+            m = {{}}"#,
+            dir.display()
+        ))
+        .unwrap();
+        (tmp_dir, config)
+    }
+
+    #[test]
+    fn sbom_components() {
+        let (tmp_dir, config) = project();
+        let components = components(&config, tmp_dir.path()).unwrap();
+
+        assert_eq!(components.len(), 2);
+        assert_eq!(components[0].name, "m");
+        assert_eq!(components[0].hash, None);
+        let parse = &components[1];
+        assert_eq!(parse.version.as_deref(), Some("2.1.0"));
+        assert_eq!(parse.license.as_deref(), Some("MIT"));
+        assert_eq!(
+            parse.hash,
+            Some(utils::hash_file(&tmp_dir.path().join("vendor/libparse.a")).unwrap())
+        );
+        assert_eq!(
+            parse.files.iter().map(|(path, _)| path).collect::<Vec<_>>(),
+            vec![Path::new("vendor/parse/parse.c")]
+        );
+        assert_eq!(parse.deps, vec!["m"]);
+    }
+
+    #[test]
+    fn sbom_cyclonedx() {
+        let (tmp_dir, config) = project();
+        let json = sbom(&tmp_dir.path().join("main.c"), &config, Format::CycloneDx).unwrap();
+        let document: Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(document["bomFormat"], "CycloneDX");
+        assert_eq!(document["metadata"]["component"]["name"], "calc");
+        assert_eq!(document["metadata"]["component"]["version"], "1.2.0");
+        assert_eq!(document["components"][1]["name"], "parse");
+        assert_eq!(
+            document["components"][1]["licenses"][0]["expression"],
+            "MIT"
+        );
+        assert_eq!(
+            document["dependencies"][2],
+            json!({ "ref": "parse", "dependsOn": ["m"] })
+        );
+    }
+
+    #[test]
+    fn sbom_spdx() {
+        let (tmp_dir, config) = project();
+        let json = sbom(&tmp_dir.path().join("main.c"), &config, Format::Spdx).unwrap();
+        let document: Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(document["spdxVersion"], "SPDX-2.3");
+        assert_eq!(document["packages"][2]["SPDXID"], "SPDXRef-Package-parse");
+        assert_eq!(document["packages"][2]["licenseDeclared"], "MIT");
+        assert_eq!(
+            document["files"][0]["SPDXID"],
+            "SPDXRef-File-vendor-parse-parse.c"
+        );
+        assert!(document["relationships"]
+            .as_array()
+            .unwrap()
+            .contains(&relationship(
+                "SPDXRef-Package-parse",
+                "CONTAINS",
+                "SPDXRef-File-vendor-parse-parse.c"
+            )));
+    }
+
+    #[test]
+    fn sbom_unmatched_sources() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let config: Config = toml::from_str(
+            r#"
+            cc = "gcc"
+
+            [libraries]
+            parse = { sources = ["third_party/*.c"] }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            components(&config, tmp_dir.path()),
+            Err(MorfoError::FileNotFound(
+                tmp_dir.path().join("third_party/*.c")
+            ))
+        );
+    }
+}
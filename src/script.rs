@@ -0,0 +1,222 @@
+//! Build scripts.
+//!
+//! With `script = "build.rhai"`, morfo runs a [Rhai] script before every build, once the
+//! dependency tree is known, for the logic that does not fit in a config file. The script
+//! can define any of these functions, each of which is given `build`, a map of the
+//! `main_file`, `cc`, compiler `family`, `target` and `profile` of the build, and the `os`
+//! morfo runs on, with `()` for a target or a profile that is not set:
+//!
+//! * `veto(build)` stops the build when it returns a string, the reason why, as when a
+//!   program cannot be built for a target. Anything else lets the build go on.
+//! * `sources(build)` returns a map of file names to C sources, which are written to
+//!   `<builddir>/gen/` and built as translation units of the program. A source is only
+//!   written again when it changes, so that it is not rebuilt every time.
+//! * `flags(file, build)` returns the flags to compile the source `file` with on top of
+//!   the others, as an array or a string split like a shell would, or `()` for none.
+//!   The flags are part of the fingerprint of the object, which is rebuilt when they
+//!   change.
+//!
+//! The script can read the environment with `env("NAME")`, which returns `()` when the
+//! variable is not set.
+//!
+//! ```rhai
+//! fn veto(build) {
+//!     if build.os == "windows" { "needs epoll" }
+//! }
+//!
+//! fn flags(file, build) {
+//!     if file.ends_with("crypto.c") { ["-O3", "-funroll-loops"] }
+//! }
+//! ```
+//!
+//! Scripts need morfo to be built with the `scripting` feature, which is on by default.
+//!
+//! [Rhai]: https://rhai.rs
+
+use crate::{act::Act, config::Config, error::MorfoResult};
+
+/// Runs the build script of `config`, if there is one, over the tree of `act`: adds the
+/// sources it generates to `act` and returns the config with the flags it sets.
+///
+/// # Errors
+///
+/// If the script cannot be read or run, returns something invalid, or vetoes the build.
+#[cfg(feature = "scripting")]
+pub(crate) fn apply(act: &mut Act, config: Config) -> MorfoResult<Config> {
+    use rhai::{Dynamic, Engine, Scope};
+
+    use crate::error::MorfoError;
+
+    let Some(path) = config.get_script() else {
+        return Ok(config);
+    };
+    let failure = |msg: String| MorfoError::ScriptFailure(path.clone(), msg);
+    let mut engine = Engine::new();
+    engine.register_fn("env", |name: &str| {
+        std::env::var(name).map_or(Dynamic::UNIT, Dynamic::from)
+    });
+    let ast = engine
+        .compile_file(path.clone())
+        .map_err(|e| failure(e.to_string()))?;
+    let defines = |name: &str, arity: usize| {
+        ast.iter_functions()
+            .any(|function| function.name == name && function.params.len() == arity)
+    };
+    let build = build_map(&act.name, &config);
+    let call = |name: &str, args: Vec<Dynamic>| -> MorfoResult<Dynamic> {
+        engine
+            .call_fn(&mut Scope::new(), &ast, name, args)
+            .map_err(|e| failure(format!("{}: {}", name, e)))
+    };
+
+    if defines("veto", 1) {
+        let verdict = call("veto", vec![build.clone()])?;
+        if let Ok(reason) = verdict.into_string() {
+            return Err(MorfoError::Vetoed(reason));
+        }
+    }
+
+    if defines("sources", 1) {
+        let sources = call("sources", vec![build.clone()])?
+            .try_cast::<rhai::Map>()
+            .ok_or_else(|| failure("sources must return a map".to_owned()))?;
+        let out_dir = crate::layout::generated_dir(&config);
+        for (name, code) in sources {
+            let code = code
+                .into_string()
+                .map_err(|_| failure(format!("the source of {} is not a string", name)))?;
+            let file_name = std::path::Path::new(name.as_str());
+            if file_name.file_name() != Some(file_name.as_os_str()) {
+                return Err(failure(format!("{} is not a file name", name)));
+            }
+            let source = out_dir.join(file_name);
+            if !config.get_dry_run() {
+                write_if_changed(&source, &code)?;
+            }
+            act.add_generated(&source);
+        }
+    }
+
+    let mut config = config;
+    if defines("flags", 2) {
+        for source in act.sources() {
+            let file = Dynamic::from(source.to_string_lossy().into_owned());
+            let flags = call("flags", vec![file, build.clone()])?;
+            let flags = if flags.is_unit() {
+                continue;
+            } else if flags.is_string() {
+                crate::utils::split_words(&flags.into_string().unwrap_or_default())
+            } else {
+                flags
+                    .into_array()
+                    .map_err(|_| failure("flags must return an array or a string".to_owned()))?
+                    .into_iter()
+                    .map(|flag| flag.into_string())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| failure("flags must be strings".to_owned()))?
+            };
+            config = config.with_source_flags(&source, flags);
+        }
+    }
+    Ok(config)
+}
+
+/// Fails if `config` has a build script, as this morfo was built without scripting.
+#[cfg(not(feature = "scripting"))]
+pub(crate) fn apply(_act: &mut Act, config: Config) -> MorfoResult<Config> {
+    match config.get_script() {
+        Some(_) => Err(crate::error::MorfoError::Unsupported(
+            "build scripts need morfo built with the `scripting` feature".to_owned(),
+        )),
+        None => Ok(config),
+    }
+}
+
+/// Returns what the script is told about the build of `main_file`.
+#[cfg(feature = "scripting")]
+fn build_map(main_file: &std::path::Path, config: &Config) -> rhai::Dynamic {
+    use rhai::Dynamic;
+
+    let optional = |value: Option<String>| value.map_or(Dynamic::UNIT, Dynamic::from);
+    let family = serde_json::to_value(config.get_family())
+        .ok()
+        .and_then(|family| family.as_str().map(str::to_owned));
+    let mut build = rhai::Map::new();
+    build.insert(
+        "main_file".into(),
+        Dynamic::from(main_file.to_string_lossy().into_owned()),
+    );
+    build.insert("cc".into(), Dynamic::from(config.get_cc().clone()));
+    build.insert("family".into(), optional(family));
+    build.insert("target".into(), optional(config.get_target()));
+    build.insert("profile".into(), optional(config.get_profile()));
+    build.insert("os".into(), Dynamic::from(std::env::consts::OS.to_owned()));
+    Dynamic::from_map(build)
+}
+
+/// Writes `code` to `source` unless it already holds it, keeping its modification time.
+#[cfg(feature = "scripting")]
+fn write_if_changed(source: &std::path::Path, code: &str) -> MorfoResult<()> {
+    if std::fs::read_to_string(source).is_ok_and(|old| old == code) {
+        return Ok(());
+    }
+    if let Some(dir) = source.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(source, code)?;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "scripting"))]
+mod tests {
+    use std::fs;
+
+    use crate::{config::Config, error::MorfoError};
+
+    #[test]
+    fn script_hooks() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let main_file = dir.join("main.c");
+        fs::write(
+            &main_file,
+            "int answer(void);\nint main(void) { return answer() + FAST; }\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("build.rhai"),
+            r#"
+            fn veto(build) {
+                if env("MORFO_TEST_VETO") == "yes" && build.family == "gcc" { "vetoed" }
+            }
+
+            fn sources(build) {
+                #{ "answer.c": "int answer(void) { return 40; }\n" }
+            }
+
+            fn flags(file, build) {
+                if file.ends_with("main.c") { "-DFAST=2" }
+            }
+            "#,
+        )
+        .unwrap();
+        let config: Config = toml::from_str(&format!(
+            "cc = \"gcc\"\nbuilddir = \"{}\"\nscript = \"{}\"",
+            dir.join(".out").display(),
+            dir.join("build.rhai").display()
+        ))
+        .unwrap();
+
+        let artifact = crate::compile(&main_file, &config).unwrap();
+        let outcome = crate::run(&artifact, crate::RunOptions::new(), &mut Vec::new()).unwrap();
+        assert_eq!(
+            outcome.exit_status.and_then(|status| status.code()),
+            Some(42)
+        );
+
+        std::env::set_var("MORFO_TEST_VETO", "yes");
+        let vetoed = crate::compile(&main_file, &config);
+        std::env::remove_var("MORFO_TEST_VETO");
+        assert_eq!(vetoed.err(), Some(MorfoError::Vetoed("vetoed".to_owned())));
+    }
+}
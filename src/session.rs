@@ -0,0 +1,334 @@
+//! Recorded sessions of a program.
+//!
+//! `morfo record main.c --session s1` builds and runs the program like `morfo run`, but
+//! passes what is typed to it and what it prints through morfo, recording every chunk
+//! with the time it came at in `sessions/s1.json`. `morfo replay --session s1` builds the
+//! main file of the session again, runs it with the same arguments, feeds it what was
+//! typed at the same times, and compares what it prints and how it exits with the
+//! recording, so that the bug report of an interactive program can be reproduced from the
+//! session attached to it.
+//!
+//! Sessions are plain JSON, with the chunks as text, so bytes that are not UTF-8 are
+//! recorded replaced.
+
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::{ChildStdin, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::Config,
+    error::{MorfoError, MorfoResult},
+    interrupt, snapshot, Artifact,
+};
+
+/// A recorded run of a program.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Session {
+    /// The main file of the program.
+    pub main_file: PathBuf,
+    /// The arguments passed to the program.
+    pub args: Vec<String>,
+    /// What was typed to the program and what it printed, in the order it happened.
+    pub events: Vec<Event>,
+    /// How the program exited, or `None` if it was killed by a signal.
+    pub exit_code: Option<i32>,
+}
+
+/// A chunk typed to the program or printed by it.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Event {
+    /// When the chunk came, in seconds since the program started.
+    pub time: f64,
+    /// Where the chunk went.
+    pub stream: Stream,
+    /// The chunk.
+    pub data: String,
+}
+
+/// A standard stream of the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stream {
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+impl Session {
+    /// Returns everything that went through `stream`.
+    pub fn text(&self, stream: Stream) -> String {
+        self.events
+            .iter()
+            .filter(|event| event.stream == stream)
+            .map(|event| event.data.as_str())
+            .collect()
+    }
+}
+
+/// How a replayed session compares with its recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    /// The recording.
+    pub recorded: Session,
+    /// The run replaying it.
+    pub replayed: Session,
+}
+
+impl Replay {
+    /// Returns the lines of `stream` that changed since the recording, as a diff of the
+    /// recording to the replay, or `None` if they are the same.
+    pub fn diff(&self, stream: Stream) -> Option<String> {
+        let recorded = self.recorded.text(stream);
+        let replayed = self.replayed.text(stream);
+        (recorded != replayed).then(|| snapshot::diff(&recorded, &replayed))
+    }
+
+    /// Returns whether the program printed the same and exited the same way again.
+    pub fn matches(&self) -> bool {
+        self.diff(Stream::Stdout).is_none()
+            && self.diff(Stream::Stderr).is_none()
+            && self.recorded.exit_code == self.replayed.exit_code
+    }
+}
+
+/// Returns the file the session called `name` is saved in.
+pub fn session_path(name: &str) -> PathBuf {
+    Path::new("sessions").join(format!("{}.json", name))
+}
+
+/// Builds `main_file`, runs it with `args` reading morfo's stdin, and saves what went
+/// through its standard streams to `path`.
+///
+/// # Errors
+///
+/// If the build fails, the program cannot be run or the session cannot be written.
+pub fn record(
+    main_file: &Path,
+    config: &Config,
+    args: Vec<String>,
+    path: &Path,
+) -> MorfoResult<Session> {
+    let artifact = crate::compile(main_file, config)?;
+    let session = run(&artifact, main_file, args, None)?;
+    save(&session, path)?;
+    Ok(session)
+}
+
+/// Builds the main file of the session saved at `path` and runs it like it was recorded.
+///
+/// # Errors
+///
+/// If the session cannot be read, the build fails or the program cannot be run. A
+/// program behaving differently is not an error, but a [`Replay`] that does not match.
+pub fn replay(path: &Path, config: &Config) -> MorfoResult<Replay> {
+    let recorded: Session = serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| MorfoError::InvlidConfig(format!("session {}: {}", path.display(), e)))?;
+    let artifact = crate::compile(&recorded.main_file, config)?;
+    let typed = recorded
+        .events
+        .iter()
+        .filter(|event| event.stream == Stream::Stdin)
+        .cloned()
+        .collect();
+    let replayed = run(
+        &artifact,
+        &recorded.main_file,
+        recorded.args.clone(),
+        Some(typed),
+    )?;
+    Ok(Replay { recorded, replayed })
+}
+
+fn save(session: &Session, path: &Path) -> MorfoResult<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let json = serde_json::to_string_pretty(session)
+        .map_err(|e| MorfoError::InvlidConfig(e.to_string()))?;
+    fs::write(path, json + "\n")?;
+    Ok(())
+}
+
+/// Runs `artifact` with `args`, feeding it `typed` at the times they were typed, or
+/// morfo's stdin if there are none, and records its standard streams. What the program
+/// prints is only passed on when it is fed morfo's stdin.
+fn run(
+    artifact: &Artifact,
+    main_file: &Path,
+    args: Vec<String>,
+    typed: Option<Vec<Event>>,
+) -> MorfoResult<Session> {
+    let mut cmd = crate::executable_command(&artifact.executable_path, &artifact.config)?;
+    cmd.args(&args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let start = Instant::now();
+    let mut child = interrupt::spawn(&mut cmd, None)?;
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let record = {
+        let events = Arc::clone(&events);
+        move |stream: Stream, data: &[u8]| {
+            let mut events = events.lock().unwrap_or_else(|e| e.into_inner());
+            events.push(Event {
+                time: start.elapsed().as_secs_f64(),
+                stream,
+                data: String::from_utf8_lossy(data).into_owned(),
+            });
+        }
+    };
+    let echo = typed.is_none();
+
+    // reading the terminal blocks past the exit of the program, so this thread is left
+    if let Some(stdin) = child.stdin.take() {
+        let record = record.clone();
+        thread::spawn(move || feed(stdin, typed, start, record));
+    }
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    thread::scope(|scope| {
+        scope.spawn(|| stdout.map(|from| pass(from, Stream::Stdout, echo, &record)));
+        scope.spawn(|| stderr.map(|from| pass(from, Stream::Stderr, echo, &record)));
+    });
+    let status = interrupt::wait(&mut child)?;
+
+    let events = events.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    Ok(Session {
+        main_file: main_file.to_path_buf(),
+        args,
+        events,
+        exit_code: status.code(),
+    })
+}
+
+/// Writes `typed` to `stdin` at the times they were typed, or what is read from morfo's
+/// stdin, recording it, until there is nothing left.
+fn feed(
+    mut stdin: ChildStdin,
+    typed: Option<Vec<Event>>,
+    start: Instant,
+    record: impl Fn(Stream, &[u8]),
+) -> io::Result<()> {
+    match typed {
+        Some(typed) => {
+            for event in typed {
+                let at = start + Duration::from_secs_f64(event.time);
+                thread::sleep(at.saturating_duration_since(Instant::now()));
+                record(Stream::Stdin, event.data.as_bytes());
+                stdin.write_all(event.data.as_bytes())?;
+            }
+        }
+        None => {
+            let mut buf = [0; 4096];
+            loop {
+                let n = io::stdin().read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                record(Stream::Stdin, &buf[..n]);
+                stdin.write_all(&buf[..n])?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Records what is read from `from` as `stream`, passing it on to morfo's own stream if
+/// `echo`.
+fn pass(
+    mut from: impl Read,
+    stream: Stream,
+    echo: bool,
+    record: &impl Fn(Stream, &[u8]),
+) -> io::Result<()> {
+    let mut buf = [0; 4096];
+    loop {
+        let n = match from.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        record(stream, &buf[..n]);
+        if echo {
+            match stream {
+                Stream::Stderr => io::stderr().write_all(&buf[..n])?,
+                _ => {
+                    let mut stdout = io::stdout();
+                    stdout.write_all(&buf[..n])?;
+                    stdout.flush()?;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_replay() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let main_file = dir.join("main.c");
+        let echo = |greeting: &str| {
+            fs::write(
+                &main_file,
+                format!(
+                    "#include <stdio.h>\n\
+                     int main(void) {{\n\
+                     char name[64];\n\
+                     while (scanf(\"%63s\", name) == 1) printf(\"{} %s\\n\", name);\n\
+                     fprintf(stderr, \"bye\\n\");\n\
+                     return 3;\n}}\n",
+                    greeting
+                ),
+            )
+            .unwrap();
+        };
+        let config: Config = toml::from_str(&format!(
+            "cc = \"gcc\"\nbuilddir = \"{}\"",
+            dir.join(".out").display()
+        ))
+        .unwrap();
+        let typed = |time: f64, data: &str| Event {
+            time,
+            stream: Stream::Stdin,
+            data: data.to_owned(),
+        };
+
+        echo("hello");
+        let artifact = crate::compile(&main_file, &config).unwrap();
+        let session = run(
+            &artifact,
+            &main_file,
+            Vec::new(),
+            Some(vec![typed(0.0, "ada\n"), typed(0.05, "grace\n")]),
+        )
+        .unwrap();
+        assert_eq!(session.text(Stream::Stdout), "hello ada\nhello grace\n");
+        assert_eq!(session.text(Stream::Stderr), "bye\n");
+        assert_eq!(session.exit_code, Some(3));
+        let path = dir.join("sessions/s1.json");
+        save(&session, &path).unwrap();
+
+        let replay = super::replay(&path, &config).unwrap();
+        assert!(replay.matches());
+
+        echo("hi");
+        let replay = super::replay(&path, &config).unwrap();
+        assert!(!replay.matches());
+        assert_eq!(
+            replay.diff(Stream::Stdout).unwrap(),
+            "-hello ada\n-hello grace\n+hi ada\n+hi grace\n"
+        );
+        assert_eq!(replay.diff(Stream::Stderr), None);
+    }
+}
@@ -0,0 +1,188 @@
+//! Snapshot testing of the output of a program.
+//!
+//! `morfo test sol.c --snapshot` runs the program once for every `<name>.in` in the
+//! snapshot directory, `snapshots/` unless `--dir` says otherwise, with the file as its
+//! stdin, or once with no input if there are none. The first run records what the
+//! program printed on its stdout in `<name>.snap`, named after the main file when there
+//! is no input, and the next runs compare it with the output, showing the lines that
+//! changed. `--accept` records the new output instead.
+//!
+//! Snapshots are plain files, meant to be committed with the sources so that a change
+//! to the output shows up in review.
+
+use std::{
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{config::Config, error::MorfoResult, utils};
+
+/// What became of a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    /// There was no snapshot, so the output was recorded.
+    Recorded,
+    /// The output is the snapshot.
+    Matched,
+    /// The output changed, as this diff of the snapshot to the output shows.
+    Changed(String),
+    /// The output changed and was recorded as the new snapshot.
+    Accepted,
+}
+
+/// A snapshot checked against the output of the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotResult {
+    /// The snapshot file.
+    pub snapshot: PathBuf,
+    /// What became of it.
+    pub status: Status,
+}
+
+/// Builds `main_file` and checks its output on every input in `dir` against their
+/// snapshots, recording those that are missing, and the changed ones too if `accept`.
+///
+/// # Errors
+///
+/// If the build fails, the program cannot be run or a snapshot cannot be read or
+/// written. A changed output is not an error, but a [`Status`].
+pub fn check(
+    main_file: &Path,
+    config: &Config,
+    dir: &Path,
+    accept: bool,
+) -> MorfoResult<Vec<SnapshotResult>> {
+    let artifact = crate::compile(main_file, config)?;
+    let mut inputs: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "in"))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    inputs.sort();
+
+    let runs: Vec<(Option<&Path>, PathBuf)> = if inputs.is_empty() {
+        let snapshot = dir.join(format!("{}.snap", utils::file_name(main_file)));
+        vec![(None, snapshot)]
+    } else {
+        inputs
+            .iter()
+            .map(|input| (Some(input.as_path()), input.with_extension("snap")))
+            .collect()
+    };
+    let mut results = Vec::new();
+    for (input, snapshot) in runs {
+        let run = crate::capture_output(&artifact.executable_path, config, input, None)?;
+        let status = match fs::read(&snapshot) {
+            Ok(recorded) if recorded == run.stdout => Status::Matched,
+            Ok(_) if accept => Status::Accepted,
+            Ok(recorded) => Status::Changed(diff(
+                &String::from_utf8_lossy(&recorded),
+                &String::from_utf8_lossy(&run.stdout),
+            )),
+            Err(_) => Status::Recorded,
+        };
+        if matches!(status, Status::Recorded | Status::Accepted) {
+            fs::create_dir_all(dir)?;
+            fs::write(&snapshot, &run.stdout)?;
+        }
+        results.push(SnapshotResult { snapshot, status });
+    }
+    Ok(results)
+}
+
+/// Returns the lines of `old` and `new`, those only in `old` marked with `-` and those
+/// only in `new` with `+`, leaving out the lines both have.
+pub(crate) fn diff(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // the length of the longest common subsequence of every pair of suffixes
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            let _ = writeln!(diff, "-{}", old[i]);
+            i += 1;
+        } else {
+            let _ = writeln!(diff, "+{}", new[j]);
+            j += 1;
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigBuilder;
+
+    #[test]
+    fn snapshot_diff() {
+        assert_eq!(diff("a\nb\nc\n", "a\nc\nd\n"), "-b\n+d\n");
+        assert_eq!(diff("", "a\n"), "+a\n");
+    }
+
+    #[test]
+    fn snapshot_record_then_compare() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let main_file = dir.join("echo.c");
+        let write_main = |greeting: &str| {
+            let source = format!(
+                "#include <stdio.h>\n\
+                 int main(void) {{\n\
+                     char name[32];\n\
+                     while (scanf(\"%31s\", name) == 1) printf(\"{} %s\\n\", name);\n\
+                 }}\n",
+                greeting
+            );
+            fs::write(&main_file, source).unwrap();
+        };
+        write_main("hello");
+        let snapshots = dir.join("snapshots");
+        fs::create_dir(&snapshots).unwrap();
+        fs::write(snapshots.join("names.in"), "ada\ngrace\n").unwrap();
+        let config = ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir(dir.join(".out").to_str().unwrap())
+            .build();
+        let statuses = |accept: bool| -> Vec<Status> {
+            check(&main_file, &config, &snapshots, accept)
+                .unwrap()
+                .into_iter()
+                .map(|result| result.status)
+                .collect()
+        };
+
+        assert_eq!(statuses(false), [Status::Recorded]);
+        assert_eq!(
+            fs::read_to_string(snapshots.join("names.snap")).unwrap(),
+            "hello ada\nhello grace\n"
+        );
+        assert_eq!(statuses(false), [Status::Matched]);
+
+        write_main("hi");
+        let diff = "-hello ada\n-hello grace\n+hi ada\n+hi grace\n".to_owned();
+        assert_eq!(statuses(false), [Status::Changed(diff)]);
+        assert_eq!(statuses(true), [Status::Accepted]);
+        assert_eq!(statuses(false), [Status::Matched]);
+    }
+}
@@ -0,0 +1,121 @@
+//! Debug information kept out of the shipped executable.
+//!
+//! With `split_debug = true` the program is built with debug information, which is then
+//! moved out of the executable so that it stays small:
+//!
+//! * With GCC and Clang on Linux, each object's DWARF goes to a `.dwo` file next to it
+//!   (`-gsplit-dwarf`), and what remains is moved to `<exe>.debug` with objcopy. The
+//!   executable keeps a debug link to it, which gdb and lldb follow.
+//! * On macOS, the debug information is collected into `<exe>.dSYM` and stripped from
+//!   the executable.
+//! * MSVC always keeps debug information in a separate `.pdb` file.
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    error::{MorfoError, MorfoResult},
+    toolchain::CompilerFamily,
+    utils,
+};
+
+/// Returns the flags that split the debug information when compiling.
+pub(crate) fn compile_flags(family: CompilerFamily) -> Vec<String> {
+    match family {
+        CompilerFamily::Gcc | CompilerFamily::Clang if !cfg!(target_os = "macos") => {
+            vec!["-gsplit-dwarf".to_owned()]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Moves the debug information out of `executable` and returns where it went.
+///
+/// # Errors
+///
+/// If the tools separating the debug information fail.
+pub(crate) fn separate(executable: &Path, family: CompilerFamily) -> MorfoResult<Option<PathBuf>> {
+    if family == CompilerFamily::Msvc {
+        return Ok(None);
+    }
+
+    if cfg!(target_os = "macos") {
+        let dsym = with_suffix(executable, ".dSYM");
+        run(Command::new("dsymutil")
+            .arg(executable)
+            .arg("-o")
+            .arg(&dsym))?;
+        run(Command::new("strip").arg("-S").arg(executable))?;
+        return Ok(Some(dsym));
+    }
+
+    let debug = with_suffix(executable, ".debug");
+    run(Command::new("objcopy")
+        .arg("--only-keep-debug")
+        .arg(executable)
+        .arg(&debug))?;
+    run(Command::new("objcopy").arg("--strip-debug").arg(executable))?;
+    let mut debuglink = OsString::from("--add-gnu-debuglink=");
+    debuglink.push(&debug);
+    run(Command::new("objcopy").arg(debuglink).arg(executable))?;
+    Ok(Some(debug))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+fn run(cmd: &mut Command) -> MorfoResult<()> {
+    let output = utils::run_tool(cmd)?;
+    if !output.status.success() {
+        return Err(MorfoError::CommandFailure(
+            format!("{:?}", cmd).replace('\"', ""),
+            output.status.code(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use crate::{compile_act, config::Config, prepare};
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn splitdebug_separate() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let main_file = tmp_dir.path().join("main.c");
+        fs::write(&main_file, "int main(void) { return 0; }\n").unwrap();
+        let build_dir = tmp_dir.path().join(".out");
+        let config: Config = toml::from_str(&format!(
+            r#"
+            cc = "gcc"
+            builddir = "{}"
+            split_debug = true"#,
+            build_dir.display()
+        ))
+        .unwrap();
+
+        let (act, config) = prepare(&main_file, config).unwrap();
+        compile_act(&act, &config).unwrap();
+
+        assert!(build_dir.join("main.dwo").exists());
+        assert!(build_dir.join("main.debug").exists());
+        let sections = Command::new("readelf")
+            .arg("-S")
+            .arg(build_dir.join("main"))
+            .output()
+            .unwrap();
+        let sections = String::from_utf8_lossy(&sections.stdout);
+        assert!(sections.contains(".gnu_debuglink"));
+        assert!(!sections.contains(".debug_info"));
+    }
+}
@@ -0,0 +1,141 @@
+//! Build summaries, for dashboards charting the health of the builds over time.
+//!
+//! At the end of every build, whether it failed or not, morfo writes
+//! `<builddir>/build-summary.json`, or the file given with `--summary-path`, recording
+//! whether it succeeded, how long it took, the artifacts it built and those that were up
+//! to date, how many objects came from the object cache, how many warnings the sources
+//! have and how big the executable is. A dry run writes none.
+//!
+//! A batch or matrix build writes a summary for every main file or cell in its own
+//! build directory.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    error::{MorfoError, MorfoResult},
+    utils, Artifact,
+};
+
+/// What a build did, as written to the summary.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BuildSummary {
+    /// The version of morfo that ran the build.
+    pub morfo_version: String,
+    /// The main file built.
+    pub main_file: PathBuf,
+    /// When the build finished, in UTC.
+    pub finished_at: String,
+    /// Whether the build succeeded.
+    pub success: bool,
+    /// Why the build failed, if it did.
+    pub error: Option<String>,
+    /// How long the build took, in seconds.
+    pub duration: f64,
+    /// The objects compiled and the executable linked.
+    pub built: Vec<PathBuf>,
+    /// The artifacts that were up to date, and not built again.
+    pub fresh: Vec<PathBuf>,
+    /// How many of the objects compiled were copied from the object cache.
+    pub cache_hits: u64,
+    /// How many warnings the compiler printed for the sources of the program.
+    pub warnings: usize,
+    /// The executable built, if the build succeeded.
+    pub executable: Option<PathBuf>,
+    /// The size of the executable in bytes, if the build succeeded.
+    pub size: Option<u64>,
+}
+
+impl BuildSummary {
+    /// Returns the summary of building `main_file` into `result` in `duration`.
+    pub fn new(main_file: &Path, result: &MorfoResult<Artifact>, duration: Duration) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs());
+        let mut summary = BuildSummary {
+            morfo_version: env!("CARGO_PKG_VERSION").to_owned(),
+            main_file: main_file.to_path_buf(),
+            finished_at: utils::format_utc(now),
+            success: result.is_ok(),
+            error: None,
+            duration: duration.as_secs_f64(),
+            built: Vec::new(),
+            fresh: Vec::new(),
+            cache_hits: 0,
+            warnings: 0,
+            executable: None,
+            size: None,
+        };
+        match result {
+            Ok(artifact) => {
+                let stats = &artifact.compile_stats;
+                summary.built = stats.built.clone();
+                summary.fresh = stats.fresh.clone();
+                summary.cache_hits = stats.cache_hits;
+                summary.warnings = stats.warnings;
+                summary.size = fs::metadata(&artifact.executable_path)
+                    .ok()
+                    .map(|metadata| metadata.len());
+                summary.executable = Some(artifact.executable_path.clone());
+            }
+            Err(e) => summary.error = Some(e.to_string()),
+        }
+        summary
+    }
+}
+
+/// Writes `summary` to `path`.
+///
+/// # Errors
+///
+/// If the summary cannot be written.
+pub(crate) fn write(summary: &BuildSummary, path: &Path) -> MorfoResult<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let json = serde_json::to_string_pretty(summary)
+        .map_err(|e| MorfoError::InvlidConfig(e.to_string()))?;
+    fs::write(path, json + "\n")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigBuilder;
+
+    #[test]
+    fn summary_written_after_every_build() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let main_file = dir.join("main.c");
+        fs::write(&main_file, "int main(void) { return 0; }\n").unwrap();
+        let out = dir.join(".out");
+        let config = ConfigBuilder::default()
+            .set_cc("gcc")
+            .set_build_dir(out.to_str().unwrap())
+            .build();
+        let read = |path: &Path| -> BuildSummary {
+            serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+        };
+
+        crate::compile(&main_file, &config).unwrap();
+        let summary = read(&out.join("build-summary.json"));
+        assert!(summary.success);
+        assert_eq!(summary.built, [out.join("main.o"), out.join("main")]);
+        assert_eq!(summary.executable, Some(out.join("main")));
+        assert!(summary.size.unwrap() > 0);
+
+        fs::write(&main_file, "int main(void) { return x; }\n").unwrap();
+        let summary_path = dir.join("ci/summary.json");
+        let config = config.with_summary_path(&summary_path);
+        assert!(crate::compile(&main_file, &config).is_err());
+        let summary = read(&summary_path);
+        assert!(!summary.success);
+        assert!(summary.error.is_some());
+        assert_eq!(summary.executable, None);
+    }
+}
@@ -0,0 +1,120 @@
+//! The duplicate symbol check before linking.
+//!
+//! With `symbol_check = true` in the config, or `--check-symbols` on the command line,
+//! the symbol table of every object is read with `nm` before linking. When two
+//! translation units define the same strong symbol, say two `main`s or a global defined
+//! in a header, the build stops and names both definitions, with the line they are on if
+//! the objects have debug information. Weak and common symbols are left to the linker.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    error::{MorfoError, MorfoResult},
+    utils,
+};
+
+/// A symbol defined by two translation units.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Duplicate {
+    pub symbol: String,
+    pub first: String,
+    pub second: String,
+}
+
+impl fmt::Display for Duplicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "`{}` is defined in both {} and {}",
+            self.symbol, self.first, self.second
+        )
+    }
+}
+
+/// Returns the strong symbols defined more than once among `objects`, each with the
+/// source it is compiled from.
+///
+/// # Errors
+///
+/// If `nm` is missing or cannot read an object.
+pub(crate) fn duplicates(objects: &[(PathBuf, PathBuf)]) -> MorfoResult<Vec<Duplicate>> {
+    let mut defined: BTreeMap<String, String> = BTreeMap::new();
+    let mut duplicates = Vec::new();
+    for (source, object) in objects {
+        for (symbol, location) in definitions(object)? {
+            let location = location.unwrap_or_else(|| source.display().to_string());
+            match defined.get(&symbol) {
+                Some(first) => duplicates.push(Duplicate {
+                    symbol,
+                    first: first.clone(),
+                    second: location,
+                }),
+                None => {
+                    defined.insert(symbol, location);
+                }
+            }
+        }
+    }
+    Ok(duplicates)
+}
+
+/// Returns the strong symbols `object` defines, with where they are defined if known.
+fn definitions(object: &Path) -> MorfoResult<Vec<(String, Option<String>)>> {
+    let mut cmd = Command::new("nm");
+    cmd.args(["-P", "-l", "--defined-only"]).arg(object);
+    let output = utils::run_tool(&mut cmd)?;
+    if !output.status.success() {
+        return Err(MorfoError::CommandFailure(
+            format!("{:?}", cmd).replace('\"', ""),
+            output.status.code(),
+        ));
+    }
+    Ok(parse_nm(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses the strong symbols out of `nm -P -l` output such as
+/// `main T 0 b\t/src/main.c:3`.
+fn parse_nm(output: &str) -> Vec<(String, Option<String>)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (symbol, location) = match line.split_once('\t') {
+                Some((symbol, location)) => (symbol, Some(location.to_owned())),
+                None => (line, None),
+            };
+            let mut fields = symbol.split_whitespace();
+            let name = fields.next()?;
+            let kind = fields.next()?;
+            // the uppercase kinds are global, less the weak (V, W) and common (C) ones
+            matches!(kind, "T" | "D" | "B" | "R" | "G" | "S").then(|| (name.to_owned(), location))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbols_parse_strong_definitions() {
+        let output = "counter D 0 4\t/src/a.c:1\n\
+                      main T 0 b\t/src/a.c:2\n\
+                      s b 4 4\t/src/a.c:3\n\
+                      w V 0 4\t/src/a.c:4\n\
+                      shared C 4 4\n\
+                      table R 0 10\n";
+        assert_eq!(
+            parse_nm(output),
+            [
+                ("counter".to_owned(), Some("/src/a.c:1".to_owned())),
+                ("main".to_owned(), Some("/src/a.c:2".to_owned())),
+                ("table".to_owned(), None),
+            ]
+        );
+    }
+}
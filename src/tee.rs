@@ -0,0 +1,154 @@
+//! Program output.
+//!
+//! The output of the program is passed on byte for byte as it is written, so binary
+//! output and partial lines come through unchanged. With `--output-file`, the output is
+//! also written to a file, stdout and stderr alike, and with `--no-echo` it is only
+//! written to the file.
+
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    process::{Command, ExitStatus, Stdio},
+    thread,
+};
+
+use crate::{config::Config, error::MorfoResult, interrupt};
+
+/// A writer passing everything written on to the echo, unless echoing is off, and to the
+/// output file, if there is one.
+pub(crate) struct Tee<W: Write> {
+    echo: Option<W>,
+    file: Option<File>,
+}
+
+impl<W: Write> Tee<W> {
+    /// Returns a tee echoing to `echo` and writing the output file of `config`.
+    ///
+    /// # Errors
+    ///
+    /// If the output file cannot be created.
+    pub(crate) fn new(echo: W, config: &Config) -> MorfoResult<Tee<W>> {
+        let file = match config.get_output_file() {
+            Some(path) => Some(File::create(path)?),
+            None => None,
+        };
+        Ok(Tee {
+            echo: (!config.get_no_echo()).then_some(echo),
+            file,
+        })
+    }
+
+    /// Returns a tee echoing to `echo` and writing to the same output file as this one.
+    fn split<E: Write>(&self, echo: E) -> io::Result<Tee<E>> {
+        Ok(Tee {
+            echo: self.echo.as_ref().map(|_| echo),
+            file: self.file.as_ref().map(File::try_clone).transpose()?,
+        })
+    }
+}
+
+impl<W: Write> Write for Tee<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(echo) = &mut self.echo {
+            echo.write_all(buf)?;
+        }
+        if let Some(file) = &mut self.file {
+            file.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(echo) = &mut self.echo {
+            echo.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `cmd` with pipes until it exits, streaming its stdout to `out` and its stderr to
+/// morfo's stderr, both through the tee. With `input`, it is fed to the program as its
+/// stdin.
+///
+/// # Errors
+///
+/// If the program cannot be run or its output cannot be written.
+pub(crate) fn run<W: Write>(
+    cmd: &mut Command,
+    input: Option<Vec<u8>>,
+    out: &mut Tee<W>,
+) -> MorfoResult<ExitStatus> {
+    cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    if input.is_some() {
+        cmd.stdin(Stdio::piped());
+    }
+    let mut child = interrupt::spawn(cmd, None)?;
+
+    // the program may exit without reading everything, which is not an error
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        thread::spawn(move || stdin.write_all(&input));
+    }
+
+    let stderr = child.stderr.take();
+    let mut errors = out.split(io::stderr())?;
+    let copy_errors = thread::spawn(move || match stderr {
+        Some(mut stderr) => copy(&mut stderr, &mut errors),
+        None => Ok(()),
+    });
+    if let Some(stdout) = &mut child.stdout {
+        copy(stdout, out)?;
+    }
+    copy_errors.join().unwrap_or(Ok(()))?;
+
+    Ok(interrupt::wait(&mut child)?)
+}
+
+/// Copies `from` to `to` chunk by chunk, flushing every chunk, so output is passed on as
+/// soon as it is written.
+fn copy<R: Read, W: Write>(from: &mut R, to: &mut W) -> io::Result<()> {
+    let mut buf = [0; 4096];
+    loop {
+        match from.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => {
+                to.write_all(&buf[..n])?;
+                to.flush()?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::config::ConfigBuilder;
+
+    fn run_binary(config: &Config) -> Vec<u8> {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", r"printf 'a\000\377'; printf err >&2"]);
+        let mut out = Vec::new();
+        let status = run(&mut cmd, None, &mut Tee::new(&mut out, config).unwrap()).unwrap();
+        assert!(status.success());
+        out
+    }
+
+    #[test]
+    fn tee_output_file() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let log = tmp_dir.path().join("run.log");
+        let config = ConfigBuilder::default().build().with_output_file(&log);
+
+        assert_eq!(run_binary(&config), b"a\0\xff");
+        let logged = fs::read(&log).unwrap();
+        assert_eq!(logged.len(), 6);
+        assert!(logged.windows(3).any(|window| window == b"a\0\xff"));
+        assert!(logged.windows(3).any(|window| window == b"err"));
+
+        assert!(run_binary(&config.with_no_echo(true)).is_empty());
+        assert_eq!(fs::read(&log).unwrap().len(), 6);
+    }
+}
@@ -0,0 +1,181 @@
+//! Variables in the config.
+//!
+//! The `[vars]` section of the config file declares variables that the other settings
+//! use as `${vars.name}`, anywhere in a string and in any section, so that a set of flags
+//! shared by several profiles or targets is written once:
+//!
+//! ```toml
+//! [vars]
+//! opt = "-O2"
+//! arch = "-march=native"
+//!
+//! [profiles.bench]
+//! cflags = ["${vars.opt}", "${vars.arch}"]
+//! ```
+//!
+//! A string made of a single variable takes the value of the variable as it is, so that
+//! a variable can also hold a number, a boolean or an array. Anywhere else, the variable
+//! is written into the string. Variables are not expanded in the values of other
+//! variables, and using a variable that is not declared is an error.
+//!
+//! `--set vars.opt=-O3` on the command line overrides a variable, or declares it. The
+//! value is read as TOML when it is one, such as `4`, `true` or `["-O3", "-g"]`, and as
+//! a string otherwise.
+
+use crate::error::{MorfoError, MorfoResult};
+
+/// Takes the `[vars]` section out of `config`, with `overrides` of the form
+/// `vars.name=value` applied to it, and expands the variables in the rest of `config`.
+///
+/// # Errors
+///
+/// If an override is not of the form `vars.name=value`, or `config` uses a variable that
+/// is not declared.
+pub(crate) fn expand(config: &mut toml::Table, overrides: &[String]) -> MorfoResult<()> {
+    let mut vars = match config.remove("vars") {
+        Some(toml::Value::Table(vars)) => vars,
+        Some(_) => {
+            return Err(MorfoError::InvlidConfig(
+                "`vars` must be a table".to_owned(),
+            ))
+        }
+        None => toml::Table::new(),
+    };
+    for assignment in overrides {
+        let (name, value) = parse_override(assignment)?;
+        vars.insert(name.to_owned(), value);
+    }
+    for (_, value) in config.iter_mut() {
+        expand_value(value, &vars)?;
+    }
+    Ok(())
+}
+
+/// Splits `vars.name=value` into the name and the value of the variable.
+fn parse_override(assignment: &str) -> MorfoResult<(&str, toml::Value)> {
+    let invalid = || {
+        MorfoError::InvlidConfig(format!(
+            "`--set {}` must be of the form `vars.name=value`",
+            assignment
+        ))
+    };
+    let (name, value) = assignment.split_once('=').ok_or_else(invalid)?;
+    let name = name.strip_prefix("vars.").ok_or_else(invalid)?;
+    if name.is_empty() {
+        return Err(invalid());
+    }
+    let value = toml::from_str::<toml::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_owned()));
+    Ok((name, value))
+}
+
+fn expand_value(value: &mut toml::Value, vars: &toml::Table) -> MorfoResult<()> {
+    match value {
+        toml::Value::String(string) => *value = expand_string(string, vars)?,
+        toml::Value::Array(values) => {
+            for value in values {
+                expand_value(value, vars)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (_, value) in table.iter_mut() {
+                expand_value(value, vars)?;
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Returns `string` with its variables expanded, or the value of the variable it is
+/// made of.
+fn expand_string(string: &str, vars: &toml::Table) -> MorfoResult<toml::Value> {
+    const OPEN: &str = "${vars.";
+
+    let lookup = |name: &str| {
+        vars.get(name).ok_or_else(|| {
+            MorfoError::InvlidConfig(format!("`${{vars.{}}}` is not declared in [vars]", name))
+        })
+    };
+    if let Some(name) = string
+        .strip_prefix(OPEN)
+        .and_then(|rest| rest.strip_suffix('}'))
+        .filter(|name| !name.contains('}'))
+    {
+        return lookup(name).cloned();
+    }
+
+    let mut expanded = String::new();
+    let mut rest = string;
+    while let Some(start) = rest.find(OPEN) {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        expanded.push_str(&rest[..start]);
+        match lookup(&rest[start + OPEN.len()..start + end])? {
+            toml::Value::String(value) => expanded.push_str(value),
+            value => expanded.push_str(&value.to_string()),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(toml::Value::String(expanded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_vars() {
+        let mut config: toml::Table = toml::from_str(
+            r#"
+            cflags = ["${vars.opt}", "-DNAME=\"${vars.name}-${vars.version}\""]
+            runner = "$ORIGIN/run"
+            compile_timeout = "${vars.timeout}"
+
+            [vars]
+            opt = "-O2"
+            name = "app"
+            version = 3
+            timeout = 60
+
+            [profiles.bench]
+            cflags = "${vars.bench}"
+            "#,
+        )
+        .unwrap();
+        let overrides = [
+            "vars.opt=-O3".to_owned(),
+            r#"vars.bench=["-O3", "-g"]"#.to_owned(),
+        ];
+        expand(&mut config, &overrides).unwrap();
+
+        let expected: toml::Table = toml::from_str(
+            r#"
+            cflags = ["-O3", "-DNAME=\"app-3\""]
+            runner = "$ORIGIN/run"
+            compile_timeout = 60
+
+            [profiles.bench]
+            cflags = ["-O3", "-g"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config, expected);
+
+        let mut config: toml::Table = toml::from_str(r#"cc = "${vars.cc}""#).unwrap();
+        assert!(matches!(
+            expand(&mut config, &[]),
+            Err(MorfoError::InvlidConfig(_))
+        ));
+        let mut config = toml::Table::new();
+        let bad = ["opt=-O3".to_owned()];
+        assert!(matches!(
+            expand(&mut config, &bad),
+            Err(MorfoError::InvlidConfig(_))
+        ));
+    }
+}
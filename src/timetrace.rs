@@ -0,0 +1,233 @@
+//! Where the time of compiling goes, from Clang's `-ftime-trace`.
+//!
+//! With `--time-trace`, every source is compiled with `-ftime-trace`, which has Clang
+//! write a Chrome trace of the compilation next to the object, `<object>.json`. After
+//! the build the traces of every object, those of the objects that were up to date
+//! included, are read and the biggest time sinks are printed: the slowest sources, the
+//! headers that take longest to parse, counting the headers they include, and the
+//! functions that take longest to parse, instantiate, generate and optimize, summed
+//! over the sources.
+//!
+//! `--time-trace-out trace.json` also merges the traces into one file, with a process
+//! for every source, to open in `chrome://tracing` or Perfetto.
+//!
+//! Only Clang writes time traces, and the objects compiled on other hosts or taken from
+//! the object cache have none, so they are compiled locally while tracing.
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+
+use crate::error::{MorfoError, MorfoResult};
+
+/// How many of the biggest time sinks of each kind are printed.
+const TOP: usize = 10;
+
+/// The events whose time is spent on the function in their `detail`.
+const FUNCTION_EVENTS: [&str; 4] = [
+    "ParseFunctionDefinition",
+    "InstantiateFunction",
+    "CodeGen Function",
+    "OptFunction",
+];
+
+/// Where the time of compiling the sources of a program went, every list slowest first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimeReport {
+    /// The time of compiling every source traced.
+    pub sources: Vec<(PathBuf, Duration)>,
+    /// The time of parsing every header, with the headers it includes.
+    pub headers: Vec<(String, Duration)>,
+    /// The time spent on every function.
+    pub functions: Vec<(String, Duration)>,
+}
+
+impl fmt::Display for TimeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.sources.is_empty() {
+            return writeln!(f, "Time trace: no traces were found");
+        }
+        let total: Duration = self.sources.iter().map(|(_, duration)| *duration).sum();
+        writeln!(
+            f,
+            "Time trace: {:.3}s compiling {} source(s)",
+            total.as_secs_f64(),
+            self.sources.len()
+        )?;
+        let sources: Vec<_> = self
+            .sources
+            .iter()
+            .map(|(source, duration)| (source.display().to_string(), *duration))
+            .collect();
+        for (title, sinks) in [
+            ("Sources", &sources),
+            ("Headers", &self.headers),
+            ("Functions", &self.functions),
+        ] {
+            if sinks.is_empty() {
+                continue;
+            }
+            writeln!(f, "  {}:", title)?;
+            for (name, duration) in sinks.iter().take(TOP) {
+                writeln!(f, "    {:>8.3}s  {}", duration.as_secs_f64(), name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns where Clang writes the time trace of compiling `object`.
+fn trace_path(object: &Path) -> PathBuf {
+    object.with_extension("json")
+}
+
+/// Reads the time traces of `objects`, each with the source it is compiled from, and
+/// returns where the time went, writing the traces merged to `output` if there is one.
+///
+/// # Errors
+///
+/// If a trace is not valid JSON, or the merged trace cannot be written.
+pub fn report(objects: &[(PathBuf, PathBuf)], output: Option<&Path>) -> MorfoResult<TimeReport> {
+    let mut sources = Vec::new();
+    let mut headers: HashMap<String, Duration> = HashMap::new();
+    let mut functions: HashMap<String, Duration> = HashMap::new();
+    let mut merged = Vec::new();
+    for (source, object) in objects {
+        let Ok(trace) = fs::read_to_string(trace_path(object)) else {
+            continue;
+        };
+        let trace: Value = serde_json::from_str(&trace).map_err(|e| {
+            MorfoError::InvlidConfig(format!("time trace of {}: {}", source.display(), e))
+        })?;
+        let events = trace["traceEvents"].as_array().cloned().unwrap_or_default();
+
+        let mut total = Duration::ZERO;
+        for event in &events {
+            let (Some(name), Some(dur)) = (event["name"].as_str(), event["dur"].as_u64()) else {
+                continue;
+            };
+            let dur = Duration::from_micros(dur);
+            let detail = event["args"]["detail"].as_str().unwrap_or_default();
+            if name == "ExecuteCompiler" {
+                total = total.max(dur);
+            } else if name == "Source" && !detail.is_empty() {
+                *headers.entry(detail.to_owned()).or_default() += dur;
+            } else if FUNCTION_EVENTS.contains(&name) && !detail.is_empty() {
+                *functions.entry(detail.to_owned()).or_default() += dur;
+            }
+        }
+        sources.push((source.clone(), total));
+
+        // every source is a process of its own, named after it
+        let pid = sources.len();
+        merged.push(json!({
+            "ph": "M",
+            "pid": pid,
+            "tid": 0,
+            "name": "process_name",
+            "args": { "name": source.display().to_string() },
+        }));
+        for mut event in events {
+            if event["ph"] == "M" && event["name"] == "process_name" {
+                continue;
+            }
+            event["pid"] = json!(pid);
+            merged.push(event);
+        }
+    }
+
+    if let Some(output) = output {
+        if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(output, json!({ "traceEvents": merged }).to_string())?;
+    }
+    Ok(TimeReport {
+        sources: slowest_first(sources),
+        headers: slowest_first(headers.into_iter().collect()),
+        functions: slowest_first(functions.into_iter().collect()),
+    })
+}
+
+fn slowest_first<T: Ord>(mut sinks: Vec<(T, Duration)>) -> Vec<(T, Duration)> {
+    sinks.sort_by(|(a, a_time), (b, b_time)| b_time.cmp(a_time).then_with(|| a.cmp(b)));
+    sinks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_trace(object: &Path, events: Value) {
+        fs::write(
+            trace_path(object),
+            json!({ "traceEvents": events }).to_string(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn timetrace_report_merges_traces() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let dir = tmp_dir.path();
+        let event = |name: &str, dur: u64, detail: &str| {
+            json!({ "ph": "X", "pid": 7, "tid": 0, "ts": 0, "dur": dur, "name": name,
+                    "args": { "detail": detail } })
+        };
+        write_trace(
+            &dir.join("main.o"),
+            json!([
+                { "ph": "M", "pid": 7, "tid": 0, "name": "process_name", "args": { "name": "clang" } },
+                event("ExecuteCompiler", 5000, ""),
+                event("Source", 3000, "big.h"),
+                event("ParseFunctionDefinition", 400, "main"),
+                event("Total Source", 3000, ""),
+            ]),
+        );
+        write_trace(
+            &dir.join("util.o"),
+            json!([
+                event("ExecuteCompiler", 9000, ""),
+                event("Source", 2000, "big.h"),
+                event("OptFunction", 1500, "util"),
+                event("CodeGen Function", 100, "main"),
+            ]),
+        );
+        let objects = [
+            (dir.join("main.c"), dir.join("main.o")),
+            (dir.join("util.c"), dir.join("util.o")),
+            (dir.join("cached.c"), dir.join("cached.o")),
+        ];
+        let output = dir.join("trace.json");
+
+        let report = report(&objects, Some(&output)).unwrap();
+        let ms = Duration::from_millis;
+        assert_eq!(
+            report.sources,
+            [(dir.join("util.c"), ms(9)), (dir.join("main.c"), ms(5))]
+        );
+        assert_eq!(report.headers, [("big.h".to_owned(), ms(5))]);
+        assert_eq!(
+            report.functions,
+            [
+                ("util".to_owned(), Duration::from_micros(1500)),
+                ("main".to_owned(), Duration::from_micros(500)),
+            ]
+        );
+
+        let merged: Value = serde_json::from_str(&fs::read_to_string(output).unwrap()).unwrap();
+        let events = merged["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 2 + 4 + 4);
+        assert_eq!(
+            events[0]["args"]["name"],
+            dir.join("main.c").display().to_string()
+        );
+        assert!(events[1..5].iter().all(|event| event["pid"] == 1));
+        assert!(events[6..].iter().all(|event| event["pid"] == 2));
+    }
+}
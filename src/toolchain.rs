@@ -0,0 +1,615 @@
+//! Compiler families and the translation of build options into their flags.
+//!
+//! morfo builds the same way no matter which compiler is configured. The
+//! [`CompilerFamily`] of the configured `cc` decides how each option is spelled on
+//! the command line, e.g. `-o main` for GCC and Clang or `/Femain.exe` for MSVC.
+//!
+//! [`CompilerFamily`]: enum.CompilerFamily.html
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Mutex,
+};
+
+use crate::{
+    config::Linkage,
+    error::{MorfoError, MorfoResult},
+    fortran,
+};
+
+/// The compilers probed for, in order, when no `cc` is configured.
+pub const DEFAULT_CANDIDATES: [&str; 4] = ["cc", "gcc", "clang", "cl"];
+
+/// The linkers `linker = "auto"` probes for, fastest first.
+pub const FAST_LINKERS: [&str; 2] = ["mold", "lld"];
+
+/// The fast linker found for each compiler, so that it is only probed for once.
+static FAST_LINKER: Mutex<BTreeMap<String, Option<&str>>> = Mutex::new(BTreeMap::new());
+
+/// The command line conventions a compiler follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompilerFamily {
+    Gcc,
+    Clang,
+    Msvc,
+}
+
+impl CompilerFamily {
+    /// Guesses the family from the name of the compiler command.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::toolchain::CompilerFamily;
+    ///
+    /// assert_eq!(CompilerFamily::from_cc("gcc"), CompilerFamily::Gcc);
+    /// assert_eq!(CompilerFamily::from_cc("/usr/bin/clang-17"), CompilerFamily::Clang);
+    /// assert_eq!(CompilerFamily::from_cc("cl.exe"), CompilerFamily::Msvc);
+    /// ```
+    pub fn from_cc(cc: &str) -> Self {
+        let name = Path::new(cc)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(cc)
+            .to_lowercase();
+
+        match name.as_str() {
+            "cl" | "clang-cl" => CompilerFamily::Msvc,
+            "emcc" => CompilerFamily::Clang,
+            _ if name.starts_with("clang") => CompilerFamily::Clang,
+            _ => CompilerFamily::Gcc,
+        }
+    }
+
+    /// Identifies the family from the output of `<cc> --version`.
+    /// `cc` is usually a GCC or Clang in disguise, so the output is more reliable than the name.
+    fn from_version(version: &str) -> Option<Self> {
+        let version = version.to_lowercase();
+        if version.contains("clang") {
+            Some(CompilerFamily::Clang)
+        } else if version.contains("microsoft") {
+            Some(CompilerFamily::Msvc)
+        } else if version.contains("gcc") || version.contains("free software foundation") {
+            Some(CompilerFamily::Gcc)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the extension of the object files the family produces.
+    pub fn object_extension(&self) -> &'static str {
+        match self {
+            CompilerFamily::Gcc | CompilerFamily::Clang => "o",
+            CompilerFamily::Msvc => "obj",
+        }
+    }
+
+    /// Returns the arguments to compile `source` into the object file `object`.
+    /// Debug databases (PDBs) are placed in `build_dir`.
+    pub fn compile_args(&self, source: &Path, object: &Path, build_dir: &Path) -> Vec<OsString> {
+        match self {
+            CompilerFamily::Gcc | CompilerFamily::Clang => {
+                vec!["-c".into(), source.into(), "-o".into(), object.into()]
+            }
+            CompilerFamily::Msvc => vec![
+                "/nologo".into(),
+                "/c".into(),
+                source.into(),
+                prefixed("/Fo", object),
+                prefixed("/Fd", &with_trailing_separator(build_dir)),
+            ],
+        }
+    }
+
+    /// Returns the arguments to preprocess `source` to standard output, without line markers.
+    pub fn preprocess_args(&self, source: &Path) -> Vec<OsString> {
+        match self {
+            CompilerFamily::Gcc | CompilerFamily::Clang => {
+                vec!["-E".into(), "-P".into(), source.into()]
+            }
+            CompilerFamily::Msvc => vec!["/nologo".into(), "/EP".into(), source.into()],
+        }
+    }
+
+    /// Returns the arguments to list the user headers `source` includes as a make rule on
+    /// standard output. MSVC has no such output, so its headers are not tracked.
+    pub fn depend_args(&self, source: &Path) -> Option<Vec<OsString>> {
+        match self {
+            CompilerFamily::Gcc | CompilerFamily::Clang => Some(vec!["-MM".into(), source.into()]),
+            CompilerFamily::Msvc => None,
+        }
+    }
+
+    /// Returns the arguments to print the macros the compiler predefines as `#define`
+    /// lines on standard output, for C++ if `cpp` and C otherwise, reading the empty
+    /// source from standard input. MSVC has no such output.
+    pub fn predefine_args(&self, cpp: bool) -> Option<Vec<OsString>> {
+        let language = if cpp { "c++" } else { "c" };
+        match self {
+            CompilerFamily::Gcc | CompilerFamily::Clang => Some(
+                ["-dM", "-E", "-x", language, "-"]
+                    .map(OsString::from)
+                    .to_vec(),
+            ),
+            CompilerFamily::Msvc => None,
+        }
+    }
+
+    /// Returns the arguments that name the linked executable. They must come after the inputs.
+    pub fn link_output_args(&self, executable: &Path) -> Vec<OsString> {
+        match self {
+            CompilerFamily::Gcc | CompilerFamily::Clang => vec!["-o".into(), executable.into()],
+            CompilerFamily::Msvc => vec![
+                "/nologo".into(),
+                prefixed("/Fe", executable),
+                "/link".into(),
+                prefixed("/PDB:", &executable.with_extension("pdb")),
+            ],
+        }
+    }
+
+    /// Returns the arguments selecting `linkage`, for compiling and for linking.
+    /// MSVC picks the C runtime when compiling, the others link statically with `-static`.
+    pub fn linkage_args(&self, linkage: Linkage) -> (Vec<String>, Vec<String>) {
+        match (self, linkage) {
+            (CompilerFamily::Msvc, Linkage::Dynamic) => (vec!["/MD".to_owned()], Vec::new()),
+            (CompilerFamily::Msvc, Linkage::Static) => (vec!["/MT".to_owned()], Vec::new()),
+            (_, Linkage::Dynamic) => (Vec::new(), Vec::new()),
+            (_, Linkage::Static) => (Vec::new(), vec!["-static".to_owned()]),
+        }
+    }
+
+    /// Returns the argument that makes the compiler link with `linker`, if it is only the
+    /// name of a linker, such as `lld` or `ld.mold`, rather than a command linking in place
+    /// of the compiler. MSVC takes no such argument.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::toolchain::CompilerFamily;
+    ///
+    /// assert_eq!(CompilerFamily::Gcc.fuse_ld_arg("mold").as_deref(), Some("-fuse-ld=mold"));
+    /// assert_eq!(CompilerFamily::Clang.fuse_ld_arg("ld.lld").as_deref(), Some("-fuse-ld=lld"));
+    /// assert_eq!(CompilerFamily::Gcc.fuse_ld_arg("arm-none-eabi-gcc"), None);
+    /// assert_eq!(CompilerFamily::Msvc.fuse_ld_arg("lld"), None);
+    /// ```
+    pub fn fuse_ld_arg(&self, linker: &str) -> Option<String> {
+        let name = linker.strip_prefix("ld.").unwrap_or(linker);
+        match self {
+            CompilerFamily::Gcc | CompilerFamily::Clang
+                if ["bfd", "gold", "lld", "mold"].contains(&name) =>
+            {
+                Some(format!("-fuse-ld={}", name))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the argument that links the library `name` from the library search path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::toolchain::CompilerFamily;
+    ///
+    /// assert_eq!(CompilerFamily::Gcc.library_arg("m"), "-lm");
+    /// assert_eq!(CompilerFamily::Msvc.library_arg("ws2_32"), "ws2_32.lib");
+    /// ```
+    pub fn library_arg(&self, name: &str) -> String {
+        match self {
+            CompilerFamily::Gcc | CompilerFamily::Clang => format!("-l{}", name),
+            CompilerFamily::Msvc => format!("{}.lib", name),
+        }
+    }
+
+    /// Returns the argument that adds `dir` to the include search path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::toolchain::CompilerFamily;
+    ///
+    /// assert_eq!(CompilerFamily::Gcc.include_arg("include"), "-Iinclude");
+    /// assert_eq!(CompilerFamily::Msvc.include_arg("include"), "/Iinclude");
+    /// ```
+    pub fn include_arg(&self, dir: &str) -> String {
+        match self {
+            CompilerFamily::Gcc | CompilerFamily::Clang => format!("-I{}", dir),
+            CompilerFamily::Msvc => format!("/I{}", dir),
+        }
+    }
+
+    /// Returns the argument that defines the preprocessor macro `name`, optionally to `value`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::toolchain::CompilerFamily;
+    ///
+    /// assert_eq!(CompilerFamily::Clang.define_arg("DEBUG", Some("1")), "-DDEBUG=1");
+    /// assert_eq!(CompilerFamily::Msvc.define_arg("NDEBUG", None), "/DNDEBUG");
+    /// ```
+    pub fn define_arg(&self, name: &str, value: Option<&str>) -> String {
+        let prefix = match self {
+            CompilerFamily::Gcc | CompilerFamily::Clang => "-D",
+            CompilerFamily::Msvc => "/D",
+        };
+        match value {
+            Some(value) => format!("{}{}={}", prefix, name, value),
+            None => format!("{}{}", prefix, name),
+        }
+    }
+
+    /// Returns the argument that generates debug information.
+    pub fn debug_arg(&self) -> &'static str {
+        match self {
+            CompilerFamily::Gcc | CompilerFamily::Clang => "-g",
+            CompilerFamily::Msvc => "/Zi",
+        }
+    }
+
+    /// Returns the argument that keeps the frame pointer in every function.
+    pub fn frame_pointer_arg(&self) -> &'static str {
+        match self {
+            CompilerFamily::Gcc | CompilerFamily::Clang => "-fno-omit-frame-pointer",
+            CompilerFamily::Msvc => "/Oy-",
+        }
+    }
+
+    /// Returns the argument that stops the compiler after `max_errors` errors, if it has one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::toolchain::CompilerFamily;
+    ///
+    /// assert_eq!(CompilerFamily::Gcc.max_errors_arg(5).as_deref(), Some("-fmax-errors=5"));
+    /// assert_eq!(CompilerFamily::Msvc.max_errors_arg(5), None);
+    /// ```
+    pub fn max_errors_arg(&self, max_errors: u32) -> Option<String> {
+        match self {
+            CompilerFamily::Gcc => Some(format!("-fmax-errors={}", max_errors)),
+            CompilerFamily::Clang => Some(format!("-ferror-limit={}", max_errors)),
+            CompilerFamily::Msvc => None,
+        }
+    }
+
+    /// Returns the argument that enables `sanitizers`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::toolchain::CompilerFamily;
+    ///
+    /// let sanitizers = vec!["fuzzer".to_owned(), "address".to_owned()];
+    /// assert_eq!(CompilerFamily::Clang.sanitize_arg(&sanitizers), "-fsanitize=fuzzer,address");
+    /// ```
+    pub fn sanitize_arg(&self, sanitizers: &[String]) -> String {
+        match self {
+            CompilerFamily::Gcc | CompilerFamily::Clang => {
+                format!("-fsanitize={}", sanitizers.join(","))
+            }
+            CompilerFamily::Msvc => format!("/fsanitize={}", sanitizers.join(",")),
+        }
+    }
+
+    /// Returns the argument for the GCC-style optimization `level` (`0`, `1`, `2`, `3`, `s`, `z`).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::toolchain::CompilerFamily;
+    ///
+    /// assert_eq!(CompilerFamily::Gcc.opt_arg("2"), "-O2");
+    /// assert_eq!(CompilerFamily::Msvc.opt_arg("0"), "/Od");
+    /// ```
+    pub fn opt_arg(&self, level: &str) -> String {
+        match self {
+            CompilerFamily::Gcc | CompilerFamily::Clang => format!("-O{}", level),
+            CompilerFamily::Msvc => match level {
+                "0" => "/Od".to_owned(),
+                "1" | "s" | "z" => "/O1".to_owned(),
+                _ => "/O2".to_owned(),
+            },
+        }
+    }
+
+    /// Returns the argument selecting the C standard `std`, e.g. `c99` or `gnu17`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::toolchain::CompilerFamily;
+    ///
+    /// assert_eq!(CompilerFamily::Clang.std_arg("c99"), "-std=c99");
+    /// assert_eq!(CompilerFamily::Msvc.std_arg("c17"), "/std:c17");
+    /// ```
+    pub fn std_arg(&self, std: &str) -> String {
+        match self {
+            CompilerFamily::Gcc | CompilerFamily::Clang => format!("-std={}", std),
+            CompilerFamily::Msvc => format!("/std:{}", std),
+        }
+    }
+}
+
+/// The language of a source, which decides the compiler it is compiled with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    C,
+    Cxx,
+    Asm,
+    Cuda,
+    Fortran,
+}
+
+impl Language {
+    /// Returns the language of `source`, going by its extension. Objective-C sources and
+    /// those of an unknown extension are compiled like C.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use morfo::toolchain::Language;
+    /// use std::path::Path;
+    ///
+    /// assert_eq!(Language::of(Path::new("main.c")), Language::C);
+    /// assert_eq!(Language::of(Path::new("math.cppm")), Language::Cxx);
+    /// assert_eq!(Language::of(Path::new("start.S")), Language::Asm);
+    /// assert_eq!(Language::of(Path::new("kernel.cu")), Language::Cuda);
+    /// ```
+    pub fn of(source: &Path) -> Language {
+        let extension = source
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+        match extension {
+            "cpp" | "cc" | "cxx" | "c++" | "C" | "cppm" | "ixx" | "mpp" | "cxxm" => Language::Cxx,
+            "s" | "S" | "sx" | "asm" => Language::Asm,
+            "cu" => Language::Cuda,
+            _ if fortran::is_fortran(source) => Language::Fortran,
+            _ => Language::C,
+        }
+    }
+}
+
+/// Returns the C++ compiler that goes with the C compiler `cc`, named the same way, or
+/// `cc` itself when it compiles both, as MSVC does.
+///
+/// # Examples
+///
+/// ```
+/// use morfo::toolchain::cxx_compiler;
+///
+/// assert_eq!(cxx_compiler("gcc"), "g++");
+/// assert_eq!(cxx_compiler("/usr/bin/clang-17"), "/usr/bin/clang++-17");
+/// assert_eq!(cxx_compiler("arm-linux-gnueabihf-gcc-12"), "arm-linux-gnueabihf-g++-12");
+/// assert_eq!(cxx_compiler("cc"), "c++");
+/// assert_eq!(cxx_compiler("cl"), "cl");
+/// ```
+pub fn cxx_compiler(cc: &str) -> String {
+    let name_start = cc.rfind(['/', '\\']).map_or(0, |i| i + 1);
+    let (dir, name) = cc.split_at(name_start);
+    let name = match name {
+        "cc" => "c++".to_owned(),
+        "emcc" => "em++".to_owned(),
+        _ if CompilerFamily::from_cc(name) == CompilerFamily::Msvc => name.to_owned(),
+        _ if name.contains("clang") && !name.contains("clang++") => {
+            name.replacen("clang", "clang++", 1)
+        }
+        _ => match name.rfind("gcc") {
+            Some(i) => format!("{}g++{}", &name[..i], &name[i + 3..]),
+            None => name.to_owned(),
+        },
+    };
+    format!("{}{}", dir, name)
+}
+
+/// A compiler found on the PATH.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Compiler {
+    /// The command used to invoke the compiler.
+    pub command: String,
+    /// The family the compiler belongs to.
+    pub family: CompilerFamily,
+    /// The first line the compiler reports about its version.
+    pub version: String,
+}
+
+/// Checks whether `command` runs as a compiler and identifies it.
+///
+/// GCC-style compilers are asked for `--version`. MSVC has no such flag and prints
+/// its banner when invoked without arguments instead.
+///
+/// # Examples
+///
+/// ```
+/// use morfo::toolchain::probe;
+///
+/// assert!(probe("morfo-no-such-compiler").is_none());
+/// ```
+pub fn probe(command: &str) -> Option<Compiler> {
+    let guessed = CompilerFamily::from_cc(command);
+    let mut cmd = Command::new(command);
+    if guessed != CompilerFamily::Msvc {
+        cmd.arg("--version");
+    }
+
+    let output = cmd.output().ok()?;
+    if guessed != CompilerFamily::Msvc && !output.status.success() {
+        return None;
+    }
+
+    // MSVC prints its banner on stderr
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    let version = text
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or_default()
+        .trim()
+        .to_owned();
+
+    Some(Compiler {
+        command: command.to_owned(),
+        family: CompilerFamily::from_version(&text).unwrap_or(guessed),
+        version,
+    })
+}
+
+/// Returns the first of [`FAST_LINKERS`] that the compiler `cc` of `family` links with,
+/// or `None` if it links with neither and keeps its default linker. A linker is usable
+/// when `cc -fuse-ld=<name> -Wl,--version` succeeds.
+///
+/// # Examples
+///
+/// ```
+/// use morfo::toolchain::{fast_linker, CompilerFamily};
+///
+/// assert_eq!(fast_linker("morfo-no-such-compiler", CompilerFamily::Gcc), None);
+/// ```
+pub fn fast_linker(cc: &str, family: CompilerFamily) -> Option<&'static str> {
+    let mut found = FAST_LINKER.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(linker) = found.get(cc) {
+        return *linker;
+    }
+    let linker = FAST_LINKERS.into_iter().find(|&linker| {
+        let Some(fuse_ld) = family.fuse_ld_arg(linker) else {
+            return false;
+        };
+        Command::new(cc)
+            .args([fuse_ld.as_str(), "-Wl,--version"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    });
+    found.insert(cc.to_owned(), linker);
+    linker
+}
+
+/// Returns every candidate that runs as a compiler, in the order given.
+pub fn list(candidates: &[String]) -> Vec<Compiler> {
+    candidates.iter().filter_map(|cc| probe(cc)).collect()
+}
+
+/// Returns the first candidate that runs as a compiler.
+///
+/// # Errors
+///
+/// If none of the candidates could be run.
+pub fn detect(candidates: &[String]) -> MorfoResult<Compiler> {
+    candidates
+        .iter()
+        .find_map(|cc| probe(cc))
+        .ok_or_else(|| MorfoError::MissingCompiler(candidates.to_vec()))
+}
+
+fn prefixed(prefix: &str, path: &Path) -> OsString {
+    let mut arg = OsString::from(prefix);
+    arg.push(path);
+    arg
+}
+
+fn with_trailing_separator(dir: &Path) -> PathBuf {
+    // MSVC treats a `/Fd` value ending in a separator as a directory
+    let mut dir = dir.as_os_str().to_owned();
+    dir.push(std::path::MAIN_SEPARATOR_STR);
+    PathBuf::from(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toolchain_from_cc() {
+        assert_eq!(CompilerFamily::from_cc("cc"), CompilerFamily::Gcc);
+        assert_eq!(
+            CompilerFamily::from_cc("arm-linux-gnueabihf-gcc"),
+            CompilerFamily::Gcc
+        );
+        assert_eq!(CompilerFamily::from_cc("emcc"), CompilerFamily::Clang);
+        assert_eq!(CompilerFamily::from_cc("CL.EXE"), CompilerFamily::Msvc);
+        assert_eq!(CompilerFamily::from_cc("clang-cl"), CompilerFamily::Msvc);
+    }
+
+    #[test]
+    fn toolchain_from_version() {
+        let gcc = "gcc (Ubuntu 13.2.0-4ubuntu3) 13.2.0\nCopyright (C) 2023 Free Software Foundation, Inc.";
+        assert_eq!(CompilerFamily::from_version(gcc), Some(CompilerFamily::Gcc));
+
+        let apple = "Apple clang version 15.0.0 (clang-1500.1.0.2.5)";
+        assert_eq!(
+            CompilerFamily::from_version(apple),
+            Some(CompilerFamily::Clang)
+        );
+
+        let msvc = "Microsoft (R) C/C++ Optimizing Compiler Version 19.38.33134 for x64";
+        assert_eq!(
+            CompilerFamily::from_version(msvc),
+            Some(CompilerFamily::Msvc)
+        );
+    }
+
+    #[test]
+    fn toolchain_detect_none_found() {
+        let candidates = vec!["morfo-no-such-compiler".to_owned()];
+        assert_eq!(
+            detect(&candidates).unwrap_err(),
+            MorfoError::MissingCompiler(candidates)
+        );
+    }
+
+    #[test]
+    fn toolchain_compile_args() {
+        let gcc = CompilerFamily::Gcc.compile_args(
+            Path::new("main.c"),
+            Path::new(".out/main.o"),
+            Path::new(".out"),
+        );
+        assert_eq!(gcc, vec!["-c", "main.c", "-o", ".out/main.o"]);
+
+        let msvc = CompilerFamily::Msvc.compile_args(
+            Path::new("main.c"),
+            Path::new(".out/main.obj"),
+            Path::new(".out"),
+        );
+        assert_eq!(msvc[..3], ["/nologo", "/c", "main.c"]);
+        assert_eq!(msvc[3], "/Fo.out/main.obj");
+        assert!(msvc[4].to_str().unwrap().starts_with("/Fd.out"));
+    }
+
+    #[test]
+    fn toolchain_link_output_args() {
+        let gcc = CompilerFamily::Clang.link_output_args(Path::new(".out/main"));
+        assert_eq!(gcc, vec!["-o", ".out/main"]);
+
+        let msvc = CompilerFamily::Msvc.link_output_args(Path::new(".out/main.exe"));
+        assert_eq!(
+            msvc,
+            vec!["/nologo", "/Fe.out/main.exe", "/link", "/PDB:.out/main.pdb"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fast_linker_probe() {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        // a compiler that only finds lld
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let cc = tmp_dir.path().join("cc");
+        fs::write(&cc, "#!/bin/sh\n[ \"$1\" = -fuse-ld=lld ]\n").unwrap();
+        fs::set_permissions(&cc, fs::Permissions::from_mode(0o755)).unwrap();
+        let cc = cc.to_str().unwrap();
+
+        assert_eq!(fast_linker(cc, CompilerFamily::Gcc), Some("lld"));
+        assert_eq!(fast_linker(cc, CompilerFamily::Gcc), Some("lld"));
+        assert_eq!(fast_linker("cl", CompilerFamily::Msvc), None);
+    }
+}
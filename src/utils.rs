@@ -1,16 +1,417 @@
-pub fn file_name(path: &str) -> String {
-    let file_name = path.split('/').last().unwrap().to_string();
-    file_name.split('.').next().unwrap().to_string()
+use std::{
+    collections::VecDeque,
+    env, fs, io,
+    path::{Component, Path, PathBuf},
+    process::{Command, Output},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+use crate::{
+    error::{MorfoError, MorfoResult},
+    jobserver::{self, Jobserver},
+};
+
+/// Returns the name of the file at `path` without its directory and extension.
+pub fn file_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Applies `f` to every item, `jobs` at a time, and returns the results in the order
+/// of the items. Under `make -jN`, the jobs beyond the first also wait for a token of
+/// make's jobserver.
+pub fn parallel_map<T, R, F>(items: &[T], jobs: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    parallel_map_with(items, jobs, jobserver::client(), f)
+}
+
+/// Applies `f` to every item like [`parallel_map`], with the tokens of `jobserver`.
+pub(crate) fn parallel_map_with<T, R, F>(
+    items: &[T],
+    jobs: usize,
+    jobserver: Option<&Jobserver>,
+    f: F,
+) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let queue = Mutex::new(items.iter().enumerate().collect::<VecDeque<_>>());
+    let results = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for worker in 0..jobs.max(1) {
+            let queue = &queue;
+            let results = &results;
+            let f = &f;
+            scope.spawn(move || loop {
+                // the first job runs in the slot make gave morfo, the others need a token
+                let _token = match jobserver {
+                    Some(jobserver) if worker > 0 => {
+                        match jobserver.acquire_while(|| !queue.lock().unwrap().is_empty()) {
+                            Some(token) => Some(token),
+                            None => return,
+                        }
+                    }
+                    _ => None,
+                };
+                let Some((index, item)) = queue.lock().unwrap().pop_front() else {
+                    return;
+                };
+                let result = f(item);
+                results.lock().unwrap().push((index, result));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Returns `path` with `.` components removed and `..` components applied, without
+/// touching the file system, so that paths to the same file written differently compare
+/// equal.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        normalized.push(".");
+    }
+    normalized
+}
+
+/// Returns the files under `project_dir` in name order, skipping `build_dir`.
+pub fn project_files(project_dir: &Path, build_dir: &Path) -> Vec<PathBuf> {
+    let build_dir = fs::canonicalize(build_dir).ok();
+    WalkDir::new(project_dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            !entry.file_type().is_dir() || fs::canonicalize(entry.path()).ok() != build_dir
+        })
+        .flatten()
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+/// Runs an external tool to completion and collects its output.
+/// A tool that is not installed is reported as [`MorfoError::MissingTool`] rather than an IO error.
+pub fn run_tool(cmd: &mut Command) -> MorfoResult<Output> {
+    cmd.output().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => {
+            MorfoError::MissingTool(cmd.get_program().to_string_lossy().into_owned())
+        }
+        _ => e.into(),
+    })
+}
+
+/// Returns whether `tool` is an executable on the PATH.
+pub fn tool_available(tool: &str) -> bool {
+    let Some(paths) = env::var_os("PATH") else {
+        return false;
+    };
+    env::split_paths(&paths).any(|dir| {
+        dir.join(format!("{}{}", tool, env::consts::EXE_SUFFIX))
+            .is_file()
+    })
+}
+
+/// Parses a size such as `512`, `64K`, `1.5MiB` or `2GB` into bytes.
+/// Suffixes are binary, so `1K` is 1024 bytes.
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let scale: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        _ => return None,
+    };
+    Some((number * scale as f64) as u64)
+}
+
+/// Parses a duration such as `45s`, `90m`, `12h`, `30d` or `2w`. A number without a unit
+/// is in seconds.
+pub fn parse_duration(duration: &str) -> Option<Duration> {
+    let duration = duration.trim();
+    let split = duration
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(duration.len());
+    let (number, unit) = duration.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let scale: f64 = match unit.trim() {
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        "w" => 7.0 * 86400.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(number * scale).ok()
+}
+
+/// Quotes `word` for a POSIX shell, unless it is safe as it is.
+pub fn shell_quote(word: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "@%+=:,./_-".contains(c);
+    if !word.is_empty() && word.chars().all(safe) {
+        word.to_owned()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// Formats `cmd` as a line that can be pasted into a POSIX shell, with the environment
+/// variables it sets in front.
+pub fn format_command(cmd: &Command) -> String {
+    let env = cmd.get_envs().filter_map(|(name, value)| {
+        value.map(|value| {
+            format!(
+                "{}={}",
+                name.to_string_lossy(),
+                shell_quote(&value.to_string_lossy())
+            )
+        })
+    });
+    let words = std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|word| shell_quote(&word.to_string_lossy()));
+    env.chain(words).collect::<Vec<_>>().join(" ")
+}
+
+/// Splits `line` into words like a POSIX shell does, without expanding anything:
+/// whitespace separates the words, single quotes keep everything, double quotes keep
+/// everything but a backslash before `"`, `\`, `$` or a backquote, and a backslash
+/// elsewhere keeps the next character. A quote left open runs to the end of `line`.
+pub fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                word.extend(chars.by_ref().take_while(|&c| c != '\''));
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => word.extend(['\\', c]),
+                            None => word.push('\\'),
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// Returns the number of characters to insert, delete or replace to turn `a` into `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let replaced = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Formats `bytes` with the largest binary unit that keeps it above one, e.g. `1.50 MiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next;
+    }
+    format!("{:.2} {}", size, unit)
+}
+
+/// Returns the hex SHA-256 of the contents of the file at `path`.
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    Ok(format!("{:x}", Sha256::digest(fs::read(path)?)))
+}
+
+/// Returns the hex SHA-256 of the paths and contents of `paths`, in order.
+pub fn hash_files(paths: &[PathBuf]) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    for path in paths {
+        hasher.update(path.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(fs::read(path)?);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Formats seconds since the Unix epoch as an ISO 8601 UTC timestamp.
+pub fn format_utc(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+
+    // civil-from-days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn utils_normalize() {
+        assert_eq!(normalize(Path::new("./lib/../util.h")), Path::new("util.h"));
+        assert_eq!(normalize(Path::new("/src/./a/b/..")), Path::new("/src/a"));
+        assert_eq!(normalize(Path::new("../inc/a.h")), Path::new("../inc/a.h"));
+        assert_eq!(normalize(Path::new("a/..")), Path::new("."));
+    }
+
+    #[test]
+    fn utils_format_command() {
+        let mut cmd = Command::new("gcc");
+        cmd.args(["-DNAME=it's", "-c", "my file.c", ""])
+            .env("SOURCE_DATE_EPOCH", "0");
+        assert_eq!(
+            format_command(&cmd),
+            "SOURCE_DATE_EPOCH=0 gcc '-DNAME=it'\\''s' -c 'my file.c' ''"
+        );
+    }
+
+    #[test]
+    fn utils_edit_distance() {
+        assert_eq!(edit_distance("util.h", "util.h"), 0);
+        assert_eq!(edit_distance("utl.h", "util.h"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "net.h"), 5);
+    }
+
+    #[test]
+    fn utils_split_words() {
+        assert_eq!(split_words("  -Wall\t-O2 "), vec!["-Wall", "-O2"]);
+        assert_eq!(split_words("-I 'my dir'"), vec!["-I", "my dir"]);
+        assert_eq!(
+            split_words(r#"-DMSG="say \"hi\"" -DPATH=a\ b ''"#),
+            vec![r#"-DMSG=say "hi""#, "-DPATH=a b", ""]
+        );
+        assert_eq!(split_words(r#""a\n"'b"#), vec![r"a\nb"]);
+        assert!(split_words("").is_empty());
+    }
+
     #[test]
     fn utils_file_name() {
-        assert_eq!(file_name("main.c"), "main");
+        assert_eq!(file_name(Path::new("main.c")), "main");
+
+        assert_eq!(file_name(Path::new("src/main.cpp")), "main");
+
+        let nested: std::path::PathBuf = ["src", "aux", "util.c"].iter().collect();
+        assert_eq!(file_name(&nested), "util");
+    }
+
+    #[test]
+    fn utils_hash_files() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let file = tmp_dir.path().join("main.c");
+        fs::write(&file, "int main(void) { return 0; }").unwrap();
+
+        let files = vec![file.clone()];
+        let before = hash_files(&files).unwrap();
+        assert_eq!(before.len(), 64);
+        assert_eq!(before, hash_files(&files).unwrap());
 
-        assert_eq!(file_name("src/main.cpp"), "main");
+        fs::write(&file, "int main(void) { return 1; }").unwrap();
+        assert_ne!(before, hash_files(&[file]).unwrap());
+    }
+
+    #[test]
+    fn utils_parse_size() {
+        assert_eq!(parse_size("512"), Some(512));
+        assert_eq!(parse_size("64K"), Some(64 * 1024));
+        assert_eq!(parse_size("1.5MiB"), Some(3 * 512 * 1024));
+        assert_eq!(parse_size("2 GB"), Some(2 << 30));
+        assert_eq!(parse_size("lots"), None);
+        assert_eq!(parse_size("5X"), None);
+    }
+
+    #[test]
+    fn utils_format_size() {
+        assert_eq!(format_size(100), "100 B");
+        assert_eq!(format_size(1536), "1.50 KiB");
+        assert_eq!(format_size(64 << 20), "64.00 MiB");
+    }
+
+    #[test]
+    fn utils_format_utc() {
+        assert_eq!(format_utc(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_utc(951782400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_utc(1706702400), "2024-01-31T12:00:00Z");
     }
 }
@@ -0,0 +1,70 @@
+//! What a long-running morfo keeps in memory between builds.
+//!
+//! A build run from the command line starts cold: it reads the scans of the sources
+//! back from the build directory and asks the compiler for its predefined macros and
+//! search directories. `morfo daemon` builds many times in one process, so once it has
+//! turned this on, both are kept in memory and reused by the next builds. Nothing is
+//! kept otherwise.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::act::ScanCache;
+
+/// The macros the compiler predefines and the directories it searches.
+pub(crate) type Predefines = (BTreeMap<String, String>, Option<Vec<PathBuf>>);
+
+#[derive(Debug, Default)]
+struct Warm {
+    /// The scan caches, by the path they are saved at.
+    scans: HashMap<PathBuf, ScanCache>,
+    /// The predefines, by the command printing them.
+    predefines: HashMap<String, Predefines>,
+}
+
+static WARM: Mutex<Option<Warm>> = Mutex::new(None);
+
+/// Keeps what the builds of this process learn in memory from now on.
+pub(crate) fn enable() {
+    let mut warm = WARM.lock().unwrap_or_else(|e| e.into_inner());
+    warm.get_or_insert_with(Warm::default);
+}
+
+/// Returns the scan cache saved at `path`, from memory if it is kept there.
+pub(crate) fn scan_cache(path: &Path) -> ScanCache {
+    let warm = WARM.lock().unwrap_or_else(|e| e.into_inner());
+    match warm.as_ref().and_then(|warm| warm.scans.get(path)) {
+        Some(cache) => cache.clone(),
+        None => ScanCache::load(path),
+    }
+}
+
+/// Keeps the scan cache saved at `path` in memory, if anything is kept.
+pub(crate) fn keep_scan_cache(path: &Path, cache: ScanCache) {
+    let mut warm = WARM.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(warm) = warm.as_mut() {
+        warm.scans.insert(path.to_path_buf(), cache);
+    }
+}
+
+/// Returns the predefines printed by `command`, from memory if they are kept there, or
+/// from `predefines`, keeping them if they were found.
+pub(crate) fn predefines(
+    command: String,
+    predefines: impl FnOnce() -> Option<Predefines>,
+) -> Option<Predefines> {
+    if let Some(warm) = WARM.lock().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        if let Some(predefines) = warm.predefines.get(&command) {
+            return Some(predefines.clone());
+        }
+    }
+    let found = predefines()?;
+    let mut warm = WARM.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(warm) = warm.as_mut() {
+        warm.predefines.insert(command, found.clone());
+    }
+    Some(found)
+}
@@ -0,0 +1,157 @@
+//! The warning policy and the count of the warnings of a build.
+//!
+//! `warnings` in the config says which compiler warnings fail the build:
+//!
+//! ```toml
+//! # every warning is an error
+//! warnings = "deny"
+//! # no warning is an error, even with -Werror in cflags
+//! warnings = "allow"
+//! # these warnings are errors, and these are not
+//! warnings = { deny = ["unused-variable"], allow = ["sign-compare"] }
+//! ```
+//!
+//! The warnings are named like the GCC and Clang `-W` options, with or without the
+//! `-W`. MSVC only knows its warnings by number, so it is given the ones named like
+//! `4101` or `C4101`, and has no way to keep a single warning from being an error.
+//!
+//! Whatever the policy, the warnings GCC and Clang print for every source are counted
+//! and recorded next to its object, and after each build morfo reports how many
+//! warnings the program has, those of the sources that were up to date included, so
+//! that a project can watch the number go down and deny them once it reaches zero.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    diagnostic::{self, Severity},
+    toolchain::CompilerFamily,
+};
+
+/// Which warnings are errors, set with `warnings`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(untagged)]
+pub enum Warnings {
+    /// `"deny"` or `"allow"`: every warning is an error, or none is.
+    All(Level),
+    /// Only the warnings in `deny` are errors, and those in `allow` are not.
+    Each {
+        #[serde(default)]
+        deny: Vec<String>,
+        #[serde(default)]
+        allow: Vec<String>,
+    },
+}
+
+/// Whether warnings are errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    /// The warnings are errors.
+    Deny,
+    /// The warnings are only warnings.
+    Allow,
+}
+
+/// Returns the flags applying `warnings` for the compiler `family`.
+pub(crate) fn compile_flags(family: CompilerFamily, warnings: &Warnings) -> Vec<String> {
+    match (family, warnings) {
+        (CompilerFamily::Msvc, Warnings::All(Level::Deny)) => vec!["/WX".to_owned()],
+        (CompilerFamily::Msvc, Warnings::All(Level::Allow)) => vec!["/WX-".to_owned()],
+        (CompilerFamily::Msvc, Warnings::Each { deny, .. }) => deny
+            .iter()
+            .filter_map(|warning| msvc_number(warning))
+            .map(|number| format!("/we{}", number))
+            .collect(),
+        (_, Warnings::All(Level::Deny)) => vec!["-Werror".to_owned()],
+        (_, Warnings::All(Level::Allow)) => vec!["-Wno-error".to_owned()],
+        (_, Warnings::Each { deny, allow }) => {
+            let named = |warnings: &[String], flag: &str| -> Vec<String> {
+                warnings
+                    .iter()
+                    .filter(|warning| msvc_number(warning).is_none())
+                    .map(|warning| format!("{}={}", flag, warning.trim_start_matches("-W")))
+                    .collect()
+            };
+            let mut flags = named(deny, "-Werror");
+            flags.extend(named(allow, "-Wno-error"));
+            flags
+        }
+    }
+}
+
+/// Returns the number of `warning` if it names an MSVC warning, like `4101` or `C4101`.
+fn msvc_number(warning: &str) -> Option<&str> {
+    let number = warning.trim_start_matches(['C', 'c']);
+    (!number.is_empty() && number.chars().all(|c| c.is_ascii_digit())).then_some(number)
+}
+
+/// Returns how many warnings are in `diagnostics`, as printed by GCC or Clang.
+pub(crate) fn count(diagnostics: &str) -> usize {
+    diagnostic::parse(diagnostics)
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Warning)
+        .count()
+}
+
+/// Records that building `artifact` printed `count` warnings.
+pub(crate) fn record(artifact: &Path, count: usize) -> io::Result<()> {
+    fs::write(record_path(artifact), count.to_string())
+}
+
+/// Returns how many warnings building `artifacts` printed, the last time each was built,
+/// and how many of them printed any.
+pub(crate) fn total(artifacts: &[PathBuf]) -> (usize, usize) {
+    let counts: Vec<usize> = artifacts
+        .iter()
+        .filter_map(|artifact| fs::read_to_string(record_path(artifact)).ok())
+        .filter_map(|count| count.trim().parse().ok())
+        .filter(|count| *count > 0)
+        .collect();
+    (counts.iter().sum(), counts.len())
+}
+
+/// Returns where the number of warnings of `artifact` is recorded.
+fn record_path(artifact: &Path) -> PathBuf {
+    let mut path = artifact.as_os_str().to_owned();
+    path.push(".warnings");
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn warnings_policy_flags() {
+        let config: Config = toml::from_str(
+            r#"warnings = { deny = ["unused-variable", "C4101"], allow = ["-Wsign-compare"] }"#,
+        )
+        .unwrap();
+        let warnings = config.get_warnings().unwrap();
+        assert_eq!(
+            compile_flags(CompilerFamily::Gcc, &warnings),
+            ["-Werror=unused-variable", "-Wno-error=sign-compare"]
+        );
+        assert_eq!(compile_flags(CompilerFamily::Msvc, &warnings), ["/we4101"]);
+
+        let config: Config = toml::from_str(r#"warnings = "deny""#).unwrap();
+        let warnings = config.get_warnings().unwrap();
+        assert_eq!(warnings, Warnings::All(Level::Deny));
+        assert_eq!(compile_flags(CompilerFamily::Clang, &warnings), ["-Werror"]);
+        assert!(toml::from_str::<Config>(r#"warnings = "ignore""#).is_err());
+    }
+
+    #[test]
+    fn warnings_count() {
+        let stderr = "main.c: In function 'main':\n\
+                      main.c:3:9: warning: unused variable 'x' [-Wunused-variable]\n\
+                      \x20   3 |     int x;\n\
+                      main.c:4:5: error: 'y' undeclared (first use in this function)\n\
+                      util.h:1:12: warning: 'f' defined but not used [-Wunused-function]\n";
+        assert_eq!(count(stderr), 2);
+    }
+}